@@ -18,6 +18,10 @@ const dbStack = new EmotionDatabaseStack(app, 'ItsukiEmotionDBStack', {
 })
 const handlerStack = new EmotionHandlerStack(app, 'ItsukiEmotionHandlerStack', {
     table: dbStack.table,
+    usageTable: dbStack.usageTable,
+    scoreCacheTable: dbStack.scoreCacheTable,
+    rollupTable: dbStack.rollupTable,
+    dataKeyTable: dbStack.dataKeyTable,
     env: {
         region: region
     }
@@ -5,6 +5,10 @@ import { Construct } from "constructs";
 
 export class EmotionDatabaseStack extends Stack {
     table: Table;
+    usageTable: Table;
+    scoreCacheTable: Table;
+    rollupTable: Table;
+    dataKeyTable: Table;
 
     constructor(scope: Construct, id: string, props?: StackProps) {
         super(scope, id, props);
@@ -13,7 +17,9 @@ export class EmotionDatabaseStack extends Stack {
             partitionKey: { name: 'event_id', type: AttributeType.STRING },
             billingMode: BillingMode.PAY_PER_REQUEST,
             removalPolicy: RemovalPolicy.DESTROY,
-            pointInTimeRecovery: true
+            pointInTimeRecovery: true,
+            // set from ENTRY_RETENTION_DAYS, entries without it are kept
+            timeToLiveAttribute: 'ttl',
         });
 
         this.table.addGlobalSecondaryIndex({
@@ -28,5 +34,50 @@ export class EmotionDatabaseStack extends Stack {
             sortKey: { name: 'timestamp', type: AttributeType.NUMBER },
         });
 
+        this.table.addGlobalSecondaryIndex({
+            indexName: 'gsi-channel',
+            partitionKey: { name: 'channel_id', type: AttributeType.STRING },
+            sortKey: { name: 'timestamp', type: AttributeType.NUMBER },
+        });
+
+        // token usage, latency and estimated cost per Bedrock call
+        this.usageTable = new Table(this, 'BedrockUsageTable', {
+            partitionKey: { name: 'workspace_id', type: AttributeType.STRING },
+            sortKey: { name: 'record_id', type: AttributeType.STRING },
+            billingMode: BillingMode.PAY_PER_REQUEST,
+            removalPolicy: RemovalPolicy.DESTROY,
+        });
+
+        // emotion scores of short repeated messages, keyed by hash of text + model + prompt version
+        this.scoreCacheTable = new Table(this, 'ScoreCacheTable', {
+            partitionKey: { name: 'cache_key', type: AttributeType.STRING },
+            billingMode: BillingMode.PAY_PER_REQUEST,
+            removalPolicy: RemovalPolicy.DESTROY,
+            timeToLiveAttribute: 'ttl',
+        });
+
+        // count, sum, sum of squares and max of each emotion per user / channel / workspace and day / week / month
+        this.rollupTable = new Table(this, 'EmotionRollupTable', {
+            partitionKey: { name: 'rollup_id', type: AttributeType.STRING },
+            sortKey: { name: 'period', type: AttributeType.STRING },
+            billingMode: BillingMode.PAY_PER_REQUEST,
+            removalPolicy: RemovalPolicy.DESTROY,
+        });
+
+        // every user (channel, workspace) with a rollup for the period, for the digests
+        this.rollupTable.addGlobalSecondaryIndex({
+            indexName: 'gsi-period',
+            partitionKey: { name: 'period', type: AttributeType.STRING },
+            sortKey: { name: 'rollup_id', type: AttributeType.STRING },
+        });
+
+        // data key of each workspace for the text encryption, wrapped by the key provider.
+        // the entries carry the wrapped key too, so losing the table does not lose the text
+        this.dataKeyTable = new Table(this, 'DataKeyTable', {
+            partitionKey: { name: 'workspace_id', type: AttributeType.STRING },
+            billingMode: BillingMode.PAY_PER_REQUEST,
+            removalPolicy: RemovalPolicy.DESTROY,
+        });
+
     }
 }
\ No newline at end of file
@@ -0,0 +1,29 @@
+import { readFileSync } from 'fs';
+import { join } from 'path';
+
+// same file the lambdas embed when EMOTION_TAXONOMY is not set
+const defaultTaxonomyPath = join(__dirname, '..', '..', 'lambdas/lib/taxonomy/default.json');
+
+export interface Emotion {
+    name: string;
+    description: string;
+    polarity: "positive" | "negative" | "neutral";
+    warnings?: { [language: string]: string };
+}
+
+export interface EmotionTaxonomy {
+    emotions: Emotion[];
+    // minified json for the EMOTION_TAXONOMY env, empty when using the default
+    json: string;
+}
+
+// path: relative to the cdk directory, or empty for the default taxonomy
+export function loadEmotionTaxonomy(path?: string): EmotionTaxonomy {
+    const isDefault = !path;
+    const content = readFileSync(isDefault ? defaultTaxonomyPath : join(__dirname, '..', path), 'utf-8');
+    const emotions: Emotion[] = JSON.parse(content).emotions;
+    return {
+        emotions,
+        json: isDefault ? "" : JSON.stringify({ emotions }),
+    };
+}
@@ -2,17 +2,23 @@ import { join } from 'path';
 import { RustFunction } from 'cargo-lambda-cdk';
 import { EndpointType, LambdaRestApi } from 'aws-cdk-lib/aws-apigateway'
 import { Table } from 'aws-cdk-lib/aws-dynamodb';
-import { Duration, Stack, StackProps } from "aws-cdk-lib";
+import { Key } from 'aws-cdk-lib/aws-kms';
+import { Duration, RemovalPolicy, Stack, StackProps } from "aws-cdk-lib";
 import { Construct } from "constructs";
 import { Effect, PolicyStatement } from 'aws-cdk-lib/aws-iam';
 import { Queue } from 'aws-cdk-lib/aws-sqs';
 import { SqsEventSource } from 'aws-cdk-lib/aws-lambda-event-sources';
-import { Rule, Schedule } from 'aws-cdk-lib/aws-events';
+import { Rule, RuleTargetInput, Schedule } from 'aws-cdk-lib/aws-events';
 import { LambdaFunction } from 'aws-cdk-lib/aws-events-targets';
+import { loadEmotionTaxonomy } from './emotion-taxonomy';
 
 
 export interface HandlerStackProps extends StackProps {
     table: Table;
+    usageTable: Table;
+    scoreCacheTable: Table;
+    rollupTable: Table;
+    dataKeyTable: Table;
 }
 
 export class EmotionHandlerStack extends Stack {
@@ -21,18 +27,76 @@ export class EmotionHandlerStack extends Stack {
     private slackToken = this.context["SLACK_VERIFICATION_TOKEN"];
     private botToken = this.context["BOT_OAUTH_TOKEN"];
     private warningThreshold = this.context["IMMEDIATE_WARNING_THRESHOLD"] ?? "0.6";
+    private warningMaxStdDev = this.context["WARNING_MAX_STD_DEV"] ?? "";
+    private scoringSamples = this.context["SCORING_SAMPLES"] ?? "1";
+    private scoringTemperatures = this.context["SCORING_TEMPERATURES"] ?? "";
+    private redactionDetectors = this.context["REDACTION_DETECTORS"] ?? "";
+    private redactionPlaceholders = this.context["REDACTION_PLACEHOLDERS"] ?? "";
+    private persistedText = this.context["PERSISTED_TEXT"] ?? "redacted";
+    private toxicityClassifierEnabled = this.context["TOXICITY_CLASSIFIER_ENABLED"] ?? "false";
+    private toxicityThreshold = this.context["TOXICITY_THRESHOLD"] ?? "0.7";
+    private moderatorChannelId = this.context["MODERATOR_CHANNEL_ID"] ?? "";
+    private personDirectedWarning = this.context["PERSON_DIRECTED_WARNING"] ?? "private";
+    private personDirectedWarningThreshold = this.context["PERSON_DIRECTED_WARNING_THRESHOLD"] ?? "";
     private resultChannelId = this.context["RESULT_CHANNEL_ID"];
     private chatModel = this.context["CHAT_MODEL"] ?? "anthropic.claude-3-haiku-20240307-v1:0";
+    private fallbackChatModels = this.context["FALLBACK_CHAT_MODELS"] ?? "";
+    private bedrockMaxRetries = this.context["BEDROCK_MAX_RETRIES"] ?? "3";
+    private workspaceId = this.context["WORKSPACE_ID"] ?? "default";
+    private modelPrices = this.context["MODEL_PRICES"] ?? "";
+    private adminApiToken = this.context["ADMIN_API_TOKEN"] ?? "";
+    private promptVersions = this.context["PROMPT_VERSIONS"] ?? "";
+    private scoreCacheTtlDays = this.context["SCORE_CACHE_TTL_DAYS"] ?? "30";
+    private scoreCacheMaxLength = this.context["SCORE_CACHE_MAX_LENGTH"] ?? "40";
+    private entryRetentionDays = this.context["ENTRY_RETENTION_DAYS"] ?? "";
+    private textEncryption = this.context["TEXT_ENCRYPTION"] ?? "none";
+    private textEncryptionKmsKeyArn = this.context["TEXT_ENCRYPTION_KMS_KEY_ARN"] ?? "";
+    private emotionScorer = this.context["EMOTION_SCORER"] ?? "bedrock";
+    private scorerFallback = this.context["SCORER_FALLBACK"] ?? "lexicon";
+    private digestTeams = this.context["DIGEST_TEAMS"] ?? "";
+    private reportCharts = this.context["REPORT_CHARTS"] ?? "false";
+    private teamReportGroups = this.context["TEAM_REPORT_GROUPS"] ?? "";
+    private teamReportMinGroupSize = this.context["TEAM_REPORT_MIN_GROUP_SIZE"] ?? "5";
+    private teamReportChannelId = this.context["TEAM_REPORT_CHANNEL_ID"] ?? "";
+    private emotionTaxonomy = loadEmotionTaxonomy(this.context["EMOTION_TAXONOMY_FILE"]);
 
     constructor(scope: Construct, id: string, props: HandlerStackProps) {
         super(scope, id, props);
 
         const table = props.table;
+        const usageTable = props.usageTable;
+        const scoreCacheTable = props.scoreCacheTable;
+        const rollupTable = props.rollupTable;
+        const dataKeyTable = props.dataKeyTable;
+
+        // wraps the data keys of the text encryption, created unless an existing key is given.
+        // retained: the stored text can not be read without it
+        const textKey = this.textEncryption !== "kms" ? undefined
+            : this.textEncryptionKmsKeyArn ? Key.fromKeyArn(this, 'TextEncryptionKey', this.textEncryptionKmsKeyArn)
+            : new Key(this, 'TextEncryptionKey', {
+                enableKeyRotation: true,
+                removalPolicy: RemovalPolicy.RETAIN,
+            });
+        const textEncryptionEnvironment = {
+            "TEXT_ENCRYPTION": this.textEncryption,
+            "TEXT_ENCRYPTION_KMS_KEY_ID": textKey?.keyArn ?? "",
+            "DATA_KEY_TABLE_NAME": dataKeyTable.tableName,
+        };
 
         // sqs
+        // messages that still fail to be scored after the retries end up here instead of being lost
+        const deadLetterQueue = new Queue(this, 'SlackEventDeadLetterQueue.fifo', {
+            retentionPeriod: Duration.days(14),
+            fifo: true,
+        });
+
         const queue = new Queue(this, 'SlackEventQueue.fifo', {
             visibilityTimeout: Duration.minutes(10),
             fifo: true,
+            deadLetterQueue: {
+                queue: deadLetterQueue,
+                maxReceiveCount: 5,
+            },
         });
 
 
@@ -43,10 +107,21 @@ export class EmotionHandlerStack extends Stack {
             environment: {
                 "SLACK_VERIFICATION_TOKEN": this.slackToken,
                 "QUEUE_URL": queue.queueUrl,
+                "USAGE_TABLE_NAME": usageTable.tableName,
+                "WORKSPACE_ID": this.workspaceId,
+                "ADMIN_API_TOKEN": this.adminApiToken,
+                // channel weather reports
+                'TABLE_NAME': table.tableName,
+                "BOT_OAUTH_TOKEN": this.botToken,
+                "IMMEDIATE_WARNING_THRESHOLD": this.warningThreshold,
+                "TEAM_REPORT_MIN_GROUP_SIZE": this.teamReportMinGroupSize,
+                "EMOTION_TAXONOMY": this.emotionTaxonomy.json
             }
         });
 
         queue.grantSendMessages(apigatewayLambda)
+        usageTable.grantReadData(apigatewayLambda)
+        table.grantReadData(apigatewayLambda)
 
         const restApi = new LambdaRestApi(this, 'EmotionAPIGateway', {
             handler: apigatewayLambda,
@@ -60,9 +135,35 @@ export class EmotionHandlerStack extends Stack {
                 "SLACK_VERIFICATION_TOKEN": this.slackToken,
                 "QUEUE_ARN": queue.queueArn,
                 'TABLE_NAME': table.tableName,
+                "ROLLUP_TABLE_NAME": rollupTable.tableName,
                 "BOT_OAUTH_TOKEN": this.botToken,
                 "IMMEDIATE_WARNING_THRESHOLD": this.warningThreshold,
-                "CHAT_MODEL": this.chatModel
+                "WARNING_MAX_STD_DEV": this.warningMaxStdDev,
+                "REDACTION_DETECTORS": this.redactionDetectors,
+                "REDACTION_PLACEHOLDERS": this.redactionPlaceholders,
+                "PERSISTED_TEXT": this.persistedText,
+                ...textEncryptionEnvironment,
+                "ENTRY_RETENTION_DAYS": this.entryRetentionDays,
+                "SCORING_SAMPLES": this.scoringSamples,
+                "SCORING_TEMPERATURES": this.scoringTemperatures,
+                "TOXICITY_CLASSIFIER_ENABLED": this.toxicityClassifierEnabled,
+                "TOXICITY_THRESHOLD": this.toxicityThreshold,
+                "MODERATOR_CHANNEL_ID": this.moderatorChannelId,
+                "PERSON_DIRECTED_WARNING": this.personDirectedWarning,
+                "PERSON_DIRECTED_WARNING_THRESHOLD": this.personDirectedWarningThreshold,
+                "CHAT_MODEL": this.chatModel,
+                "FALLBACK_CHAT_MODELS": this.fallbackChatModels,
+                "BEDROCK_MAX_RETRIES": this.bedrockMaxRetries,
+                "EMOTION_SCORER": this.emotionScorer,
+                "SCORER_FALLBACK": this.scorerFallback,
+                "SCORE_CACHE_TABLE_NAME": scoreCacheTable.tableName,
+                "SCORE_CACHE_TTL_DAYS": this.scoreCacheTtlDays,
+                "SCORE_CACHE_MAX_LENGTH": this.scoreCacheMaxLength,
+                "USAGE_TABLE_NAME": usageTable.tableName,
+                "WORKSPACE_ID": this.workspaceId,
+                "MODEL_PRICES": this.modelPrices,
+                "PROMPT_VERSIONS": this.promptVersions,
+                "EMOTION_TAXONOMY": this.emotionTaxonomy.json
             },
             timeout: Duration.minutes(5)
         });
@@ -74,6 +175,11 @@ export class EmotionHandlerStack extends Stack {
             })
         )
         table.grantReadWriteData(sqsLambda)
+        usageTable.grantWriteData(sqsLambda)
+        scoreCacheTable.grantReadWriteData(sqsLambda)
+        rollupTable.grantReadWriteData(sqsLambda)
+        dataKeyTable.grantReadWriteData(sqsLambda)
+        textKey?.grant(sqsLambda, 'kms:GenerateDataKey', 'kms:Decrypt')
         sqsLambda.addToRolePolicy(new PolicyStatement({
             effect: Effect.ALLOW,
             actions: [
@@ -87,14 +193,34 @@ export class EmotionHandlerStack extends Stack {
             manifestPath: join(__dirname, '..', '..', 'lambdas/daily_handler/'),
             environment: {
                 'TABLE_NAME': table.tableName,
+                "ROLLUP_TABLE_NAME": rollupTable.tableName,
                 "RESULT_CHANNEL_ID": this.resultChannelId,
+                "REPORT_CHARTS": this.reportCharts,
+                "WARNING_MAX_STD_DEV": this.warningMaxStdDev,
+                "DIGEST_TEAMS": this.digestTeams,
+                "TEAM_REPORT_GROUPS": this.teamReportGroups,
+                "TEAM_REPORT_MIN_GROUP_SIZE": this.teamReportMinGroupSize,
+                "TEAM_REPORT_CHANNEL_ID": this.teamReportChannelId,
+                "IMMEDIATE_WARNING_THRESHOLD": this.warningThreshold,
+                ...textEncryptionEnvironment,
                 "BOT_OAUTH_TOKEN": this.botToken,
-                "CHAT_MODEL": this.chatModel
+                "CHAT_MODEL": this.chatModel,
+                "FALLBACK_CHAT_MODELS": this.fallbackChatModels,
+                "BEDROCK_MAX_RETRIES": this.bedrockMaxRetries,
+                "USAGE_TABLE_NAME": usageTable.tableName,
+                "WORKSPACE_ID": this.workspaceId,
+                "MODEL_PRICES": this.modelPrices,
+                "PROMPT_VERSIONS": this.promptVersions,
+                "EMOTION_TAXONOMY": this.emotionTaxonomy.json
             },
             timeout: Duration.minutes(5)
         });
 
         table.grantReadWriteData(dailyLambda)
+        usageTable.grantWriteData(dailyLambda)
+        rollupTable.grantReadData(dailyLambda)
+        dataKeyTable.grantReadData(dailyLambda)
+        textKey?.grantDecrypt(dailyLambda)
         dailyLambda.addToRolePolicy(new PolicyStatement({
             effect: Effect.ALLOW,
             actions: [
@@ -113,5 +239,68 @@ export class EmotionHandlerStack extends Stack {
                 retryAttempts: 0
             })]
         })
+
+        const digestLambda =  new RustFunction(this, 'EmotionDigestLambda', {
+            // Path to the root directory.
+            manifestPath: join(__dirname, '..', '..', 'lambdas/digest_handler/'),
+            environment: {
+                'TABLE_NAME': table.tableName,
+                "ROLLUP_TABLE_NAME": rollupTable.tableName,
+                "DIGEST_TEAMS": this.digestTeams,
+                "REPORT_CHARTS": this.reportCharts,
+                "TEAM_REPORT_GROUPS": this.teamReportGroups,
+                "TEAM_REPORT_MIN_GROUP_SIZE": this.teamReportMinGroupSize,
+                "TEAM_REPORT_CHANNEL_ID": this.teamReportChannelId,
+                "IMMEDIATE_WARNING_THRESHOLD": this.warningThreshold,
+                ...textEncryptionEnvironment,
+                "BOT_OAUTH_TOKEN": this.botToken,
+                "CHAT_MODEL": this.chatModel,
+                "FALLBACK_CHAT_MODELS": this.fallbackChatModels,
+                "BEDROCK_MAX_RETRIES": this.bedrockMaxRetries,
+                "USAGE_TABLE_NAME": usageTable.tableName,
+                "WORKSPACE_ID": this.workspaceId,
+                "MODEL_PRICES": this.modelPrices,
+                "PROMPT_VERSIONS": this.promptVersions,
+                "EMOTION_TAXONOMY": this.emotionTaxonomy.json
+            },
+            timeout: Duration.minutes(15)
+        });
+
+        table.grantReadData(digestLambda)
+        rollupTable.grantReadData(digestLambda)
+        usageTable.grantWriteData(digestLambda)
+        dataKeyTable.grantReadData(digestLambda)
+        textKey?.grantDecrypt(digestLambda)
+        digestLambda.addToRolePolicy(new PolicyStatement({
+            effect: Effect.ALLOW,
+            actions: [
+                'bedrock:InvokeModel'
+            ],
+            resources: ['*'],
+        }))
+        // 09:00 JST on Mondays, for the week before
+        const weeklyDigestRule = new Rule(this, 'EmotionWeeklyDigestRule', {
+            schedule: Schedule.cron({
+                minute: '0',
+                hour: '0',
+                weekDay: 'MON',
+            }),
+            targets: [new LambdaFunction(digestLambda, {
+                event: RuleTargetInput.fromObject({ period: 'week' }),
+                retryAttempts: 0
+            })]
+        })
+        // 09:00 JST on the 1st, for the month before
+        const monthlyDigestRule = new Rule(this, 'EmotionMonthlyDigestRule', {
+            schedule: Schedule.cron({
+                minute: '0',
+                hour: '0',
+                day: '1',
+            }),
+            targets: [new LambdaFunction(digestLambda, {
+                event: RuleTargetInput.fromObject({ period: 'month' }),
+                retryAttempts: 0
+            })]
+        })
     }
 }
\ No newline at end of file
@@ -10,6 +10,7 @@ import { LambdaFunction } from 'aws-cdk-lib/aws-events-targets';
 import { Bucket, EventType } from 'aws-cdk-lib/aws-s3';
 import { CfnTable } from 'aws-cdk-lib/aws-glue';
 import { CfnAnalysis, CfnDashboard, CfnDataSet, CfnDataSource, CfnTemplate } from 'aws-cdk-lib/aws-quicksight';
+import { loadEmotionTaxonomy } from './emotion-taxonomy';
 
 
 export interface VisualizerStackProps extends StackProps {
@@ -26,6 +27,14 @@ export class EmotionVisualizerStack extends Stack {
 
     private quicksightUserName = this.context["QUICKSIGHT_USER_NAME"]
     private timezone = this.context["QUICKSIGHT_TIMEZONE"] ?? "Asia/Tokyo"
+    // days, empty to keep forever
+    private textRetentionDays = this.context["TEXT_RETENTION_DAYS"] ?? ""
+    private textRetentionLookbackDays = this.context["TEXT_RETENTION_LOOKBACK_DAYS"] ?? "7"
+    private entryRetentionDays = this.context["ENTRY_RETENTION_DAYS"] ?? ""
+    // default to the shorter of TEXT_RETENTION_DAYS and ENTRY_RETENTION_DAYS in the lambda
+    private exportRetentionDays = this.context["EXPORT_RETENTION_DAYS"] ?? ""
+    // one column per emotion
+    private emotions = loadEmotionTaxonomy(this.context["EMOTION_TAXONOMY_FILE"]).emotions
 
 
     constructor(scope: Construct, id: string, props: VisualizerStackProps) {
@@ -95,6 +104,35 @@ export class EmotionVisualizerStack extends Stack {
         }))
         dataBucket.grantReadWrite(exportFinishLambda)
 
+
+        // drops text and old exports past retention, writes a report to retention-reports/
+        const retentionLambda =  new RustFunction(this, 'RetentionLambda', {
+            // Path to the root directory.
+            manifestPath: join(__dirname, '..', '..', 'lambdas/retention_handler/'),
+            environment: {
+                'TABLE_NAME': table.tableName,
+                "BUCKET_NAME": dataBucket.bucketName,
+                "TEXT_RETENTION_DAYS": this.textRetentionDays,
+                "TEXT_RETENTION_LOOKBACK_DAYS": this.textRetentionLookbackDays,
+                "ENTRY_RETENTION_DAYS": this.entryRetentionDays,
+                "EXPORT_RETENTION_DAYS": this.exportRetentionDays,
+            },
+            timeout: Duration.minutes(15)
+        });
+        table.grantReadWriteData(retentionLambda)
+        dataBucket.grantReadWrite(retentionLambda)
+
+        // Daily 18:00 UTC (03:00 JST)
+        const retentionRule = new Rule(this, 'RetentionDailyRule', {
+            schedule: Schedule.cron({
+                minute: '00',
+                hour: '18',
+            }),
+            targets: [new LambdaFunction(retentionLambda, {
+                retryAttempts: 0
+            })]
+        })
+
         const glueTable = new CfnTable(this, "EmotionDatatable", {
             databaseName: this.databaseName,
             catalogId: this.account,
@@ -107,7 +145,7 @@ export class EmotionVisualizerStack extends Stack {
                     columns: [
                         {
                             "name": "item",
-                            "type": "struct<event_id:struct<S:string>,surprise:struct<N:string>,timestamp:struct<N:string>,text:struct<S:string>,contempt:struct<N:string>,fear:struct<N:string>,joy:struct<N:string>,user_id:struct<S:string>,date:struct<S:string>,channel_id:struct<S:string>,month:struct<S:string>,sad:struct<N:string>,anger:struct<N:string>,channel_type:struct<S:string>,disgust:struct<N:string>>"
+                            "type": `struct<event_id:struct<S:string>,timestamp:struct<N:string>,text:struct<S:string>,user_id:struct<S:string>,date:struct<S:string>,channel_id:struct<S:string>,month:struct<S:string>,channel_type:struct<S:string>,${this.emotions.map(e => `${e.name}:struct<N:string>`).join(",")},language:struct<S:string>,model_id:struct<S:string>,prompt_version:struct<S:string>,from_cache:struct<BOOL:boolean>,target:struct<M:struct<category:struct<S:string>>>>`
                         }
                     ],
                     inputFormat: "org.apache.hadoop.mapred.TextInputFormat",
@@ -228,37 +266,33 @@ export class EmotionVisualizerStack extends Stack {
                                 name: "month",
                                 type: "STRING"
                             },
-                            {
-                                name: "anger",
-                                type: "INTEGER"
-                            },
-                            {
-                                name: "contempt",
+                            ...this.emotions.map(e => ({
+                                name: e.name,
                                 type: "INTEGER"
-                            },
+                            })),
                             {
-                                name: "disgust",
+                                name: "timestamp",
                                 type: "INTEGER"
                             },
                             {
-                                name: "fear",
-                                type: "INTEGER"
+                                name: "language",
+                                type: "STRING"
                             },
                             {
-                                name: "joy",
-                                type: "INTEGER"
+                                name: "model_id",
+                                type: "STRING"
                             },
                             {
-                                name: "sad",
-                                type: "INTEGER"
+                                name: "prompt_version",
+                                type: "STRING"
                             },
                             {
-                                name: "surprise",
-                                type: "INTEGER"
+                                name: "from_cache",
+                                type: "BOOLEAN"
                             },
                             {
-                                name: "timestamp",
-                                type: "INTEGER"
+                                name: "target_category",
+                                type: "STRING"
                             }
                         ],
                         name: "EmotionDataCustomSql",
@@ -271,14 +305,13 @@ export class EmotionVisualizerStack extends Stack {
                             Item.channel_type.S AS channel_type,
                             CAST(Item.date.S AS date) AS date,
                             Item.month.S AS month,
-                            CAST(Item.anger.N AS DECIMAL(38, 2)) AS anger,
-                            CAST(Item.contempt.N AS DECIMAL(38, 2)) AS contempt,
-                            CAST(Item.disgust.N AS DECIMAL(38, 2)) AS disgust,
-                            CAST(Item.fear.N AS DECIMAL(38, 2)) AS fear,
-                            CAST(Item.joy.N AS DECIMAL(38, 2)) AS joy,
-                            CAST(Item.sad.N AS DECIMAL(38, 2)) AS sad,
-                            CAST(Item.surprise.N AS DECIMAL(38, 2)) AS surprise,
-                            CAST(Item.timestamp.N AS Int) AS timestamp
+                            ${this.emotions.map(e => `CAST(COALESCE(Item.${e.name}.N, '0') AS DECIMAL(38, 2)) AS ${e.name},`).join("\n                            ")}
+                            CAST(Item.timestamp.N AS Int) AS timestamp,
+                            COALESCE(Item.language.S, 'unknown') AS language,
+                            Item.model_id.S AS model_id,
+                            COALESCE(Item.prompt_version.S, 'legacy') AS prompt_version,
+                            COALESCE(Item.from_cache.BOOL, false) AS from_cache,
+                            COALESCE(Item.target.M.category.S, 'none') AS target_category
                         FROM "AwsDataCatalog"."${this.databaseName}"."${this.glueTableName}"
                         `
                     }
@@ -322,9 +355,11 @@ export class EmotionVisualizerStack extends Stack {
             name: datetimeColumnName
         }
 
+        const warningEmotions = this.emotions.filter(e => e.warnings && Object.keys(e.warnings).length > 0).map(e => e.name)
         const negativeScore: CfnAnalysis.CalculatedFieldProperty = {
             dataSetIdentifier: this.datasetName,
-            expression: "(anger+contempt+disgust)/3",
+            // average of the emotions that trigger an immediate warning
+            expression: `(${warningEmotions.join("+")})/${warningEmotions.length}`,
             name: negativeScoreColumnName
         }
 
@@ -450,61 +485,13 @@ export class EmotionVisualizerStack extends Stack {
                                     },
                                     fieldId: `EmotionDataTableVisual-${datetime.name}`
                                 },
-                                {
+                                ...this.emotions.map(e => ({
                                     column: {
-                                        columnName: "anger",
+                                        columnName: e.name,
                                         dataSetIdentifier: this.datasetName
                                     },
-                                    fieldId: `EmotionDataTableVisual-anger`
-
-                                },
-                                {
-                                    column: {
-                                        columnName: "contempt",
-                                        dataSetIdentifier: this.datasetName
-                                    },
-                                    fieldId: `EmotionDataTableVisual-contempt`
-
-                                },
-                                {
-                                    column: {
-                                        columnName: "disgust",
-                                        dataSetIdentifier: this.datasetName
-                                    },
-                                    fieldId: `EmotionDataTableVisual-disgust`
-
-                                },
-                                {
-                                    column: {
-                                        columnName: "surprise",
-                                        dataSetIdentifier: this.datasetName
-                                    },
-                                    fieldId: `EmotionDataTableVisual-surprise`
-
-                                },
-                                {
-                                    column: {
-                                        columnName: "fear",
-                                        dataSetIdentifier: this.datasetName
-                                    },
-                                    fieldId: `EmotionDataTableVisual-fear`
-
-                                },
-                                {
-                                    column: {
-                                        columnName: "joy",
-                                        dataSetIdentifier: this.datasetName
-                                    },
-                                    fieldId: `EmotionDataTableVisual-joy`
-
-                                },
-                                {
-                                    column: {
-                                        columnName: "sad",
-                                        dataSetIdentifier: this.datasetName
-                                    },
-                                    fieldId: `EmotionDataTableVisual-sad`
-                                }
+                                    fieldId: `EmotionDataTableVisual-${e.name}`
+                                }))
                             ]
                         }
                     }
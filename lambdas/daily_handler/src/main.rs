@@ -6,8 +6,15 @@ use lambda_runtime::{service_fn, tracing::{self}, Error, LambdaEvent};
 use serde_json::{json, Value};
 
 
-use lib::env_keys::TABLE_NAME;
-use lib::service::{common_structs::EmotionScores, CommonService};
+use lib::charts::{charts_enabled_from_env, mean_profile, render_radar, render_timeline, EmotionTimeline};
+use lib::env_keys::WARNING_MAX_STD_DEV;
+use lib::language::Language;
+use lib::rollup::{Granularity, Rollup, RollupQuery, RollupScope};
+use lib::service::{common_structs::{DailyPick, EmotionScores, DAILY_PICK_MIN_SCORE}, dynamo_service::structs::EmotionTableEntry, line_service::ChartImage, CommonService};
+use lib::statistics::summarize_by_language;
+use lib::taxonomy::taxonomy;
+use lib::team_report::team_reports_from_env;
+use lib::utilities::get_previous_weekday;
 
 
 #[tokio::main]
@@ -25,7 +32,18 @@ async fn main() -> Result<(), Error> {
 
 async fn eventbridge_handler(event: LambdaEvent<EventBridgeEvent>, service: &CommonService) -> Result<Value, Error> {
     println!("{:?}", event.payload);
-    match process_event(service).await {
+    // scores only, the text of the picked entries is read on its own.
+    // the picks come from the day rollups, but the advice, the language, the split of the messages aimed at a person,
+    // the hourly chart and the team reports all need the scores of each message, which the rollups do not keep
+    let date = get_previous_weekday()?;
+    let entries = match service.repository.query_date_scores_only(&date).await {
+        Ok(entries) => entries,
+        Err(error) => {
+            println!("Error querying entries: {:?}", error);
+            return Ok(json!({}))
+        },
+    };
+    match process_event(service, &date, &entries).await {
         Ok(_) => {
             println!("finish processing event with success!")
         },
@@ -33,24 +51,23 @@ async fn eventbridge_handler(event: LambdaEvent<EventBridgeEvent>, service: &Com
             println!("Error processing event: {:?}", error)
         },
     }
-    return Ok(json!({}))
+    // sent whether or not the daily reports failed
+    if let Err(error) = send_team_reports(service, &date, &entries).await {
+        println!("Error sending team reports: {:?}", error)
+    }
+    Ok(json!({}))
 }
 
 
-async fn process_event(service: &CommonService) -> anyhow::Result<()> {
-    let table_name = std::env::var(TABLE_NAME)?;
-    let entries = service.dynamo.query_yesterday(&table_name).await?;
+async fn process_event(service: &CommonService, date: &str, entries: &[EmotionTableEntry]) -> anyhow::Result<()> {
+    let language_summaries = summarize_by_language(entries);
+    let max_std_dev: Option<f64> = std::env::var(WARNING_MAX_STD_DEV).ok().and_then(|s| s.parse().ok());
 
-    let mut map: HashMap<String, Vec<(EmotionScores, String)>> = HashMap::new();
+    let mut map: HashMap<String, Vec<EmotionTableEntry>> = HashMap::new();
+    let mut language_counts: HashMap<String, HashMap<Language, usize>> = HashMap::new();
     for entry in entries {
-        let user_id = entry.user_id;
-        let score = entry.scores;
-        let text = entry.text;
-        if map.contains_key(&user_id) {
-            map.entry(user_id).and_modify(|vec| vec.push((score, text)));
-        } else {
-            map.insert(user_id, vec![(score, text)]);
-        }
+        *language_counts.entry(entry.user_id.clone()).or_default().entry(entry.language).or_default() += 1;
+        map.entry(entry.user_id.clone()).or_default().push(entry.to_owned());
     };
 
     if map.is_empty() {
@@ -61,30 +78,122 @@ async fn process_event(service: &CommonService) -> anyhow::Result<()> {
 
 
     for (user_id, results) in map.into_iter() {
-        let scores: Vec<EmotionScores> = results.clone().into_iter().map(|r| r.0).collect();
-        let advice = service.bedrock.get_daily_advice(&scores).await?;
-        println!("userId: {}, advice: {:?}", user_id, advice);
-
-        let max_anger = results.clone().into_iter().reduce(|e1, e2| {
-            if e1.0.anger > e2.0.anger {
-                return e1
-            } else { return e2 }
-        }).context("failed to find max anger")?;
-
-        let max_contempt = results.clone().into_iter().reduce(|e1, e2| {
-            if e1.0.contempt > e2.0.contempt {
-                return e1
-            } else { return e2 }
-        }).context("failed to find max contempt")?;
-
-        let max_disgust = results.clone().into_iter().reduce(|e1, e2| {
-            if e1.0.disgust > e2.0.disgust {
-                return e1
-            } else { return e2 }
-        }).context("failed to find max disgust")?;
-
-        service.line.send_daily_advice(&thread_ts, &user_id, &advice, &max_anger, &max_contempt, &max_disgust).await?;
+        let scores: Vec<EmotionScores> = results.iter().map(|r| r.scores.to_owned()).collect();
+        // advice in the language the user wrote in the most
+        let language = language_counts.get(&user_id)
+            .and_then(|counts| counts.iter().max_by_key(|(_, count)| **count).map(|(language, _)| *language))
+            .unwrap_or_default();
+        let advice = service.bedrock.get_daily_advice(&scores, &language).await?;
+        println!("userId: {}, advice by {:?}", user_id, advice.model_id);
+
+        // messages aimed at a person are picked up separately
+        let (person_directed, others): (Vec<&EmotionTableEntry>, Vec<&EmotionTableEntry>) = results.iter().partition(|r| r.is_person_directed());
+        let day_rollup = match service.repository.query_rollups(&RollupQuery::new(RollupScope::User, &user_id, Granularity::Day, date, date)?).await {
+            Ok(rollups) => rollups.into_iter().next(),
+            Err(error) => {
+                println!("Error reading the day rollup of {}, picks are taken from the entries: {:?}", user_id, error);
+                None
+            },
+        };
+        let mut picks = vec![];
+        for (group, is_person_directed) in [(others, false), (person_directed.clone(), true)] {
+            if group.is_empty() {
+                continue;
+            }
+            for emotion in taxonomy().warning_emotions() {
+                let max = match is_person_directed {
+                    false => rollup_max(day_rollup.as_ref(), &emotion.name, &group),
+                    true => None,
+                };
+                let max = max.or_else(|| group_max(&group, &emotion.name)).context(format!("failed to find max {}", emotion.name))?;
+                let score = max.scores.get(&emotion.name);
+                if score < DAILY_PICK_MIN_SCORE {
+                    continue;
+                }
+                // only the quoted entries are read with their text and decrypted, a failure leaves the pick unquoted
+                let text = match service.repository.get_entry(&max.event_id).await {
+                    Ok(Some(quoted)) => match service.open(&quoted).await {
+                        Ok(opened) => opened.text,
+                        Err(error) => {
+                            println!("Error decrypting entry {}, the pick is left unquoted: {:?}", max.event_id, error);
+                            String::new()
+                        },
+                    },
+                    Ok(None) => {
+                        println!("entry {} not found, the pick is left unquoted", max.event_id);
+                        String::new()
+                    },
+                    Err(error) => {
+                        println!("Error reading entry {}, the pick is left unquoted: {:?}", max.event_id, error);
+                        String::new()
+                    },
+                };
+                picks.push(DailyPick {
+                    emotion: emotion.name.to_owned(),
+                    score,
+                    text,
+                    uncertain: max.is_uncertain(&emotion.name, max_std_dev),
+                    person_directed: is_person_directed,
+                });
+            }
+        }
+
+        let charts = match charts_enabled_from_env() {
+            true => upload_charts(service, &user_id, &results).await,
+            false => vec![],
+        };
+        service.line.send_daily_advice(&thread_ts, &user_id, &advice, &picks, person_directed.len(), &charts).await?;
+    }
+
+    if language_summaries.len() > 1 {
+        service.line.send_language_comparison(&thread_ts, &language_summaries).await?;
     }
 
     Ok(())
 }
+
+// the message of the argmax of the day rollup, if it is one of the group.
+// none without rollup, or if the max of the day was aimed at a person: the rollups do not tell the groups apart
+fn rollup_max<'a>(rollup: Option<&Rollup>, emotion: &str, group: &[&'a EmotionTableEntry]) -> Option<&'a EmotionTableEntry> {
+    let argmax = rollup?.stats.get(emotion).argmax?;
+    group.iter().copied().find(|e| e.event_id == argmax)
+}
+
+// the message of the group with the highest score for the emotion, the last one on a tie
+fn group_max<'a>(group: &[&'a EmotionTableEntry], emotion: &str) -> Option<&'a EmotionTableEntry> {
+    group.iter()
+        .copied()
+        .reduce(|e1, e2| if e1.scores.get(emotion) > e2.scores.get(emotion) { e1 } else { e2 })
+}
+
+// anonymised, for the managers, see team_reports_from_env
+async fn send_team_reports(service: &CommonService, date: &str, entries: &[EmotionTableEntry]) -> anyhow::Result<()> {
+    let reports = team_reports_from_env(entries);
+    if reports.is_empty() {
+        return Ok(())
+    }
+    service.line.send_team_reports("day", date, date, &reports).await
+}
+
+// the day by hour and the average profile of the user.
+// a chart that fails to render or upload is left out, the report is sent without it
+async fn upload_charts(service: &CommonService, user_id: &str, entries: &[EmotionTableEntry]) -> Vec<ChartImage> {
+    let entries: Vec<&EmotionTableEntry> = entries.iter().collect();
+    let charts = [
+        ("timeline", "Emotions by hour", EmotionTimeline::hourly(&entries).and_then(|timeline| render_timeline(&timeline))),
+        ("profile", "Average profile", render_radar(&mean_profile(&entries))),
+    ];
+
+    let mut images = vec![];
+    for (name, title, png) in charts {
+        let filename = format!("{}-{}.png", user_id, name);
+        match png {
+            Ok(png) => match service.line.upload_chart(&filename, title, png).await {
+                Ok(image) => images.push(image),
+                Err(error) => println!("Error uploading {}: {:?}", filename, error),
+            },
+            Err(error) => println!("Error rendering {}: {:?}", filename, error),
+        }
+    }
+    images
+}
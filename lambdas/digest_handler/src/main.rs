@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+use anyhow::bail;
+use chrono::NaiveDate;
+use lambda_runtime::{service_fn, tracing::{self}, Error, LambdaEvent};
+use serde_json::{json, Value};
+
+use lib::charts::{charts_enabled_from_env, render_radar, render_timeline, EmotionTimeline};
+use lib::digest::{main_language, Digest, DigestPeriod};
+use lib::rollup::{dates_between, Granularity, Rollup, RollupQuery, RollupScope};
+use lib::team_report::{team_reports_enabled_from_env, team_reports_from_env};
+use lib::service::{emotion_repository::UserQuery, line_service::ChartImage, CommonService};
+use lib::utilities::{get_day_range, get_today};
+
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing::init_default_subscriber();
+
+    let config = aws_config::load_from_env().await;
+    let service = CommonService::new(&config);
+    let service_function = service_fn(|event| async { digest_handler(event, &service).await });
+    lambda_runtime::run(service_function).await?;
+
+    Ok(())
+
+}
+
+// the input of the schedule rule: {"period": "week" | "month"}
+async fn digest_handler(event: LambdaEvent<Value>, service: &CommonService) -> Result<Value, Error> {
+    println!("{:?}", event.payload);
+    match process_event(&event.payload, service).await {
+        Ok(_) => {
+            println!("finish processing event with success!")
+        },
+        Err(error) => {
+            println!("Error processing event: {:?}", error)
+        },
+    }
+    Ok(json!({}))
+}
+
+
+async fn process_event(payload: &Value, service: &CommonService) -> anyhow::Result<()> {
+    let period = DigestPeriod::parse(payload.get("period").and_then(|p| p.as_str()).unwrap_or(DigestPeriod::Week.code()))?;
+    let ranges = period.ranges(get_today()?)?;
+    let ((from, to), _) = ranges;
+
+    // the team reports are sent whether or not the digests fail
+    let digests = send_digests(service, period, ranges).await;
+    let team_reports = send_team_reports(service, period, from, to).await;
+    if let Err(error) = &team_reports {
+        println!("Error sending team reports: {:?}", error);
+    }
+    digests.and(team_reports)
+}
+
+// one digest per user with a rollup for the period, read from the week or month rollups of the user.
+// a digest that fails is logged and the others are still sent, the error comes at the end
+async fn send_digests(service: &CommonService, period: DigestPeriod, ranges: ((NaiveDate, NaiveDate), (NaiveDate, NaiveDate))) -> anyhow::Result<()> {
+    let ((from, to), (previous_from, _)) = ranges;
+    let granularity = period.granularity();
+    let mut rollups = service.repository.query_period_rollups(RollupScope::User, granularity, &granularity.period_start(&from.to_string())?).await?;
+    let previous: HashMap<String, Rollup> = service.repository.query_period_rollups(RollupScope::User, granularity, &granularity.period_start(&previous_from.to_string())?).await?
+        .into_iter()
+        .map(|rollup| (rollup.scope_id.to_owned(), rollup))
+        .collect();
+    rollups.sort_by(|r1, r2| r1.scope_id.cmp(&r2.scope_id));
+
+    let dates = dates_between(from, to);
+    let total = rollups.len();
+    let mut failed = 0;
+    for rollup in rollups {
+        let Some(digest) = Digest::new(period, &rollup.scope_id, ranges, &rollup, previous.get(&rollup.scope_id)) else {
+            continue
+        };
+        if let Err(error) = send_digest(service, digest, &rollup, &dates).await {
+            println!("Error sending digest of {}: {:?}", rollup.scope_id, error);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        bail!("{} of {} digests failed", failed, total)
+    }
+    Ok(())
+}
+
+// anonymised, for the managers, see team_reports_from_env.
+// read from the entries, scores only: a group is made of users and channels, it counts people and the messages
+// over the threshold and leaves out the messages aimed at a person, none of which the rollups keep
+async fn send_team_reports(service: &CommonService, period: DigestPeriod, from: NaiveDate, to: NaiveDate) -> anyhow::Result<()> {
+    if !team_reports_enabled_from_env() {
+        return Ok(())
+    }
+    let mut entries = vec![];
+    for date in dates_between(from, to) {
+        entries.append(&mut service.repository.query_date_scores_only(&date).await?);
+    }
+    let reports = team_reports_from_env(&entries);
+    service.line.send_team_reports(period.code(), &from.to_string(), &to.to_string(), &reports).await
+}
+
+async fn send_digest(service: &CommonService, mut digest: Digest, rollup: &Rollup, dates: &[String]) -> anyhow::Result<()> {
+    // only the picked entries are read with their text and decrypted.
+    // an entry deleted since is left unquoted, the max of a rollup is not lowered by it
+    for pick in digest.picks_mut() {
+        if let Some(entry) = service.repository.get_entry(&pick.event_id).await? {
+            pick.text = service.open(&entry).await?.text;
+        }
+    }
+    // the language is counted over the entries of the user in the period, scores only
+    let (from, to) = get_day_range(&digest.from, &digest.to)?;
+    let entries = service.repository.query_user(&UserQuery::new(&digest.user_id, from, to).with_scores_only()).await?;
+    let summary = service.bedrock.get_digest_summary(&digest, &main_language(&entries.iter().collect::<Vec<_>>())).await?;
+    println!("userId: {}, summary by {:?}", digest.user_id, summary.model_id);
+
+    let charts = match charts_enabled_from_env() {
+        true => upload_charts(service, digest.period, rollup, dates).await,
+        false => vec![],
+    };
+    service.line.send_digest(&digest, &summary, &charts).await
+}
+
+// the period by day, from the day rollups of the user, and the average profile of the user.
+// a chart that fails to render or upload is left out, the digest is sent without it
+async fn upload_charts(service: &CommonService, period: DigestPeriod, rollup: &Rollup, dates: &[String]) -> Vec<ChartImage> {
+    let timeline = match day_rollups(service, &rollup.scope_id, dates).await {
+        Ok(days) => render_timeline(&EmotionTimeline::daily(&days, dates)),
+        Err(error) => Err(error),
+    };
+    let charts = [
+        ("timeline", "Emotions by day", timeline),
+        ("profile", "Average profile", render_radar(&rollup.profile())),
+    ];
+
+    let mut images = vec![];
+    for (name, title, png) in charts {
+        let filename = format!("{}-{}.png", period.code(), name);
+        match png {
+            Ok(png) => match service.line.upload_chart(&filename, title, png).await {
+                Ok(image) => images.push(image),
+                Err(error) => println!("Error uploading {}: {:?}", filename, error),
+            },
+            Err(error) => println!("Error rendering {}: {:?}", filename, error),
+        }
+    }
+    images
+}
+
+async fn day_rollups(service: &CommonService, user_id: &str, dates: &[String]) -> anyhow::Result<Vec<Rollup>> {
+    let (Some(from), Some(to)) = (dates.first(), dates.last()) else {
+        return Ok(vec![])
+    };
+    service.repository.query_rollups(&RollupQuery::new(RollupScope::User, user_id, Granularity::Day, from, to)?).await
+}
@@ -29,7 +29,7 @@ async fn export_finish_handler(event: LambdaEvent<S3Event>, service: &CommonServ
             println!("Error processing event: {:?}", error)
         },
     }
-    return Ok(json!({}))
+    Ok(json!({}))
 }
 
 
@@ -3,7 +3,7 @@ use aws_lambda_events::eventbridge::EventBridgeEvent;
 use lambda_runtime::{service_fn, tracing::{self}, Error, LambdaEvent};
 use serde_json::{json, Value};
 
-use lib::env_keys::{BUCKET_NAME, TABLE_ARN};
+use lib::env_keys::BUCKET_NAME;
 use lib::service::CommonService;
 
 
@@ -30,13 +30,13 @@ async fn eventbridge_handler(event: LambdaEvent<EventBridgeEvent>, service: &Com
             println!("Error processing event: {:?}", error)
         },
     }
-    return Ok(json!({}))
+    Ok(json!({}))
 }
 
 
 async fn process_event(service: &CommonService) -> anyhow::Result<()> {
-    let table_arn = std::env::var(TABLE_ARN)?;
     let bucket_name = std::env::var(BUCKET_NAME)?;
-    service.dynamo.export_data(&table_arn, &bucket_name).await?;
+    let export_id = service.repository.start_export(&bucket_name).await?;
+    println!("export started: {}", export_id);
     Ok(())
 }
@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Context, Result};
+use tiny_skia::{Color, FillRule, Paint, PathBuilder, Pixmap, Rect, Stroke, Transform};
+
+use crate::{env_keys::REPORT_CHARTS, rollup::{Granularity, Rollup}, service::dynamo_service::structs::EmotionTableEntry, taxonomy::taxonomy, utilities::get_jst_hour};
+
+// Charts are drawn without text, labels and the legend are written in the message they are attached to.
+// tiny-skia is built without simd and points are snapped to a quarter pixel, so the same scores always give the same png.
+
+const BACKGROUND: (u8, u8, u8) = (255, 255, 255);
+const GRID: (u8, u8, u8) = (221, 221, 221);
+const AXIS: (u8, u8, u8) = (119, 119, 119);
+const PROFILE: (u8, u8, u8) = (59, 130, 246);
+
+// the colors of the slack square emojis, one per emotion in taxonomy order, repeated past the 8th
+const PALETTE: [((u8, u8, u8), &str); 8] = [
+    ((221, 46, 68), ":large_red_square:"),
+    ((244, 144, 12), ":large_orange_square:"),
+    ((253, 203, 88), ":large_yellow_square:"),
+    ((120, 177, 89), ":large_green_square:"),
+    ((85, 172, 238), ":large_blue_square:"),
+    ((170, 142, 214), ":large_purple_square:"),
+    ((193, 105, 79), ":large_brown_square:"),
+    ((49, 55, 61), ":black_large_square:"),
+];
+
+const TIMELINE_WIDTH: u32 = 720;
+const TIMELINE_HEIGHT: u32 = 240;
+const TIMELINE_PADDING: f32 = 16.0;
+const RADAR_SIZE: u32 = 360;
+const RADAR_PADDING: f32 = 24.0;
+
+
+// REPORT_CHARTS: "true" to attach charts to the reports, the slack app needs the files:write scope
+pub fn charts_enabled_from_env() -> bool {
+    std::env::var(REPORT_CHARTS).unwrap_or_default() == "true"
+}
+
+// the emoji of the color of the emotion, for the legend
+pub fn color_emoji(index: usize) -> &'static str {
+    PALETTE[index % PALETTE.len()].1
+}
+
+fn color(index: usize) -> (u8, u8, u8) {
+    PALETTE[index % PALETTE.len()].0
+}
+
+
+// the mean score of each emotion in consecutive buckets of time, a bucket without message is empty
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EmotionTimeline {
+    pub buckets: Vec<BTreeMap<String, f64>>,
+}
+
+impl EmotionTimeline {
+    // the 24 JST hours of the day
+    pub fn hourly(entries: &[&EmotionTableEntry]) -> Result<Self> {
+        let mut buckets: Vec<Vec<&EmotionTableEntry>> = vec![vec![]; 24];
+        for entry in entries {
+            buckets[get_jst_hour(entry.timestamp)? as usize].push(entry);
+        }
+        Ok(Self { buckets: buckets.iter().map(|b| mean_profile(b)).collect() })
+    }
+
+    // one bucket per date (YYYY-MM-DD), in the order given, from the day rollups of a single subject
+    pub fn daily(rollups: &[Rollup], dates: &[String]) -> Self {
+        let buckets = dates.iter()
+            .map(|date| {
+                rollups.iter()
+                    .find(|r| r.granularity == Granularity::Day && &r.period_start == date)
+                    .map(|r| r.profile())
+                    .unwrap_or_default()
+            })
+            .collect();
+        Self { buckets }
+    }
+}
+
+
+// the mean score of each emotion of the taxonomy, empty without entries
+pub fn mean_profile(entries: &[&EmotionTableEntry]) -> BTreeMap<String, f64> {
+    if entries.is_empty() {
+        return BTreeMap::new()
+    }
+    taxonomy().names()
+        .map(|name| (name.to_owned(), entries.iter().map(|e| e.scores.get(name)).sum::<f64>() / entries.len() as f64))
+        .collect()
+}
+
+
+// one bar per bucket, the mean of each emotion stacked from the bottom in taxonomy order.
+// the scale is the highest stack, at least 1, with a grid line every 0.5.
+pub fn render_timeline(timeline: &EmotionTimeline) -> Result<Vec<u8>> {
+    if timeline.buckets.is_empty() {
+        bail!("No bucket to draw")
+    }
+    let mut pixmap = canvas(TIMELINE_WIDTH, TIMELINE_HEIGHT)?;
+    let (left, top) = (TIMELINE_PADDING, TIMELINE_PADDING);
+    let (width, height) = (TIMELINE_WIDTH as f32 - 2.0 * TIMELINE_PADDING, TIMELINE_HEIGHT as f32 - 2.0 * TIMELINE_PADDING);
+    let bottom = top + height;
+
+    let totals = timeline.buckets.iter().map(|b| b.values().sum::<f64>());
+    let scale = totals.fold(1.0, f64::max) as f32;
+
+    let mut grid = 0.5;
+    while grid < scale {
+        let y = snap(bottom - height * grid / scale);
+        line(&mut pixmap, (left, y), (left + width, y), GRID, 1.0)?;
+        grid += 0.5;
+    }
+
+    let slot = width / timeline.buckets.len() as f32;
+    for (i, bucket) in timeline.buckets.iter().enumerate() {
+        let x = snap(left + slot * i as f32 + slot * 0.15);
+        let mut y = bottom;
+        for (index, name) in taxonomy().names().enumerate() {
+            let bar = height * bucket.get(name).copied().unwrap_or(0.0) as f32 / scale;
+            if let Some(rect) = Rect::from_xywh(x, snap(y - bar), snap(slot * 0.7), snap(bar)) {
+                pixmap.fill_rect(rect, &paint(color(index), 255), Transform::identity(), None);
+            }
+            y -= bar;
+        }
+    }
+
+    line(&mut pixmap, (left, bottom), (left + width, bottom), AXIS, 1.0)?;
+    encode(pixmap)
+}
+
+
+// one axis per emotion of the taxonomy clockwise from the top, scores from 0 at the center to 1,
+// with a grid every 0.25 and a dot of the emotion color at each point of the profile
+pub fn render_radar(profile: &BTreeMap<String, f64>) -> Result<Vec<u8>> {
+    let names: Vec<&str> = taxonomy().names().collect();
+    if names.len() < 3 {
+        bail!("A radar chart needs at least 3 emotions")
+    }
+    let mut pixmap = canvas(RADAR_SIZE, RADAR_SIZE)?;
+    let center = RADAR_SIZE as f32 / 2.0;
+    let radius = center - RADAR_PADDING;
+    let point = |index: usize, value: f64| {
+        let angle = -std::f32::consts::FRAC_PI_2 + std::f32::consts::TAU * index as f32 / names.len() as f32;
+        let distance = radius * value.clamp(0.0, 1.0) as f32;
+        (snap(center + distance * angle.cos()), snap(center + distance * angle.sin()))
+    };
+
+    for step in [0.25, 0.5, 0.75, 1.0] {
+        let ring = polygon(&(0..names.len()).map(|i| point(i, step)).collect::<Vec<_>>())?;
+        pixmap.stroke_path(&ring, &paint(GRID, 255), &Stroke { width: 1.0, ..Default::default() }, Transform::identity(), None);
+    }
+    for i in 0..names.len() {
+        line(&mut pixmap, point(i, 0.0), point(i, 1.0), GRID, 1.0)?;
+    }
+
+    let points: Vec<(f32, f32)> = names.iter().enumerate().map(|(i, name)| point(i, profile.get(*name).copied().unwrap_or(0.0))).collect();
+    let shape = polygon(&points)?;
+    pixmap.fill_path(&shape, &paint(PROFILE, 64), FillRule::Winding, Transform::identity(), None);
+    pixmap.stroke_path(&shape, &paint(PROFILE, 255), &Stroke { width: 2.0, ..Default::default() }, Transform::identity(), None);
+    for (index, (x, y)) in points.into_iter().enumerate() {
+        let dot = PathBuilder::from_circle(x, y, 4.0).context("Error building dot")?;
+        pixmap.fill_path(&dot, &paint(color(index), 255), FillRule::Winding, Transform::identity(), None);
+    }
+
+    encode(pixmap)
+}
+
+
+fn canvas(width: u32, height: u32) -> Result<Pixmap> {
+    let mut pixmap = Pixmap::new(width, height).context("Error creating pixmap")?;
+    let (r, g, b) = BACKGROUND;
+    pixmap.fill(Color::from_rgba8(r, g, b, 255));
+    Ok(pixmap)
+}
+
+fn paint((r, g, b): (u8, u8, u8), alpha: u8) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(r, g, b, alpha);
+    paint.anti_alias = true;
+    paint
+}
+
+fn line(pixmap: &mut Pixmap, from: (f32, f32), to: (f32, f32), color: (u8, u8, u8), width: f32) -> Result<()> {
+    let mut builder = PathBuilder::new();
+    builder.move_to(from.0, from.1);
+    builder.line_to(to.0, to.1);
+    let path = builder.finish().context("Error building line")?;
+    pixmap.stroke_path(&path, &paint(color, 255), &Stroke { width, ..Default::default() }, Transform::identity(), None);
+    Ok(())
+}
+
+fn polygon(points: &[(f32, f32)]) -> Result<tiny_skia::Path> {
+    let mut builder = PathBuilder::new();
+    for (i, (x, y)) in points.iter().enumerate() {
+        match i {
+            0 => builder.move_to(*x, *y),
+            _ => builder.line_to(*x, *y),
+        }
+    }
+    builder.close();
+    builder.finish().context("Error building polygon")
+}
+
+// a last bit of difference in sin or cos never moves a point
+fn snap(value: f32) -> f32 {
+    (value * 4.0).round() / 4.0
+}
+
+fn encode(pixmap: Pixmap) -> Result<Vec<u8>> {
+    Ok(pixmap.encode_png()?)
+}
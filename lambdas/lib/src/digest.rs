@@ -0,0 +1,188 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{bail, Context, Result};
+use chrono::{Datelike, Days, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::{language::Language, rollup::{Granularity, Rollup}, service::dynamo_service::structs::EmotionTableEntry, taxonomy::{taxonomy, Polarity}};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestPeriod {
+    // JST, Monday to Sunday
+    Week,
+    Month,
+}
+
+impl DigestPeriod {
+    pub fn parse(period: &str) -> Result<Self> {
+        match period {
+            "week" => Ok(Self::Week),
+            "month" => Ok(Self::Month),
+            _ => bail!("Unknown digest period {}, week or month", period),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Week => "week",
+            Self::Month => "month",
+        }
+    }
+
+    // of the rollups the digest is read from
+    pub fn granularity(&self) -> Granularity {
+        match self {
+            Self::Week => Granularity::Week,
+            Self::Month => Granularity::Month,
+        }
+    }
+
+    // the last whole period before today, and the one before it: ((from, to), (previous from, previous to)), days included
+    pub fn ranges(&self, today: NaiveDate) -> Result<((NaiveDate, NaiveDate), (NaiveDate, NaiveDate))> {
+        let start = match self {
+            Self::Week => today.checked_sub_days(Days::new(today.weekday().num_days_from_monday() as u64)).context("Error getting monday")?,
+            Self::Month => today.with_day(1).context("Error getting first of month")?,
+        };
+        let current = self.previous(start)?;
+        let previous = self.previous(current.0)?;
+        Ok((current, previous))
+    }
+
+    // the period ending the day before start
+    fn previous(&self, start: NaiveDate) -> Result<(NaiveDate, NaiveDate)> {
+        let to = start.pred_opt().context("Error getting previous day")?;
+        let from = match self {
+            Self::Week => to.checked_sub_days(Days::new(6)).context("Error getting previous monday")?,
+            Self::Month => to.with_day(1).context("Error getting first of month")?,
+        };
+        Ok((from, to))
+    }
+}
+
+
+// the mean of an emotion over the period, and how it moved since the previous one
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct EmotionChange {
+    pub mean: f64,
+    // none if there was no message in the previous period
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_mean: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub change: Option<f64>,
+}
+
+
+// the message with the highest score among the negative, or positive, emotions
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DigestPick {
+    pub event_id: String,
+    pub emotion: String,
+    pub score: f64,
+    // filled in once the digest is summarised, the text is never sent for the summary
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub text: String,
+}
+
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Digest {
+    pub period: DigestPeriod,
+    // who the digest is about, and who it is sent to. teams get the anonymised team reports instead, see team_report
+    pub user_id: String,
+    // YYYY-MM-DD, days included
+    pub from: String,
+    pub to: String,
+    pub previous_from: String,
+    pub previous_to: String,
+    pub message_count: usize,
+    pub previous_message_count: usize,
+    pub emotions: BTreeMap<String, EmotionChange>,
+    // the emotion that changed the most for the better: negative emotions down, positive ones up
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub most_improved: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub most_negative: Option<DigestPick>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub most_positive: Option<DigestPick>,
+}
+
+impl Digest {
+    // from the rollups of the user for the period and the one before, none if there is no message in the period.
+    // the picks are the argmax of the rollup, their text is not read here
+    pub fn new(period: DigestPeriod, user_id: &str, ranges: ((NaiveDate, NaiveDate), (NaiveDate, NaiveDate)), rollup: &Rollup, previous: Option<&Rollup>) -> Option<Self> {
+        if rollup.count == 0 {
+            return None
+        }
+        let ((from, to), (previous_from, previous_to)) = ranges;
+        let previous = previous.filter(|p| p.count > 0);
+
+        let mut emotions = BTreeMap::new();
+        let mut most_improved: Option<(&str, f64)> = None;
+        for emotion in &taxonomy().emotions {
+            let mean = rollup.mean(&emotion.name);
+            let previous_mean = previous.map(|p| p.mean(&emotion.name));
+            let change = previous_mean.map(|previous| mean - previous);
+            let improvement = match (emotion.polarity, change) {
+                (Polarity::Negative, Some(change)) => -change,
+                (Polarity::Positive, Some(change)) => change,
+                _ => 0.0,
+            };
+            if improvement > most_improved.map(|(_, i)| i).unwrap_or(0.0) {
+                most_improved = Some((&emotion.name, improvement));
+            }
+            emotions.insert(emotion.name.to_owned(), EmotionChange { mean, previous_mean, change });
+        }
+
+        Some(Self {
+            period,
+            user_id: user_id.to_owned(),
+            from: from.format(DATE_FORMAT).to_string(),
+            to: to.format(DATE_FORMAT).to_string(),
+            previous_from: previous_from.format(DATE_FORMAT).to_string(),
+            previous_to: previous_to.format(DATE_FORMAT).to_string(),
+            message_count: rollup.count as usize,
+            previous_message_count: previous.map(|p| p.count as usize).unwrap_or_default(),
+            emotions,
+            most_improved: most_improved.map(|(name, _)| name.to_owned()),
+            most_negative: pick(rollup, Polarity::Negative),
+            most_positive: pick(rollup, Polarity::Positive),
+        })
+    }
+
+    // the picks, for their text to be filled in
+    pub fn picks_mut(&mut self) -> impl Iterator<Item = &mut DigestPick> {
+        [&mut self.most_negative, &mut self.most_positive].into_iter().flatten()
+    }
+}
+
+
+// the highest max of an emotion of the polarity, the first emotion of the taxonomy on a tie
+fn pick(rollup: &Rollup, polarity: Polarity) -> Option<DigestPick> {
+    let mut best: Option<DigestPick> = None;
+    for emotion in taxonomy().with_polarity(polarity) {
+        let stats = rollup.stats.get(&emotion.name);
+        let (Some(score), Some(event_id)) = (stats.max, stats.argmax) else {
+            continue;
+        };
+        if score > best.as_ref().map(|b| b.score).unwrap_or(0.0) {
+            best = Some(DigestPick { event_id, emotion: emotion.name.to_owned(), score, text: "".to_owned() });
+        }
+    }
+    best
+}
+
+// the language most of the entries of the period are written in, the summary is written in it
+pub fn main_language(entries: &[&EmotionTableEntry]) -> Language {
+    let mut counts: HashMap<Language, usize> = HashMap::new();
+    for entry in entries {
+        *counts.entry(entry.language).or_default() += 1;
+    }
+    counts.into_iter()
+        .max_by(|(l1, c1), (l2, c2)| c1.cmp(c2).then_with(|| l2.code().cmp(l1.code())))
+        .map(|(language, _)| language)
+        .unwrap_or_default()
+}
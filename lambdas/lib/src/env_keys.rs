@@ -1,14 +1,60 @@
 pub static SLACK_VERIFICATION_TOKEN: &str = "SLACK_VERIFICATION_TOKEN";
 pub static BOT_OAUTH_TOKEN: &str = "BOT_OAUTH_TOKEN";
 pub static RESULT_CHANNEL_ID: &str = "RESULT_CHANNEL_ID";
+pub static WORKSPACE_ID: &str = "WORKSPACE_ID";
+pub static ADMIN_API_TOKEN: &str = "ADMIN_API_TOKEN";
 
 pub static TABLE_NAME: &str = "TABLE_NAME";
 pub static TABLE_ARN: &str = "TABLE_ARN";
+pub static USAGE_TABLE_NAME: &str = "USAGE_TABLE_NAME";
+pub static ROLLUP_TABLE_NAME: &str = "ROLLUP_TABLE_NAME";
+pub static DATA_KEY_TABLE_NAME: &str = "DATA_KEY_TABLE_NAME";
+pub static SCORE_CACHE_TABLE_NAME: &str = "SCORE_CACHE_TABLE_NAME";
+pub static SCORE_CACHE_TTL_DAYS: &str = "SCORE_CACHE_TTL_DAYS";
+pub static SCORE_CACHE_MAX_LENGTH: &str = "SCORE_CACHE_MAX_LENGTH";
 pub static QUEUE_URL: &str = "QUEUE_URL";
 pub static QUEUE_ARN: &str = "QUEUE_ARN";
 pub static CHAT_MODEL: &str = "CHAT_MODEL";
+pub static FALLBACK_CHAT_MODELS: &str = "FALLBACK_CHAT_MODELS";
+pub static BEDROCK_MAX_RETRIES: &str = "BEDROCK_MAX_RETRIES";
+pub static BEDROCK_BACKOFF_BASE_MS: &str = "BEDROCK_BACKOFF_BASE_MS";
+pub static EMOTION_SCORER: &str = "EMOTION_SCORER";
+pub static SCORER_FALLBACK: &str = "SCORER_FALLBACK";
+pub static EMOTION_REPOSITORY: &str = "EMOTION_REPOSITORY";
+pub static EMOTION_REPOSITORY_FILE: &str = "EMOTION_REPOSITORY_FILE";
+pub static MODEL_PRICES: &str = "MODEL_PRICES";
+pub static PROMPT_VERSIONS: &str = "PROMPT_VERSIONS";
+pub static EMOTION_TAXONOMY: &str = "EMOTION_TAXONOMY";
+
+pub static REDACTION_DETECTORS: &str = "REDACTION_DETECTORS";
+pub static REDACTION_PLACEHOLDERS: &str = "REDACTION_PLACEHOLDERS";
+pub static PERSISTED_TEXT: &str = "PERSISTED_TEXT";
+pub static TEXT_ENCRYPTION: &str = "TEXT_ENCRYPTION";
+pub static TEXT_ENCRYPTION_KMS_KEY_ID: &str = "TEXT_ENCRYPTION_KMS_KEY_ID";
+pub static TEXT_ENCRYPTION_LOCAL_KEY_FILE: &str = "TEXT_ENCRYPTION_LOCAL_KEY_FILE";
 
 pub static IMMEDIATE_WARNING_THRESHOLD: &str  = "IMMEDIATE_WARNING_THRESHOLD";
+pub static WARNING_MAX_STD_DEV: &str = "WARNING_MAX_STD_DEV";
+pub static SCORING_SAMPLES: &str = "SCORING_SAMPLES";
+pub static SCORING_TEMPERATURES: &str = "SCORING_TEMPERATURES";
+pub static TOXICITY_CLASSIFIER_ENABLED: &str = "TOXICITY_CLASSIFIER_ENABLED";
+pub static TOXICITY_THRESHOLD: &str = "TOXICITY_THRESHOLD";
+pub static MODERATOR_CHANNEL_ID: &str = "MODERATOR_CHANNEL_ID";
+pub static PERSON_DIRECTED_WARNING: &str = "PERSON_DIRECTED_WARNING";
+pub static PERSON_DIRECTED_WARNING_THRESHOLD: &str = "PERSON_DIRECTED_WARNING_THRESHOLD";
+
+pub static TEXT_RETENTION_DAYS: &str = "TEXT_RETENTION_DAYS";
+pub static TEXT_RETENTION_LOOKBACK_DAYS: &str = "TEXT_RETENTION_LOOKBACK_DAYS";
+pub static ENTRY_RETENTION_DAYS: &str = "ENTRY_RETENTION_DAYS";
+pub static EXPORT_RETENTION_DAYS: &str = "EXPORT_RETENTION_DAYS";
 
 pub static PROCESSED_S3_FOLDER: &str = "PROCESSED_S3_FOLDER";
+pub static USER_ID_HASH_KEY: &str = "USER_ID_HASH_KEY";
 pub static BUCKET_NAME: &str = "BUCKET_NAME";
+
+pub static DIGEST_TEAMS: &str = "DIGEST_TEAMS";
+pub static TEAM_REPORT_GROUPS: &str = "TEAM_REPORT_GROUPS";
+pub static TEAM_REPORT_MIN_GROUP_SIZE: &str = "TEAM_REPORT_MIN_GROUP_SIZE";
+pub static TEAM_REPORT_CHANNEL_ID: &str = "TEAM_REPORT_CHANNEL_ID";
+
+pub static REPORT_CHARTS: &str = "REPORT_CHARTS";
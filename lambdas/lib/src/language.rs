@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    En,
+    Ja,
+    // emoji only, numbers, entries registered before language detection, etc.
+    #[default]
+    Unknown,
+}
+
+impl Language {
+    // A Japanese character carries roughly as much as a short english word,
+    // so a message counts as Japanese once it has one Japanese character for every 3 latin letters.
+    // This keeps messages such as "PR見ました、LGTMです" Japanese.
+    // Ideographs alone are as likely Chinese, at least one kana is needed.
+    // Expects the prose of the normalised text, see NormalizedText::prose.
+    pub fn detect(text: &str) -> Self {
+        let mut japanese_count = 0;
+        let mut kana_count = 0;
+        let mut latin_count = 0;
+        for c in text.chars() {
+            if is_kana(c) {
+                kana_count += 1;
+                japanese_count += 1;
+            } else if is_japanese(c) {
+                japanese_count += 1;
+            } else if c.is_ascii_alphabetic() {
+                latin_count += 1;
+            }
+        }
+
+        if kana_count > 0 && japanese_count * 3 >= latin_count {
+            Self::Ja
+        } else if latin_count > 0 {
+            Self::En
+        } else {
+            Self::Unknown
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::En => "en",
+            Self::Ja => "ja",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+
+fn is_kana(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{309F}' | // hiragana
+        '\u{30A0}'..='\u{30FF}' | // katakana
+        '\u{FF66}'..='\u{FF9F}'   // half-width katakana
+    )
+}
+
+fn is_japanese(c: char) -> bool {
+    is_kana(c) || matches!(c,
+        '\u{4E00}'..='\u{9FFF}' | // CJK unified ideographs
+        '\u{3000}'..='\u{303F}'   // japanese punctuation
+    )
+}
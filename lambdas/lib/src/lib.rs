@@ -2,4 +2,16 @@
 pub mod service;
 pub mod env_keys;
 pub mod utilities;
-pub mod warnings;
\ No newline at end of file
+pub mod warnings;
+pub mod language;
+pub mod statistics;
+pub mod taxonomy;
+pub mod redaction;
+pub mod slack_markup;
+pub mod rollup;
+pub mod retention;
+pub mod schema;
+pub mod rescoring;
+pub mod digest;
+pub mod team_report;
+pub mod charts;
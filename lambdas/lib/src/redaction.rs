@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use regex::{Captures, Regex};
+
+use crate::env_keys::{PERSISTED_TEXT, REDACTION_DETECTORS, REDACTION_PLACEHOLDERS};
+
+pub const EMAIL_DETECTOR: &str = "email";
+pub const URL_DETECTOR: &str = "url";
+pub const USER_MENTION_DETECTOR: &str = "user_mention";
+pub const API_KEY_DETECTOR: &str = "api_key";
+pub const PHONE_DETECTOR: &str = "phone";
+
+pub const PERSIST_REDACTED: &str = "redacted";
+pub const PERSIST_ORIGINAL: &str = "original";
+
+const DISABLED: &str = "none";
+
+// (name, default placeholder, pattern), applied in this order.
+// Slack sends emails, links and mentions wrapped in <>, ie: <mailto:a@b.com|a@b.com>, <https://b.com|b.com>, <@U0123>,
+// the whole wrapped form is replaced.
+// A first capture group, if any, is kept in front of the placeholder, ie: the key of password=xxx.
+const DETECTORS: &[(&str, &str, &str)] = &[
+    (API_KEY_DETECTOR, "<API_KEY>", concat!(
+        r"\bAKIA[0-9A-Z]{16}\b",
+        r"|\bxox[abposr]-[A-Za-z0-9-]{10,}",
+        r"|\bgh[pousr]_[A-Za-z0-9]{36,}\b",
+        r"|\bsk-[A-Za-z0-9_-]{20,}",
+        r"|\bAIza[0-9A-Za-z_-]{35}\b",
+        r"|(?i)\b((?:api[_-]?key|secret|token|password|passwd)\s*[:=]\s*)[^\s,;]+",
+    )),
+    (EMAIL_DETECTOR, "<EMAIL>", r"<mailto:[^>]+>|[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}"),
+    (URL_DETECTOR, "<URL>", r"<https?://[^>]+>|https?://[^\s<>]+"),
+    (USER_MENTION_DETECTOR, "<@USER>", r"<@[UW][A-Z0-9]+(?:\|[^>]*)?>"),
+    // international, japanese (03-1234-5678, 090-1234-5678) and north american (555) 123-4567 forms, dates do not match
+    (PHONE_DETECTOR, "<PHONE>", r"(?:\+\d{1,3}[ -]?)?\(?\b\d{2,4}\)?[ -]?\d{2,4}[ -]\d{3,4}\b|\b0\d{9,10}\b"),
+];
+
+
+#[derive(Debug, Clone)]
+struct Detector {
+    name: &'static str,
+    placeholder: String,
+    pattern: Regex,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Redaction {
+    pub text: String,
+    // detector name -> number of replacements
+    pub counts: HashMap<&'static str, usize>,
+}
+
+
+// Replaces personal information with placeholder tokens before the text reaches Bedrock or storage.
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    detectors: Vec<Detector>,
+    persist_original: bool,
+}
+
+impl Redactor {
+    // names: detectors to enable, placeholders: detector name -> placeholder to use instead of the default.
+    // an unknown name is an error, a typo must never turn a detector off unnoticed
+    pub fn new(names: &[&str], placeholders: &HashMap<String, String>, persist_original: bool) -> Result<Self> {
+        let unknown: Vec<&str> = names.iter().copied().filter(|name| !DETECTORS.iter().any(|(n, ..)| n == name)).collect();
+        if !unknown.is_empty() {
+            bail!("Unknown redaction detectors {:?}, use {} or {}", unknown, detector_names().join(", "), DISABLED)
+        }
+        let detectors = DETECTORS.iter()
+            .filter(|(name, ..)| names.contains(name))
+            .map(|(name, placeholder, pattern)| Detector {
+                name,
+                placeholder: placeholders.get(*name).cloned().unwrap_or(placeholder.to_string()),
+                pattern: Regex::new(pattern).expect("invalid redaction pattern"),
+            })
+            .collect();
+        Ok(Self { detectors, persist_original })
+    }
+
+    // REDACTION_DETECTORS: comma separated detector names, or none. Default to all, also if a name is unknown.
+    // REDACTION_PLACEHOLDERS: {"email": "[email]", ...}
+    // PERSISTED_TEXT: redacted (default) | original
+    pub fn from_env() -> Self {
+        let detectors = std::env::var(REDACTION_DETECTORS).unwrap_or("".to_owned());
+        let names: Vec<&str> = match detectors.trim() {
+            "" => detector_names(),
+            DISABLED => vec![],
+            detectors => detectors.split(',').map(|name| name.trim()).collect(),
+        };
+
+        let placeholders = match std::env::var(REDACTION_PLACEHOLDERS).map(|p| serde_json::from_str::<HashMap<String, String>>(&p)) {
+            Ok(Ok(placeholders)) => placeholders,
+            Ok(Err(error)) => {
+                println!("Error parsing redaction placeholders: {:?}", error);
+                HashMap::new()
+            },
+            Err(_) => HashMap::new(),
+        };
+
+        let persist_original = std::env::var(PERSISTED_TEXT).unwrap_or("".to_owned()) == PERSIST_ORIGINAL;
+        match Self::new(&names, &placeholders, persist_original) {
+            Ok(redactor) => redactor,
+            Err(error) => {
+                println!("Error configuring redaction, redacting with every detector: {:?}", error);
+                Self::new(&detector_names(), &placeholders, persist_original).expect("every detector is known")
+            },
+        }
+    }
+
+    pub fn redact(&self, text: &str) -> Redaction {
+        let mut redacted = text.to_owned();
+        let mut counts = HashMap::new();
+        for detector in &self.detectors {
+            let mut count = 0;
+            redacted = detector.pattern.replace_all(&redacted, |captures: &Captures| {
+                count += 1;
+                let kept = captures.get(1).map(|m| m.as_str()).unwrap_or("");
+                format!("{}{}", kept, detector.placeholder)
+            }).into_owned();
+            if count > 0 {
+                counts.insert(detector.name, count);
+            }
+        }
+        Redaction { text: redacted, counts }
+    }
+
+    // the text to store, according to PERSISTED_TEXT
+    pub fn persisted_text<'a>(&self, original: &'a str, redaction: &'a Redaction) -> &'a str {
+        if self.persist_original {
+            original
+        } else {
+            &redaction.text
+        }
+    }
+}
+
+
+pub fn detector_names() -> Vec<&'static str> {
+    DETECTORS.iter().map(|(name, ..)| *name).collect()
+}
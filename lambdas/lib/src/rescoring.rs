@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::{rollup::dates_between, service::{common_structs::{EmotionScores, ScoringResult}, dynamo_service::structs::EmotionTableEntry}, taxonomy::taxonomy};
+
+
+// Scores of an entry by another model or prompt, kept next to the original scores in generations.{id}
+// so that history scored before a model or prompt change can be compared with new data.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ScoringGeneration {
+    pub model_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+    pub scores: EmotionScores,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub std_dev: Option<EmotionScores>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub samples: Option<u32>,
+    // epoch seconds
+    pub scored_at: u64,
+}
+
+impl ScoringGeneration {
+    pub fn new(result: &ScoringResult, scored_at: u64) -> Self {
+        Self {
+            model_id: result.model_id.to_owned(),
+            prompt_version: result.prompt_version.to_owned(),
+            scores: result.scores.to_owned(),
+            std_dev: result.std_dev.to_owned(),
+            samples: result.samples,
+            scored_at,
+        }
+    }
+}
+
+// default id of the generation of a model and prompt, ie: anthropic.claude-3-haiku-20240307-v1:0@emotion_scores/v3
+pub fn generation_id(model_id: &str, prompt_version: Option<&str>) -> String {
+    match prompt_version {
+        Some(prompt_version) => format!("{}@{}", model_id, prompt_version),
+        None => model_id.to_owned(),
+    }
+}
+
+
+// the entries of the dates from to to (YYYY-MM-DD, JST), of the user and the channel if given
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RescoreSelection {
+    pub from: String,
+    pub to: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel_id: Option<String>,
+}
+
+impl RescoreSelection {
+    pub fn new(from: &str, to: &str) -> Self {
+        Self { from: from.to_owned(), to: to.to_owned(), user_id: None, channel_id: None }
+    }
+
+    pub fn dates(&self) -> Result<Vec<String>> {
+        let from = NaiveDate::parse_from_str(&self.from, "%Y-%m-%d")?;
+        let to = NaiveDate::parse_from_str(&self.to, "%Y-%m-%d")?;
+        if to < from {
+            bail!("{} is before {}", self.to, self.from)
+        }
+        Ok(dates_between(from, to))
+    }
+
+    pub fn matches(&self, entry: &EmotionTableEntry) -> bool {
+        self.user_id.as_ref().map(|u| *u == entry.user_id).unwrap_or(true)
+            && self.channel_id.as_ref().map(|c| *c == entry.channel_id).unwrap_or(true)
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RescoreLimits {
+    // messages scored at the same time
+    pub concurrency: usize,
+    // the job stops once this many messages are rescored, or the estimated cost (USD) of the calls reaches max_cost.
+    // max_cost is checked before each call and the calls in flight still finish, so it can be overshot by up to concurrency - 1 calls.
+    pub max_messages: Option<usize>,
+    pub max_cost: Option<f64>,
+}
+
+impl Default for RescoreLimits {
+    fn default() -> Self {
+        Self { concurrency: 4, max_messages: None, max_cost: None }
+    }
+}
+
+
+// how the scores of an emotion moved from the original scores to the generation
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct EmotionDiff {
+    pub count: usize,
+    pub old_mean: f64,
+    pub new_mean: f64,
+    // new - old
+    pub mean_delta: f64,
+    pub mean_abs_delta: f64,
+    pub max_abs_delta: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_abs_delta_event_id: Option<String>,
+    // messages that crossed the warning threshold, upwards and downwards
+    pub crossed_up: usize,
+    pub crossed_down: usize,
+}
+
+impl EmotionDiff {
+    pub fn add(&mut self, event_id: &str, old: f64, new: f64, threshold: f64) {
+        self.count += 1;
+        let count = self.count as f64;
+        let delta = new - old;
+        // running means, so that nothing but the diff is kept
+        self.old_mean += (old - self.old_mean) / count;
+        self.new_mean += (new - self.new_mean) / count;
+        self.mean_delta += (delta - self.mean_delta) / count;
+        self.mean_abs_delta += (delta.abs() - self.mean_abs_delta) / count;
+        if self.max_abs_delta_event_id.is_none() || delta.abs() > self.max_abs_delta {
+            self.max_abs_delta = delta.abs();
+            self.max_abs_delta_event_id = Some(event_id.to_owned());
+        }
+        match (old > threshold, new > threshold) {
+            (false, true) => self.crossed_up += 1,
+            (true, false) => self.crossed_down += 1,
+            _ => {},
+        }
+    }
+}
+
+
+// what a rescoring job did, with the diff per emotion of the messages it rescored
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct RescoreReport {
+    pub generation: String,
+    pub model_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selection: Option<RescoreSelection>,
+    // epoch seconds
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub selected: usize,
+    pub rescored: usize,
+    // already have the generation
+    pub skipped_existing: usize,
+    // text purged, or not readable
+    pub skipped_without_text: usize,
+    pub failed: usize,
+    // left out once a limit was reached
+    pub not_rescored: usize,
+    pub limit_reached: bool,
+    pub estimated_cost: f64,
+    pub warning_threshold: f64,
+    pub emotions: BTreeMap<String, EmotionDiff>,
+}
+
+impl RescoreReport {
+    pub fn add_diff(&mut self, event_id: &str, old: &EmotionScores, new: &EmotionScores) {
+        for name in taxonomy().names() {
+            self.emotions.entry(name.to_owned()).or_default().add(event_id, old.get(name), new.get(name), self.warning_threshold);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use chrono::{Days, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::{env_keys::{ENTRY_RETENTION_DAYS, EXPORT_RETENTION_DAYS, TEXT_RETENTION_DAYS, TEXT_RETENTION_LOOKBACK_DAYS}, utilities::get_date_month};
+
+const DAY_SECONDS: u64 = 24 * 3600;
+const DEFAULT_LOOKBACK_DAYS: u64 = 7;
+
+
+// How long the text, the entries and the table exports are kept, in days. None keeps them forever.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetentionPolicy {
+    // the text and the rewrite are dropped, the scores stay
+    pub text_days: Option<u64>,
+    // the whole entry is deleted, through the ttl attribute
+    pub entry_days: Option<u64>,
+    // exports of the table in the data bucket
+    pub export_days: Option<u64>,
+    // how many dates before the text cutoff are purged again, so that missed runs catch up.
+    // set it high once to purge the text of older entries.
+    pub lookback_days: u64,
+}
+
+impl RetentionPolicy {
+    // TEXT_RETENTION_DAYS, ENTRY_RETENTION_DAYS: days, empty to keep forever
+    // EXPORT_RETENTION_DAYS: days, default to the shorter of TEXT_RETENTION_DAYS and ENTRY_RETENTION_DAYS,
+    // so that exports keep neither the text nor the entries longer than the table
+    // TEXT_RETENTION_LOOKBACK_DAYS: default to 7
+    pub fn from_env() -> Self {
+        let days = |key: &str| std::env::var(key).ok().and_then(|d| d.trim().parse::<u64>().ok());
+        let text_days = days(TEXT_RETENTION_DAYS);
+        let entry_days = days(ENTRY_RETENTION_DAYS);
+        Self {
+            text_days,
+            entry_days,
+            export_days: days(EXPORT_RETENTION_DAYS).or(text_days.into_iter().chain(entry_days).min()),
+            lookback_days: days(TEXT_RETENTION_LOOKBACK_DAYS).unwrap_or(DEFAULT_LOOKBACK_DAYS),
+        }
+    }
+
+    // ttl of an entry written at timestamp (epoch seconds)
+    pub fn expires_at(&self, timestamp: u64) -> Option<u64> {
+        self.entry_days.map(|days| timestamp + days * DAY_SECONDS)
+    }
+
+    // JST dates (YYYY-MM-DD) whose text is past retention at now, oldest first.
+    // a date is purged once all of it is older than text_days.
+    pub fn text_purge_dates(&self, now: u64) -> Result<Vec<String>> {
+        let Some(days) = self.text_days else {
+            return Ok(vec![])
+        };
+        let (cutoff, _) = get_date_month(now.saturating_sub(days * DAY_SECONDS))?;
+        let cutoff = NaiveDate::parse_from_str(&cutoff, "%Y-%m-%d")?;
+        let first = cutoff.checked_sub_days(Days::new(self.lookback_days)).context("Error getting first purge date")?;
+        Ok(first.iter_days().take_while(|d| *d < cutoff).map(|d| d.format("%Y-%m-%d").to_string()).collect())
+    }
+
+    // exports last written before this (epoch seconds) are deleted
+    pub fn export_cutoff(&self, now: u64) -> Option<u64> {
+        self.export_days.map(|days| now.saturating_sub(days * DAY_SECONDS))
+    }
+}
+
+
+// what a retention run purged, kept in the data bucket under retention-reports/
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct PurgeReport {
+    // epoch seconds
+    pub run_at: u64,
+    pub text_retention_days: Option<u64>,
+    pub entry_retention_days: Option<u64>,
+    pub export_retention_days: Option<u64>,
+    // date -> entries whose text was dropped
+    pub texts_purged: BTreeMap<String, usize>,
+    // expired entries deleted by the repository, none for dynamo that deletes them on its own through the ttl
+    pub entries_deleted: Option<usize>,
+    // export prefixes deleted, ie: AWSDynamoDB/01234567890123-abcdefgh/
+    pub exports_deleted: Vec<String>,
+    pub objects_deleted: usize,
+}
+
+impl PurgeReport {
+    pub fn new(policy: &RetentionPolicy, run_at: u64) -> Self {
+        Self {
+            run_at,
+            text_retention_days: policy.text_days,
+            entry_retention_days: policy.entry_days,
+            export_retention_days: policy.export_days,
+            ..Default::default()
+        }
+    }
+
+    pub fn texts_purged_total(&self) -> usize {
+        self.texts_purged.values().sum()
+    }
+}
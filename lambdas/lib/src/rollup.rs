@@ -0,0 +1,407 @@
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::{Context, Result};
+use chrono::{Datelike, Days, Months, NaiveDate};
+use serde::{de::{IgnoredAny, MapAccess, Visitor}, ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{service::dynamo_service::structs::EmotionTableEntry, taxonomy::taxonomy};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const MONTH_FORMAT: &str = "%Y-%m";
+
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum RollupScope {
+    User,
+    Channel,
+    Workspace,
+}
+
+impl RollupScope {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::User => "user",
+            Self::Channel => "channel",
+            Self::Workspace => "workspace",
+        }
+    }
+}
+
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    Day,
+    // JST weeks, MON-SUN
+    Week,
+    Month,
+}
+
+impl Granularity {
+    pub const ALL: [Granularity; 3] = [Granularity::Day, Granularity::Week, Granularity::Month];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+        }
+    }
+
+    // the period containing the date (YYYY-MM-DD): the date, the monday of the week, or YYYY-MM
+    pub fn period_start(&self, date: &str) -> Result<String> {
+        let date = parse_date(date)?;
+        Ok(match self {
+            Self::Day => date.format(DATE_FORMAT).to_string(),
+            Self::Week => monday_of(date)?.format(DATE_FORMAT).to_string(),
+            Self::Month => date.format(MONTH_FORMAT).to_string(),
+        })
+    }
+
+    // (first day, last day) of the period starting at period_start
+    pub fn period_dates(&self, period_start: &str) -> Result<(NaiveDate, NaiveDate)> {
+        match self {
+            Self::Day => {
+                let date = parse_date(period_start)?;
+                Ok((date, date))
+            },
+            Self::Week => {
+                let monday = parse_date(period_start)?;
+                Ok((monday, monday.checked_add_days(Days::new(6)).context("Error getting sunday")?))
+            },
+            Self::Month => {
+                let first = parse_date(&format!("{}-01", period_start))?;
+                let last = first.checked_add_months(Months::new(1)).and_then(|d| d.pred_opt()).context("Error getting end of month")?;
+                Ok((first, last))
+            },
+        }
+    }
+}
+
+
+// what a rollup is about, for one period
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RollupKey {
+    pub scope: RollupScope,
+    pub scope_id: String,
+    pub granularity: Granularity,
+    pub period_start: String,
+}
+
+impl RollupKey {
+    // partition key, ie: user#U0123
+    pub fn rollup_id(&self) -> String {
+        format!("{}#{}", self.scope.code(), self.scope_id)
+    }
+
+    // sort key, ie: week#2024-10-14
+    pub fn period(&self) -> String {
+        format!("{}#{}", self.granularity.code(), self.period_start)
+    }
+
+    // the user, the channel and the workspace of the entry, for each granularity
+    pub fn for_entry(entry: &EmotionTableEntry, workspace_id: &str) -> Result<Vec<Self>> {
+        let mut keys = vec![];
+        for (scope, scope_id) in [(RollupScope::User, &entry.user_id), (RollupScope::Channel, &entry.channel_id), (RollupScope::Workspace, &workspace_id.to_owned())] {
+            for granularity in Granularity::ALL {
+                keys.push(Self {
+                    scope,
+                    scope_id: scope_id.to_owned(),
+                    granularity,
+                    period_start: granularity.period_start(&entry.date)?,
+                });
+            }
+        }
+        Ok(keys)
+    }
+}
+
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EmotionStats {
+    pub sum: f64,
+    pub sum_sq: f64,
+    // none until a message is counted.
+    // an upper bound once entries are replaced, rebuild the rollups to get it exact.
+    pub max: Option<f64>,
+    // event id of the message with the max score, the first one on ties
+    pub argmax: Option<String>,
+}
+
+impl EmotionStats {
+    pub fn add(&mut self, score: f64, event_id: &str) {
+        self.sum += score;
+        self.sum_sq += score * score;
+        if self.max.map(|max| score > max).unwrap_or(true) {
+            self.max = Some(score);
+            self.argmax = Some(event_id.to_owned());
+        }
+    }
+
+    pub fn mean(&self, count: u64) -> f64 {
+        if count == 0 {
+            return 0.0
+        }
+        self.sum / count as f64
+    }
+
+    // population standard deviation
+    pub fn std_dev(&self, count: u64) -> f64 {
+        if count == 0 {
+            return 0.0
+        }
+        let mean = self.mean(count);
+        (self.sum_sq / count as f64 - mean * mean).max(0.0).sqrt()
+    }
+}
+
+
+// emotion name -> stats, stored as {emotion}_sum, {emotion}_sum_sq, {emotion}_max and {emotion}_argmax
+// so that each of them can be updated on its own.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RollupStats(pub BTreeMap<String, EmotionStats>);
+
+pub const SUM_SUFFIX: &str = "_sum";
+pub const SUM_SQ_SUFFIX: &str = "_sum_sq";
+pub const MAX_SUFFIX: &str = "_max";
+pub const ARGMAX_SUFFIX: &str = "_argmax";
+
+impl RollupStats {
+    pub fn get(&self, emotion: &str) -> EmotionStats {
+        self.0.get(emotion).cloned().unwrap_or_default()
+    }
+}
+
+impl Serialize for RollupStats {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        for name in taxonomy().names() {
+            let Some(stats) = self.0.get(name) else {
+                continue;
+            };
+            map.serialize_entry(&format!("{}{}", name, SUM_SUFFIX), &stats.sum)?;
+            map.serialize_entry(&format!("{}{}", name, SUM_SQ_SUFFIX), &stats.sum_sq)?;
+            if let (Some(max), Some(argmax)) = (stats.max, &stats.argmax) {
+                map.serialize_entry(&format!("{}{}", name, MAX_SUFFIX), &max)?;
+                map.serialize_entry(&format!("{}{}", name, ARGMAX_SUFFIX), argmax)?;
+            }
+        }
+        map.end()
+    }
+}
+
+// takes the stats of the emotions of the taxonomy and ignores every other key, like EmotionScores
+impl<'de> Deserialize<'de> for RollupStats {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct StatsVisitor;
+
+        impl<'de> Visitor<'de> for StatsVisitor {
+            type Value = RollupStats;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map of emotion stats")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut stats: BTreeMap<String, EmotionStats> = BTreeMap::new();
+                while let Some(key) = map.next_key::<String>()? {
+                    // _sum_sq before _sum, the latter is a suffix of the former
+                    let field = [SUM_SQ_SUFFIX, SUM_SUFFIX, MAX_SUFFIX, ARGMAX_SUFFIX].into_iter()
+                        .find_map(|suffix| key.strip_suffix(suffix).filter(|name| taxonomy().contains(name)).map(|name| (name.to_owned(), suffix)));
+                    let Some((name, suffix)) = field else {
+                        map.next_value::<IgnoredAny>()?;
+                        continue;
+                    };
+                    let entry = stats.entry(name).or_default();
+                    match suffix {
+                        SUM_SUFFIX => entry.sum = map.next_value()?,
+                        SUM_SQ_SUFFIX => entry.sum_sq = map.next_value()?,
+                        MAX_SUFFIX => entry.max = Some(map.next_value()?),
+                        _ => entry.argmax = Some(map.next_value()?),
+                    }
+                }
+                Ok(RollupStats(stats))
+            }
+        }
+
+        deserializer.deserialize_map(StatsVisitor)
+    }
+}
+
+
+// count, sum, sum of squares, max and argmax of each emotion,
+// over the messages of a user, a channel or the workspace in a day, a week or a month
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Rollup {
+    pub rollup_id: String,
+    pub period: String,
+    pub scope: RollupScope,
+    pub scope_id: String,
+    pub granularity: Granularity,
+    pub period_start: String,
+    pub count: u64,
+    #[serde(flatten)]
+    pub stats: RollupStats,
+}
+
+impl Rollup {
+    pub fn new(key: &RollupKey) -> Self {
+        Self {
+            rollup_id: key.rollup_id(),
+            period: key.period(),
+            scope: key.scope,
+            scope_id: key.scope_id.to_owned(),
+            granularity: key.granularity,
+            period_start: key.period_start.to_owned(),
+            count: 0,
+            stats: RollupStats::default(),
+        }
+    }
+
+    pub fn add(&mut self, entry: &EmotionTableEntry) {
+        self.count += 1;
+        for name in taxonomy().names() {
+            self.stats.0.entry(name.to_owned()).or_default().add(entry.scores.get(name), &entry.event_id);
+        }
+    }
+
+    // the ADDs of RollupService, the max is left alone
+    pub fn apply(&mut self, delta: &RollupDelta) {
+        self.count += delta.count;
+        for (name, sum, sum_sq) in &delta.sums {
+            let stats = self.stats.0.entry(name.to_owned()).or_default();
+            stats.sum += sum;
+            stats.sum_sq += sum_sq;
+        }
+    }
+
+    pub fn mean(&self, emotion: &str) -> f64 {
+        self.stats.get(emotion).mean(self.count)
+    }
+
+    pub fn std_dev(&self, emotion: &str) -> f64 {
+        self.stats.get(emotion).std_dev(self.count)
+    }
+
+    // the mean of each emotion of the taxonomy, empty without messages, as charts::mean_profile
+    pub fn profile(&self) -> BTreeMap<String, f64> {
+        if self.count == 0 {
+            return BTreeMap::new()
+        }
+        taxonomy().names().map(|name| (name.to_owned(), self.mean(name))).collect()
+    }
+}
+
+
+// what writing entry over previous (if any) adds to each rollup of the entry: the count, and the sum and
+// sum of squares of every emotion of the taxonomy. an emotion missing from either entry counts as 0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RollupDelta {
+    pub count: u64,
+    // emotion name, sum, sum of squares
+    pub sums: Vec<(String, f64, f64)>,
+}
+
+impl RollupDelta {
+    pub fn new(entry: &EmotionTableEntry, previous: Option<&EmotionTableEntry>) -> Self {
+        let sums = taxonomy().names()
+            .map(|name| {
+                let score = entry.scores.get(name);
+                let old = previous.map(|p| p.scores.get(name)).unwrap_or(0.0);
+                (name.to_owned(), score - old, score * score - old * old)
+            })
+            .collect();
+        Self { count: if previous.is_some() { 0 } else { 1 }, sums }
+    }
+}
+
+
+// rollups of the scope, with period_start from <= period_start <= to
+#[derive(Debug, Clone, PartialEq)]
+pub struct RollupQuery {
+    pub scope: RollupScope,
+    pub scope_id: String,
+    pub granularity: Granularity,
+    pub from: String,
+    pub to: String,
+}
+
+impl RollupQuery {
+    // the periods containing from_date to to_date (YYYY-MM-DD)
+    pub fn new(scope: RollupScope, scope_id: &str, granularity: Granularity, from_date: &str, to_date: &str) -> Result<Self> {
+        Ok(Self {
+            scope,
+            scope_id: scope_id.to_owned(),
+            granularity,
+            from: granularity.period_start(from_date)?,
+            to: granularity.period_start(to_date)?,
+        })
+    }
+
+    pub fn rollup_id(&self) -> String {
+        format!("{}#{}", self.scope.code(), self.scope_id)
+    }
+
+    pub fn matches(&self, rollup: &Rollup) -> bool {
+        rollup.rollup_id == self.rollup_id()
+            && rollup.granularity == self.granularity
+            && rollup.period_start >= self.from
+            && rollup.period_start <= self.to
+    }
+}
+
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RebuildReport {
+    // the dates read, widened to whole weeks and months
+    pub from: String,
+    pub to: String,
+    pub entries: usize,
+    pub rollups: usize,
+    // rollups of the range no entry maps to anymore, ie: after a deletion
+    pub deleted: usize,
+}
+
+
+// every rollup of the entries, ordered by key
+pub fn compute_rollups(entries: &[EmotionTableEntry], workspace_id: &str) -> Result<Vec<Rollup>> {
+    let mut rollups: BTreeMap<RollupKey, Rollup> = BTreeMap::new();
+    let mut sorted: Vec<&EmotionTableEntry> = entries.iter().collect();
+    // the first message wins ties for the max
+    sorted.sort_by(|e1, e2| e1.timestamp.cmp(&e2.timestamp).then_with(|| e1.event_id.cmp(&e2.event_id)));
+    for entry in sorted {
+        for key in RollupKey::for_entry(entry, workspace_id)? {
+            rollups.entry(key.clone()).or_insert_with(|| Rollup::new(&key)).add(entry);
+        }
+    }
+    Ok(rollups.into_values().collect())
+}
+
+// from and to (YYYY-MM-DD) widened so that every month they touch is whole, then every week.
+// only rollups of periods within the range are rebuilt, see is_within.
+pub fn rebuild_range(from: &str, to: &str) -> Result<(NaiveDate, NaiveDate)> {
+    let (month_start, _) = Granularity::Month.period_dates(&Granularity::Month.period_start(from)?)?;
+    let (_, month_end) = Granularity::Month.period_dates(&Granularity::Month.period_start(to)?)?;
+    let start = monday_of(month_start)?;
+    let end = monday_of(month_end)?.checked_add_days(Days::new(6)).context("Error getting sunday")?;
+    Ok((start, end))
+}
+
+pub fn is_within(rollup: &Rollup, start: NaiveDate, end: NaiveDate) -> Result<bool> {
+    let (first, last) = rollup.granularity.period_dates(&rollup.period_start)?;
+    Ok(first >= start && last <= end)
+}
+
+// every date from start to end, both included
+pub fn dates_between(start: NaiveDate, end: NaiveDate) -> Vec<String> {
+    start.iter_days().take_while(|d| *d <= end).map(|d| d.format(DATE_FORMAT).to_string()).collect()
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(date, DATE_FORMAT).context(format!("Invalid date {}", date))
+}
+
+fn monday_of(date: NaiveDate) -> Result<NaiveDate> {
+    date.checked_sub_days(Days::new(date.weekday().num_days_from_monday() as u64)).context("Error getting monday")
+}
@@ -0,0 +1,23 @@
+use anyhow::Result;
+
+use crate::service::dynamo_service::structs::EmotionTableEntry;
+
+// version written with every new entry, see upgrade
+pub const CURRENT_SCHEMA_VERSION: u32 = 2;
+// model_id of the entries scored before the model was recorded
+pub const UNKNOWN_MODEL_ID: &str = "unknown";
+
+
+// Upgrades an entry read in an older shape to the current one, entries of the current version are returned as is.
+// 0: no schema_version, entries written before it was added
+// 1: model_id always set
+// 2: message_ts and thread_ts, unknown for older entries
+pub fn upgrade(entry: EmotionTableEntry) -> Result<EmotionTableEntry> {
+    let mut entry = entry;
+    if entry.schema_version < 1 {
+        entry.model_id = entry.model_id.or(Some(UNKNOWN_MODEL_ID.to_owned()));
+    }
+    // nothing can be derived for 2, the slack timestamps were not kept
+    entry.schema_version = entry.schema_version.max(CURRENT_SCHEMA_VERSION);
+    Ok(entry)
+}
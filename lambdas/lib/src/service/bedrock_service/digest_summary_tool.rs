@@ -0,0 +1,27 @@
+use serde_json::json;
+use anyhow::Result;
+use super::tools::{ToDocument, ToolDefinition};
+
+
+pub fn get_digest_summary_tool_definition() -> Result<ToolDefinition> {
+    let name = "print_digest_summary";
+    let description = "Print a summary of the emotions over a period and advice for the next one.";
+
+    let json_schema = json!({
+        "type": "object",
+        "properties": {
+            "summary": {
+                "type": "string",
+                "description": "Two or three sentences on how the period went compared to the previous one.",
+            },
+            "advice": {
+                "type": "string",
+                "description": "The one sentence advice for the next period.",
+            }
+        },
+        "required": ["summary", "advice"],
+    });
+
+    let schema = json_schema.to_document();
+    Ok(ToolDefinition::new(name, description, &schema))
+}
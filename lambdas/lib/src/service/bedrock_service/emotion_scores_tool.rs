@@ -1,45 +1,43 @@
-use serde_json::json;
+use serde_json::{json, Map, Value};
 use anyhow::Result;
+use crate::taxonomy::taxonomy;
 use super::tools::{ToDocument, ToolDefinition};
 
 
+// one property per emotion of the taxonomy, and the target of the negative emotion
 pub fn get_emotion_scores_tool_definition() -> Result<ToolDefinition> {
     let name = "print_emotion_scores";
     let description = "Print emotion score of a given text.";
 
-    let json_schema = json!({
+    let mut properties = Map::new();
+    for emotion in &taxonomy().emotions {
+        properties.insert(emotion.name.to_owned(), json!({
+            "type": "number",
+            "description": format!("Score for {} ({}), ranging from 0.0 to 1.0.", emotion.name, emotion.description),
+        }));
+    }
+    properties.insert("target".to_owned(), json!({
         "type": "object",
         "properties": {
-            "fear": {
-                "type": "number",
-                "description": "Score for fear, ranging from 0.0 to 1.0.",
-            },
-            "anger": {
-                "type": "number",
-                "description": "Score for anger, ranging from 0.0 to 1.0.",
-            },
-            "joy": {
-                "type": "number",
-                "description": "Score for joy, ranging from 0.0 to 1.0.",
-            },
-            "sad": {
-                "type": "number",
-                "description": "Score for sad, ranging from 0.0 to 1.0.",
-            },
-            "contempt": {
-                "type": "number",
-                "description": "Score for contempt, ranging from 0.0 to 1.0.",
-            },
-            "disgust": {
-                "type": "number",
-                "description": "Score for disgust, ranging from 0.0 to 1.0.",
-            },
-            "surprise": {
-                "type": "number",
-                "description": "Score for surprise, ranging from 0.0 to 1.0.",
+            "category": {
+                "type": "string",
+                "enum": ["none", "self", "person", "group", "thing"],
+                "description": "What the negative emotion is aimed at. none if there is no negative emotion.",
             },
+            "mention": {
+                "type": "string",
+                "description": "The mention of the person, ie: @user1, only when the category is person and the person is mentioned.",
+            }
         },
-        "required": ["fear", "anger", "joy", "sad", "contempt", "disgust", "surprise"],
+        "required": ["category"],
+    }));
+    let mut required: Vec<Value> = taxonomy().names().map(|name| json!(name)).collect();
+    required.push(json!("target"));
+
+    let json_schema = json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
     });
 
     let schema = json_schema.to_document();
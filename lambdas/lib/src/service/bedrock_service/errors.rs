@@ -0,0 +1,61 @@
+use std::time::Duration;
+use aws_sdk_bedrockruntime::{error::SdkError, operation::converse::ConverseError};
+use rand::Rng;
+
+pub const MAX_BACKOFF_MS: u64 = 20_000;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BedrockErrorKind {
+    // throttled, model not ready, temporarily unavailable: retry the same model with backoff
+    Retryable,
+    // model not enabled for the account or not found: move on to the next model
+    ModelUnavailable,
+    // bad request, broken tool output, etc: retrying will not help
+    Fatal,
+}
+
+impl BedrockErrorKind {
+    pub fn classify(error: &anyhow::Error) -> Self {
+        let Some(sdk_error) = error.downcast_ref::<SdkError<ConverseError>>() else {
+            return Self::Fatal;
+        };
+        match sdk_error {
+            SdkError::ServiceError(service_error) => match service_error.err() {
+                ConverseError::ThrottlingException(_) |
+                ConverseError::ModelNotReadyException(_) |
+                ConverseError::ModelTimeoutException(_) |
+                ConverseError::ServiceUnavailableException(_) |
+                ConverseError::InternalServerException(_) => Self::Retryable,
+                ConverseError::AccessDeniedException(_) |
+                ConverseError::ResourceNotFoundException(_) => Self::ModelUnavailable,
+                _ => Self::Fatal,
+            },
+            SdkError::TimeoutError(_) |
+            SdkError::DispatchFailure(_) |
+            SdkError::ResponseError(_) => Self::Retryable,
+            _ => Self::Fatal,
+        }
+    }
+
+    // stored in the usage record of a failed attempt
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Retryable => "retryable",
+            Self::ModelUnavailable => "model_unavailable",
+            Self::Fatal => "fatal",
+        }
+    }
+}
+
+
+// Bedrock is throttling or temporarily unavailable, as opposed to a bad request or a broken tool output.
+pub fn is_throttling_error(error: &anyhow::Error) -> bool {
+    BedrockErrorKind::classify(error) == BedrockErrorKind::Retryable
+}
+
+// full jitter: random between 0 and base * 2^attempt, capped at MAX_BACKOFF_MS
+pub fn backoff_delay(base_ms: u64, attempt: u32) -> Duration {
+    let ceiling = base_ms.saturating_mul(2_u64.saturating_pow(attempt)).min(MAX_BACKOFF_MS);
+    Duration::from_millis(rand::thread_rng().gen_range(0..=ceiling))
+}
@@ -2,184 +2,287 @@
 pub mod tools;
 pub mod emotion_scores_tool;
 pub mod daily_advice_tool;
+pub mod rewrite_tool;
+pub mod toxicity_tool;
+pub mod digest_summary_tool;
+pub mod prompts;
+pub mod errors;
 
 use core::str;
-use std::env;
-use anyhow::{bail, Context, Result};
+use std::{env, time::{Duration, Instant}};
+use anyhow::{anyhow, bail, Context, Result};
 use aws_sdk_bedrockruntime::types::{SpecificToolChoice, ToolChoice};
 use aws_sdk_bedrockruntime::Client;
-use aws_sdk_bedrockruntime::types::{ContentBlock, Message, SystemContentBlock, Tool, ToolConfiguration, ToolInputSchema, ToolSpecification, ConversationRole::User};
+use aws_sdk_bedrockruntime::types::{ContentBlock, InferenceConfiguration, Message, SystemContentBlock, Tool, ToolConfiguration, ToolInputSchema, ToolSpecification, ConversationRole::User};
 use aws_sdk_bedrockruntime::operation::converse::ConverseOutput;
+use errors::{backoff_delay, BedrockErrorKind};
 use daily_advice_tool::get_daily_advice_tool_definition;
+use digest_summary_tool::get_digest_summary_tool_definition;
 use emotion_scores_tool::get_emotion_scores_tool_definition;
+use rewrite_tool::get_rewrite_tool_definition;
+use toxicity_tool::get_toxicity_tool_definition;
+use prompts::{emotions_variable, example_scores_variables, score_example_variable, warning_emotions_variable, PromptRegistry, CALMER_REWRITE_PROMPT, DAILY_ADVICE_PROMPT, DIGEST_SUMMARY_PROMPT, EMOTION_SCORES_PROMPT, TOXICITY_PROMPT};
+use serde::de::DeserializeOwned;
 
-use tools::ToValue;
-use crate::env_keys::CHAT_MODEL;
-use super::common_structs::{DailyAdvice, EmotionScores};
+use tools::{ToValue, ToolDefinition};
+use crate::{digest::Digest, env_keys::{BEDROCK_BACKOFF_BASE_MS, BEDROCK_MAX_RETRIES, CHAT_MODEL, FALLBACK_CHAT_MODELS, SCORING_SAMPLES, SCORING_TEMPERATURES}, language::Language, taxonomy::taxonomy};
+use super::{common_structs::{DailyAdvice, DigestSummary, EmotionScores, EmotionScoresOutput, MessageRewrite, NegativityTarget, ScoringResult, ToxicityScores}, usage_service::{CostMeter, UsageService}};
+
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const DEFAULT_BACKOFF_BASE_MS: u64 = 500;
 
 
 #[derive(Debug, Clone)]
 pub struct BedrockService {
     client: Client,
-    chat_model_id: String,
+    // CHAT_MODEL first, followed by FALLBACK_CHAT_MODELS in order
+    chat_model_ids: Vec<String>,
+    max_retries: u32,
+    backoff_base_ms: u64,
+    prompts: PromptRegistry,
+    // how many times each message is scored, and the temperatures to cycle through (model default if empty)
+    scoring_samples: usize,
+    scoring_temperatures: Vec<f32>,
+    // records token usage, latency and cost of every call
+    usage: Option<UsageService>,
+    // adds up the estimated cost of the calls of this service, ie: to stop a job at a budget
+    cost_meter: Option<CostMeter>,
 }
 
 impl BedrockService {
     pub fn new(client: &aws_sdk_bedrockruntime::Client) -> Self {
+        let mut chat_model_ids = vec![env::var(CHAT_MODEL).unwrap_or("".to_owned())];
+        chat_model_ids.extend(
+            env::var(FALLBACK_CHAT_MODELS).unwrap_or("".to_owned())
+                .split(',')
+                .map(|id| id.trim().to_owned())
+                .filter(|id| !id.is_empty())
+        );
+
         Self {
             client: client.to_owned(),
-            chat_model_id: env::var(CHAT_MODEL).unwrap_or("".to_owned())
+            chat_model_ids,
+            max_retries: env::var(BEDROCK_MAX_RETRIES).ok().and_then(|r| r.parse().ok()).unwrap_or(DEFAULT_MAX_RETRIES),
+            backoff_base_ms: env::var(BEDROCK_BACKOFF_BASE_MS).ok().and_then(|b| b.parse().ok()).unwrap_or(DEFAULT_BACKOFF_BASE_MS),
+            prompts: PromptRegistry::from_env(),
+            scoring_samples: env::var(SCORING_SAMPLES).ok().and_then(|s| s.parse().ok()).unwrap_or(1),
+            scoring_temperatures: env::var(SCORING_TEMPERATURES).unwrap_or("".to_owned())
+                .split(',')
+                .filter_map(|t| t.trim().parse().ok())
+                .collect(),
+            usage: None,
+            cost_meter: None,
         }
     }
 
-    pub async fn get_emotion_scroe(&self, text: &str) -> Result<EmotionScores> {
+    pub fn primary_model_id(&self) -> &str {
+        self.chat_model_ids.first().map(|id| id.as_str()).unwrap_or("")
+    }
 
-        let tool_definition = get_emotion_scores_tool_definition()?;
-        let emotion_scores_tool = Tool::ToolSpec(
-            ToolSpecification::builder()
-                .name(&tool_definition.name)
-                .description(&tool_definition.description)
-                .input_schema(ToolInputSchema::Json(tool_definition.schema))
-                .build()?
-        );
+    // {name}/{version} of the configured prompt
+    pub fn prompt_version(&self, name: &str) -> Result<String> {
+        Ok(format!("{}/{}", name, self.prompts.version(name)?))
+    }
 
-        let tool_config = ToolConfiguration::builder()
-            .set_tools(Some(vec![emotion_scores_tool]))
-            .tool_choice(ToolChoice::Tool(SpecificToolChoice::builder().name(&tool_definition.name).build()?))
-            .build()?;
+    pub fn with_usage(mut self, usage: &UsageService) -> Self {
+        self.usage = Some(usage.to_owned());
+        self
+    }
+
+    // calls only model, without fallbacks
+    pub fn with_chat_model(mut self, model_id: &str) -> Self {
+        self.chat_model_ids = vec![model_id.to_owned()];
+        self
+    }
 
-        let system_prompt = format!("
-            You will be acting as an AI Empath.
-            Your are an expert at reading emotions within text messages and chats.
-            The text given will be a message sent to a Slack Channel of a company.
-            The target text will be surrounded by <text></text>.
-            You have to use using {} to print out the score for each emotion.
-        ", tool_definition.name);
+    // version (ie: v2) of the prompt name instead of the one in PROMPT_VERSIONS
+    pub fn with_prompt_version(mut self, name: &str, version: &str) -> Result<Self> {
+        self.prompts = self.prompts.with_version(name, version)?;
+        Ok(self)
+    }
+
+    // charged with the estimated cost of every answered call, whether or not usage is recorded
+    pub fn with_cost_meter(mut self, cost_meter: &CostMeter) -> Self {
+        self.cost_meter = Some(cost_meter.to_owned());
+        self
+    }
+
+    // temperature of each of the SCORING_SAMPLES samples, cycling through SCORING_TEMPERATURES
+    pub fn sample_temperatures(&self) -> Vec<Option<f32>> {
+        (0..self.scoring_samples.max(1))
+            .map(|index| match self.scoring_temperatures.is_empty() {
+                true => None,
+                false => Some(self.scoring_temperatures[index % self.scoring_temperatures.len()]),
+            })
+            .collect()
+    }
+
+    // SCORING_SAMPLES > 1 scores the text several times, and returns the mean with the standard deviation
+    pub async fn get_emotion_scroe(&self, text: &str, language: &Language) -> Result<ScoringResult> {
+        let temperatures = self.sample_temperatures();
+        if temperatures.len() == 1 {
+            return self.get_emotion_scroe_sample(text, language, temperatures[0]).await
+        }
+
+        // sequential, samples sent at once would only be throttled
+        let mut samples = vec![];
+        for temperature in temperatures {
+            samples.push(self.get_emotion_scroe_sample(text, language, temperature).await?);
+        }
+
+        ScoringResult::from_samples(samples)
+    }
+
+    async fn get_emotion_scroe_sample(&self, text: &str, language: &Language, temperature: Option<f32>) -> Result<ScoringResult> {
+
+        let tool_definition = get_emotion_scores_tool_definition()?;
+        let tool_config = self.build_tool_config(&tool_definition)?;
+
+        let emotions = emotions_variable();
+        let examples = example_scores_variables(taxonomy(), language);
+        let mut variables = vec![("tool_name", tool_definition.name.as_str()), ("emotions", emotions.as_str())];
+        variables.extend(examples.iter().map(|(key, value)| (key.as_str(), value.as_str())));
+        let system_prompt = self.prompts.render(EMOTION_SCORES_PROMPT, None, language, &variables)?;
 
         let message = Message::builder()
             .role(User)
             .content(ContentBlock::Text(format!("<text>{}</text>", text)))
             .build()?;
 
-        let response = self.send(&system_prompt, vec![message], Some(tool_config)).await?;
+        let (response, model_id) = self.send(&tool_definition.name, &system_prompt.text, vec![message], Some(tool_config), temperature).await?;
 
-        println!("response: {:?}", response);
-        self.process_emotion_score_output(response, &tool_definition.name)
+        let output: EmotionScoresOutput = self.process_tool_output(response, &tool_definition.name)?;
+        let target = output.target.map(|target| NegativityTarget {
+            // an empty mention means there is none
+            mention: target.mention.filter(|m| !m.trim().is_empty()),
+            ..target
+        });
+        // anything else the model printed is not a score
+        Ok(ScoringResult { scores: output.scores.known(), model_id, prompt_version: Some(system_prompt.version), from_cache: false, std_dev: None, samples: None, target })
     }
 
 
-    fn process_emotion_score_output(&self, response: ConverseOutput, tool_name: &str) -> Result<EmotionScores> {
-        let output = response.output.context("Error getting output")?;
-        let message = match output.as_message() {
-            Ok(message) => message.to_owned(),
-            Err(output) => {
-                bail!("Converse output is not message: {:?}", output)
-            },
-        };
+    pub async fn get_daily_advice(&self, emotion_scores: &[EmotionScores], language: &Language) -> Result<DailyAdvice> {
 
-        let contents = message.content;
-        let mut scores: Option<EmotionScores> = None;
+        let tool_definition = get_daily_advice_tool_definition()?;
+        let tool_config = self.build_tool_config(&tool_definition)?;
 
-        for content in contents {
-            if !content.is_tool_use() {
-                continue;
-            }
-            let tool_use  = match content.as_tool_use() {
-                Ok(tool_use) => tool_use,
-                Err(block) => {
-                    println!("Block: {:?} is not tool use.", block);
-                    continue;
-                },
-            };
+        let system_prompt = self.prompts.render(DAILY_ADVICE_PROMPT, None, language, &[
+            ("tool_name", &tool_definition.name),
+            ("emotions", &emotions_variable()),
+            ("score_example", &score_example_variable()),
+        ])?;
 
-            if tool_use.name() != tool_name {
-                continue;
-            }
-            let input = tool_use.input().to_value();
-            match serde_json::from_value(input) {
-                Ok(s) => {
-                    scores = Some(s);
-                    break;
-                },
-                Err(error) => {
-                    println!("error getting scores from tool input: {}.", error);
-                    continue;
-                },
-            };
-        }
+        let score_string_vec = emotion_scores
+            .iter()
+            .map(|s| serde_json::to_string(&s).unwrap_or("".to_owned()))
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<String>>();
 
-        println!("tool use. name: {}, input: {:?}", tool_name, scores);
+        let message_text = format!("<scores>\n{}\n<scores>", score_string_vec.join("\n"));
 
-        if scores.is_none() {
-            bail!("Error getting emotion scores")
-        }
+        println!("message sent: {} scores", score_string_vec.len());
 
-        Ok(scores.unwrap())
+        let message = Message::builder()
+            .role(User)
+            .content(ContentBlock::Text(message_text))
+            .build()?;
+
+        let (response, model_id) = self.send(&tool_definition.name, &system_prompt.text, vec![message], Some(tool_config), None).await?;
+
+        let advice: DailyAdvice = self.process_tool_output(response, &tool_definition.name)?;
+        Ok(DailyAdvice {
+            model_id: Some(model_id),
+            prompt_version: Some(system_prompt.version),
+            ..advice
+        })
     }
 
 
-    pub async fn get_daily_advice(&self, emotion_scores: &Vec<EmotionScores>) -> Result<DailyAdvice> {
+    // the picks are sent without their text
+    pub async fn get_digest_summary(&self, digest: &Digest, language: &Language) -> Result<DigestSummary> {
 
-        let tool_definition = get_daily_advice_tool_definition()?;
-        let daily_advice_tool = Tool::ToolSpec(
-            ToolSpecification::builder()
-                .name(&tool_definition.name)
-                .description(&tool_definition.description)
-                .input_schema(ToolInputSchema::Json(tool_definition.schema))
-                .build()?
-        );
+        let tool_definition = get_digest_summary_tool_definition()?;
+        let tool_config = self.build_tool_config(&tool_definition)?;
 
-        let tool_config = ToolConfiguration::builder()
-            .set_tools(Some(vec![daily_advice_tool]))
-            .tool_choice(ToolChoice::Tool(SpecificToolChoice::builder().name(&tool_definition.name).build()?))
-            .build()?;
+        let system_prompt = self.prompts.render(DIGEST_SUMMARY_PROMPT, None, language, &[
+            ("tool_name", &tool_definition.name),
+            ("emotions", &emotions_variable()),
+            ("period", digest.period.code()),
+        ])?;
 
-        let system_prompt = format!("
-            You are a mental health professional.
-            You give advices to employees based on the emotion scores evaluated for the text messages they sent to Slack through out the day.
-            The emotion scores for a single emplyee in a single day will be given in the following format.
+        let mut anonymous = digest.to_owned();
+        for pick in anonymous.picks_mut() {
+            pick.text = "".to_owned();
+        }
+        let message_text = format!("<digest>\n{}\n</digest>", serde_json::to_string(&anonymous)?);
 
-            <scores>
-            {{anger: 0.6, contempt: 0.0, disgust: 0.0, fear: 0.1, joy: 0.6, surprise: 0.0, sad: 0.1}}
-            {{anger: 0.8, contempt: 0.0, disgust: 0.0, fear: 0.1, joy: 0.0, surprise: 0.0, sad: 0.1}}
-            ...
-            <scores>
+        let message = Message::builder()
+            .role(User)
+            .content(ContentBlock::Text(message_text))
+            .build()?;
 
-            Each line represents a set of emotion scores for a single text message.
-            Lines are in the order of when the text message is sent. Earliest comes first.
-            Each score is evaluated in the range of 0.0 to 1.0.
+        let (response, model_id) = self.send(&tool_definition.name, &system_prompt.text, vec![message], Some(tool_config), None).await?;
 
-            You are job is to
-            - Give a one sentence advice
-            - Recommend a song to listen.
+        let summary: DigestSummary = self.process_tool_output(response, &tool_definition.name)?;
+        Ok(DigestSummary {
+            model_id: Some(model_id),
+            prompt_version: Some(system_prompt.version),
+            ..summary
+        })
+    }
 
-            You have to use using {} to print out the advice and the recommended song.
-        ", tool_definition.name);
 
+    // the prompt is picked by language, the same way as for scoring
+    pub async fn get_calmer_rewrite(&self, text: &str, scores: &EmotionScores, language: &Language) -> Result<MessageRewrite> {
 
-        let score_string_vec = emotion_scores.to_owned()
-            .into_iter()
-            .map(|s| serde_json::to_string(&s).unwrap_or("".to_owned()))
-            .filter(|s| !s.is_empty())
-            .collect::<Vec<String>>();
+        let tool_definition = get_rewrite_tool_definition()?;
+        let tool_config = self.build_tool_config(&tool_definition)?;
 
-        let message_text = format!("<scores>\n{}\n<scores>", score_string_vec.join("\n"));
+        let system_prompt = self.prompts.render(CALMER_REWRITE_PROMPT, None, language, &[("tool_name", &tool_definition.name), ("warning_emotions", &warning_emotions_variable())])?;
 
-        println!("message sent: {:?}", message_text);
+        let message_text = format!("<text>{}</text>\n<scores>{}</scores>", text, serde_json::to_string(scores)?);
 
         let message = Message::builder()
             .role(User)
             .content(ContentBlock::Text(message_text))
             .build()?;
 
-        let response = self.send(&system_prompt, vec![message], Some(tool_config)).await?;
+        let (response, model_id) = self.send(&tool_definition.name, &system_prompt.text, vec![message], Some(tool_config), None).await?;
+
+        let rewrite: MessageRewrite = self.process_tool_output(response, &tool_definition.name)?;
+        Ok(MessageRewrite {
+            model_id: Some(model_id),
+            prompt_version: Some(system_prompt.version),
+            ..rewrite
+        })
+    }
+
+
+    pub async fn get_toxicity(&self, text: &str, language: &Language) -> Result<ToxicityScores> {
+
+        let tool_definition = get_toxicity_tool_definition()?;
+        let tool_config = self.build_tool_config(&tool_definition)?;
+
+        let system_prompt = self.prompts.render(TOXICITY_PROMPT, None, language, &[("tool_name", &tool_definition.name)])?;
+
+        let message = Message::builder()
+            .role(User)
+            .content(ContentBlock::Text(format!("<text>{}</text>", text)))
+            .build()?;
+
+        let (response, model_id) = self.send(&tool_definition.name, &system_prompt.text, vec![message], Some(tool_config), None).await?;
 
-        println!("response: {:?}", response);
-        self.process_daily_advice_output(response, &tool_definition.name)
+        let toxicity: ToxicityScores = self.process_tool_output(response, &tool_definition.name)?;
+        Ok(ToxicityScores {
+            model_id: Some(model_id),
+            prompt_version: Some(system_prompt.version),
+            ..toxicity
+        })
     }
 
 
-    fn process_daily_advice_output(&self, response: ConverseOutput, tool_name: &str) -> Result<DailyAdvice> {
+    fn process_tool_output<T: DeserializeOwned + std::fmt::Debug>(&self, response: ConverseOutput, tool_name: &str) -> Result<T> {
         let output = response.output.context("Error getting output")?;
         let message = match output.as_message() {
             Ok(message) => message.to_owned(),
@@ -189,7 +292,7 @@ impl BedrockService {
         };
 
         let contents = message.content;
-        let mut advice: Option<DailyAdvice> = None;
+        let mut result: Option<T> = None;
 
         for content in contents {
             if !content.is_tool_use() {
@@ -197,8 +300,8 @@ impl BedrockService {
             }
             let tool_use  = match content.as_tool_use() {
                 Ok(tool_use) => tool_use,
-                Err(block) => {
-                    println!("Block: {:?} is not tool use.", block);
+                Err(_) => {
+                    println!("Block is not tool use.");
                     continue;
                 },
             };
@@ -208,39 +311,130 @@ impl BedrockService {
             }
             let input = tool_use.input().to_value();
             match serde_json::from_value(input) {
-                Ok(a) => {
-                    advice = Some(a);
+                Ok(r) => {
+                    result = Some(r);
                     break;
                 },
                 Err(error) => {
-                    println!("error getting advices from tool input: {}.", error);
+                    println!("error parsing tool input: {}.", error);
                     continue;
                 },
             };
         }
 
-        println!("tool use. name: {}, input: {:?}", tool_name, advice);
+        println!("tool use. name: {}, parsed: {}", tool_name, result.is_some());
+
+        result.context(format!("Error getting tool output for {}", tool_name))
+    }
+
+
+    fn build_tool_config(&self, tool_definition: &ToolDefinition) -> Result<ToolConfiguration> {
+        let tool = Tool::ToolSpec(
+            ToolSpecification::builder()
+                .name(&tool_definition.name)
+                .description(&tool_definition.description)
+                .input_schema(ToolInputSchema::Json(tool_definition.schema.to_owned()))
+                .build()?
+        );
+
+        let tool_config = ToolConfiguration::builder()
+            .set_tools(Some(vec![tool]))
+            .tool_choice(ToolChoice::Tool(SpecificToolChoice::builder().name(&tool_definition.name).build()?))
+            .build()?;
+        Ok(tool_config)
+    }
+
+
+    // Tries each model in order.
+    // Retryable errors are retried on the same model with jittered exponential backoff before moving on to the next model,
+    // fatal errors are returned right away.
+    async fn send(&self, operation: &str, system_prompt: &str, messages: Vec<Message>, tool_config: Option<ToolConfiguration>, temperature: Option<f32>) -> Result<(ConverseOutput, String)> {
+        let mut last_error: Option<anyhow::Error> = None;
 
-        if advice.is_none() {
-            bail!("Error getting daily advice")
+        for model_id in self.chat_model_ids.iter().filter(|id| !id.is_empty()) {
+            match self.send_with_retry(operation, model_id, system_prompt, &messages, &tool_config, temperature).await {
+                Ok(response) => return Ok((response, model_id.to_owned())),
+                Err(error) => {
+                    if BedrockErrorKind::classify(&error) == BedrockErrorKind::Fatal {
+                        return Err(error)
+                    }
+                    println!("model {} failed, trying next model: {:?}", model_id, error);
+                    last_error = Some(error);
+                },
+            }
         }
 
-        Ok(advice.unwrap())
+        Err(last_error.unwrap_or(anyhow!("No chat model configured")))
     }
 
 
-    async fn send(&self, system_prompt: &str, messages: Vec<Message>, tool_config: Option<ToolConfiguration>) -> Result<ConverseOutput> {
-        let builder = self.client
-            .converse()
-            .model_id(&self.chat_model_id)
-            .system(SystemContentBlock::Text(system_prompt.to_owned()))
-            .set_messages(Some(messages))
-            .set_tool_config(tool_config);
+    // every attempt is recorded, a response is recorded before its tool output is parsed so that a broken output still counts
+    async fn send_with_retry(&self, operation: &str, model_id: &str, system_prompt: &str, messages: &[Message], tool_config: &Option<ToolConfiguration>, temperature: Option<f32>) -> Result<ConverseOutput> {
+        let mut attempt: u32 = 0;
+        loop {
+            let start = Instant::now();
+            let result = self.client
+                .converse()
+                .model_id(model_id)
+                .system(SystemContentBlock::Text(system_prompt.to_owned()))
+                .set_messages(Some(messages.to_vec()))
+                .set_tool_config(tool_config.to_owned())
+                .set_inference_config(temperature.map(|t| InferenceConfiguration::builder().temperature(t).build()))
+                .send()
+                .await;
+
+            let error = match result {
+                Ok(response) => {
+                    // never the response itself, it holds the model output about the message
+                    let (input_tokens, output_tokens) = response.usage.as_ref().map(|u| (u.input_tokens, u.output_tokens)).unwrap_or_default();
+                    println!("{}: model {}, input tokens {}, output tokens {}, stop reason {}", operation, model_id, input_tokens, output_tokens, response.stop_reason);
+                    self.record_usage(operation, model_id, &response, start.elapsed()).await;
+                    return Ok(response)
+                },
+                Err(error) => anyhow::Error::from(error),
+            };
+            // bedrock reports no tokens with an error, the attempt is still recorded so that failures show up in the usage
+            self.record_failure(operation, model_id, &error, start.elapsed()).await;
 
-        let response = builder
-            .send()
-            .await?;
-        Ok(response)
+            if BedrockErrorKind::classify(&error) != BedrockErrorKind::Retryable || attempt >= self.max_retries {
+                return Err(error)
+            }
+
+            let delay = backoff_delay(self.backoff_base_ms, attempt);
+            println!("model {} attempt {} failed, retrying in {:?}: {:?}", model_id, attempt + 1, delay, error);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
     }
 
+    // failing to record usage should never fail the call itself
+    async fn record_usage(&self, operation: &str, model_id: &str, response: &ConverseOutput, elapsed: Duration) {
+        let (input_tokens, output_tokens) = response.usage.as_ref()
+            .map(|u| (u.input_tokens.max(0) as u64, u.output_tokens.max(0) as u64))
+            .unwrap_or((0, 0));
+        // charged before and regardless of the usage table, so max_cost holds even when recording fails or is off
+        if let Some(cost_meter) = &self.cost_meter {
+            cost_meter.charge(model_id, input_tokens, output_tokens);
+        }
+        let Some(usage) = &self.usage else {
+            return
+        };
+        let latency_ms = response.metrics.as_ref()
+            .map(|m| m.latency_ms.max(0) as u64)
+            .unwrap_or(elapsed.as_millis() as u64);
+
+        if let Err(error) = usage.record(operation, model_id, input_tokens, output_tokens, latency_ms, None).await {
+            println!("Error recording usage: {:?}", error);
+        }
+    }
+
+    async fn record_failure(&self, operation: &str, model_id: &str, error: &anyhow::Error, elapsed: Duration) {
+        let Some(usage) = &self.usage else {
+            return
+        };
+        let kind = BedrockErrorKind::classify(error);
+        if let Err(error) = usage.record(operation, model_id, 0, 0, elapsed.as_millis() as u64, Some(kind.code())).await {
+            println!("Error recording usage: {:?}", error);
+        }
+    }
 }
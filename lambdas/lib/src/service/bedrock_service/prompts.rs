@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+
+use crate::{env_keys::PROMPT_VERSIONS, language::Language, service::common_structs::EmotionScores, taxonomy::{taxonomy, Polarity, Taxonomy}};
+
+pub const EMOTION_SCORES_PROMPT: &str = "emotion_scores";
+pub const DAILY_ADVICE_PROMPT: &str = "daily_advice";
+pub const CALMER_REWRITE_PROMPT: &str = "calmer_rewrite";
+pub const TOXICITY_PROMPT: &str = "toxicity";
+pub const DIGEST_SUMMARY_PROMPT: &str = "digest_summary";
+
+
+// Prompt templates live in lib/prompts/{name}/{version}.{language}.txt and are embedded at build time.
+// Never edit a released version in place, add a new one instead,
+// so that scores stay comparable within a version.
+struct PromptTemplate {
+    name: &'static str,
+    version: &'static str,
+    language: Language,
+    template: &'static str,
+}
+
+const TEMPLATES: &[PromptTemplate] = &[
+    PromptTemplate { name: EMOTION_SCORES_PROMPT, version: "v1", language: Language::En, template: include_str!("../../../prompts/emotion_scores/v1.en.txt") },
+    PromptTemplate { name: EMOTION_SCORES_PROMPT, version: "v1", language: Language::Ja, template: include_str!("../../../prompts/emotion_scores/v1.ja.txt") },
+    PromptTemplate { name: DAILY_ADVICE_PROMPT, version: "v1", language: Language::En, template: include_str!("../../../prompts/daily_advice/v1.en.txt") },
+    PromptTemplate { name: DAILY_ADVICE_PROMPT, version: "v1", language: Language::Ja, template: include_str!("../../../prompts/daily_advice/v1.ja.txt") },
+    PromptTemplate { name: CALMER_REWRITE_PROMPT, version: "v1", language: Language::En, template: include_str!("../../../prompts/calmer_rewrite/v1.en.txt") },
+    PromptTemplate { name: EMOTION_SCORES_PROMPT, version: "v2", language: Language::En, template: include_str!("../../../prompts/emotion_scores/v2.en.txt") },
+    PromptTemplate { name: EMOTION_SCORES_PROMPT, version: "v2", language: Language::Ja, template: include_str!("../../../prompts/emotion_scores/v2.ja.txt") },
+    PromptTemplate { name: DAILY_ADVICE_PROMPT, version: "v2", language: Language::En, template: include_str!("../../../prompts/daily_advice/v2.en.txt") },
+    PromptTemplate { name: DAILY_ADVICE_PROMPT, version: "v2", language: Language::Ja, template: include_str!("../../../prompts/daily_advice/v2.ja.txt") },
+    PromptTemplate { name: CALMER_REWRITE_PROMPT, version: "v2", language: Language::En, template: include_str!("../../../prompts/calmer_rewrite/v2.en.txt") },
+    PromptTemplate { name: TOXICITY_PROMPT, version: "v1", language: Language::En, template: include_str!("../../../prompts/toxicity/v1.en.txt") },
+    PromptTemplate { name: EMOTION_SCORES_PROMPT, version: "v3", language: Language::En, template: include_str!("../../../prompts/emotion_scores/v3.en.txt") },
+    PromptTemplate { name: EMOTION_SCORES_PROMPT, version: "v3", language: Language::Ja, template: include_str!("../../../prompts/emotion_scores/v3.ja.txt") },
+    PromptTemplate { name: DIGEST_SUMMARY_PROMPT, version: "v1", language: Language::En, template: include_str!("../../../prompts/digest_summary/v1.en.txt") },
+    PromptTemplate { name: DIGEST_SUMMARY_PROMPT, version: "v1", language: Language::Ja, template: include_str!("../../../prompts/digest_summary/v1.ja.txt") },
+    PromptTemplate { name: CALMER_REWRITE_PROMPT, version: "v3", language: Language::En, template: include_str!("../../../prompts/calmer_rewrite/v3.en.txt") },
+    PromptTemplate { name: CALMER_REWRITE_PROMPT, version: "v3", language: Language::Ja, template: include_str!("../../../prompts/calmer_rewrite/v3.ja.txt") },
+];
+
+// used when PROMPT_VERSIONS does not specify one
+const DEFAULT_VERSIONS: &[(&str, &str)] = &[
+    (EMOTION_SCORES_PROMPT, "v3"),
+    (DAILY_ADVICE_PROMPT, "v2"),
+    (CALMER_REWRITE_PROMPT, "v3"),
+    (TOXICITY_PROMPT, "v1"),
+    (DIGEST_SUMMARY_PROMPT, "v1"),
+];
+
+
+#[derive(Debug, Clone)]
+pub struct RenderedPrompt {
+    pub text: String,
+    // {name}/{version}, ie: emotion_scores/v1
+    pub version: String,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct PromptRegistry {
+    // prompt name -> version
+    versions: HashMap<String, String>,
+}
+
+impl PromptRegistry {
+    // PROMPT_VERSIONS: {"emotion_scores": "v1", ...}
+    pub fn from_env() -> Self {
+        let mut versions: HashMap<String, String> = DEFAULT_VERSIONS.iter()
+            .map(|(name, version)| (name.to_string(), version.to_string()))
+            .collect();
+
+        match std::env::var(PROMPT_VERSIONS).map(|v| serde_json::from_str::<HashMap<String, String>>(&v)) {
+            Ok(Ok(overrides)) => versions.extend(overrides),
+            Ok(Err(error)) => println!("Error parsing prompt versions: {:?}", error),
+            Err(_) => {},
+        }
+
+        Self { versions }
+    }
+
+    // the registry with version used for the prompt name, which must exist
+    pub fn with_version(mut self, name: &str, version: &str) -> Result<Self> {
+        if !TEMPLATES.iter().any(|t| t.name == name && t.version == version) {
+            bail!("Prompt template {}/{} not found", name, version)
+        }
+        self.versions.insert(name.to_owned(), version.to_owned());
+        Ok(self)
+    }
+
+    pub fn version(&self, name: &str) -> Result<&str> {
+        self.versions.get(name).map(|v| v.as_str()).context(format!("No version configured for prompt {}", name))
+    }
+
+    // {{variable}} in the template is replaced with the value.
+    // Falls back to the english template if there is none for the language.
+    pub fn render(&self, name: &str, version: Option<&str>, language: &Language, variables: &[(&str, &str)]) -> Result<RenderedPrompt> {
+        let version = match version {
+            Some(version) => version,
+            None => self.version(name)?,
+        };
+        let candidates: Vec<&PromptTemplate> = TEMPLATES.iter()
+            .filter(|t| t.name == name && t.version == version)
+            .collect();
+        let template = candidates.iter()
+            .find(|t| &t.language == language)
+            .or(candidates.iter().find(|t| t.language == Language::En))
+            .context(format!("Prompt template {}/{} not found", name, version))?;
+
+        let mut text = template.template.to_owned();
+        for (key, value) in variables {
+            text = text.replace(&format!("{{{{{}}}}}", key), value);
+        }
+        if let Some(start) = text.find("{{") {
+            let variable: String = text[start..].chars().take(40).collect();
+            bail!("Unsubstituted variable in prompt {}/{}: {}", name, version, variable);
+        }
+
+        Ok(RenderedPrompt {
+            text,
+            version: format!("{}/{}", name, version),
+        })
+    }
+}
+
+
+// Variables derived from the taxonomy, available to every prompt from v2 on.
+
+// {{emotions}}: one line per emotion, ie: - anger (negative): Irritation, ...
+pub fn emotions_variable() -> String {
+    taxonomy().emotions.iter()
+        .map(|e| format!("- {} ({}): {}", e.name, polarity_name(e.polarity), e.description))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// {{warning_emotions}}: ie: anger, contempt and disgust
+pub fn warning_emotions_variable() -> String {
+    let names: Vec<&str> = taxonomy().warning_emotions().map(|e| e.name.as_str()).collect();
+    match names.split_last() {
+        Some((last, [])) => last.to_string(),
+        Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+        None => "negative emotion".to_owned(),
+    }
+}
+
+// {{score_example}}: two lines in the format the scores are sent in
+pub fn score_example_variable() -> String {
+    [[0.6, 0.0, 0.1], [0.8, 0.1, 0.0]].iter()
+        .map(|pattern| {
+            let scores = EmotionScores::from_pairs(taxonomy().names().enumerate().map(|(index, name)| (name, pattern[index % pattern.len()])));
+            serde_json::to_string(&scores).unwrap_or_default()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// the scores of a few-shot example, emotions not listed score 0.0
+struct ScoreExample {
+    scores: &'static [(&'static str, f64)],
+    target: &'static str,
+}
+
+// the examples of emotion_scores/v3, in the order of the template
+const SCORE_EXAMPLES_EN: &[ScoreExample] = &[
+    ScoreExample { scores: &[("joy", 0.8), ("surprise", 0.1)], target: "{category: none}" },
+    ScoreExample { scores: &[("anger", 0.7), ("contempt", 0.2), ("disgust", 0.2), ("surprise", 0.2)], target: "{category: thing}" },
+    ScoreExample { scores: &[("anger", 0.4), ("contempt", 0.5), ("disgust", 0.1)], target: "{category: person, mention: @user1}" },
+    ScoreExample { scores: &[("anger", 0.2), ("disgust", 0.1), ("sad", 0.3)], target: "{category: self}" },
+];
+const SCORE_EXAMPLES_JA: &[ScoreExample] = &[
+    ScoreExample { scores: &[("joy", 0.8), ("surprise", 0.1)], target: "{category: none}" },
+    ScoreExample { scores: &[("anger", 0.6), ("contempt", 0.2), ("disgust", 0.2), ("surprise", 0.2)], target: "{category: thing}" },
+    ScoreExample { scores: &[("anger", 0.4), ("contempt", 0.4), ("disgust", 0.1), ("sad", 0.1)], target: "{category: person, mention: @user1}" },
+    ScoreExample { scores: &[("anger", 0.2), ("disgust", 0.1), ("sad", 0.3)], target: "{category: self}" },
+];
+
+// {{example_scores_1}}, {{example_scores_2}}, ...: the scores of the examples, with every emotion of the taxonomy,
+// ie: {anger: 0.0, ..., joy: 0.8, ..., target: {category: none}}
+// so that the examples never show an emotion the model is not asked for.
+pub fn example_scores_variables(taxonomy: &Taxonomy, language: &Language) -> Vec<(String, String)> {
+    let examples = match language {
+        Language::Ja => SCORE_EXAMPLES_JA,
+        _ => SCORE_EXAMPLES_EN,
+    };
+    examples.iter().enumerate()
+        .map(|(index, example)| {
+            let scores: Vec<String> = taxonomy.names()
+                .map(|name| {
+                    let score = example.scores.iter().find(|(n, _)| *n == name).map(|(_, score)| *score).unwrap_or(0.0);
+                    format!("{}: {:.1}", name, score)
+                })
+                .collect();
+            (format!("example_scores_{}", index + 1), format!("{{{}, target: {}}}", scores.join(", "), example.target))
+        })
+        .collect()
+}
+
+fn polarity_name(polarity: Polarity) -> &'static str {
+    match polarity {
+        Polarity::Positive => "positive",
+        Polarity::Negative => "negative",
+        Polarity::Neutral => "neutral",
+    }
+}
@@ -0,0 +1,23 @@
+use serde_json::json;
+use anyhow::Result;
+use super::tools::{ToDocument, ToolDefinition};
+
+
+pub fn get_rewrite_tool_definition() -> Result<ToolDefinition> {
+    let name = "print_calmer_rewrite";
+    let description = "Print a calmer rewrite of a given text.";
+
+    let json_schema = json!({
+        "type": "object",
+        "properties": {
+            "rewrite": {
+                "type": "string",
+                "description": "The rewritten text with the same meaning and less negative emotion.",
+            }
+        },
+        "required": ["rewrite"],
+    });
+
+    let schema = json_schema.to_document();
+    Ok(ToolDefinition::new(name, description, &schema))
+}
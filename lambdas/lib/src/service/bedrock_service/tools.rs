@@ -18,7 +18,7 @@ impl ToDocument for Value {
             return Document::String(string.to_owned());
         }
 
-        if let Some(_) = self.as_null() {
+        if self.as_null().is_some() {
             return Document::Null
         }
 
@@ -46,7 +46,7 @@ impl ToDocument for Value {
             return Document::Object(doc_map);
         }
 
-        return Document::Null;
+        Document::Null
     }
 }
 
@@ -56,7 +56,7 @@ impl ToValue for Document {
         match self {
             Document::Object(map) => {
                 let mut value_map: HashMap<String, Value> = HashMap::new();
-                for (key, value) in map.into_iter() {
+                for (key, value) in map.iter() {
                     value_map.insert(key.to_owned(), value.to_value());
                 };
                 json!(value_map)
@@ -66,7 +66,7 @@ impl ToValue for Document {
                 for item in array {
                     value_array.push(item.to_value())
                 }
-                return json!(value_array)
+                json!(value_array)
             },
             Document::Number(number) =>json!(number.to_f64_lossy()),
             Document::String(str) => json!(str),
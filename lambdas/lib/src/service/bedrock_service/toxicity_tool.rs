@@ -0,0 +1,39 @@
+use serde_json::{json, Map, Value};
+use anyhow::Result;
+use crate::service::common_structs::TOXICITY_CATEGORIES;
+use super::tools::{ToDocument, ToolDefinition};
+
+
+pub fn get_toxicity_tool_definition() -> Result<ToolDefinition> {
+    let name = "print_toxicity";
+    let description = "Print toxicity classification of a given text.";
+
+    let mut properties = Map::new();
+    for category in TOXICITY_CATEGORIES {
+        properties.insert(category.to_string(), json!({
+            "type": "object",
+            "properties": {
+                "score": {
+                    "type": "number",
+                    "description": format!("Score for {}, ranging from 0.0 to 1.0.", category),
+                },
+                "target": {
+                    "type": "string",
+                    "enum": ["none", "individual", "group", "other"],
+                    "description": format!("Who the {} is aimed at.", category),
+                }
+            },
+            "required": ["score", "target"],
+        }));
+    }
+    let required: Vec<Value> = TOXICITY_CATEGORIES.iter().map(|category| json!(category)).collect();
+
+    let json_schema = json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    });
+
+    let schema = json_schema.to_document();
+    Ok(ToolDefinition::new(name, description, &schema))
+}
@@ -1,16 +1,249 @@
 
-use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::{Context, Result};
+use serde::{de::{IgnoredAny, MapAccess, Visitor}, ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{statistics::ScoreSummary, taxonomy::{is_reserved_name, taxonomy}};
+
+
+// emotion name -> score, for the emotions of the taxonomy.
+// Stored as one attribute per emotion, so entries written with the original seven fields read as is.
+// An emotion that was not scored, ie: added to the taxonomy after the entry was written, reads as 0.0.
+// Scores of emotions the taxonomy does not know are kept as they are, so that an entry read and written again
+// by a lambda or a tool configured with another taxonomy does not lose them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EmotionScores(BTreeMap<String, f64>);
+
+impl EmotionScores {
+    pub fn zero() -> Self {
+        Self(taxonomy().names().map(|name| (name.to_owned(), 0.0)).collect())
+    }
+
+    pub fn from_pairs<'a>(pairs: impl IntoIterator<Item = (&'a str, f64)>) -> Self {
+        Self(pairs.into_iter().map(|(name, score)| (name.to_owned(), score)).collect())
+    }
+
+    pub fn get(&self, name: &str) -> f64 {
+        self.0.get(name).copied().unwrap_or(0.0)
+    }
+
+    pub fn set(&mut self, name: &str, score: f64) {
+        self.0.insert(name.to_owned(), score);
+    }
+
+    // the highest score among the emotions that trigger an immediate warning
+    pub fn max_negative(&self) -> f64 {
+        taxonomy().warning_emotions().map(|e| self.get(&e.name)).fold(0.0, f64::max)
+    }
+
+    // apply f to every emotion
+    pub fn map(&self, f: impl Fn(f64) -> f64) -> Self {
+        Self(self.0.iter().map(|(name, score)| (name.to_owned(), f(*score))).collect())
+    }
+
+    // apply f to every emotion pair of self and other, a missing emotion counts as 0.0
+    pub fn combine(&self, other: &Self, f: impl Fn(f64, f64) -> f64) -> Self {
+        let mut combined = self.clone();
+        for name in self.0.keys().chain(other.0.keys()) {
+            combined.0.insert(name.to_owned(), f(self.get(name), other.get(name)));
+        }
+        combined
+    }
+
+    // only the emotions of the taxonomy, ie: for the output of a model
+    pub fn known(&self) -> Self {
+        Self(self.0.iter().filter(|(name, _)| taxonomy().contains(name)).map(|(name, score)| (name.to_owned(), *score)).collect())
+    }
+
+    // in taxonomy order
+    pub fn to_pairs(&self) -> Vec<(&str, f64)> {
+        taxonomy().names()
+            .filter_map(|name| self.0.get(name).map(|score| (name, *score)))
+            .collect()
+    }
+}
+
+// the emotions of the taxonomy in order, then the unknown ones
+impl Serialize for EmotionScores {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let pairs = self.to_pairs();
+        let unknown: Vec<(&String, &f64)> = self.0.iter().filter(|(name, _)| !taxonomy().contains(name)).collect();
+        let mut map = serializer.serialize_map(Some(pairs.len() + unknown.len()))?;
+        for (name, score) in pairs {
+            map.serialize_entry(name, &score)?;
+        }
+        for (name, score) in unknown {
+            map.serialize_entry(name, score)?;
+        }
+        map.end()
+    }
+}
+
+// a score, or any other attribute left over by the struct the scores are flattened into
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScoreValue {
+    Score(f64),
+    Other(IgnoredAny),
+}
+
+// Takes the emotions of the taxonomy, and the numbers of other keys that are not reserved names,
+// so that it can be flattened into a table entry.
+impl<'de> Deserialize<'de> for EmotionScores {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ScoresVisitor;
+
+        impl<'de> Visitor<'de> for ScoresVisitor {
+            type Value = EmotionScores;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map of emotion scores")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut scores = BTreeMap::new();
+                while let Some(key) = map.next_key::<String>()? {
+                    if taxonomy().contains(&key) {
+                        scores.insert(key, map.next_value::<f64>()?);
+                    } else if is_reserved_name(&key) {
+                        map.next_value::<IgnoredAny>()?;
+                    } else if let ScoreValue::Score(score) = map.next_value::<ScoreValue>()? {
+                        scores.insert(key, score);
+                    }
+                }
+                Ok(EmotionScores(scores))
+            }
+        }
+
+        deserializer.deserialize_map(ScoresVisitor)
+    }
+}
+
+
+// what the negative emotion of a message is aimed at
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetCategory {
+    // no negative emotion
+    #[default]
+    None,
+    // the author, ie: "I messed up again"
+    #[serde(rename = "self")]
+    Oneself,
+    // someone in particular, ie: "@ken broke it again"
+    Person,
+    // a team or people in general, ie: "the backend team never tests anything"
+    Group,
+    // a thing or the situation, ie: "this flaky CI"
+    Thing,
+}
+
+impl TargetCategory {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Oneself => "self",
+            Self::Person => "person",
+            Self::Group => "group",
+            Self::Thing => "thing",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct NegativityTarget {
+    pub category: TargetCategory,
+    // the mention token of the person in the scored text, ie: @user2, see slack_markup::NormalizedText
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mention: Option<String>,
+    // resolved from the mention, not part of the tool output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+}
+
+impl NegativityTarget {
+    pub fn is_person(&self) -> bool {
+        self.category == TargetCategory::Person
+    }
+
+    // the category most samples agree on, with the first target of that category
+    pub fn most_common(targets: &[NegativityTarget]) -> Option<NegativityTarget> {
+        let mut counts: Vec<(TargetCategory, usize)> = vec![];
+        for target in targets {
+            match counts.iter_mut().find(|(category, _)| *category == target.category) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((target.category, 1)),
+            }
+        }
+        // max_by_key keeps the last of equal counts, reversed so that ties go to the first sample
+        let (category, _) = counts.into_iter().rev().max_by_key(|(_, count)| *count)?;
+        targets.iter().find(|t| t.category == category).cloned()
+    }
+}
+
+
+// the input of the emotion scores tool
+#[derive(Debug, Deserialize, Clone)]
+pub struct EmotionScoresOutput {
+    #[serde(flatten)]
+    pub scores: EmotionScores,
+    #[serde(default)]
+    pub target: Option<NegativityTarget>,
+}
 
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct EmotionScores {
-    pub anger: f64,
-    pub contempt: f64,
-    pub disgust: f64,
-    pub fear: f64,
-    pub joy: f64,
-    pub surprise: f64,
-    pub sad: f64,
+pub struct ScoringResult {
+    pub scores: EmotionScores,
+    // the model (or scorer) that actually produced the scores
+    pub model_id: String,
+    // none if no prompt is involved, ie: the lexicon scorer
+    pub prompt_version: Option<String>,
+    #[serde(default)]
+    pub from_cache: bool,
+    // standard deviation per emotion when scored from several samples, scores are then the mean
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub std_dev: Option<EmotionScores>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub samples: Option<u32>,
+    // none if the scorer does not tell, ie: the lexicon scorer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<NegativityTarget>,
+}
+
+impl ScoringResult {
+    // The mean of samples of the same text, with their standard deviation.
+    // Samples of a retry that fell back to another model (or prompt version) would be stored under the model
+    // of the first sample, so only the samples of the most common model are kept, ties going to the first sample.
+    // `samples` is the number of samples kept.
+    pub fn from_samples(samples: Vec<ScoringResult>) -> Result<Self> {
+        let mut groups: Vec<Vec<ScoringResult>> = vec![];
+        for sample in samples {
+            match groups.iter_mut().find(|g| g[0].model_id == sample.model_id && g[0].prompt_version == sample.prompt_version) {
+                Some(group) => group.push(sample),
+                None => groups.push(vec![sample]),
+            }
+        }
+        if groups.len() > 1 {
+            let counts: Vec<String> = groups.iter().map(|g| format!("{} ({:?}): {}", g[0].model_id, g[0].prompt_version, g.len())).collect();
+            println!("Scoring samples from different models, keeping the most common: {}", counts.join(", "));
+        }
+        // max_by_key keeps the last of equal groups
+        let mut samples = groups.into_iter().rev().max_by_key(|g| g.len()).context("No samples")?;
+
+        let scores: Vec<EmotionScores> = samples.iter().map(|s| s.scores.to_owned()).collect();
+        let summary = ScoreSummary::from_scores(&scores).context("No samples")?;
+        let targets: Vec<NegativityTarget> = samples.iter().filter_map(|s| s.target.to_owned()).collect();
+        let first = samples.swap_remove(0);
+        Ok(Self {
+            scores: summary.mean,
+            std_dev: Some(summary.std_dev),
+            samples: Some(summary.count as u32),
+            target: NegativityTarget::most_common(&targets),
+            ..first
+        })
+    }
 }
 
 
@@ -18,4 +251,112 @@ pub struct EmotionScores {
 pub struct DailyAdvice {
     pub advice: String,
     pub song: String,
+    // not part of the tool output, set after the call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+}
+
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DigestSummary {
+    pub summary: String,
+    pub advice: String,
+    // not part of the tool output, set after the call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+}
+
+
+// picks scoring lower are left out of the daily report, and their text is never read
+pub const DAILY_PICK_MIN_SCORE: f64 = 0.4;
+
+// the message with the highest score for an emotion, picked up in the daily report
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DailyPick {
+    pub emotion: String,
+    pub score: f64,
+    pub text: String,
+    // the samples disagreed on the score
+    pub uncertain: bool,
+    // picked among the messages aimed at a person, reported separately
+    #[serde(default)]
+    pub person_directed: bool,
+}
+
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MessageRewrite {
+    pub rewrite: String,
+    // not part of the tool output, set after the call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+}
+
+
+pub const TOXICITY_CATEGORIES: [&str; 4] = ["insult", "identity_attack", "threat", "sexual_content"];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ToxicityTarget {
+    #[default]
+    None,
+    Individual,
+    Group,
+    Other,
+}
+
+impl ToxicityTarget {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Individual => "individual",
+            Self::Group => "group",
+            Self::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ToxicityLabel {
+    pub score: f64,
+    #[serde(default)]
+    pub target: ToxicityTarget,
+}
+
+// Harassment rather than emotion, a calm insult aimed at a colleague scores high here but not in EmotionScores.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ToxicityScores {
+    pub insult: ToxicityLabel,
+    pub identity_attack: ToxicityLabel,
+    pub threat: ToxicityLabel,
+    pub sexual_content: ToxicityLabel,
+    // not part of the tool output, set after the call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+}
+
+impl ToxicityScores {
+    // in TOXICITY_CATEGORIES order
+    pub fn to_pairs(&self) -> Vec<(&'static str, &ToxicityLabel)> {
+        TOXICITY_CATEGORIES.into_iter()
+            .zip([&self.insult, &self.identity_attack, &self.threat, &self.sexual_content])
+            .collect()
+    }
+
+    // Categories over the threshold that call for a moderator.
+    // Insults, identity attacks and threats only count when aimed at someone,
+    // swearing at a broken build is not harassment. Sexual content counts either way.
+    pub fn escalations(&self, threshold: f64) -> Vec<(&'static str, &ToxicityLabel)> {
+        self.to_pairs().into_iter()
+            .filter(|(category, label)| label.score > threshold && (*category == "sexual_content" || label.target != ToxicityTarget::None))
+            .collect()
+    }
 }
@@ -1,19 +1,27 @@
 pub mod structs;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use anyhow::{Context, Ok, Result};
-use aws_sdk_dynamodb::{operation::query::QueryOutput, types::AttributeValue};
-use serde_dynamo::{from_items, to_item};
-use structs::EmotionTableEntry;
+use anyhow::{bail, Context, Ok, Result};
+use aws_sdk_dynamodb::{operation::query::{builders::QueryFluentBuilder, QueryOutput}, types::{AttributeValue, Put, TransactWriteItem}};
+use serde_dynamo::{from_item, from_items, to_item};
+use structs::{EmotionTableEntry, SCORES_ONLY_ATTRIBUTES};
 
-use crate::utilities::get_previous_weekday;
-use super::{common_structs::EmotionScores, line_service::MessageEventRequest};
+use crate::{env_keys::{ROLLUP_TABLE_NAME, TABLE_ARN, TABLE_NAME}, rescoring::ScoringGeneration, rollup::{compute_rollups, dates_between, is_within, rebuild_range, Granularity, RebuildReport, Rollup, RollupQuery, RollupScope}, taxonomy::taxonomy};
+use super::{emotion_repository::{parse_entry_cursor, ChannelQuery, EntryPage, UserQuery}, rollup_service::RollupService};
+
+
+// attempts of register_entry when a concurrent write cancels its transaction
+const REGISTER_ATTEMPTS: u32 = 3;
 
 
 #[derive(Debug, Clone)]
 pub struct DynamoService {
     client: aws_sdk_dynamodb::Client,
+    // the emotion table, empty if not set for the lambda
+    table_name: String,
+    table_arn: String,
+    rollups: RollupService,
 }
 
 
@@ -21,28 +29,189 @@ pub struct DynamoService {
 impl DynamoService {
     pub fn new(client: &aws_sdk_dynamodb::Client) -> Self {
         Self {
-            client: client.to_owned()
+            client: client.to_owned(),
+            table_name: std::env::var(TABLE_NAME).unwrap_or("".to_owned()),
+            table_arn: std::env::var(TABLE_ARN).unwrap_or("".to_owned()),
+            rollups: RollupService::new(client),
         }
     }
 
-    pub async fn query_yesterday(&self, table_name: &str) -> Result<Vec<EmotionTableEntry>>{
-        let yesterday = get_previous_weekday()?;
+    // date: YYYY-MM-DD
+    pub async fn query_date(&self, date: &str) -> Result<Vec<EmotionTableEntry>>{
+        self.query_date_projected(date, false).await
+    }
+
+    // the entries of the date read with the scores only projection, the text is never read
+    pub async fn query_date_scores_only(&self, date: &str) -> Result<Vec<EmotionTableEntry>>{
+        self.query_date_projected(date, true).await
+    }
+
+    async fn query_date_projected(&self, date: &str, scores_only: bool) -> Result<Vec<EmotionTableEntry>>{
+        let mut attribute_names: HashMap<String, String> = HashMap::from([
+            ("#date".to_owned(), "date".to_owned()),
+        ]);
         let attribute_values: HashMap<String, AttributeValue> = HashMap::from([
-            (":date".to_owned(), AttributeValue::S(yesterday.clone())),
+            (":date".to_owned(), AttributeValue::S(date.to_owned())),
         ]);
+        let projection = match scores_only {
+            true => scores_only_projection(&mut attribute_names),
+            false => vec![],
+        };
 
-        let mut builder = self.client.clone()
+        let builder = self.client.clone()
             .query()
-            // .limit(1)
             .scan_index_forward(true)
-            .table_name(table_name)
+            .table_name(&self.table_name)
             .index_name("gsi-date")
             .key_condition_expression("#date = :date")
-            .expression_attribute_names("#date", "date")
+            .set_projection_expression((!projection.is_empty()).then(|| projection.join(", ")))
+            .set_expression_attribute_names(Some(attribute_names))
             .set_expression_attribute_values(Some(attribute_values));
 
+        let entries = self.query_all(builder).await?;
+        println!("entries for {}: {}", date, entries.len());
+        Ok(entries)
+    }
+
+    // consistent, it is read right before the entry is replaced
+    pub async fn get_entry(&self, event_id: &str) -> Result<Option<EmotionTableEntry>> {
+        let output = self.client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("event_id", AttributeValue::S(event_id.to_owned()))
+            .consistent_read(true)
+            .send()
+            .await?;
+        Ok(output.item.map(from_item).transpose()?)
+    }
+
+    // one page of gsi-userid, the cursor is {timestamp}:{event_id} of the last item read
+    pub async fn query_user_page(&self, query: &UserQuery, cursor: Option<&str>) -> Result<EntryPage> {
+        if query.to <= query.from {
+            return Ok(EntryPage { entries: vec![], cursor: None })
+        }
+        let mut attribute_names: HashMap<String, String> = HashMap::from([
+            ("#timestamp".to_owned(), "timestamp".to_owned()),
+        ]);
+        let mut attribute_values: HashMap<String, AttributeValue> = HashMap::from([
+            (":user_id".to_owned(), AttributeValue::S(query.user_id.to_owned())),
+            (":from".to_owned(), AttributeValue::N(query.from.to_string())),
+            // BETWEEN is inclusive
+            (":to".to_owned(), AttributeValue::N((query.to - 1).to_string())),
+        ]);
+
+        // emotion names are aliased, they may be reserved words
+        let mut filters = vec![];
+        for (index, (emotion, score)) in query.scores_above.iter().enumerate() {
+            attribute_names.insert(format!("#emotion{}", index), emotion.to_owned());
+            attribute_values.insert(format!(":score{}", index), AttributeValue::N(score.to_string()));
+            filters.push(format!("#emotion{} > :score{}", index, index));
+        }
+
+        let projection = match query.scores_only {
+            true => scores_only_projection(&mut attribute_names),
+            false => vec![],
+        };
+
+        let exclusive_start_key = match cursor {
+            Some(cursor) => {
+                let (timestamp, event_id) = parse_entry_cursor(cursor)?;
+                Some(HashMap::from([
+                    ("event_id".to_owned(), AttributeValue::S(event_id)),
+                    ("user_id".to_owned(), AttributeValue::S(query.user_id.to_owned())),
+                    ("timestamp".to_owned(), AttributeValue::N(timestamp.to_string())),
+                ]))
+            },
+            None => None,
+        };
+
+        let output = self.client.clone()
+            .query()
+            .scan_index_forward(true)
+            .table_name(&self.table_name)
+            .index_name("gsi-userid")
+            .key_condition_expression("user_id = :user_id AND #timestamp BETWEEN :from AND :to")
+            .set_filter_expression((!filters.is_empty()).then(|| filters.join(" AND ")))
+            .set_projection_expression((!projection.is_empty()).then(|| projection.join(", ")))
+            .set_expression_attribute_names(Some(attribute_names))
+            .set_expression_attribute_values(Some(attribute_values))
+            .limit(query.page_size as i32)
+            .set_exclusive_start_key(exclusive_start_key)
+            .send()
+            .await?;
+
+        let entries = self.output_to_entries(&output)?;
+        let cursor = match output.last_evaluated_key {
+            Some(key) => {
+                let timestamp = key.get("timestamp").and_then(|t| t.as_n().ok()).context("timestamp not in last evaluated key")?;
+                let event_id = key.get("event_id").and_then(|e| e.as_s().ok()).context("event_id not in last evaluated key")?;
+                Some(format!("{}:{}", timestamp, event_id))
+            },
+            None => None,
+        };
+        Ok(EntryPage { entries, cursor })
+    }
+
+    // one page of gsi-channel, the cursor is {timestamp}:{event_id} of the last item read
+    pub async fn query_channel_page(&self, query: &ChannelQuery, cursor: Option<&str>) -> Result<EntryPage> {
+        if query.to <= query.from {
+            return Ok(EntryPage { entries: vec![], cursor: None })
+        }
+        let mut attribute_names: HashMap<String, String> = HashMap::from([
+            ("#timestamp".to_owned(), "timestamp".to_owned()),
+        ]);
+        let attribute_values: HashMap<String, AttributeValue> = HashMap::from([
+            (":channel_id".to_owned(), AttributeValue::S(query.channel_id.to_owned())),
+            (":from".to_owned(), AttributeValue::N(query.from.to_string())),
+            // BETWEEN is inclusive
+            (":to".to_owned(), AttributeValue::N((query.to - 1).to_string())),
+        ]);
+
+        let projection = match query.scores_only {
+            true => scores_only_projection(&mut attribute_names),
+            false => vec![],
+        };
+
+        let exclusive_start_key = match cursor {
+            Some(cursor) => {
+                let (timestamp, event_id) = parse_entry_cursor(cursor)?;
+                Some(HashMap::from([
+                    ("event_id".to_owned(), AttributeValue::S(event_id)),
+                    ("channel_id".to_owned(), AttributeValue::S(query.channel_id.to_owned())),
+                    ("timestamp".to_owned(), AttributeValue::N(timestamp.to_string())),
+                ]))
+            },
+            None => None,
+        };
+
+        let output = self.client.clone()
+            .query()
+            .scan_index_forward(true)
+            .table_name(&self.table_name)
+            .index_name("gsi-channel")
+            .key_condition_expression("channel_id = :channel_id AND #timestamp BETWEEN :from AND :to")
+            .set_projection_expression((!projection.is_empty()).then(|| projection.join(", ")))
+            .set_expression_attribute_names(Some(attribute_names))
+            .set_expression_attribute_values(Some(attribute_values))
+            .limit(query.page_size as i32)
+            .set_exclusive_start_key(exclusive_start_key)
+            .send()
+            .await?;
+
+        let entries = self.output_to_entries(&output)?;
+        let cursor = match output.last_evaluated_key {
+            Some(key) => {
+                let timestamp = key.get("timestamp").and_then(|t| t.as_n().ok()).context("timestamp not in last evaluated key")?;
+                let event_id = key.get("event_id").and_then(|e| e.as_s().ok()).context("event_id not in last evaluated key")?;
+                Some(format!("{}:{}", timestamp, event_id))
+            },
+            None => None,
+        };
+        Ok(EntryPage { entries, cursor })
+    }
+
+    async fn query_all(&self, mut builder: QueryFluentBuilder) -> Result<Vec<EmotionTableEntry>> {
         let output = builder.clone().send().await?;
-        // let items = results.items.context("items not available")?;
         let mut entries= self.output_to_entries(&output)?;
 
         let mut last_evaluated_key = output.last_evaluated_key;
@@ -53,8 +222,6 @@ impl DynamoService {
             entries.append(&mut self.output_to_entries(&output)?);
             last_evaluated_key = output.last_evaluated_key;
         }
-
-        println!("entries for {}: {:?}", yesterday, entries);
         Ok(entries)
     }
 
@@ -64,16 +231,261 @@ impl DynamoService {
         Ok(entries)
     }
 
-    pub async fn register_entry(&self, table_name: &str, message_request: &MessageEventRequest, scores: &EmotionScores) -> Result<EmotionTableEntry>{
-        let entry = EmotionTableEntry::new(message_request, scores)?;
-        self
-            .client.clone()
+    // also updates the rollups if the rollup table is set
+    // the entry and its rollups are written in one transaction, neither is stored without the other.
+    // retried when a concurrent write changed the entry or a max the transaction was computed from.
+    pub async fn register_entry(&self, entry: &EmotionTableEntry) -> Result<()>{
+        if !self.rollups.is_enabled() {
+            return self.replace_entry(entry).await
+        }
+
+        let mut attempt = 1;
+        loop {
+            let previous = self.get_entry(&entry.event_id).await?;
+            let mut items = vec![self.put_entry_item(entry, previous.as_ref())?];
+            items.append(&mut self.rollups.updates(entry, previous.as_ref()).await?);
+            let result = self.client
+                .transact_write_items()
+                .set_transact_items(Some(items))
+                .send()
+                .await;
+            match result {
+                std::result::Result::Ok(_) => return Ok(()),
+                Err(e) if attempt < REGISTER_ATTEMPTS && e.as_service_error().map(|e| e.is_transaction_canceled_exception()).unwrap_or(false) => {
+                    println!("Registering {} was cancelled (attempt {}), retrying: {:?}", entry.event_id, attempt, e);
+                    attempt += 1;
+                },
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    // put on condition that the stored entry is still previous: scores of a replaced entry are subtracted from the rollups
+    fn put_entry_item(&self, entry: &EmotionTableEntry, previous: Option<&EmotionTableEntry>) -> Result<TransactWriteItem> {
+        let mut put = Put::builder()
+            .table_name(&self.table_name)
+            .set_item(Some(to_item(entry)?));
+        let Some(previous) = previous else {
+            return Ok(TransactWriteItem::builder().put(put.condition_expression("attribute_not_exists(event_id)").build()?).build())
+        };
+
+        let mut conditions = vec!["attribute_exists(event_id)".to_owned()];
+        for (index, name) in taxonomy().names().enumerate() {
+            let score = previous.scores.get(name);
+            put = put
+                .expression_attribute_names(format!("#score{}", index), name)
+                .expression_attribute_values(format!(":score{}", index), AttributeValue::N(score.to_string()));
+            // a missing score reads as 0
+            conditions.push(match score == 0.0 {
+                true => format!("(attribute_not_exists(#score{}) OR #score{} = :score{})", index, index, index),
+                false => format!("#score{} = :score{}", index, index),
+            });
+        }
+        Ok(TransactWriteItem::builder().put(put.condition_expression(conditions.join(" AND ")).build()?).build())
+    }
+}
+
+
+// retention related
+impl DynamoService {
+
+    // removes the text and the rewrite of the entries of the date that still have them
+    pub async fn purge_text(&self, date: &str) -> Result<usize> {
+        let mut purged = 0;
+        for entry in self.query_date(date).await?.iter().filter(|e| e.has_text()) {
+            let result = self.client
+                .update_item()
+                .table_name(&self.table_name)
+                .key("event_id", AttributeValue::S(entry.event_id.to_owned()))
+                .update_expression("REMOVE #text, encrypted_text, rewrite, encrypted_rewrite, rewrite_scores, rewrite_model_id, rewrite_prompt_version, rewrite_scores_model_id, rewrite_scores_prompt_version SET text_purged = :purged")
+                // do not create an item for an entry deleted in the meantime
+                .condition_expression("attribute_exists(event_id)")
+                .expression_attribute_names("#text", "text")
+                .expression_attribute_values(":purged", AttributeValue::Bool(true))
+                .send()
+                .await;
+            match result {
+                std::result::Result::Ok(_) => purged += 1,
+                Err(e) if e.as_service_error().map(|e| e.is_conditional_check_failed_exception()).unwrap_or(false) => {},
+                Err(e) => return Err(e.into()),
+            }
+        }
+        println!("texts purged for {}: {}", date, purged);
+        Ok(purged)
+    }
+
+}
+
+
+// erasure related
+impl DynamoService {
+
+    // scans the whole table, there is no index on the target
+    pub async fn query_mentions(&self, user_id: &str) -> Result<Vec<EmotionTableEntry>> {
+        self.scan_filtered(
+            "#target.#user_id = :user_id",
+            HashMap::from([("#target".to_owned(), "target".to_owned()), ("#user_id".to_owned(), "user_id".to_owned())]),
+            HashMap::from([(":user_id".to_owned(), AttributeValue::S(user_id.to_owned()))]),
+        ).await
+    }
+
+    // scans the whole table. contains finds the prefix of the mention, mentions_in_text checks the rest
+    pub async fn query_text_mentions(&self, user_id: &str) -> Result<Vec<EmotionTableEntry>> {
+        let entries = self.scan_filtered(
+            "contains(#text, :mention) OR contains(#rewrite, :mention)",
+            HashMap::from([("#text".to_owned(), "text".to_owned()), ("#rewrite".to_owned(), "rewrite".to_owned())]),
+            HashMap::from([(":mention".to_owned(), AttributeValue::S(format!("<@{}", user_id)))]),
+        ).await?;
+        Ok(entries.into_iter().filter(|e| e.mentions_in_text(user_id)).collect())
+    }
+
+    pub async fn query_encrypted(&self) -> Result<Vec<EmotionTableEntry>> {
+        self.scan_filtered("attribute_exists(encrypted_text) OR attribute_exists(encrypted_rewrite)", HashMap::new(), HashMap::new()).await
+    }
+
+    async fn scan_filtered(&self, filter: &str, attribute_names: HashMap<String, String>, attribute_values: HashMap<String, AttributeValue>) -> Result<Vec<EmotionTableEntry>> {
+        let mut entries = vec![];
+        let mut exclusive_start_key = None;
+        loop {
+            let output = self.client
+                .scan()
+                .table_name(&self.table_name)
+                .filter_expression(filter)
+                // dynamo rejects empty maps
+                .set_expression_attribute_names(Some(attribute_names.clone()).filter(|n| !n.is_empty()))
+                .set_expression_attribute_values(Some(attribute_values.clone()).filter(|v| !v.is_empty()))
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await?;
+            let mut page: Vec<EmotionTableEntry> = from_items(output.items.unwrap_or_default())?;
+            entries.append(&mut page);
+            exclusive_start_key = output.last_evaluated_key;
+            if exclusive_start_key.is_none() {
+                break
+            }
+        }
+        Ok(entries)
+    }
+
+    pub async fn replace_entry(&self, entry: &EmotionTableEntry) -> Result<()> {
+        self.client
             .put_item()
-            .table_name(table_name)
-            .set_item(Some(to_item(&entry)?))
+            .table_name(&self.table_name)
+            .set_item(Some(to_item(entry)?))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_entry(&self, event_id: &str) -> Result<()> {
+        self.client
+            .delete_item()
+            .table_name(&self.table_name)
+            .key("event_id", AttributeValue::S(event_id.to_owned()))
             .send()
             .await?;
-        Ok(entry)
+        Ok(())
+    }
+
+    // generations is a map attribute: the generation is set in it, or the map is created with it if there is none yet
+    pub async fn put_generation(&self, event_id: &str, generation_id: &str, generation: &ScoringGeneration) -> Result<()> {
+        let value = AttributeValue::M(to_item(generation)?);
+        for _ in 0..2 {
+            let result = self.client
+                .update_item()
+                .table_name(&self.table_name)
+                .key("event_id", AttributeValue::S(event_id.to_owned()))
+                .update_expression("SET #generations.#id = :generation")
+                .condition_expression("attribute_exists(#generations)")
+                .expression_attribute_names("#generations", "generations")
+                .expression_attribute_names("#id", generation_id)
+                .expression_attribute_values(":generation", value.clone())
+                .send()
+                .await;
+            match result {
+                std::result::Result::Ok(_) => return Ok(()),
+                Err(e) if e.as_service_error().map(|e| e.is_conditional_check_failed_exception()).unwrap_or(false) => {},
+                Err(e) => return Err(e.into()),
+            }
+
+            let result = self.client
+                .update_item()
+                .table_name(&self.table_name)
+                .key("event_id", AttributeValue::S(event_id.to_owned()))
+                .update_expression("SET #generations = :generations")
+                .condition_expression("attribute_exists(event_id) AND attribute_not_exists(#generations)")
+                .expression_attribute_names("#generations", "generations")
+                .expression_attribute_values(":generations", AttributeValue::M(HashMap::from([(generation_id.to_owned(), value.clone())])))
+                .send()
+                .await;
+            match result {
+                std::result::Result::Ok(_) => return Ok(()),
+                // the map was created in the meantime, or the entry does not exist
+                Err(e) if e.as_service_error().map(|e| e.is_conditional_check_failed_exception()).unwrap_or(false) => {},
+                Err(e) => return Err(e.into()),
+            }
+        }
+        bail!("entry {} not found", event_id)
+    }
+
+    pub async fn delete_user_rollups(&self, user_id: &str) -> Result<usize> {
+        if !self.rollups.is_enabled() {
+            return Ok(0)
+        }
+        self.rollups.delete_all(&format!("{}#{}", RollupScope::User.code(), user_id)).await
+    }
+
+}
+
+
+// rollup related
+impl DynamoService {
+
+    pub fn workspace_id(&self) -> &str {
+        self.rollups.workspace_id()
+    }
+
+    pub async fn query_rollups(&self, query: &RollupQuery) -> Result<Vec<Rollup>> {
+        if !self.rollups.is_enabled() {
+            bail!("{} is not set", ROLLUP_TABLE_NAME);
+        }
+        self.rollups.query(query).await
+    }
+
+    pub async fn query_period_rollups(&self, scope: RollupScope, granularity: Granularity, period_start: &str) -> Result<Vec<Rollup>> {
+        if !self.rollups.is_enabled() {
+            bail!("{} is not set", ROLLUP_TABLE_NAME);
+        }
+        self.rollups.query_period(scope, granularity, period_start).await
+    }
+
+    // recomputes the rollups of the periods within from and to (YYYY-MM-DD), widened to whole weeks and months
+    pub async fn rebuild_rollups(&self, from: &str, to: &str) -> Result<RebuildReport> {
+        if !self.rollups.is_enabled() {
+            bail!("{} is not set", ROLLUP_TABLE_NAME);
+        }
+        let (start, end) = rebuild_range(from, to)?;
+        let mut entries = vec![];
+        for date in dates_between(start, end) {
+            entries.append(&mut self.query_date(&date).await?);
+        }
+
+        let mut rebuilt = HashSet::new();
+        for rollup in compute_rollups(&entries, self.rollups.workspace_id())? {
+            if is_within(&rollup, start, end)? {
+                self.rollups.put(&rollup).await?;
+                rebuilt.insert((rollup.rollup_id, rollup.period));
+            }
+        }
+        // ie: the last message of a user in the period was deleted
+        let deleted = self.rollups.delete_stale(start, end, &rebuilt).await?;
+        Ok(RebuildReport {
+            from: start.to_string(),
+            to: end.to_string(),
+            entries: entries.len(),
+            rollups: rebuilt.len(),
+            deleted,
+        })
     }
 
 }
@@ -82,16 +494,31 @@ impl DynamoService {
 // data export related
 impl DynamoService {
 
-    pub async fn export_data(&self, table_arn: &str, bucket: &str) -> Result<()> {
+    // returns the export arn
+    pub async fn export_data(&self, bucket: &str) -> Result<String> {
         let response = self.client.export_table_to_point_in_time()
-            .table_arn(table_arn)
+            .table_arn(&self.table_arn)
             .s3_bucket(bucket)
             .export_format(aws_sdk_dynamodb::types::ExportFormat::DynamodbJson)
             .send()
             .await?;
 
         println!("response: {:?}", response);
-        Ok(())
+        response.export_description
+            .and_then(|d| d.export_arn)
+            .context("export arn not available")
     }
 
-}
\ No newline at end of file
+}
+
+
+// the projection expression of the scores only attributes, aliased in attribute_names
+fn scores_only_projection(attribute_names: &mut HashMap<String, String>) -> Vec<String> {
+    let attributes = SCORES_ONLY_ATTRIBUTES.iter().copied().chain(taxonomy().names());
+    attributes.enumerate()
+        .map(|(index, attribute)| {
+            attribute_names.insert(format!("#projection{}", index), attribute.to_owned());
+            format!("#projection{}", index)
+        })
+        .collect()
+}
@@ -1,9 +1,26 @@
-use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-use crate::{service::{common_structs::EmotionScores, line_service::MessageEventRequest}, utilities::get_date_month};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
+use crate::{language::Language, rescoring::ScoringGeneration, schema::{upgrade, CURRENT_SCHEMA_VERSION}, service::{common_structs::{EmotionScores, NegativityTarget, ScoringResult, ToxicityScores}, encryption::EncryptedText, line_service::MessageEventRequest}, utilities::get_date_month};
+
+// attributes kept by a scores only projection, on top of the emotion scores.
+// everything but the text, and the rewrite that derives from it, in the clear or encrypted.
+pub const SCORES_ONLY_ATTRIBUTES: &[&str] = &[
+    "schema_version", "event_id", "user_id", "timestamp", "date", "month", "channel_id", "channel_type", "message_ts", "thread_ts",
+    "text_redacted", "language", "model_id", "prompt_version", "from_cache", "std_dev", "samples", "target", "toxicity", "generations",
+    "text_purged", "ttl",
+];
+
+
+// Read in any schema version and upgraded to the current one, see schema::upgrade.
+// serde(remote = "Self") derives inherent (de)serialize functions, the trait impls below wrap them.
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(remote = "Self")]
 pub struct EmotionTableEntry {
+    // 0 for entries written before it was added
+    #[serde(default)]
+    pub schema_version: u32,
     pub event_id: String,
     pub user_id: String,
     pub timestamp: u64,
@@ -12,18 +29,98 @@ pub struct EmotionTableEntry {
 
     pub channel_id: String,
     pub channel_type: String, // channel, im
+    // ts of the slack message and of the thread it replies to, none for entries written before they were kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_ts: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread_ts: Option<String>,
+    // empty when projected out, see scores_only
+    #[serde(default)]
     pub text: String,
+    // the text when encryption is on, text is then empty. see TextCipher
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted_text: Option<EncryptedText>,
+    // personal information in text was replaced with placeholders, see redaction
+    #[serde(default)]
+    pub text_redacted: bool,
+    #[serde(default)]
+    pub language: Language,
 
     #[serde(flatten)]
-    pub scores: EmotionScores
+    pub scores: EmotionScores,
+    // model (or scorer) that produced the scores, none for entries registered before it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_id: Option<String>,
+    // {name}/{version} of the prompt that produced the scores
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+    // scores reused from the score cache, rather than produced for this message
+    #[serde(default)]
+    pub from_cache: bool,
+    // standard deviation per emotion and number of samples, when scored from several samples
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub std_dev: Option<EmotionScores>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub samples: Option<u32>,
+    // what the negative emotion is aimed at, with the user id when it is a mentioned person
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<NegativityTarget>,
+
+    // calmer rewrite suggested when a warning fires, with the scores of the rewrite.
+    // mentions stay placeholders (@user1), see slack_markup::NormalizedText
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rewrite: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted_rewrite: Option<EncryptedText>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rewrite_scores: Option<EmotionScores>,
+    // model and {name}/{version} prompt that wrote the rewrite, then the ones that scored it, see rewrite_scored_alike
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rewrite_model_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rewrite_prompt_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rewrite_scores_model_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rewrite_scores_prompt_version: Option<String>,
+
+    // none if the toxicity classifier is disabled or failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toxicity: Option<ToxicityScores>,
+
+    // generation id -> scores by another model or prompt, see rescoring
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub generations: BTreeMap<String, ScoringGeneration>,
+
+    // the text and the rewrite were dropped by the retention policy, the scores are kept
+    #[serde(default)]
+    pub text_purged: bool,
+    // epoch seconds, dynamo TTL attribute. none keeps the entry forever
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u64>,
+}
+
+impl Serialize for EmotionTableEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        EmotionTableEntry::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for EmotionTableEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entry = EmotionTableEntry::deserialize(deserializer)?;
+        upgrade(entry).map_err(de::Error::custom)
+    }
 }
 
 impl EmotionTableEntry {
-    pub fn new(message_request: &MessageEventRequest, scores: &EmotionScores) -> anyhow::Result<Self> {
+    // language: detected once by the caller, the same one the message was scored in
+    pub fn new(message_request: &MessageEventRequest, scoring: &ScoringResult, language: Language) -> anyhow::Result<Self> {
         let message_event = message_request.to_owned().event;
         let (date, month) = get_date_month(message_request.event_time)?;
         Ok(
             Self {
+                schema_version: CURRENT_SCHEMA_VERSION,
                 event_id: message_request.event_id.to_owned(),
                 user_id: message_event.user,
                 timestamp: message_request.event_time,
@@ -31,9 +128,99 @@ impl EmotionTableEntry {
                 month,
                 channel_id: message_event.channel,
                 channel_type: message_event.channel_type,
+                message_ts: Some(message_event.event_ts),
+                thread_ts: message_event.thread_ts,
+                language,
                 text: message_event.text,
-                scores: scores.to_owned()
+                encrypted_text: None,
+                text_redacted: false,
+                scores: scoring.scores.to_owned(),
+                model_id: Some(scoring.model_id.to_owned()),
+                prompt_version: scoring.prompt_version.to_owned(),
+                from_cache: scoring.from_cache,
+                std_dev: scoring.std_dev.to_owned(),
+                samples: scoring.samples,
+                target: scoring.target.to_owned(),
+                rewrite: None,
+                encrypted_rewrite: None,
+                rewrite_scores: None,
+                rewrite_model_id: None,
+                rewrite_prompt_version: None,
+                rewrite_scores_model_id: None,
+                rewrite_scores_prompt_version: None,
+                toxicity: None,
+                generations: BTreeMap::new(),
+                text_purged: false,
+                ttl: None,
             }
         )
     }
-}
\ No newline at end of file
+
+    // the entry as read with the scores only projection
+    pub fn scores_only(self) -> Self {
+        Self {
+            text: "".to_owned(),
+            encrypted_text: None,
+            rewrite: None,
+            encrypted_rewrite: None,
+            rewrite_scores: None,
+            rewrite_model_id: None,
+            rewrite_prompt_version: None,
+            rewrite_scores_model_id: None,
+            rewrite_scores_prompt_version: None,
+            ..self
+        }
+    }
+
+    // the entry once its text is past retention
+    pub fn text_purged(self) -> Self {
+        Self {
+            text_purged: true,
+            ..self.scores_only()
+        }
+    }
+
+    // the text or the rewrite is still stored
+    pub fn has_text(&self) -> bool {
+        !self.text.is_empty() || self.rewrite.is_some() || self.rewrite_scores.is_some() || self.is_encrypted()
+    }
+
+    // the text or the rewrite is stored encrypted, see TextCipher::open
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted_text.is_some() || self.encrypted_rewrite.is_some()
+    }
+
+    // the rewrite was scored by the model and prompt that scored the message, so that their scores compare.
+    // false if the scorer fell back to another model (or the lexicon) for either of them
+    pub fn rewrite_scored_alike(&self) -> bool {
+        self.rewrite_scores.is_some()
+            && self.rewrite_scores_model_id.is_some()
+            && self.rewrite_scores_model_id == self.model_id
+            && self.rewrite_scores_prompt_version == self.prompt_version
+    }
+
+    // the negative emotion is aimed at the user
+    pub fn is_aimed_at(&self, user_id: &str) -> bool {
+        self.target.as_ref().and_then(|t| t.user_id.as_deref()) == Some(user_id)
+    }
+
+    // the plain text or rewrite mentions the user, ie: <@U0123> or <@U0123|name>
+    pub fn mentions_in_text(&self, user_id: &str) -> bool {
+        let mention = format!("<@{}", user_id);
+        let mentions = |text: &str| text.match_indices(&mention).any(|(i, _)| matches!(text[i + mention.len()..].chars().next(), Some('>' | '|')));
+        mentions(&self.text) || self.rewrite.as_deref().map(mentions).unwrap_or(false)
+    }
+
+    pub fn is_person_directed(&self) -> bool {
+        self.target.as_ref().map(|t| t.is_person()).unwrap_or(false)
+    }
+
+    // the samples disagree too much on the emotion for its score to be relied on.
+    // entries scored from a single sample are never uncertain, there is nothing to compare.
+    pub fn is_uncertain(&self, emotion: &str, max_std_dev: Option<f64>) -> bool {
+        match (&self.std_dev, max_std_dev) {
+            (Some(std_dev), Some(max_std_dev)) => std_dev.get(emotion) > max_std_dev,
+            _ => false,
+        }
+    }
+}
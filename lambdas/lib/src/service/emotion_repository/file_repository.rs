@@ -0,0 +1,209 @@
+use std::{collections::HashMap, fs::{self, OpenOptions}, io::Write, path::{Path, PathBuf}, sync::{Arc, Mutex, MutexGuard}};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+
+use crate::{rescoring::ScoringGeneration, rollup::{compute_rollups, rebuild_range, Granularity, RebuildReport, Rollup, RollupQuery, RollupScope}, service::{dynamo_service::structs::EmotionTableEntry, usage_service::DEFAULT_WORKSPACE_ID}};
+use super::{channel_page_of, rebuild_report_of, sort_entries, user_page_of, ChannelQuery, EmotionRepository, EntryPage, UserQuery, FILE_REPOSITORY};
+
+
+// Appends entries as json lines to a local file, for local development without AWS.
+// A later line replaces an earlier one with the same event_id.
+// Uses blocking file io, it is not meant for the lambdas.
+// Rollups are computed from the entries when queried.
+#[derive(Debug, Clone)]
+pub struct FileRepository {
+    path: PathBuf,
+    workspace_id: String,
+    // appends from the same process do not interleave
+    lock: Arc<Mutex<()>>,
+}
+
+impl FileRepository {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+            workspace_id: DEFAULT_WORKSPACE_ID.to_owned(),
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub fn with_workspace_id(mut self, workspace_id: &str) -> Self {
+        self.workspace_id = workspace_id.to_owned();
+        self
+    }
+
+    // held while the file is read or written
+    fn guard(&self) -> Result<MutexGuard<'_, ()>> {
+        self.lock.lock().map_err(|e| anyhow!("file repository lock poisoned: {}", e))
+    }
+
+    fn read_all(&self) -> Result<Vec<EmotionTableEntry>> {
+        if !self.path.exists() {
+            return Ok(vec![])
+        }
+        let content = fs::read_to_string(&self.path).context(format!("Error reading {:?}", self.path))?;
+        let mut entries: HashMap<String, EmotionTableEntry> = HashMap::new();
+        for (index, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let entry: EmotionTableEntry = serde_json::from_str(line).context(format!("Error parsing line {} of {:?}", index + 1, self.path))?;
+            entries.insert(entry.event_id.to_owned(), entry);
+        }
+        Ok(entries.into_values().collect())
+    }
+
+    // replaces the whole file, through a temporary file so that a failed write loses nothing
+    fn write_all(&self, entries: &[EmotionTableEntry]) -> Result<()> {
+        let lines = entries.iter()
+            .map(serde_json::to_string)
+            .collect::<Result<Vec<String>, _>>()?;
+        let temporary = self.path.with_extension("tmp");
+        fs::write(&temporary, lines.iter().map(|line| format!("{}\n", line)).collect::<String>())?;
+        fs::rename(&temporary, &self.path).context(format!("Error replacing {:?}", self.path))?;
+        Ok(())
+    }
+
+    fn filtered(&self, predicate: impl Fn(&EmotionTableEntry) -> bool) -> Result<Vec<EmotionTableEntry>> {
+        let _guard = self.guard()?;
+        let mut filtered: Vec<EmotionTableEntry> = self.read_all()?.into_iter().filter(|e| predicate(e)).collect();
+        sort_entries(&mut filtered);
+        Ok(filtered)
+    }
+}
+
+#[async_trait]
+impl EmotionRepository for FileRepository {
+    fn name(&self) -> &str {
+        FILE_REPOSITORY
+    }
+
+    async fn register_entry(&self, entry: &EmotionTableEntry) -> Result<()> {
+        let _guard = self.guard()?;
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+
+    async fn get_entry(&self, event_id: &str) -> Result<Option<EmotionTableEntry>> {
+        Ok(self.filtered(|e| e.event_id == event_id)?.pop())
+    }
+
+    async fn query_date(&self, date: &str) -> Result<Vec<EmotionTableEntry>> {
+        self.filtered(|e| e.date == date)
+    }
+
+    async fn query_date_scores_only(&self, date: &str) -> Result<Vec<EmotionTableEntry>> {
+        Ok(self.filtered(|e| e.date == date)?.into_iter().map(|e| e.scores_only()).collect())
+    }
+
+    async fn query_user_page(&self, query: &UserQuery, cursor: Option<&str>) -> Result<EntryPage> {
+        user_page_of(self.filtered(|e| e.user_id == query.user_id)?, query, cursor)
+    }
+
+    async fn query_channel_page(&self, query: &ChannelQuery, cursor: Option<&str>) -> Result<EntryPage> {
+        channel_page_of(self.filtered(|e| e.channel_id == query.channel_id)?, query, cursor)
+    }
+
+    // writes {destination}/{export id}.jsonl with one line per entry
+    async fn start_export(&self, destination: &str) -> Result<String> {
+        let entries = self.filtered(|_| true)?;
+        let export_id = format!("export-{}", uuid::Uuid::new_v4());
+        fs::create_dir_all(destination)?;
+        let lines = entries.iter()
+            .map(serde_json::to_string)
+            .collect::<Result<Vec<String>, _>>()?;
+        fs::write(Path::new(destination).join(format!("{}.jsonl", export_id)), lines.join("\n") + "\n")?;
+        Ok(export_id)
+    }
+
+    async fn query_rollups(&self, query: &RollupQuery) -> Result<Vec<Rollup>> {
+        let rollups = compute_rollups(&self.filtered(|_| true)?, &self.workspace_id)?;
+        Ok(rollups.into_iter().filter(|r| query.matches(r)).collect())
+    }
+
+    async fn query_period_rollups(&self, scope: RollupScope, granularity: Granularity, period_start: &str) -> Result<Vec<Rollup>> {
+        let rollups = compute_rollups(&self.filtered(|_| true)?, &self.workspace_id)?;
+        Ok(rollups.into_iter().filter(|r| r.scope == scope && r.granularity == granularity && r.period_start == period_start).collect())
+    }
+
+    // nothing is stored, reports what a rebuild would read
+    async fn rebuild_rollups(&self, from: &str, to: &str) -> Result<RebuildReport> {
+        let (start, end) = rebuild_range(from, to)?;
+        rebuild_report_of(self.filtered(|_| true)?, start, end, &self.workspace_id)
+    }
+
+    // rewrites the file, the purged text must not stay in earlier lines
+    async fn purge_text(&self, date: &str) -> Result<usize> {
+        let _guard = self.guard()?;
+        let mut entries = self.read_all()?;
+        let mut purged = 0;
+        for entry in entries.iter_mut().filter(|e| e.date == date && e.has_text()) {
+            *entry = entry.to_owned().text_purged();
+            purged += 1;
+        }
+        if purged > 0 {
+            sort_entries(&mut entries);
+            self.write_all(&entries)?;
+        }
+        Ok(purged)
+    }
+
+    async fn delete_expired(&self, now: u64) -> Result<Option<usize>> {
+        let _guard = self.guard()?;
+        let mut entries = self.read_all()?;
+        let count = entries.len();
+        entries.retain(|e| e.ttl.map(|ttl| ttl > now).unwrap_or(true));
+        let deleted = count - entries.len();
+        if deleted > 0 {
+            sort_entries(&mut entries);
+            self.write_all(&entries)?;
+        }
+        Ok(Some(deleted))
+    }
+
+    async fn query_mentions(&self, user_id: &str) -> Result<Vec<EmotionTableEntry>> {
+        self.filtered(|e| e.is_aimed_at(user_id))
+    }
+
+    async fn query_text_mentions(&self, user_id: &str) -> Result<Vec<EmotionTableEntry>> {
+        self.filtered(|e| e.mentions_in_text(user_id))
+    }
+
+    async fn query_encrypted(&self) -> Result<Vec<EmotionTableEntry>> {
+        self.filtered(|e| e.is_encrypted())
+    }
+
+    // rewrites the file, the replaced lines must not stay behind
+    async fn replace_entry(&self, entry: &EmotionTableEntry) -> Result<()> {
+        let _guard = self.guard()?;
+        let mut entries: Vec<EmotionTableEntry> = self.read_all()?.into_iter().filter(|e| e.event_id != entry.event_id).collect();
+        entries.push(entry.to_owned());
+        sort_entries(&mut entries);
+        self.write_all(&entries)
+    }
+
+    async fn delete_entry(&self, event_id: &str) -> Result<()> {
+        let _guard = self.guard()?;
+        let mut entries = self.read_all()?;
+        let count = entries.len();
+        entries.retain(|e| e.event_id != event_id);
+        if entries.len() < count {
+            sort_entries(&mut entries);
+            self.write_all(&entries)?;
+        }
+        Ok(())
+    }
+
+    async fn delete_user_rollups(&self, _user_id: &str) -> Result<usize> {
+        Ok(0)
+    }
+
+    async fn put_generation(&self, event_id: &str, generation_id: &str, generation: &ScoringGeneration) -> Result<()> {
+        let _guard = self.guard()?;
+        let mut entries = self.read_all()?;
+        let entry = entries.iter_mut().find(|e| e.event_id == event_id).context(format!("entry {} not found", event_id))?;
+        entry.generations.insert(generation_id.to_owned(), generation.to_owned());
+        self.write_all(&entries)
+    }
+}
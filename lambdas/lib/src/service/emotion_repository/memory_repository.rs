@@ -0,0 +1,159 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+
+use crate::{rescoring::ScoringGeneration, rollup::{compute_rollups, rebuild_range, Granularity, RebuildReport, Rollup, RollupQuery, RollupScope}, service::{dynamo_service::structs::EmotionTableEntry, usage_service::DEFAULT_WORKSPACE_ID}};
+use super::{channel_page_of, rebuild_report_of, sort_entries, user_page_of, ChannelQuery, EmotionRepository, EntryPage, UserQuery, MEMORY_REPOSITORY};
+
+
+// Keeps entries in process, for tests and local development.
+// Clones share the same entries.
+// Rollups are computed from the entries when queried.
+#[derive(Debug, Clone)]
+pub struct MemoryRepository {
+    workspace_id: String,
+    // event_id -> entry
+    entries: Arc<Mutex<HashMap<String, EmotionTableEntry>>>,
+    // export id -> entries at the time of the export
+    exports: Arc<Mutex<HashMap<String, Vec<EmotionTableEntry>>>>,
+}
+
+impl Default for MemoryRepository {
+    fn default() -> Self {
+        Self {
+            workspace_id: DEFAULT_WORKSPACE_ID.to_owned(),
+            entries: Arc::default(),
+            exports: Arc::default(),
+        }
+    }
+}
+
+impl MemoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_workspace_id(mut self, workspace_id: &str) -> Self {
+        self.workspace_id = workspace_id.to_owned();
+        self
+    }
+
+    pub fn export(&self, export_id: &str) -> Option<Vec<EmotionTableEntry>> {
+        self.exports.lock().ok()?.get(export_id).cloned()
+    }
+
+    fn filtered(&self, predicate: impl Fn(&EmotionTableEntry) -> bool) -> Result<Vec<EmotionTableEntry>> {
+        let entries = self.entries.lock().map_err(|e| anyhow!("memory repository lock poisoned: {}", e))?;
+        let mut filtered: Vec<EmotionTableEntry> = entries.values().filter(|e| predicate(e)).cloned().collect();
+        sort_entries(&mut filtered);
+        Ok(filtered)
+    }
+}
+
+#[async_trait]
+impl EmotionRepository for MemoryRepository {
+    fn name(&self) -> &str {
+        MEMORY_REPOSITORY
+    }
+
+    async fn register_entry(&self, entry: &EmotionTableEntry) -> Result<()> {
+        let mut entries = self.entries.lock().map_err(|e| anyhow!("memory repository lock poisoned: {}", e))?;
+        entries.insert(entry.event_id.to_owned(), entry.to_owned());
+        Ok(())
+    }
+
+    async fn get_entry(&self, event_id: &str) -> Result<Option<EmotionTableEntry>> {
+        let entries = self.entries.lock().map_err(|e| anyhow!("memory repository lock poisoned: {}", e))?;
+        Ok(entries.get(event_id).cloned())
+    }
+
+    async fn query_date(&self, date: &str) -> Result<Vec<EmotionTableEntry>> {
+        self.filtered(|e| e.date == date)
+    }
+
+    async fn query_date_scores_only(&self, date: &str) -> Result<Vec<EmotionTableEntry>> {
+        Ok(self.filtered(|e| e.date == date)?.into_iter().map(|e| e.scores_only()).collect())
+    }
+
+    async fn query_user_page(&self, query: &UserQuery, cursor: Option<&str>) -> Result<EntryPage> {
+        user_page_of(self.filtered(|e| e.user_id == query.user_id)?, query, cursor)
+    }
+
+    async fn query_channel_page(&self, query: &ChannelQuery, cursor: Option<&str>) -> Result<EntryPage> {
+        channel_page_of(self.filtered(|e| e.channel_id == query.channel_id)?, query, cursor)
+    }
+
+    async fn start_export(&self, _destination: &str) -> Result<String> {
+        let snapshot = self.filtered(|_| true)?;
+        let mut exports = self.exports.lock().map_err(|e| anyhow!("memory repository lock poisoned: {}", e))?;
+        let export_id = format!("export-{}", exports.len() + 1);
+        exports.insert(export_id.to_owned(), snapshot);
+        Ok(export_id)
+    }
+
+    async fn query_rollups(&self, query: &RollupQuery) -> Result<Vec<Rollup>> {
+        let rollups = compute_rollups(&self.filtered(|_| true)?, &self.workspace_id)?;
+        Ok(rollups.into_iter().filter(|r| query.matches(r)).collect())
+    }
+
+    async fn query_period_rollups(&self, scope: RollupScope, granularity: Granularity, period_start: &str) -> Result<Vec<Rollup>> {
+        let rollups = compute_rollups(&self.filtered(|_| true)?, &self.workspace_id)?;
+        Ok(rollups.into_iter().filter(|r| r.scope == scope && r.granularity == granularity && r.period_start == period_start).collect())
+    }
+
+    // nothing is stored, reports what a rebuild would read
+    async fn rebuild_rollups(&self, from: &str, to: &str) -> Result<RebuildReport> {
+        let (start, end) = rebuild_range(from, to)?;
+        rebuild_report_of(self.filtered(|_| true)?, start, end, &self.workspace_id)
+    }
+
+    async fn purge_text(&self, date: &str) -> Result<usize> {
+        let mut entries = self.entries.lock().map_err(|e| anyhow!("memory repository lock poisoned: {}", e))?;
+        let mut purged = 0;
+        for entry in entries.values_mut().filter(|e| e.date == date && e.has_text()) {
+            *entry = entry.to_owned().text_purged();
+            purged += 1;
+        }
+        Ok(purged)
+    }
+
+    async fn delete_expired(&self, now: u64) -> Result<Option<usize>> {
+        let mut entries = self.entries.lock().map_err(|e| anyhow!("memory repository lock poisoned: {}", e))?;
+        let count = entries.len();
+        entries.retain(|_, e| e.ttl.map(|ttl| ttl > now).unwrap_or(true));
+        Ok(Some(count - entries.len()))
+    }
+
+    async fn query_mentions(&self, user_id: &str) -> Result<Vec<EmotionTableEntry>> {
+        self.filtered(|e| e.is_aimed_at(user_id))
+    }
+
+    async fn query_text_mentions(&self, user_id: &str) -> Result<Vec<EmotionTableEntry>> {
+        self.filtered(|e| e.mentions_in_text(user_id))
+    }
+
+    async fn query_encrypted(&self) -> Result<Vec<EmotionTableEntry>> {
+        self.filtered(|e| e.is_encrypted())
+    }
+
+    async fn replace_entry(&self, entry: &EmotionTableEntry) -> Result<()> {
+        self.register_entry(entry).await
+    }
+
+    async fn delete_entry(&self, event_id: &str) -> Result<()> {
+        let mut entries = self.entries.lock().map_err(|e| anyhow!("memory repository lock poisoned: {}", e))?;
+        entries.remove(event_id);
+        Ok(())
+    }
+
+    async fn delete_user_rollups(&self, _user_id: &str) -> Result<usize> {
+        Ok(0)
+    }
+
+    async fn put_generation(&self, event_id: &str, generation_id: &str, generation: &ScoringGeneration) -> Result<()> {
+        let mut entries = self.entries.lock().map_err(|e| anyhow!("memory repository lock poisoned: {}", e))?;
+        let entry = entries.get_mut(event_id).context(format!("entry {} not found", event_id))?;
+        entry.generations.insert(generation_id.to_owned(), generation.to_owned());
+        Ok(())
+    }
+}
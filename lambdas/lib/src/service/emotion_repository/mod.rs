@@ -0,0 +1,407 @@
+pub mod memory_repository;
+pub mod file_repository;
+
+use std::{fmt::Debug, sync::Arc};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use file_repository::FileRepository;
+use futures::{stream::{self, BoxStream}, StreamExt, TryStreamExt};
+use memory_repository::MemoryRepository;
+
+use chrono::NaiveDate;
+use crate::{env_keys::{EMOTION_REPOSITORY, EMOTION_REPOSITORY_FILE}, rescoring::ScoringGeneration, rollup::{compute_rollups, dates_between, is_within, Granularity, RebuildReport, Rollup, RollupQuery, RollupScope}, utilities::get_previous_weekday};
+use super::dynamo_service::{structs::EmotionTableEntry, DynamoService};
+
+pub const DYNAMO_REPOSITORY: &str = "dynamo";
+pub const MEMORY_REPOSITORY: &str = "memory";
+pub const FILE_REPOSITORY: &str = "file";
+
+const DEFAULT_REPOSITORY_FILE: &str = "emotion_entries.jsonl";
+const DEFAULT_PAGE_SIZE: usize = 100;
+
+
+// entries of a user with from <= timestamp < to, oldest first
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserQuery {
+    pub user_id: String,
+    pub from: u64,
+    pub to: u64,
+    // (emotion, score): only entries scoring above the score for every emotion listed
+    pub scores_above: Vec<(String, f64)>,
+    // leave out the text, see EmotionTableEntry::scores_only
+    pub scores_only: bool,
+    // the most entries read per page, before the score filters
+    pub page_size: usize,
+}
+
+impl UserQuery {
+    pub fn new(user_id: &str, from: u64, to: u64) -> Self {
+        Self {
+            user_id: user_id.to_owned(),
+            from,
+            to,
+            scores_above: vec![],
+            scores_only: false,
+            page_size: DEFAULT_PAGE_SIZE,
+        }
+    }
+
+    // ie: with_score_above("anger", 0.6)
+    pub fn with_score_above(mut self, emotion: &str, score: f64) -> Self {
+        self.scores_above.push((emotion.to_owned(), score));
+        self
+    }
+
+    pub fn with_scores_only(mut self) -> Self {
+        self.scores_only = true;
+        self
+    }
+
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    pub fn matches(&self, entry: &EmotionTableEntry) -> bool {
+        entry.user_id == self.user_id
+            && entry.timestamp >= self.from
+            && entry.timestamp < self.to
+            && self.scores_above.iter().all(|(emotion, score)| entry.scores.get(emotion) > *score)
+    }
+}
+
+
+// entries of a channel with from <= timestamp < to, oldest first
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelQuery {
+    pub channel_id: String,
+    pub from: u64,
+    pub to: u64,
+    // leave out the text, see EmotionTableEntry::scores_only
+    pub scores_only: bool,
+    // the most entries read per page
+    pub page_size: usize,
+}
+
+impl ChannelQuery {
+    pub fn new(channel_id: &str, from: u64, to: u64) -> Self {
+        Self {
+            channel_id: channel_id.to_owned(),
+            from,
+            to,
+            scores_only: false,
+            page_size: DEFAULT_PAGE_SIZE,
+        }
+    }
+
+    pub fn with_scores_only(mut self) -> Self {
+        self.scores_only = true;
+        self
+    }
+
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    pub fn matches(&self, entry: &EmotionTableEntry) -> bool {
+        entry.channel_id == self.channel_id
+            && entry.timestamp >= self.from
+            && entry.timestamp < self.to
+    }
+}
+
+
+// a page of a user or a channel query
+#[derive(Debug, Clone)]
+pub struct EntryPage {
+    pub entries: Vec<EmotionTableEntry>,
+    // where the next page starts, none on the last page.
+    // with score filters a page can hold fewer entries than the page size, even none, keep going while this is some.
+    pub cursor: Option<String>,
+}
+
+
+// {timestamp}:{event_id} of the last entry read, the same for every repository
+pub fn entry_cursor(entry: &EmotionTableEntry) -> String {
+    format!("{}:{}", entry.timestamp, entry.event_id)
+}
+
+pub fn parse_entry_cursor(cursor: &str) -> Result<(u64, String)> {
+    let (timestamp, event_id) = cursor.split_once(':').context(format!("Invalid cursor {}", cursor))?;
+    Ok((timestamp.parse().context(format!("Invalid cursor {}", cursor))?, event_id.to_owned()))
+}
+
+
+// Where emotion table entries are stored.
+// Every implementation has to pass the conformance suite in lib/tests/emotion_repository.rs.
+#[async_trait]
+pub trait EmotionRepository: Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    // adds the entry, or replaces the one with the same event_id
+    async fn register_entry(&self, entry: &EmotionTableEntry) -> Result<()>;
+
+    // none if there is no entry with the event_id
+    async fn get_entry(&self, event_id: &str) -> Result<Option<EmotionTableEntry>>;
+
+    // entries of the date (YYYY-MM-DD, JST), oldest first
+    async fn query_date(&self, date: &str) -> Result<Vec<EmotionTableEntry>>;
+
+    // entries of the date without their text, see EmotionTableEntry::scores_only
+    async fn query_date_scores_only(&self, date: &str) -> Result<Vec<EmotionTableEntry>>;
+
+    // one page of the query, starting after the cursor of the previous page
+    async fn query_user_page(&self, query: &UserQuery, cursor: Option<&str>) -> Result<EntryPage>;
+
+    // one page of the channel query, the cursor is an entry_cursor as for users
+    async fn query_channel_page(&self, query: &ChannelQuery, cursor: Option<&str>) -> Result<EntryPage>;
+
+    // starts exporting every entry to destination (the bucket for dynamo, a directory for the file repository),
+    // returns the id of the export
+    async fn start_export(&self, destination: &str) -> Result<String>;
+
+    // rollups of the query, oldest period first, see rollup.rs
+    async fn query_rollups(&self, query: &RollupQuery) -> Result<Vec<Rollup>>;
+
+    // every rollup of the scope for the period (ie: of each user for the week starting period_start), in no order
+    async fn query_period_rollups(&self, scope: RollupScope, granularity: Granularity, period_start: &str) -> Result<Vec<Rollup>>;
+
+    // recomputes the rollups of from to to (YYYY-MM-DD) from the entries,
+    // widened so that every week and month touched is whole
+    async fn rebuild_rollups(&self, from: &str, to: &str) -> Result<RebuildReport>;
+
+    // drops the text and the rewrite of the entries of the date (YYYY-MM-DD, JST), keeping the scores.
+    // returns how many entries still had text.
+    async fn purge_text(&self, date: &str) -> Result<usize>;
+
+    // deletes entries whose ttl is before now (epoch seconds), returns how many.
+    // none if the store deletes them on its own through the ttl.
+    async fn delete_expired(&self, now: u64) -> Result<Option<usize>>;
+
+    // entries of other users whose negative emotion is aimed at the user, see NegativityTarget::user_id.
+    // reads the whole table for dynamo.
+    async fn query_mentions(&self, user_id: &str) -> Result<Vec<EmotionTableEntry>>;
+
+    // entries whose plain text or rewrite mentions the user, see EmotionTableEntry::mentions_in_text.
+    // encrypted text is not searched. reads the whole table for dynamo.
+    async fn query_text_mentions(&self, user_id: &str) -> Result<Vec<EmotionTableEntry>>;
+
+    // entries whose text or rewrite is encrypted, their mentions are only found once decrypted.
+    // reads the whole table for dynamo.
+    async fn query_encrypted(&self) -> Result<Vec<EmotionTableEntry>>;
+
+    // writes the entry as is, leaving the rollups alone. for erasure.
+    async fn replace_entry(&self, entry: &EmotionTableEntry) -> Result<()>;
+
+    // deletes the entry if it exists, leaving the rollups alone
+    async fn delete_entry(&self, event_id: &str) -> Result<()>;
+
+    // deletes the rollups of the user, returns how many. nothing to delete for rollups computed on read.
+    async fn delete_user_rollups(&self, user_id: &str) -> Result<usize>;
+
+    // stores the scores of a generation next to the original ones, replacing the generation with the same id.
+    // fails if there is no entry with the event_id
+    async fn put_generation(&self, event_id: &str, generation_id: &str, generation: &ScoringGeneration) -> Result<()>;
+
+    async fn query_yesterday(&self) -> Result<Vec<EmotionTableEntry>> {
+        self.query_date(&get_previous_weekday()?).await
+    }
+
+    // every page of the query, read as the stream is polled
+    fn user_pages<'a>(&'a self, query: &'a UserQuery) -> BoxStream<'a, Result<EntryPage>> {
+        // none once the last page is read
+        let start: Option<Option<String>> = Some(None);
+        stream::try_unfold(start, move |cursor| async move {
+            let Some(cursor) = cursor else {
+                return Ok(None)
+            };
+            let page = self.query_user_page(query, cursor.as_deref()).await?;
+            let next = page.cursor.clone().map(Some);
+            Ok(Some((page, next)))
+        }).boxed()
+    }
+
+    // every entry of the query at once, for ranges known to be small
+    async fn query_user(&self, query: &UserQuery) -> Result<Vec<EmotionTableEntry>> {
+        let pages: Vec<EntryPage> = self.user_pages(query).try_collect().await?;
+        Ok(pages.into_iter().flat_map(|page| page.entries).collect())
+    }
+
+    // every page of the channel query, read as the stream is polled
+    fn channel_pages<'a>(&'a self, query: &'a ChannelQuery) -> BoxStream<'a, Result<EntryPage>> {
+        let start: Option<Option<String>> = Some(None);
+        stream::try_unfold(start, move |cursor| async move {
+            let Some(cursor) = cursor else {
+                return Ok(None)
+            };
+            let page = self.query_channel_page(query, cursor.as_deref()).await?;
+            let next = page.cursor.clone().map(Some);
+            Ok(Some((page, next)))
+        }).boxed()
+    }
+
+    async fn query_channel(&self, query: &ChannelQuery) -> Result<Vec<EmotionTableEntry>> {
+        let pages: Vec<EntryPage> = self.channel_pages(query).try_collect().await?;
+        Ok(pages.into_iter().flat_map(|page| page.entries).collect())
+    }
+}
+
+
+#[async_trait]
+impl EmotionRepository for DynamoService {
+    fn name(&self) -> &str {
+        DYNAMO_REPOSITORY
+    }
+
+    async fn register_entry(&self, entry: &EmotionTableEntry) -> Result<()> {
+        DynamoService::register_entry(self, entry).await
+    }
+
+    async fn get_entry(&self, event_id: &str) -> Result<Option<EmotionTableEntry>> {
+        DynamoService::get_entry(self, event_id).await
+    }
+
+    async fn query_date(&self, date: &str) -> Result<Vec<EmotionTableEntry>> {
+        DynamoService::query_date(self, date).await
+    }
+
+    async fn query_date_scores_only(&self, date: &str) -> Result<Vec<EmotionTableEntry>> {
+        DynamoService::query_date_scores_only(self, date).await
+    }
+
+    async fn query_user_page(&self, query: &UserQuery, cursor: Option<&str>) -> Result<EntryPage> {
+        DynamoService::query_user_page(self, query, cursor).await
+    }
+
+    async fn query_channel_page(&self, query: &ChannelQuery, cursor: Option<&str>) -> Result<EntryPage> {
+        DynamoService::query_channel_page(self, query, cursor).await
+    }
+
+    async fn start_export(&self, destination: &str) -> Result<String> {
+        self.export_data(destination).await
+    }
+
+    async fn query_rollups(&self, query: &RollupQuery) -> Result<Vec<Rollup>> {
+        DynamoService::query_rollups(self, query).await
+    }
+
+    async fn query_period_rollups(&self, scope: RollupScope, granularity: Granularity, period_start: &str) -> Result<Vec<Rollup>> {
+        DynamoService::query_period_rollups(self, scope, granularity, period_start).await
+    }
+
+    async fn rebuild_rollups(&self, from: &str, to: &str) -> Result<RebuildReport> {
+        DynamoService::rebuild_rollups(self, from, to).await
+    }
+
+    async fn purge_text(&self, date: &str) -> Result<usize> {
+        DynamoService::purge_text(self, date).await
+    }
+
+    // dynamo deletes them through the ttl attribute of the table, within a few days of it
+    async fn delete_expired(&self, _now: u64) -> Result<Option<usize>> {
+        Ok(None)
+    }
+
+    async fn query_mentions(&self, user_id: &str) -> Result<Vec<EmotionTableEntry>> {
+        DynamoService::query_mentions(self, user_id).await
+    }
+
+    async fn query_text_mentions(&self, user_id: &str) -> Result<Vec<EmotionTableEntry>> {
+        DynamoService::query_text_mentions(self, user_id).await
+    }
+
+    async fn query_encrypted(&self) -> Result<Vec<EmotionTableEntry>> {
+        DynamoService::query_encrypted(self).await
+    }
+
+    async fn replace_entry(&self, entry: &EmotionTableEntry) -> Result<()> {
+        DynamoService::replace_entry(self, entry).await
+    }
+
+    async fn delete_entry(&self, event_id: &str) -> Result<()> {
+        DynamoService::delete_entry(self, event_id).await
+    }
+
+    async fn delete_user_rollups(&self, user_id: &str) -> Result<usize> {
+        DynamoService::delete_user_rollups(self, user_id).await
+    }
+
+    async fn put_generation(&self, event_id: &str, generation_id: &str, generation: &ScoringGeneration) -> Result<()> {
+        DynamoService::put_generation(self, event_id, generation_id, generation).await
+    }
+}
+
+
+// EMOTION_REPOSITORY: dynamo (default) | memory | file
+// EMOTION_REPOSITORY_FILE: the json lines file used by the file repository, for local development
+pub fn repository_from_env(dynamo: &DynamoService) -> Arc<dyn EmotionRepository> {
+    let repository = std::env::var(EMOTION_REPOSITORY).unwrap_or(DYNAMO_REPOSITORY.to_owned());
+    match repository.as_str() {
+        MEMORY_REPOSITORY => Arc::new(MemoryRepository::new().with_workspace_id(dynamo.workspace_id())),
+        FILE_REPOSITORY => {
+            let path = std::env::var(EMOTION_REPOSITORY_FILE).unwrap_or(DEFAULT_REPOSITORY_FILE.to_owned());
+            Arc::new(FileRepository::new(&path).with_workspace_id(dynamo.workspace_id()))
+        },
+        _ => Arc::new(dynamo.to_owned()),
+    }
+}
+
+
+// shared by the repositories that filter in process
+pub(crate) fn sort_entries(entries: &mut [EmotionTableEntry]) {
+    entries.sort_by(|e1, e2| e1.timestamp.cmp(&e2.timestamp).then_with(|| e1.event_id.cmp(&e2.event_id)));
+}
+
+// a page of the entries (sorted, of any user), like the dynamo query reads it
+pub(crate) fn user_page_of(entries: Vec<EmotionTableEntry>, query: &UserQuery, cursor: Option<&str>) -> Result<EntryPage> {
+    let in_range = |e: &EmotionTableEntry| e.user_id == query.user_id && e.timestamp >= query.from && e.timestamp < query.to;
+    page_of(entries, in_range, |e| query.matches(e), query.page_size, query.scores_only, cursor)
+}
+
+// a page of the entries (sorted, of any channel), like the dynamo query reads it
+pub(crate) fn channel_page_of(entries: Vec<EmotionTableEntry>, query: &ChannelQuery, cursor: Option<&str>) -> Result<EntryPage> {
+    page_of(entries, |e| query.matches(e), |_| true, query.page_size, query.scores_only, cursor)
+}
+
+// page_size entries of the key range after the cursor are read, then filtered
+fn page_of(entries: Vec<EmotionTableEntry>, in_range: impl Fn(&EmotionTableEntry) -> bool, matches: impl Fn(&EmotionTableEntry) -> bool, page_size: usize, scores_only: bool, cursor: Option<&str>) -> Result<EntryPage> {
+    let after = cursor.map(parse_entry_cursor).transpose()?;
+    let mut read: Vec<EmotionTableEntry> = entries.into_iter()
+        .filter(|e| in_range(e))
+        .filter(|e| after.as_ref().map(|(timestamp, event_id)| (e.timestamp, &e.event_id) > (*timestamp, event_id)).unwrap_or(true))
+        .take(page_size + 1)
+        .collect();
+
+    let cursor = match read.len() > page_size {
+        true => {
+            read.truncate(page_size);
+            read.last().map(entry_cursor)
+        },
+        false => None,
+    };
+    let entries = read.into_iter()
+        .filter(|e| matches(e))
+        .map(|e| if scores_only { e.scores_only() } else { e })
+        .collect();
+    Ok(EntryPage { entries, cursor })
+}
+
+// what a rebuild of start to end would write, for the repositories that compute rollups on read
+pub(crate) fn rebuild_report_of(entries: Vec<EmotionTableEntry>, start: NaiveDate, end: NaiveDate, workspace_id: &str) -> Result<RebuildReport> {
+    let dates = dates_between(start, end);
+    let entries: Vec<EmotionTableEntry> = entries.into_iter().filter(|e| dates.contains(&e.date)).collect();
+    let mut rollups = 0;
+    for rollup in compute_rollups(&entries, workspace_id)? {
+        if is_within(&rollup, start, end)? {
+            rollups += 1;
+        }
+    }
+    Ok(RebuildReport {
+        from: start.to_string(),
+        to: end.to_string(),
+        entries: entries.len(),
+        rollups,
+        deleted: 0,
+    })
+}
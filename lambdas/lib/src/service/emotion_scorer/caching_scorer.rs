@@ -0,0 +1,67 @@
+use std::sync::Arc;
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::{language::Language, service::{common_structs::ScoringResult, score_cache_service::ScoreCacheService}};
+use super::EmotionScorer;
+
+
+// Looks up the score cache before calling the inner scorer.
+// Results from a fallback model are not cached, since they are keyed under the configured model.
+#[derive(Debug, Clone)]
+pub struct CachingScorer {
+    inner: Arc<dyn EmotionScorer>,
+    cache: ScoreCacheService,
+}
+
+impl CachingScorer {
+    pub fn new(inner: Arc<dyn EmotionScorer>, cache: &ScoreCacheService) -> Self {
+        Self { inner, cache: cache.to_owned() }
+    }
+}
+
+#[async_trait]
+impl EmotionScorer for CachingScorer {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn configuration(&self) -> (String, Option<String>) {
+        self.inner.configuration()
+    }
+
+    fn sampling(&self) -> Vec<Option<f32>> {
+        self.inner.sampling()
+    }
+
+    async fn score(&self, text: &str, language: &Language) -> Result<ScoringResult> {
+        let (model_id, prompt_version) = self.configuration();
+        let Some(cache_key) = self.cache.cache_key(text, &model_id, prompt_version.as_deref(), &self.sampling()) else {
+            return self.inner.score(text, language).await
+        };
+
+        // cache errors should never stop a message from being scored
+        match self.cache.get(&cache_key).await {
+            Ok(Some(result)) => {
+                println!("score cache hit: {}", cache_key);
+                if let Err(error) = self.cache.record_lookup(true).await {
+                    println!("Error recording cache hit: {:?}", error);
+                }
+                return Ok(result)
+            },
+            Ok(None) => {},
+            Err(error) => println!("Error reading score cache: {:?}", error),
+        }
+        if let Err(error) = self.cache.record_lookup(false).await {
+            println!("Error recording cache miss: {:?}", error);
+        }
+
+        let result = self.inner.score(text, language).await?;
+        if result.model_id == model_id && result.prompt_version == prompt_version {
+            if let Err(error) = self.cache.put(&cache_key, &result).await {
+                println!("Error writing score cache: {:?}", error);
+            }
+        }
+        Ok(result)
+    }
+}
@@ -0,0 +1,154 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::{language::Language, service::common_structs::{EmotionScores, ScoringResult}, taxonomy::taxonomy};
+use super::{EmotionScorer, LEXICON_SCORER};
+
+
+// (term, weight)
+// english terms are matched against whole lower-cased words,
+// japanese terms, emoji and slack shortcodes against sub strings.
+type Lexicon = &'static [(&'static str, f64)];
+
+const ANGER_EN: Lexicon = &[("angry", 1.0), ("furious", 1.2), ("annoyed", 0.8), ("annoying", 0.8), ("mad", 0.8), ("hate", 1.0), ("wtf", 1.0), ("damn", 0.8), ("again", 0.3), ("ridiculous", 0.8), ("unacceptable", 1.0), ("broken", 0.4), ("stupid", 0.8), ("pissed", 1.2), ("frustrated", 0.8), ("frustrating", 0.8)];
+const CONTEMPT_EN: Lexicon = &[("obviously", 0.6), ("clearly", 0.4), ("whatever", 0.6), ("useless", 1.0), ("incompetent", 1.2), ("pathetic", 1.2), ("idiot", 1.2), ("lol", 0.2), ("seriously", 0.5), ("joke", 0.5), ("amateur", 0.8)];
+const DISGUST_EN: Lexicon = &[("disgusting", 1.2), ("gross", 1.0), ("nasty", 1.0), ("sick", 0.5), ("awful", 0.8), ("horrible", 0.8), ("yuck", 1.0), ("ew", 0.8), ("vile", 1.2), ("crap", 0.8), ("shit", 1.0)];
+const FEAR_EN: Lexicon = &[("afraid", 1.0), ("scared", 1.0), ("worried", 0.8), ("worry", 0.6), ("nervous", 0.8), ("anxious", 0.8), ("panic", 1.0), ("risk", 0.4), ("deadline", 0.3), ("outage", 0.6), ("incident", 0.4), ("urgent", 0.5)];
+const JOY_EN: Lexicon = &[("thanks", 0.8), ("thank", 0.8), ("great", 0.8), ("awesome", 1.0), ("love", 1.0), ("happy", 1.0), ("nice", 0.6), ("congrats", 1.0), ("congratulations", 1.0), ("glad", 0.8), ("excellent", 1.0), ("amazing", 1.0), ("lgtm", 0.4), ("yay", 1.0), ("good", 0.5), ("appreciate", 0.8)];
+const SURPRISE_EN: Lexicon = &[("wow", 1.0), ("whoa", 1.0), ("unexpected", 0.8), ("surprised", 1.0), ("surprising", 0.8), ("really", 0.3), ("suddenly", 0.6), ("omg", 1.0), ("what", 0.2)];
+const SAD_EN: Lexicon = &[("sad", 1.0), ("sorry", 0.5), ("unfortunately", 0.6), ("disappointed", 1.0), ("disappointing", 1.0), ("miss", 0.5), ("lonely", 1.0), ("tired", 0.6), ("exhausted", 0.8), ("depressed", 1.2), ("sigh", 0.6)];
+
+const ANGER_JA: Lexicon = &[("怒", 1.0), ("ムカつ", 1.2), ("むかつ", 1.2), ("イライラ", 1.0), ("いらいら", 1.0), ("ふざけ", 1.2), ("いい加減", 1.0), ("また", 0.3), ("壊れ", 0.4), ("ありえない", 1.0), ("最悪", 1.0), ("なんで", 0.4)];
+const CONTEMPT_JA: Lexicon = &[("使えない", 1.2), ("無能", 1.2), ("バカ", 1.2), ("ばか", 1.2), ("アホ", 1.0), ("何度も", 0.5), ("当然", 0.4), ("常識", 0.6), ("草", 0.3), ("笑", 0.2)];
+const DISGUST_JA: Lexicon = &[("気持ち悪", 1.2), ("キモ", 1.2), ("きも", 0.8), ("うざ", 1.0), ("ウザ", 1.0), ("汚", 0.8), ("クソ", 1.0), ("くそ", 1.0)];
+const FEAR_JA: Lexicon = &[("怖", 1.0), ("こわ", 0.8), ("不安", 1.0), ("心配", 0.8), ("やばい", 0.6), ("ヤバい", 0.6), ("障害", 0.6), ("締め切り", 0.3), ("緊急", 0.5)];
+const JOY_JA: Lexicon = &[("ありがとう", 0.8), ("嬉し", 1.0), ("うれし", 1.0), ("楽し", 1.0), ("最高", 1.0), ("素晴らし", 1.0), ("すごい", 0.6), ("助かり", 0.8), ("おめでとう", 1.0), ("良かった", 0.8), ("よかった", 0.8)];
+const SURPRISE_JA: Lexicon = &[("驚", 1.0), ("びっくり", 1.0), ("まさか", 0.8), ("えっ", 0.8), ("マジ", 0.6), ("まじ", 0.6), ("本当に", 0.3), ("突然", 0.6)];
+const SAD_JA: Lexicon = &[("悲し", 1.0), ("残念", 1.0), ("寂し", 1.0), ("さみし", 1.0), ("辛い", 1.0), ("つらい", 1.0), ("疲れ", 0.6), ("すみません", 0.3), ("申し訳", 0.4), ("がっかり", 1.0)];
+
+const ANGER_SYMBOLS: Lexicon = &[("😡", 1.2), ("😠", 1.0), ("🤬", 1.4), ("💢", 1.0), (":rage:", 1.2), (":angry:", 1.0), (":triumph:", 0.6)];
+const CONTEMPT_SYMBOLS: Lexicon = &[("🙄", 1.0), ("😒", 0.8), ("😏", 0.6), (":roll_eyes:", 1.0), (":unamused:", 0.8), (":smirk:", 0.6)];
+const DISGUST_SYMBOLS: Lexicon = &[("🤮", 1.2), ("🤢", 1.0), (":face_vomiting:", 1.2), (":nauseated_face:", 1.0), (":poop:", 0.6)];
+const FEAR_SYMBOLS: Lexicon = &[("😱", 1.0), ("😨", 1.0), ("😰", 0.8), (":scream:", 1.0), (":fearful:", 1.0), (":cold_sweat:", 0.8)];
+const JOY_SYMBOLS: Lexicon = &[("😂", 0.8), ("😊", 0.8), ("😄", 0.8), ("🎉", 1.0), ("❤", 0.8), ("👍", 0.4), (":joy:", 0.8), (":blush:", 0.8), (":smile:", 0.8), (":tada:", 1.0), (":heart:", 0.8), (":+1:", 0.4), (":pray:", 0.4)];
+const SURPRISE_SYMBOLS: Lexicon = &[("😮", 1.0), ("😲", 1.0), ("🤯", 1.0), (":open_mouth:", 1.0), (":astonished:", 1.0), (":exploding_head:", 1.0)];
+const SAD_SYMBOLS: Lexicon = &[("😢", 1.0), ("😭", 1.2), ("😞", 0.8), (":cry:", 1.0), (":sob:", 1.2), (":disappointed:", 0.8)];
+
+// emotion -> (english, japanese, symbols, amplified by intensity)
+const LEXICONS: &[(&str, Lexicon, Lexicon, Lexicon, bool)] = &[
+    ("anger", ANGER_EN, ANGER_JA, ANGER_SYMBOLS, true),
+    ("contempt", CONTEMPT_EN, CONTEMPT_JA, CONTEMPT_SYMBOLS, false),
+    ("disgust", DISGUST_EN, DISGUST_JA, DISGUST_SYMBOLS, false),
+    ("fear", FEAR_EN, FEAR_JA, FEAR_SYMBOLS, false),
+    ("joy", JOY_EN, JOY_JA, JOY_SYMBOLS, true),
+    ("surprise", SURPRISE_EN, SURPRISE_JA, SURPRISE_SYMBOLS, true),
+    ("sad", SAD_EN, SAD_JA, SAD_SYMBOLS, false),
+];
+
+const NEGATIONS_EN: &[&str] = &["not", "no", "never", "don't", "dont", "isn't", "isnt", "wasn't", "wasnt", "aren't", "arent", "without"];
+const NEGATIONS_JA: &[&str] = &["ない", "ません", "なかった", "ませんでした"];
+
+// how fast the score saturates with the number of matched terms
+const SATURATION: f64 = 0.7;
+
+
+// Deterministic, network free scorer.
+// Far less nuanced than the model, but good enough for local runs, tests, and to keep scoring while Bedrock throttles.
+#[derive(Debug, Clone, Default)]
+pub struct LexiconScorer {}
+
+impl LexiconScorer {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn score_text(&self, text: &str) -> EmotionScores {
+        let lower = text.to_lowercase();
+        let words = split_words(&lower);
+
+        let intensity = intensity(text);
+        let raw = |en: Lexicon, ja: Lexicon, symbols: Lexicon| -> f64 {
+            word_hits(&words, en) + sub_string_hits(&lower, ja, NEGATIONS_JA) + sub_string_hits(&lower, symbols, &[])
+        };
+
+        // emotions of the taxonomy without a lexicon always score 0.0
+        let raw_scores = EmotionScores::from_pairs(taxonomy().names().map(|name| {
+            let score = match LEXICONS.iter().find(|(emotion, ..)| *emotion == name) {
+                Some((_, en, ja, symbols, true)) => raw(en, ja, symbols) * intensity,
+                Some((_, en, ja, symbols, false)) => raw(en, ja, symbols),
+                None => 0.0,
+            };
+            (name, score)
+        }));
+
+        raw_scores.map(|raw| {
+            let score = 1.0 - (-SATURATION * raw).exp();
+            (score.clamp(0.0, 1.0) * 100.0).round() / 100.0
+        })
+    }
+}
+
+#[async_trait]
+impl EmotionScorer for LexiconScorer {
+    fn name(&self) -> &str {
+        LEXICON_SCORER
+    }
+
+    fn configuration(&self) -> (String, Option<String>) {
+        (LEXICON_SCORER.to_owned(), None)
+    }
+
+    async fn score(&self, text: &str, _language: &Language) -> Result<ScoringResult> {
+        Ok(ScoringResult { scores: self.score_text(text), model_id: LEXICON_SCORER.to_owned(), prompt_version: None, from_cache: false, std_dev: None, samples: None, target: None })
+    }
+}
+
+
+fn split_words(lower: &str) -> Vec<&str> {
+    lower
+        .split(|c: char| !(c.is_alphanumeric() || c == '\''))
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+// a term preceded by a negation within the 2 previous words does not count
+fn word_hits(words: &[&str], lexicon: Lexicon) -> f64 {
+    let mut total = 0.0;
+    for (index, word) in words.iter().enumerate() {
+        let Some((_, weight)) = lexicon.iter().find(|(term, _)| term == word) else {
+            continue;
+        };
+        let negated = words[index.saturating_sub(2)..index].iter().any(|w| NEGATIONS_EN.contains(w));
+        if !negated {
+            total += weight;
+        }
+    }
+    total
+}
+
+// a term directly followed by a negation (嬉しくない, 怖くない) does not count
+fn sub_string_hits(lower: &str, lexicon: Lexicon, negations: &[&str]) -> f64 {
+    let mut total = 0.0;
+    for (term, weight) in lexicon {
+        for (index, _) in lower.match_indices(term) {
+            let following: String = lower[index + term.len()..].chars().take(4).collect();
+            let negated = negations.iter().any(|n| following.contains(n));
+            if !negated {
+                total += weight;
+            }
+        }
+    }
+    total
+}
+
+// exclamation marks and shouting make anger, joy and surprise stronger
+fn intensity(text: &str) -> f64 {
+    let exclamations = text.chars().filter(|c| *c == '!' || *c == '！').count().min(3) as f64;
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let shouting = words.iter()
+        .filter(|w| w.chars().filter(|c| c.is_alphabetic()).count() >= 2 && w.chars().all(|c| !c.is_lowercase()) && w.chars().any(|c| c.is_uppercase()))
+        .count();
+    let shouting_ratio = if words.is_empty() { 0.0 } else { shouting as f64 / words.len() as f64 };
+
+    1.0 + exclamations * 0.1 + shouting_ratio * 0.5
+}
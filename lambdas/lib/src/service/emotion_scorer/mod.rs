@@ -0,0 +1,115 @@
+pub mod lexicon_scorer;
+pub mod caching_scorer;
+
+use std::{fmt::Debug, sync::Arc};
+use anyhow::Result;
+use async_trait::async_trait;
+use caching_scorer::CachingScorer;
+use lexicon_scorer::LexiconScorer;
+
+use crate::{env_keys::{EMOTION_SCORER, SCORER_FALLBACK}, language::Language};
+use super::{bedrock_service::{errors::is_throttling_error, prompts::EMOTION_SCORES_PROMPT, BedrockService}, common_structs::ScoringResult, score_cache_service::ScoreCacheService};
+
+pub const BEDROCK_SCORER: &str = "bedrock";
+pub const LEXICON_SCORER: &str = "lexicon";
+
+
+#[async_trait]
+pub trait EmotionScorer: Debug + Send + Sync {
+    fn name(&self) -> &str;
+    // (model id, prompt version) the scores are expected to come from
+    fn configuration(&self) -> (String, Option<String>);
+    // temperature of each sample a score is the mean of, none for the model default
+    fn sampling(&self) -> Vec<Option<f32>> {
+        vec![None]
+    }
+    async fn score(&self, text: &str, language: &Language) -> Result<ScoringResult>;
+}
+
+
+#[async_trait]
+impl EmotionScorer for BedrockService {
+    fn name(&self) -> &str {
+        BEDROCK_SCORER
+    }
+
+    fn configuration(&self) -> (String, Option<String>) {
+        let prompt_version = self.prompt_version(EMOTION_SCORES_PROMPT).ok();
+        (self.primary_model_id().to_owned(), prompt_version)
+    }
+
+    fn sampling(&self) -> Vec<Option<f32>> {
+        self.sample_temperatures()
+    }
+
+    async fn score(&self, text: &str, language: &Language) -> Result<ScoringResult> {
+        self.get_emotion_scroe(text, language).await
+    }
+}
+
+
+// Uses fallback only when primary is throttled, any other error is returned as it is.
+#[derive(Debug, Clone)]
+pub struct FallbackScorer {
+    primary: Arc<dyn EmotionScorer>,
+    fallback: Arc<dyn EmotionScorer>,
+}
+
+impl FallbackScorer {
+    pub fn new(primary: Arc<dyn EmotionScorer>, fallback: Arc<dyn EmotionScorer>) -> Self {
+        Self { primary, fallback }
+    }
+}
+
+#[async_trait]
+impl EmotionScorer for FallbackScorer {
+    fn name(&self) -> &str {
+        self.primary.name()
+    }
+
+    fn configuration(&self) -> (String, Option<String>) {
+        self.primary.configuration()
+    }
+
+    fn sampling(&self) -> Vec<Option<f32>> {
+        self.primary.sampling()
+    }
+
+    async fn score(&self, text: &str, language: &Language) -> Result<ScoringResult> {
+        match self.primary.score(text, language).await {
+            Ok(result) => Ok(result),
+            Err(error) if is_throttling_error(&error) => {
+                println!("{} is throttled, falling back to {}: {:?}", self.primary.name(), self.fallback.name(), error);
+                self.fallback.score(text, language).await
+            },
+            Err(error) => Err(error),
+        }
+    }
+}
+
+
+// EMOTION_SCORER: bedrock (default) | lexicon
+// SCORER_FALLBACK: lexicon (default, also when empty) | none
+// the score cache is used if SCORE_CACHE_TABLE_NAME is set
+pub fn scorer_from_env(bedrock: &BedrockService, cache: &ScoreCacheService) -> Arc<dyn EmotionScorer> {
+    let scorer = uncached_scorer_from_env(bedrock);
+    if cache.is_enabled() {
+        return Arc::new(CachingScorer::new(scorer, cache))
+    }
+    scorer
+}
+
+fn uncached_scorer_from_env(bedrock: &BedrockService) -> Arc<dyn EmotionScorer> {
+    let scorer = std::env::var(EMOTION_SCORER).unwrap_or(BEDROCK_SCORER.to_owned());
+    let fallback = std::env::var(SCORER_FALLBACK).ok().filter(|f| !f.is_empty()).unwrap_or(LEXICON_SCORER.to_owned());
+
+    let primary: Arc<dyn EmotionScorer> = match scorer.as_str() {
+        LEXICON_SCORER => Arc::new(LexiconScorer::new()),
+        _ => Arc::new(bedrock.to_owned()),
+    };
+
+    if fallback == LEXICON_SCORER && primary.name() != LEXICON_SCORER {
+        return Arc::new(FallbackScorer::new(primary, Arc::new(LexiconScorer::new())))
+    }
+    primary
+}
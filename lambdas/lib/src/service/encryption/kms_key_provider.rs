@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use aws_sdk_kms::{primitives::Blob, types::DataKeySpec};
+
+use super::{DataKey, KeyProvider, KMS_KEY_PROVIDER};
+
+const WORKSPACE_CONTEXT_KEY: &str = "workspace_id";
+
+
+// Wraps the data keys with a KMS key, the workspace id is the encryption context.
+// Throttling and transient errors are retried by the client, like the other sdk clients.
+#[derive(Debug, Clone)]
+pub struct KmsKeyProvider {
+    client: aws_sdk_kms::Client,
+    key_id: String,
+}
+
+impl KmsKeyProvider {
+    pub fn new(client: &aws_sdk_kms::Client, key_id: &str) -> Self {
+        Self {
+            client: client.to_owned(),
+            key_id: key_id.to_owned(),
+        }
+    }
+}
+
+#[async_trait]
+impl KeyProvider for KmsKeyProvider {
+    fn name(&self) -> &str {
+        KMS_KEY_PROVIDER
+    }
+
+    async fn generate_data_key(&self, workspace_id: &str) -> Result<DataKey> {
+        let output = self.client
+            .generate_data_key()
+            .key_id(&self.key_id)
+            .key_spec(DataKeySpec::Aes256)
+            .encryption_context(WORKSPACE_CONTEXT_KEY, workspace_id)
+            .send()
+            .await?;
+        Ok(DataKey {
+            plaintext: output.plaintext.context("plaintext not returned")?.into_inner(),
+            wrapped: output.ciphertext_blob.context("ciphertext blob not returned")?.into_inner(),
+        })
+    }
+
+    async fn unwrap_key(&self, workspace_id: &str, wrapped: &[u8]) -> Result<Vec<u8>> {
+        let output = self.client
+            .decrypt()
+            .key_id(&self.key_id)
+            .ciphertext_blob(Blob::new(wrapped))
+            .encryption_context(WORKSPACE_CONTEXT_KEY, workspace_id)
+            .send()
+            .await?;
+        Ok(output.plaintext.context("plaintext not returned")?.into_inner())
+    }
+}
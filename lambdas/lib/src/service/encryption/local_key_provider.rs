@@ -0,0 +1,62 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+
+use super::{open_bytes, seal_bytes, DataKey, KeyProvider, KEY_LENGTH, LOCAL_KEY_PROVIDER, NONCE_LENGTH};
+
+
+// Wraps the data keys with a master key held in a file, for tests and local runs.
+// The wrapped key is the nonce followed by the encrypted key.
+#[derive(Clone)]
+pub struct LocalKeyProvider {
+    master_key: Vec<u8>,
+}
+
+impl std::fmt::Debug for LocalKeyProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalKeyProvider").finish_non_exhaustive()
+    }
+}
+
+impl LocalKeyProvider {
+    pub fn new(master_key: &[u8]) -> Result<Self> {
+        if master_key.len() != KEY_LENGTH {
+            bail!("Master key must be {} bytes, got {}", KEY_LENGTH, master_key.len())
+        }
+        Ok(Self { master_key: master_key.to_vec() })
+    }
+
+    // the file holds the master key in hex, a random one is written if it does not exist
+    pub fn from_file(path: &str) -> Result<Self> {
+        if !Path::new(path).exists() {
+            let master_key: [u8; KEY_LENGTH] = rand::random();
+            std::fs::write(path, hex::encode(master_key)).context(format!("Error writing master key to {}", path))?;
+        }
+        let content = std::fs::read_to_string(path).context(format!("Error reading master key from {}", path))?;
+        let master_key = hex::decode(content.trim()).context("Master key is not hex")?;
+        Self::new(&master_key)
+    }
+}
+
+#[async_trait]
+impl KeyProvider for LocalKeyProvider {
+    fn name(&self) -> &str {
+        LOCAL_KEY_PROVIDER
+    }
+
+    async fn generate_data_key(&self, workspace_id: &str) -> Result<DataKey> {
+        let plaintext: [u8; KEY_LENGTH] = rand::random();
+        let (mut wrapped, ciphertext) = seal_bytes(&self.master_key, workspace_id.as_bytes(), &plaintext)?;
+        wrapped.extend(ciphertext);
+        Ok(DataKey { plaintext: plaintext.to_vec(), wrapped })
+    }
+
+    async fn unwrap_key(&self, workspace_id: &str, wrapped: &[u8]) -> Result<Vec<u8>> {
+        if wrapped.len() < NONCE_LENGTH {
+            bail!("Wrapped key is too short")
+        }
+        let (nonce, ciphertext) = wrapped.split_at(NONCE_LENGTH);
+        open_bytes(&self.master_key, workspace_id.as_bytes(), nonce, ciphertext)
+    }
+}
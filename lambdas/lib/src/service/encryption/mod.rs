@@ -0,0 +1,99 @@
+pub mod kms_key_provider;
+pub mod local_key_provider;
+pub mod text_cipher;
+
+use std::{fmt::{self, Debug}, sync::Arc};
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use kms_key_provider::KmsKeyProvider;
+use local_key_provider::LocalKeyProvider;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use serde::{Deserialize, Serialize};
+
+use crate::env_keys::{TEXT_ENCRYPTION, TEXT_ENCRYPTION_KMS_KEY_ID, TEXT_ENCRYPTION_LOCAL_KEY_FILE};
+
+pub const KMS_KEY_PROVIDER: &str = "kms";
+pub const LOCAL_KEY_PROVIDER: &str = "local";
+pub const NO_ENCRYPTION: &str = "none";
+
+// AES-256-GCM
+pub const KEY_LENGTH: usize = 32;
+pub(crate) const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+
+
+// A data key in the clear, and wrapped by the key provider to be stored.
+#[derive(Clone)]
+pub struct DataKey {
+    pub plaintext: Vec<u8>,
+    pub wrapped: Vec<u8>,
+}
+
+// keeps the key out of the logs
+impl Debug for DataKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DataKey").field("wrapped", &BASE64_STANDARD.encode(&self.wrapped)).finish_non_exhaustive()
+    }
+}
+
+
+// Wraps the data keys of the workspaces, the workspace id is bound to the wrapped key.
+#[async_trait]
+pub trait KeyProvider: Debug + Send + Sync {
+    fn name(&self) -> &str;
+    async fn generate_data_key(&self, workspace_id: &str) -> Result<DataKey>;
+    async fn unwrap_key(&self, workspace_id: &str, wrapped: &[u8]) -> Result<Vec<u8>>;
+}
+
+
+// A text encrypted with the data key of the workspace.
+// Carries the wrapped data key, so that it can be decrypted with the key provider alone.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EncryptedText {
+    pub workspace_id: String,
+    pub provider: String,
+    // base64
+    pub wrapped_key: String,
+    pub nonce: String,
+    // with the authentication tag appended
+    pub ciphertext: String,
+}
+
+
+// TEXT_ENCRYPTION: none (default) | kms | local
+// TEXT_ENCRYPTION_KMS_KEY_ID: key id, arn or alias of the KMS key, for kms
+// TEXT_ENCRYPTION_LOCAL_KEY_FILE: file of the master key (hex), created if missing, for local
+pub fn key_provider_from_env(kms_client: &aws_sdk_kms::Client) -> Result<Option<Arc<dyn KeyProvider>>> {
+    let provider = std::env::var(TEXT_ENCRYPTION).unwrap_or_default();
+    match provider.as_str() {
+        KMS_KEY_PROVIDER => {
+            let key_id = std::env::var(TEXT_ENCRYPTION_KMS_KEY_ID).context(format!("{} is required for kms", TEXT_ENCRYPTION_KMS_KEY_ID))?;
+            Ok(Some(Arc::new(KmsKeyProvider::new(kms_client, &key_id))))
+        },
+        LOCAL_KEY_PROVIDER => {
+            let path = std::env::var(TEXT_ENCRYPTION_LOCAL_KEY_FILE).context(format!("{} is required for local", TEXT_ENCRYPTION_LOCAL_KEY_FILE))?;
+            Ok(Some(Arc::new(LocalKeyProvider::from_file(&path)?)))
+        },
+        "" | NO_ENCRYPTION => Ok(None),
+        _ => bail!("Unknown {} {}, none, kms or local", TEXT_ENCRYPTION, provider),
+    }
+}
+
+
+// AES-256-GCM with a random nonce, returns (nonce, ciphertext + tag)
+pub fn seal_bytes(key: &[u8], aad: &[u8], plaintext: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let nonce: [u8; NONCE_LENGTH] = rand::random();
+    let mut tag = [0u8; TAG_LENGTH];
+    let mut ciphertext = encrypt_aead(Cipher::aes_256_gcm(), key, Some(&nonce), aad, plaintext, &mut tag)?;
+    ciphertext.extend_from_slice(&tag);
+    Ok((nonce.to_vec(), ciphertext))
+}
+
+pub fn open_bytes(key: &[u8], aad: &[u8], nonce: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+    if ciphertext.len() < TAG_LENGTH {
+        bail!("Ciphertext is too short")
+    }
+    let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - TAG_LENGTH);
+    decrypt_aead(Cipher::aes_256_gcm(), key, Some(nonce), aad, ciphertext, tag).context("Error decrypting, wrong key or tampered ciphertext")
+}
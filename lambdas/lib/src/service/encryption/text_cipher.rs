@@ -0,0 +1,205 @@
+use std::{collections::HashMap, fmt::{self, Debug}, sync::{Arc, Mutex}, time::SystemTime};
+
+use anyhow::{bail, Context, Result};
+use aws_sdk_dynamodb::types::AttributeValue;
+use base64::{prelude::BASE64_STANDARD, Engine};
+
+use crate::{env_keys::{DATA_KEY_TABLE_NAME, WORKSPACE_ID}, service::{dynamo_service::structs::EmotionTableEntry, usage_service::DEFAULT_WORKSPACE_ID}};
+use super::{key_provider_from_env, open_bytes, seal_bytes, DataKey, EncryptedText, KeyProvider};
+
+
+// Envelope encryption of the text of the entries.
+// The text is encrypted with the data key of the workspace, itself wrapped by the key provider
+// and kept in the data key table (pk workspace_id). Without the table the key only lives as long as the cipher.
+// The event id is the additional data, so that a ciphertext can not be moved to another entry.
+#[derive(Clone)]
+pub struct TextCipher {
+    provider: Arc<dyn KeyProvider>,
+    client: Option<aws_sdk_dynamodb::Client>,
+    table_name: String,
+    workspace_id: String,
+    data_key: Arc<tokio::sync::Mutex<Option<DataKey>>>,
+    // wrapped key (base64) -> data key, so that the provider is called once per key
+    unwrapped: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+}
+
+// keeps the keys out of the logs
+impl Debug for TextCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TextCipher")
+            .field("provider", &self.provider)
+            .field("table_name", &self.table_name)
+            .field("workspace_id", &self.workspace_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TextCipher {
+    pub fn new(provider: Arc<dyn KeyProvider>, workspace_id: &str) -> Self {
+        Self {
+            provider,
+            client: None,
+            table_name: "".to_owned(),
+            workspace_id: workspace_id.to_owned(),
+            data_key: Arc::new(tokio::sync::Mutex::new(None)),
+            unwrapped: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn with_table(self, client: &aws_sdk_dynamodb::Client, table_name: &str) -> Self {
+        Self {
+            client: Some(client.to_owned()),
+            table_name: table_name.to_owned(),
+            ..self
+        }
+    }
+
+    pub fn provider_name(&self) -> &str {
+        self.provider.name()
+    }
+
+    pub async fn encrypt(&self, event_id: &str, text: &str) -> Result<EncryptedText> {
+        let data_key = self.data_key().await?;
+        let (nonce, ciphertext) = seal_bytes(&data_key.plaintext, event_id.as_bytes(), text.as_bytes())?;
+        Ok(EncryptedText {
+            workspace_id: self.workspace_id.to_owned(),
+            provider: self.provider.name().to_owned(),
+            wrapped_key: BASE64_STANDARD.encode(&data_key.wrapped),
+            nonce: BASE64_STANDARD.encode(nonce),
+            ciphertext: BASE64_STANDARD.encode(ciphertext),
+        })
+    }
+
+    pub async fn decrypt(&self, event_id: &str, encrypted: &EncryptedText) -> Result<String> {
+        if encrypted.provider != self.provider.name() {
+            bail!("Text was encrypted with {}, not {}", encrypted.provider, self.provider.name())
+        }
+        let key = self.unwrap_key(&encrypted.workspace_id, &encrypted.wrapped_key).await?;
+        let nonce = BASE64_STANDARD.decode(&encrypted.nonce)?;
+        let ciphertext = BASE64_STANDARD.decode(&encrypted.ciphertext)?;
+        let plaintext = open_bytes(&key, event_id.as_bytes(), &nonce, &ciphertext)?;
+        Ok(String::from_utf8(plaintext)?)
+    }
+
+    // the entry as it is stored: the text and the rewrite are replaced with their ciphertext
+    pub async fn seal(&self, entry: &EmotionTableEntry) -> Result<EmotionTableEntry> {
+        let mut sealed = entry.to_owned();
+        if !entry.text.is_empty() {
+            sealed.encrypted_text = Some(self.encrypt(&entry.event_id, &entry.text).await?);
+            sealed.text = "".to_owned();
+        }
+        if let Some(rewrite) = &entry.rewrite {
+            sealed.encrypted_rewrite = Some(self.encrypt(&entry.event_id, rewrite).await?);
+            sealed.rewrite = None;
+        }
+        Ok(sealed)
+    }
+
+    // the entry with its text and rewrite in the clear
+    pub async fn open(&self, entry: &EmotionTableEntry) -> Result<EmotionTableEntry> {
+        let mut opened = entry.to_owned();
+        if let Some(encrypted) = opened.encrypted_text.take() {
+            opened.text = self.decrypt(&entry.event_id, &encrypted).await?;
+        }
+        if let Some(encrypted) = opened.encrypted_rewrite.take() {
+            opened.rewrite = Some(self.decrypt(&entry.event_id, &encrypted).await?);
+        }
+        Ok(opened)
+    }
+
+    // the data key of the workspace: read from the table, or generated and written if there is none
+    async fn data_key(&self) -> Result<DataKey> {
+        let mut data_key = self.data_key.lock().await;
+        if let Some(key) = data_key.as_ref() {
+            return Ok(key.to_owned())
+        }
+
+        let key = match self.read_wrapped_key().await? {
+            Some(wrapped) => DataKey { plaintext: self.unwrap_key(&self.workspace_id, &wrapped).await?, wrapped: BASE64_STANDARD.decode(&wrapped)? },
+            None => {
+                let generated = self.provider.generate_data_key(&self.workspace_id).await?;
+                match self.write_wrapped_key(&generated).await? {
+                    true => generated,
+                    // written by someone else in the meantime
+                    false => {
+                        let wrapped = self.read_wrapped_key().await?.context("data key not available")?;
+                        DataKey { plaintext: self.unwrap_key(&self.workspace_id, &wrapped).await?, wrapped: BASE64_STANDARD.decode(&wrapped)? }
+                    },
+                }
+            },
+        };
+        *data_key = Some(key.to_owned());
+        Ok(key)
+    }
+
+    async fn unwrap_key(&self, workspace_id: &str, wrapped_key: &str) -> Result<Vec<u8>> {
+        if let Some(key) = self.unwrapped.lock().unwrap().get(wrapped_key) {
+            return Ok(key.to_owned())
+        }
+        let key = self.provider.unwrap_key(workspace_id, &BASE64_STANDARD.decode(wrapped_key)?).await?;
+        self.unwrapped.lock().unwrap().insert(wrapped_key.to_owned(), key.to_owned());
+        Ok(key)
+    }
+
+    // base64
+    async fn read_wrapped_key(&self) -> Result<Option<String>> {
+        let Some(client) = &self.client else {
+            return Ok(None)
+        };
+        let output = client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("workspace_id", AttributeValue::S(self.workspace_id.to_owned()))
+            .consistent_read(true)
+            .send()
+            .await?;
+        let Some(item) = output.item else {
+            return Ok(None)
+        };
+        let provider = item.get("provider").and_then(|p| p.as_s().ok()).context("provider not available")?;
+        if provider != self.provider.name() {
+            bail!("Data key of {} is wrapped by {}, not {}", self.workspace_id, provider, self.provider.name())
+        }
+        let wrapped = item.get("wrapped_key").and_then(|w| w.as_s().ok()).context("wrapped_key not available")?;
+        Ok(Some(wrapped.to_owned()))
+    }
+
+    // false if the workspace already has a data key
+    async fn write_wrapped_key(&self, key: &DataKey) -> Result<bool> {
+        let Some(client) = &self.client else {
+            return Ok(true)
+        };
+        let created_at = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.as_secs();
+        let result = client
+            .put_item()
+            .table_name(&self.table_name)
+            .item("workspace_id", AttributeValue::S(self.workspace_id.to_owned()))
+            .item("provider", AttributeValue::S(self.provider.name().to_owned()))
+            .item("wrapped_key", AttributeValue::S(BASE64_STANDARD.encode(&key.wrapped)))
+            .item("created_at", AttributeValue::N(created_at.to_string()))
+            .condition_expression("attribute_not_exists(workspace_id)")
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if e.as_service_error().map(|e| e.is_conditional_check_failed_exception()).unwrap_or(false) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+
+// none when TEXT_ENCRYPTION is none, see key_provider_from_env.
+// DATA_KEY_TABLE_NAME: table of the wrapped data keys
+pub fn text_cipher_from_env(kms_client: &aws_sdk_kms::Client, client: &aws_sdk_dynamodb::Client) -> Result<Option<TextCipher>> {
+    let Some(provider) = key_provider_from_env(kms_client)? else {
+        return Ok(None)
+    };
+    let workspace_id = std::env::var(WORKSPACE_ID).ok().filter(|w| !w.is_empty()).unwrap_or(DEFAULT_WORKSPACE_ID.to_owned());
+    let cipher = TextCipher::new(provider, &workspace_id);
+    match std::env::var(DATA_KEY_TABLE_NAME).ok().filter(|t| !t.is_empty()) {
+        Some(table_name) => Ok(Some(cipher.with_table(client, &table_name))),
+        None => Ok(Some(cipher)),
+    }
+}
@@ -1,18 +1,22 @@
 
 
+use std::collections::HashMap;
 use anyhow::{bail, Context, Result};
 use reqwest::{header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE}, Client,};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
-use crate::{env_keys::{ BOT_OAUTH_TOKEN, RESULT_CHANNEL_ID, SLACK_VERIFICATION_TOKEN}, utilities::get_previous_weekday,};
-use super::common_structs::{DailyAdvice, EmotionScores};
+use crate::{charts::color_emoji, digest::{Digest, DigestPeriod}, env_keys::{ BOT_OAUTH_TOKEN, RESULT_CHANNEL_ID, SLACK_VERIFICATION_TOKEN, TEAM_REPORT_CHANNEL_ID}, language::Language, statistics::{ChannelMood, ScoreSummary}, team_report::TeamReport, taxonomy::taxonomy, utilities::{get_date_month, get_previous_weekday}, warnings::{person_directed_warning, rewrite_suggestion}};
+use super::{common_structs::{DailyAdvice, DailyPick, DigestSummary, DAILY_PICK_MIN_SCORE, ToxicityLabel}, dynamo_service::structs::EmotionTableEntry};
 
 pub const EVENT_CALLBACK_TYPE: &str = "event_callback";
 pub const MESSAGE_EVENT_TYPE: &str = "message";
 const VERIFICATION_TYPE: &str = "url_verification";
 
 const POST_MESSAGE_ENDPOINT: &str = "https://slack.com/api/chat.postMessage";
+const POST_EPHEMERAL_ENDPOINT: &str = "https://slack.com/api/chat.postEphemeral";
+const GET_UPLOAD_URL_ENDPOINT: &str = "https://slack.com/api/files.getUploadURLExternal";
+const COMPLETE_UPLOAD_ENDPOINT: &str = "https://slack.com/api/files.completeUploadExternal";
 
 #[derive(Debug, Clone)]
 pub struct LineService {
@@ -20,6 +24,13 @@ pub struct LineService {
     headers: HeaderMap
 }
 
+// an uploaded png, shown by the messages that refer to its file id
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartImage {
+    pub file_id: String,
+    pub title: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct EventChallengeRequest {
     pub challenge: String,
@@ -27,6 +38,17 @@ pub struct EventChallengeRequest {
     pub r#type: String
 }
 
+// the form slack posts for a slash command
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SlashCommandRequest {
+    pub token: String,
+    pub command: String,
+    #[serde(default)]
+    pub text: String,
+    pub channel_id: String,
+    pub user_id: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct MessageEventRequest {
     pub api_app_id: String,
@@ -46,6 +68,8 @@ pub struct MessageEvent {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub subtype: Option<String>, // none for user message
     pub event_ts: String, // thread_ts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread_ts: Option<String>, // none unless the message is in a thread
     pub text: String,
     pub user: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -53,6 +77,12 @@ pub struct MessageEvent {
 }
 
 
+impl Default for LineService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LineService {
     pub fn new() -> Self {
         let token: String = std::env::var(BOT_OAUTH_TOKEN).unwrap_or("".to_owned());
@@ -72,6 +102,14 @@ impl LineService {
         Ok(verification_token == challenge_request.token && challenge_request.r#type == VERIFICATION_TYPE)
     }
 
+    pub fn verify_slash_command(&self, command_request: &SlashCommandRequest) -> Result<()> {
+        let verification_token = std::env::var(SLACK_VERIFICATION_TOKEN)?;
+        if verification_token != command_request.token {
+            bail!("Wrong Verification Token");
+        }
+        Ok(())
+    }
+
     pub fn verify_message_request(&self, message_request: &MessageEventRequest) -> Result<()> {
         if message_request.r#type != EVENT_CALLBACK_TYPE || message_request.event.r#type != MESSAGE_EVENT_TYPE  {
             bail!("Wrong Event Type");
@@ -94,6 +132,45 @@ impl LineService {
         Ok(())
     }
 
+    // slack's external upload: an upload url, the bytes, then completed without a channel,
+    // so that the file is only shown in the messages that refer to it
+    pub async fn upload_chart(&self, filename: &str, title: &str, png: Vec<u8>) -> Result<ChartImage> {
+        // form requests, the json content type is left out
+        let mut headers = self.headers.clone();
+        headers.remove(CONTENT_TYPE);
+
+        let response = self.client
+            .post(GET_UPLOAD_URL_ENDPOINT)
+            .headers(headers.clone())
+            .form(&[("filename", filename.to_owned()), ("length", png.len().to_string()), ("alt_txt", title.to_owned())])
+            .send()
+            .await?;
+        let body = slack_response(response).await?;
+        let upload_url = body.get("upload_url").and_then(|u| u.as_str()).context("unable to get upload url")?;
+        let file_id = body.get("file_id").and_then(|f| f.as_str()).context("unable to get file id")?.to_owned();
+
+        let response = self.client
+            .post(upload_url)
+            .body(png)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            bail!("Error uploading {}: {}", filename, response.status())
+        }
+
+        let files = json!([{ "id": file_id, "title": title }]);
+        let response = self.client
+            .post(COMPLETE_UPLOAD_ENDPOINT)
+            .headers(headers)
+            .form(&[("files", files.to_string())])
+            .send()
+            .await?;
+        slack_response(response).await?;
+
+        Ok(ChartImage { file_id, title: title.to_owned() })
+    }
+
+
     pub async fn send_daily_thread(&self) -> Result<String> {
         let channel_id = std::env::var(RESULT_CHANNEL_ID)?;
 
@@ -119,7 +196,7 @@ impl LineService {
             .await?;
 
         let body_string = response.text().await?;
-        println!("response_body: {}", body_string);
+        log_response(&body_string);
         let body = serde_json::from_str::<Value>(&body_string)?;
         let thread_ts = body.get("ts").context("unable to get thread ts")?.to_string();
 
@@ -127,19 +204,134 @@ impl LineService {
     }
 
 
-    pub async fn send_daily_advice(&self, thread_ts: &str, user_id: &str, advice: &DailyAdvice, max_anger: &(EmotionScores, String), max_contempt: &(EmotionScores, String), max_disgust: &(EmotionScores, String)) -> Result<()> {
+    // picks: one for each emotion that warns, among the messages aimed at a person and among the others
+    // person_directed_count: number of messages aimed at a person
+    // charts: attached below the advice, none if REPORT_CHARTS is not set
+    pub async fn send_daily_advice(&self, thread_ts: &str, user_id: &str, advice: &DailyAdvice, picks: &[DailyPick], person_directed_count: usize, charts: &[ChartImage]) -> Result<()> {
         let channel_id = std::env::var(RESULT_CHANNEL_ID)?;
         println!("channel id: {}", channel_id);
 
-        let message_anger = if max_anger.0.anger >= 0.4 {
-            format!("*Message with max anger ({})*: {}\n", max_anger.0.anger, max_anger.1) } else { "".to_owned() };
-        let message_contempt = if max_contempt.0.contempt >= 0.4 {
-            format!("*Message with max contempt ({})*: {}\n", max_contempt.0.contempt, max_contempt.1) } else { "".to_owned() };
-        let message_disgust = if max_disgust.0.disgust >= 0.4 {
-            format!("*Message with max disgust ({})*: {}\n", max_disgust.0.disgust, max_disgust.1) } else { "".to_owned() };
+        // the text may not be readable, ie: purged or not decrypted
+        let quote = |text: &str| if text.is_empty() { "_text not available_".to_owned() } else { text.to_owned() };
+        let pick_line = |pick: &DailyPick| format!("*Message with max {} ({}{})*: {}\n", pick.emotion, pick.score, if pick.uncertain { ", uncertain" } else { "" }, quote(&pick.text));
+        let max_message_lines: String = picks.iter()
+            .filter(|pick| pick.score >= DAILY_PICK_MIN_SCORE && !pick.person_directed)
+            .map(pick_line)
+            .collect();
+        let person_directed_lines: String = picks.iter()
+            .filter(|pick| pick.score >= DAILY_PICK_MIN_SCORE && pick.person_directed)
+            .map(pick_line)
+            .collect();
+        let person_directed_section = match person_directed_count {
+            0 => "".to_owned(),
+            count => format!("*Aimed at a person*: {} message{}\n{}", count, if count == 1 { "" } else { "s" }, person_directed_lines),
+        };
+
+        let message = max_message_lines + &person_directed_section + &format!("*Advice*: {}", advice.advice) + "\n" + &format!("*Song Recommendation*: {}", advice.song);
+
+        let mut blocks = vec![json!({
+            "type": "section",
+            "text": {
+                "type": "mrkdwn",
+                "text": format!(":heart: <@{}> :heart:\n{}", user_id, message)
+            }
+        })];
+        blocks.extend(chart_blocks(charts));
+        let body = json!({
+            "channel": channel_id,
+            "thread_ts": thread_ts,
+            "blocks": blocks
+        });
+
+        let response = self.client
+            .post(POST_MESSAGE_ENDPOINT)
+            .headers(self.headers.clone())
+            .body(serde_json::to_string(&body)?)
+            .send()
+            .await?;
+
+        let body_string = response.text().await?;
+        log_response(&body_string);
+
+        Ok(())
+    }
 
-        let message = message_anger + &message_contempt + &message_disgust + &format!("*Advice*: {}", advice.advice) + "\n" + &format!("*Song Recommendation*: {}", advice.song);
 
+    // by direct message to the user, the picks are quoted
+    pub async fn send_digest(&self, digest: &Digest, summary: &DigestSummary, charts: &[ChartImage]) -> Result<()> {
+        let title = match digest.period {
+            DigestPeriod::Week => "Weekly digest",
+            DigestPeriod::Month => "Monthly digest",
+        };
+        let mut blocks = vec![json!({
+            "type": "section",
+            "text": {
+                "type": "mrkdwn",
+                "text": format!(":calendar: *{}: {} to {}* :calendar:\nSee how the {} went compared to the one before!\n{}", title, digest.from, digest.to, digest.period.code(), digest_text(digest, summary))
+            }
+        })];
+        blocks.extend(chart_blocks(charts));
+        // a user id as the channel opens the direct message with the app
+        let body = json!({
+            "channel": digest.user_id,
+            "blocks": blocks
+        });
+
+        let response = self.client
+            .post(POST_MESSAGE_ENDPOINT)
+            .headers(self.headers.clone())
+            .body(serde_json::to_string(&body)?)
+            .send()
+            .await?;
+
+        // an error if the user can not be messaged, ie: deactivated
+        slack_response(response).await?;
+
+        Ok(())
+    }
+
+
+    // one section per group, to TEAM_REPORT_CHANNEL_ID
+    // period: day, week or month
+    pub async fn send_team_reports(&self, period: &str, from: &str, to: &str, reports: &[TeamReport]) -> Result<()> {
+        let channel_id = std::env::var(TEAM_REPORT_CHANNEL_ID)?;
+
+        let mut blocks = vec![json!({
+            "type": "section",
+            "text": {
+                "type": "mrkdwn",
+                "text": format!(":busts_in_silhouette: *Team report of the {}: {} to {}* :busts_in_silhouette:", period, from, to)
+            }
+        })];
+        for report in reports {
+            blocks.push(json!({
+                "type": "section",
+                "text": {
+                    "type": "mrkdwn",
+                    "text": team_report_text(report)
+                }
+            }));
+        }
+        let body = json!({
+            "channel": channel_id,
+            "blocks": blocks
+        });
+
+        let response = self.client
+            .post(POST_MESSAGE_ENDPOINT)
+            .headers(self.headers.clone())
+            .body(serde_json::to_string(&body)?)
+            .send()
+            .await?;
+
+        let body_string = response.text().await?;
+        log_response(&body_string);
+
+        Ok(())
+    }
+
+
+    pub async fn send_immediate_warning(&self, channel_id: &str, thread_ts: &str, user_id: &str, message: &str) -> Result<()>{
         let body = json!({
             "channel": channel_id,
             "thread_ts": thread_ts,
@@ -148,7 +340,7 @@ impl LineService {
                     "type": "section",
                     "text": {
                         "type": "mrkdwn",
-                        "text": format!(":heart: <@{}> :heart:\n{}", user_id, message)
+                        "text": format!(":warning:<@{}>:warning:\n{}", user_id, message)
                     }
                 }
             ]
@@ -162,22 +354,162 @@ impl LineService {
             .await?;
 
         let body_string = response.text().await?;
-        println!("response_body: {}", body_string);
+        log_response(&body_string);
 
         Ok(())
     }
 
 
-    pub async fn send_immediate_warning(&self, channel_id: &str, thread_ts: &str, user_id: &str, message: &str) -> Result<()>{
+    // only visible to the author of the message, used for messages aimed at a person
+    pub async fn send_private_warning(&self, channel_id: &str, thread_ts: &str, user_id: &str, language: &Language, message: &str) -> Result<()>{
         let body = json!({
             "channel": channel_id,
             "thread_ts": thread_ts,
+            "user": user_id,
             "blocks": [
                 {
                     "type": "section",
                     "text": {
                         "type": "mrkdwn",
-                        "text": format!(":warning:<@{}>:warning:\n{}", user_id, message)
+                        "text": format!(":warning: {}\n{}", person_directed_warning(language), message)
+                    }
+                }
+            ]
+        });
+
+        let response = self.client
+            .post(POST_EPHEMERAL_ENDPOINT)
+            .headers(self.headers.clone())
+            .body(serde_json::to_string(&body)?)
+            .send()
+            .await?;
+
+        let body_string = response.text().await?;
+        log_response(&body_string);
+
+        Ok(())
+    }
+
+
+    // posted to the moderator channel instead of the public warning.
+    // reason: why the message needs a look, see toxicity_reason and person_directed_reason
+    pub async fn send_moderator_alert(&self, channel_id: &str, message_ts: &str, entry: &EmotionTableEntry, reason: &str) -> Result<()>{
+        let body = json!({
+            "channel": channel_id,
+            "blocks": [
+                {
+                    "type": "section",
+                    "text": {
+                        "type": "mrkdwn",
+                        "text": format!(":rotating_light: Message from <@{}> in <#{}> (ts: {}) needs a look :rotating_light:\n*{}*\n>{}", entry.user_id, entry.channel_id, message_ts, reason, entry.text.replace("\n", "\n>"))
+                    }
+                }
+            ]
+        });
+
+        let response = self.client
+            .post(POST_MESSAGE_ENDPOINT)
+            .headers(self.headers.clone())
+            .body(serde_json::to_string(&body)?)
+            .send()
+            .await?;
+
+        let body_string = response.text().await?;
+        log_response(&body_string);
+
+        Ok(())
+    }
+
+
+    // only visible to the author of the message
+    // rewrite is the one of the entry with the mentions restored
+    pub async fn send_rewrite_suggestion(&self, thread_ts: &str, entry: &EmotionTableEntry, rewrite: &str) -> Result<()>{
+        let rewrite_scores = entry.rewrite_scores.as_ref().context("rewrite scores not available")?;
+
+        let body = json!({
+            "channel": entry.channel_id,
+            "thread_ts": thread_ts,
+            "user": entry.user_id,
+            "blocks": [
+                {
+                    "type": "section",
+                    "text": {
+                        "type": "mrkdwn",
+                        "text": format!(":bulb: {}\n>{}\n_Negative emotion: {:.2} -> {:.2}_", rewrite_suggestion(&entry.language), rewrite.replace("\n", "\n>"), entry.scores.max_negative(), rewrite_scores.max_negative())
+                    }
+                }
+            ]
+        });
+
+        let response = self.client
+            .post(POST_EPHEMERAL_ENDPOINT)
+            .headers(self.headers.clone())
+            .body(serde_json::to_string(&body)?)
+            .send()
+            .await?;
+
+        let body_string = response.text().await?;
+        log_response(&body_string);
+
+        Ok(())
+    }
+
+
+    pub async fn send_language_comparison(&self, thread_ts: &str, summaries: &HashMap<Language, ScoreSummary>) -> Result<()> {
+        let channel_id = std::env::var(RESULT_CHANNEL_ID)?;
+
+        let mut languages: Vec<&Language> = summaries.keys().collect();
+        languages.sort_by_key(|l| l.code());
+
+        let lines = languages.into_iter()
+            .filter_map(|language| {
+                let summary = summaries.get(language)?;
+                let scores = summary.mean.to_pairs().into_iter()
+                    .zip(summary.std_dev.to_pairs())
+                    .map(|((name, mean), (_, std_dev))| format!("{} {:.2}±{:.2}", name, mean, std_dev))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                Some(format!("*{}* ({} messages): {}", language.code(), summary.count, scores))
+            })
+            .collect::<Vec<String>>();
+
+        let body = json!({
+            "channel": channel_id,
+            "thread_ts": thread_ts,
+            "blocks": [
+                {
+                    "type": "section",
+                    "text": {
+                        "type": "mrkdwn",
+                        "text": format!(":globe_with_meridians: Scores by language :globe_with_meridians:\n{}", lines.join("\n"))
+                    }
+                }
+            ]
+        });
+
+        let response = self.client
+            .post(POST_MESSAGE_ENDPOINT)
+            .headers(self.headers.clone())
+            .body(serde_json::to_string(&body)?)
+            .send()
+            .await?;
+
+        let body_string = response.text().await?;
+        log_response(&body_string);
+
+        Ok(())
+    }
+
+    // posts the weather report of the mood to its channel
+    pub async fn send_channel_weather(&self, mood: &ChannelMood) -> Result<()> {
+        let body = json!({
+            "channel": mood.channel_id,
+            "blocks": [
+                {
+                    "type": "section",
+                    "text": {
+                        "type": "mrkdwn",
+                        "text": weather_report(mood)?
                     }
                 }
             ]
@@ -191,8 +523,146 @@ impl LineService {
             .await?;
 
         let body_string = response.text().await?;
-        println!("response_body: {}", body_string);
+        log_response(&body_string);
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+
+pub fn toxicity_reason(escalations: &[(&str, &ToxicityLabel)]) -> String {
+    escalations.iter()
+        .map(|(category, label)| format!("{} {:.2} (target: {})", category, label.score, label.target.code()))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// emotions: (name, score) over the threshold
+pub fn person_directed_reason(emotions: &[(&str, f64)], target_user_id: Option<&str>) -> String {
+    let scores = emotions.iter()
+        .map(|(name, score)| format!("{} {:.2}", name, score))
+        .collect::<Vec<String>>()
+        .join(", ");
+    match target_user_id {
+        Some(user_id) => format!("{} (aimed at <@{}>)", scores, user_id),
+        None => format!("{} (aimed at a person)", scores),
+    }
+}
+
+// the channel mood as a short forecast, one line per emotion in taxonomy order
+pub fn weather_report(mood: &ChannelMood) -> Result<String> {
+    let (from, _) = get_date_month(mood.from)?;
+    let (to, _) = get_date_month(mood.to.saturating_sub(1).max(mood.from))?;
+    let forecast = mood.forecast();
+    let mut lines = vec![format!("{} Weather of <#{}> from {} to {} {}", forecast.emoji(), mood.channel_id, from, to, forecast.emoji())];
+    if mood.message_count == 0 {
+        lines.push("No messages.".to_owned());
+        return Ok(lines.join("\n"))
+    }
+
+    lines.push(format!("{} messages from {} people", mood.message_count, mood.user_count));
+    for name in taxonomy().names() {
+        let Some(distribution) = mood.get(name) else {
+            continue
+        };
+        lines.push(format!("*{}* average {:.2}, median {:.2}, 90th percentile {:.2}, {} over {:.2}", name, distribution.mean, distribution.p50, distribution.p90, distribution.above_threshold, mood.threshold));
+    }
+    Ok(lines.join("\n"))
+}
+
+// averages with the change since the previous period, in taxonomy order, then the picks and the summary
+pub fn digest_text(digest: &Digest, summary: &DigestSummary) -> String {
+    let mut lines = vec![format!("{} messages ({} the {} before)", digest.message_count, digest.previous_message_count, digest.period.code())];
+    for name in taxonomy().names() {
+        let Some(emotion) = digest.emotions.get(name) else {
+            continue
+        };
+        let change = match emotion.change {
+            Some(change) => format!(" ({:+.2})", change),
+            None => "".to_owned(),
+        };
+        let improved = if digest.most_improved.as_deref() == Some(name) { " :arrow_up: most improved" } else { "" };
+        lines.push(format!("*{}* {:.2}{}{}", name, emotion.mean, change, improved));
+    }
+    // the text may have been purged since
+    let quote = |text: &str| if text.is_empty() { "_text no longer kept_".to_owned() } else { text.to_owned() };
+    if let Some(pick) = &digest.most_negative {
+        lines.push(format!("*Most negative ({} {:.2})*: {}", pick.emotion, pick.score, quote(&pick.text)));
+    }
+    if let Some(pick) = &digest.most_positive {
+        lines.push(format!("*Most positive ({} {:.2})*: {}", pick.emotion, pick.score, quote(&pick.text)));
+    }
+    lines.push(format!("*Summary*: {}", summary.summary));
+    lines.push(format!("*Advice*: {}", summary.advice));
+    lines.join("\n")
+}
+
+// the suppressed statistics read as too few people, never as a number
+pub fn team_report_text(report: &TeamReport) -> String {
+    let too_few = format!("_fewer than {} people or messages_", report.min_group_size);
+    let (Some(people), Some(message_count)) = (report.people, report.message_count) else {
+        return format!("*{}*: {}", report.group, too_few)
+    };
+
+    let mut lines = vec![format!("*{}*: {} messages from {} people", report.group, message_count, people)];
+    match &report.emotions {
+        Some(emotions) => {
+            for name in taxonomy().names() {
+                let Some(stats) = emotions.get(name) else {
+                    continue
+                };
+                let above = match stats.above_threshold {
+                    Some(count) => format!("{} over {:.2}", count, report.threshold),
+                    None => format!("over {:.2}: {}", report.threshold, too_few),
+                };
+                lines.push(format!("{} average {:.2}, {}", name, stats.mean, above));
+            }
+        },
+        None => lines.push(format!("Emotions: {}", too_few)),
+    }
+    match (report.person_directed_count, report.person_directed_mean) {
+        (Some(count), Some(mean)) => lines.push(format!("*Aimed at a person*: {} messages, average {:.2}", count, mean)),
+        _ => lines.push(format!("*Aimed at a person*: {}", too_few)),
+    }
+    lines.join("\n")
+}
+
+// the legend of the chart colors, then the charts
+fn chart_blocks(charts: &[ChartImage]) -> Vec<Value> {
+    if charts.is_empty() {
+        return vec![]
+    }
+    let legend = taxonomy().names()
+        .enumerate()
+        .map(|(index, name)| format!("{} {}", color_emoji(index), name))
+        .collect::<Vec<String>>()
+        .join("  ");
+    let mut blocks = vec![json!({
+        "type": "context",
+        "elements": [{ "type": "mrkdwn", "text": legend }]
+    })];
+    blocks.extend(charts.iter().map(|chart| json!({
+        "type": "image",
+        "slack_file": { "id": chart.file_id },
+        "alt_text": chart.title,
+        "title": { "type": "plain_text", "text": chart.title }
+    })));
+    blocks
+}
+
+// the body of a slack api response, an error unless ok
+async fn slack_response(response: reqwest::Response) -> Result<Value> {
+    let body_string = response.text().await?;
+    log_response(&body_string);
+    let body = serde_json::from_str::<Value>(&body_string)?;
+    if body.get("ok").and_then(|ok| ok.as_bool()) != Some(true) {
+        bail!("Slack error: {}", body.get("error").and_then(|e| e.as_str()).unwrap_or("unknown"))
+    }
+    Ok(body)
+}
+
+// slack echoes the message back with its text, only whether it went through is logged
+fn log_response(body_string: &str) {
+    let body = serde_json::from_str::<Value>(body_string).unwrap_or_default();
+    println!("response: ok {}, error {}, ts {}", body["ok"], body["error"], body["ts"]);
+}
@@ -0,0 +1,302 @@
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use anyhow::{bail, Context, Result};
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde::{Deserialize, Serialize};
+use serde_dynamo::{from_item, to_attribute_value};
+use tokio::sync::Mutex;
+
+use crate::{env_keys::TABLE_NAME, retention::RetentionPolicy, schema::{CURRENT_SCHEMA_VERSION, UNKNOWN_MODEL_ID}};
+use super::{dynamo_service::structs::EmotionTableEntry, encryption::text_cipher::TextCipher};
+
+const DEFAULT_PAGE_SIZE: i32 = 100;
+
+
+// how far a segment of the scan went
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct SegmentProgress {
+    pub segment: u32,
+    // event id of the last entry read, where the scan resumes
+    pub last_event_id: Option<String>,
+    pub done: bool,
+    pub scanned: usize,
+    pub migrated: usize,
+    // entries given a ttl from ENTRY_RETENTION_DAYS, they were written before it was set
+    #[serde(default)]
+    pub ttl_set: usize,
+    // entries whose text was in the clear and is now encrypted, see MigrationService::with_cipher
+    #[serde(default)]
+    pub encrypted: usize,
+}
+
+
+// Progress of a migration to target_version, saved after every page so that an interrupted run resumes where it stopped.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MigrationCheckpoint {
+    pub target_version: u32,
+    pub total_segments: u32,
+    pub segments: Vec<SegmentProgress>,
+}
+
+impl MigrationCheckpoint {
+    pub fn new(total_segments: u32) -> Self {
+        Self {
+            target_version: CURRENT_SCHEMA_VERSION,
+            total_segments,
+            segments: (0..total_segments).map(|segment| SegmentProgress { segment, ..Default::default() }).collect(),
+        }
+    }
+
+    // the checkpoint at path if there is one, a new one otherwise.
+    // a checkpoint of another version or segment count can not be resumed.
+    pub fn load_or_new(path: &str, total_segments: u32) -> Result<Self> {
+        if !Path::new(path).exists() {
+            return Ok(Self::new(total_segments))
+        }
+        let content = std::fs::read_to_string(path).context(format!("Error reading checkpoint {}", path))?;
+        let checkpoint: Self = serde_json::from_str(&content).context(format!("Error parsing checkpoint {}", path))?;
+        if checkpoint.target_version != CURRENT_SCHEMA_VERSION {
+            bail!("Checkpoint {} migrates to version {}, not {}. Remove it to start over", path, checkpoint.target_version, CURRENT_SCHEMA_VERSION)
+        }
+        if checkpoint.total_segments != total_segments {
+            bail!("Checkpoint {} has {} segments, not {}. Resume with --segments {} or remove it", path, checkpoint.total_segments, total_segments, checkpoint.total_segments)
+        }
+        Ok(checkpoint)
+    }
+
+    // written to a temporary file first, so that a crash never leaves half a checkpoint
+    pub fn save(&self, path: &str) -> Result<()> {
+        let temporary = format!("{}.tmp", path);
+        std::fs::write(&temporary, serde_json::to_string_pretty(self)?).context(format!("Error writing checkpoint {}", temporary))?;
+        std::fs::rename(&temporary, path).context(format!("Error writing checkpoint {}", path))?;
+        Ok(())
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.segments.iter().all(|s| s.done)
+    }
+
+    // (scanned, migrated, ttl set, encrypted) over every segment
+    pub fn totals(&self) -> (usize, usize, usize, usize) {
+        self.segments.iter().fold((0, 0, 0, 0), |(scanned, migrated, ttl_set, encrypted), s| (scanned + s.scanned, migrated + s.migrated, ttl_set + s.ttl_set, encrypted + s.encrypted))
+    }
+}
+
+
+// Rewrites the entries of the emotion table to the current schema version with a parallel scan.
+// Each entry is upgraded as it is read (see schema::upgrade) and written back only if it is still of an older version
+// or has no ttl while ENTRY_RETENTION_DAYS is set, so running it again, or next to the lambdas, changes nothing that is already migrated.
+#[derive(Debug, Clone)]
+pub struct MigrationService {
+    client: aws_sdk_dynamodb::Client,
+    table_name: String,
+    page_size: i32,
+    // counts what would be migrated without writing
+    dry_run: bool,
+    // also encrypts the text of the entries still in the clear
+    cipher: Option<TextCipher>,
+    // the ttl of entries without one
+    retention: RetentionPolicy,
+}
+
+impl MigrationService {
+    pub fn new(client: &aws_sdk_dynamodb::Client) -> Self {
+        Self {
+            client: client.to_owned(),
+            table_name: std::env::var(TABLE_NAME).unwrap_or("".to_owned()),
+            page_size: DEFAULT_PAGE_SIZE,
+            dry_run: false,
+            cipher: None,
+            retention: RetentionPolicy::from_env(),
+        }
+    }
+
+    pub fn with_page_size(self, page_size: i32) -> Self {
+        Self { page_size, ..self }
+    }
+
+    pub fn with_dry_run(self, dry_run: bool) -> Self {
+        Self { dry_run, ..self }
+    }
+
+    pub fn with_cipher(self, cipher: &TextCipher) -> Self {
+        Self { cipher: Some(cipher.to_owned()), ..self }
+    }
+
+    // runs the segments not done yet in parallel, the checkpoint is saved to checkpoint_path after every page (not on a dry run)
+    pub async fn run(&self, checkpoint: MigrationCheckpoint, checkpoint_path: &str) -> Result<MigrationCheckpoint> {
+        if self.table_name.is_empty() {
+            bail!("{} is not set", TABLE_NAME)
+        }
+        let total_segments = checkpoint.total_segments;
+        let pending: Vec<u32> = checkpoint.segments.iter().filter(|s| !s.done).map(|s| s.segment).collect();
+        let checkpoint = Arc::new(Mutex::new(checkpoint));
+
+        futures::future::try_join_all(pending.into_iter().map(|segment| {
+            let checkpoint = checkpoint.clone();
+            async move { self.run_segment(segment, total_segments, checkpoint, checkpoint_path).await }
+        })).await?;
+
+        let checkpoint = checkpoint.lock().await.to_owned();
+        Ok(checkpoint)
+    }
+
+    async fn run_segment(&self, segment: u32, total_segments: u32, checkpoint: Arc<Mutex<MigrationCheckpoint>>, checkpoint_path: &str) -> Result<()> {
+        let mut progress = checkpoint.lock().await.segments[segment as usize].to_owned();
+        loop {
+            let exclusive_start_key = progress.last_event_id.as_ref()
+                .map(|event_id| HashMap::from([("event_id".to_owned(), AttributeValue::S(event_id.to_owned()))]));
+            let output = self.client
+                .scan()
+                .table_name(&self.table_name)
+                .segment(segment as i32)
+                .total_segments(total_segments as i32)
+                .limit(self.page_size)
+                .consistent_read(true)
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await?;
+
+            for item in output.items.unwrap_or_default() {
+                progress.scanned += 1;
+                let (migrated, ttl_set) = self.migrate_item(item.clone()).await?;
+                progress.migrated += migrated as usize;
+                progress.ttl_set += ttl_set as usize;
+                if let Some(cipher) = &self.cipher {
+                    if self.encrypt_item(cipher, item).await? {
+                        progress.encrypted += 1;
+                    }
+                }
+            }
+            progress.last_event_id = output.last_evaluated_key
+                .and_then(|key| key.get("event_id").and_then(|e| e.as_s().ok()).map(|e| e.to_owned()));
+            progress.done = progress.last_event_id.is_none();
+
+            let mut checkpoint = checkpoint.lock().await;
+            checkpoint.segments[segment as usize] = progress.to_owned();
+            if !self.dry_run {
+                checkpoint.save(checkpoint_path)?;
+            }
+            let (scanned, migrated, _, _) = checkpoint.totals();
+            println!("segment {}/{}: scanned {}, migrated {} (all segments: scanned {}, migrated {})", segment, total_segments, progress.scanned, progress.migrated, scanned, migrated);
+            if progress.done {
+                return Ok(())
+            }
+        }
+    }
+
+    // whether the entry was upgraded to the current version, and whether its ttl was set (see ENTRY_RETENTION_DAYS).
+    // only what the upgrade and the ttl change is written, so that attributes the entry does not know about,
+    // and writes of the lambdas in the meantime, are kept.
+    async fn migrate_item(&self, item: HashMap<String, AttributeValue>) -> Result<(bool, bool)> {
+        let version: u32 = match item.get("schema_version").map(|v| v.as_n()) {
+            Some(Ok(version)) => version.parse()?,
+            _ => 0,
+        };
+        let entry: EmotionTableEntry = from_item(item)?;
+        let upgrade = version < CURRENT_SCHEMA_VERSION;
+        // entries written before the retention was set
+        let ttl = self.retention.expires_at(entry.timestamp).filter(|_| entry.ttl.is_none());
+        if !upgrade && ttl.is_none() {
+            return Ok((false, false))
+        }
+        if self.dry_run {
+            return Ok((upgrade, ttl.is_some()))
+        }
+
+        let mut sets = vec![];
+        let mut condition = "attribute_exists(event_id)".to_owned();
+        let mut attribute_names = HashMap::new();
+        let mut attribute_values = HashMap::new();
+        if upgrade {
+            sets.push("schema_version = :version, model_id = if_not_exists(model_id, :model_id)");
+            // not migrated in the meantime
+            condition.push_str(" AND (attribute_not_exists(schema_version) OR schema_version < :version)");
+            attribute_values.insert(":version".to_owned(), AttributeValue::N(CURRENT_SCHEMA_VERSION.to_string()));
+            attribute_values.insert(":model_id".to_owned(), AttributeValue::S(entry.model_id.unwrap_or(UNKNOWN_MODEL_ID.to_owned())));
+        }
+        if let Some(ttl) = ttl {
+            // a ttl in the past is deleted by dynamo, the entry is past retention already
+            sets.push("#ttl = if_not_exists(#ttl, :ttl)");
+            if !upgrade {
+                condition.push_str(" AND attribute_not_exists(#ttl)");
+            }
+            attribute_names.insert("#ttl".to_owned(), "ttl".to_owned());
+            attribute_values.insert(":ttl".to_owned(), AttributeValue::N(ttl.to_string()));
+        }
+
+        let result = self.client
+            .update_item()
+            .table_name(&self.table_name)
+            .key("event_id", AttributeValue::S(entry.event_id.to_owned()))
+            .update_expression(format!("SET {}", sets.join(", ")))
+            // not deleted in the meantime
+            .condition_expression(condition)
+            .set_expression_attribute_names(Some(attribute_names).filter(|n| !n.is_empty()))
+            .set_expression_attribute_values(Some(attribute_values))
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok((upgrade, ttl.is_some())),
+            Err(e) if e.as_service_error().map(|e| e.is_conditional_check_failed_exception()).unwrap_or(false) => Ok((false, false)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // replaces the text and the rewrite still in the clear with their ciphertext, see TextCipher::seal.
+    // whether the entry was (or would be on a dry run) encrypted.
+    async fn encrypt_item(&self, cipher: &TextCipher, item: HashMap<String, AttributeValue>) -> Result<bool> {
+        let entry: EmotionTableEntry = from_item(item)?;
+        if entry.text.is_empty() && entry.rewrite.is_none() {
+            return Ok(false)
+        }
+        if self.dry_run {
+            return Ok(true)
+        }
+
+        let sealed = cipher.seal(&entry).await?;
+        let mut sets = vec![];
+        let mut removes = vec![];
+        let mut conditions = vec!["attribute_exists(event_id)".to_owned()];
+        let mut attribute_names = HashMap::new();
+        let mut attribute_values = HashMap::new();
+        if let Some(encrypted) = &sealed.encrypted_text {
+            sets.push("encrypted_text = :encrypted_text, #text = :empty");
+            // not changed in the meantime, ie: purged
+            conditions.push("#text = :text".to_owned());
+            attribute_names.insert("#text".to_owned(), "text".to_owned());
+            attribute_values.insert(":encrypted_text".to_owned(), to_attribute_value(encrypted)?);
+            attribute_values.insert(":empty".to_owned(), AttributeValue::S("".to_owned()));
+            attribute_values.insert(":text".to_owned(), AttributeValue::S(entry.text.to_owned()));
+        }
+        if let (Some(encrypted), Some(rewrite)) = (&sealed.encrypted_rewrite, &entry.rewrite) {
+            sets.push("encrypted_rewrite = :encrypted_rewrite");
+            removes.push("rewrite");
+            conditions.push("rewrite = :rewrite".to_owned());
+            attribute_values.insert(":encrypted_rewrite".to_owned(), to_attribute_value(encrypted)?);
+            attribute_values.insert(":rewrite".to_owned(), AttributeValue::S(rewrite.to_owned()));
+        }
+        let mut update_expression = format!("SET {}", sets.join(", "));
+        if !removes.is_empty() {
+            update_expression = format!("{} REMOVE {}", update_expression, removes.join(", "));
+        }
+
+        let result = self.client
+            .update_item()
+            .table_name(&self.table_name)
+            .key("event_id", AttributeValue::S(entry.event_id.to_owned()))
+            .update_expression(update_expression)
+            .condition_expression(conditions.join(" AND "))
+            .set_expression_attribute_names(Some(attribute_names).filter(|n| !n.is_empty()))
+            .set_expression_attribute_values(Some(attribute_values))
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if e.as_service_error().map(|e| e.is_conditional_check_failed_exception()).unwrap_or(false) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -4,34 +4,81 @@ pub mod sqs_service;
 pub mod line_service;
 pub mod s3_service;
 pub mod common_structs;
+pub mod emotion_scorer;
+pub mod emotion_repository;
+pub mod usage_service;
+pub mod score_cache_service;
+pub mod rollup_service;
+pub mod user_data_service;
+pub mod encryption;
+pub mod migration_service;
+pub mod rescoring_service;
 
+use std::sync::Arc;
+use anyhow::{bail, Result};
 use aws_config::SdkConfig;
+use dynamo_service::structs::EmotionTableEntry;
+use encryption::text_cipher::{text_cipher_from_env, TextCipher};
+use emotion_repository::{repository_from_env, EmotionRepository};
+use emotion_scorer::{scorer_from_env, EmotionScorer};
 
 
 #[derive(Debug, Clone)]
 pub struct CommonService {
-    pub dynamo: dynamo_service::DynamoService,
+    pub repository: Arc<dyn EmotionRepository>,
     pub bedrock: bedrock_service::BedrockService,
     pub sqs: sqs_service::SQSService,
     pub s3: s3_service::S3Service,
     pub line: line_service::LineService,
+    pub scorer: Arc<dyn EmotionScorer>,
+    pub usage: usage_service::UsageService,
+    // none if the text is stored in the clear
+    pub cipher: Option<TextCipher>,
 }
 
 impl CommonService {
     pub fn new(config: &SdkConfig) -> Self {
-        let dynamo_client = aws_sdk_dynamodb::Client::new(&config);
-        let bedrock_client = aws_sdk_bedrockruntime::Client::new(&config);
-        let sqs_client = aws_sdk_sqs::Client::new(&config);
-        let s3_client = aws_sdk_s3::Client::new(&config);
+        let dynamo_client = aws_sdk_dynamodb::Client::new(config);
+        let bedrock_client = aws_sdk_bedrockruntime::Client::new(config);
+        let sqs_client = aws_sdk_sqs::Client::new(config);
+        let s3_client = aws_sdk_s3::Client::new(config);
+        let kms_client = aws_sdk_kms::Client::new(config);
 
         let line_client = line_service::LineService::new();
+        let usage = usage_service::UsageService::new(&dynamo_client);
+        let bedrock = bedrock_service::BedrockService::new(&bedrock_client).with_usage(&usage);
+        let score_cache = score_cache_service::ScoreCacheService::new(&dynamo_client);
+        let scorer = scorer_from_env(&bedrock, &score_cache);
+        let repository = repository_from_env(&dynamo_service::DynamoService::new(&dynamo_client));
+        // better not to start than to store the text in the clear by mistake
+        let cipher = text_cipher_from_env(&kms_client, &dynamo_client).expect("Error configuring text encryption");
 
         Self {
-            dynamo: dynamo_service::DynamoService::new(&dynamo_client),
-            bedrock: bedrock_service::BedrockService::new(&bedrock_client),
+            repository,
+            bedrock,
             sqs: sqs_service::SQSService::new(&sqs_client),
             s3: s3_service::S3Service::new(&s3_client),
-            line: line_client
+            line: line_client,
+            scorer,
+            usage,
+            cipher,
+        }
+    }
+
+    // the entry as stored, with its text encrypted if encryption is on
+    pub async fn seal(&self, entry: &EmotionTableEntry) -> Result<EmotionTableEntry> {
+        match &self.cipher {
+            Some(cipher) => cipher.seal(entry).await,
+            None => Ok(entry.to_owned()),
+        }
+    }
+
+    // the entry with its text in the clear, only where the text is needed
+    pub async fn open(&self, entry: &EmotionTableEntry) -> Result<EmotionTableEntry> {
+        match &self.cipher {
+            Some(cipher) => cipher.open(entry).await,
+            None if entry.is_encrypted() => bail!("Entry {} is encrypted but TEXT_ENCRYPTION is off", entry.event_id),
+            None => Ok(entry.to_owned()),
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,178 @@
+use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+
+use anyhow::{bail, Result};
+use futures::{stream, StreamExt};
+
+use crate::{redaction::Redactor, rescoring::{generation_id, RescoreLimits, RescoreReport, RescoreSelection, ScoringGeneration}, slack_markup::normalize, utilities::get_day_range, warnings::DEFAULT_WARNING_THRESHOLD};
+use super::{common_structs::EmotionScores, dynamo_service::structs::EmotionTableEntry, emotion_repository::{ChannelQuery, EmotionRepository, UserQuery}, emotion_scorer::EmotionScorer, encryption::text_cipher::TextCipher, usage_service::CostMeter};
+
+
+enum Outcome {
+    Rescored { event_id: String, old: EmotionScores, new: EmotionScores },
+    Existing,
+    WithoutText,
+    LimitReached,
+    Failed,
+}
+
+
+// Scores stored entries again with the model and prompt the scorer is configured with,
+// and stores the result as a generation next to the original scores, which are never changed.
+#[derive(Debug, Clone)]
+pub struct RescoringService {
+    repository: Arc<dyn EmotionRepository>,
+    scorer: Arc<dyn EmotionScorer>,
+    // the meter the scorer charges, max_cost applies to it
+    cost_meter: CostMeter,
+    // none if the text is stored in the clear
+    cipher: Option<TextCipher>,
+    redactor: Redactor,
+    // the report counts the messages that crossed it
+    warning_threshold: f64,
+}
+
+impl RescoringService {
+    pub fn new(repository: &Arc<dyn EmotionRepository>, scorer: Arc<dyn EmotionScorer>) -> Self {
+        Self {
+            repository: repository.to_owned(),
+            scorer,
+            cost_meter: CostMeter::default(),
+            cipher: None,
+            redactor: Redactor::from_env(),
+            warning_threshold: DEFAULT_WARNING_THRESHOLD,
+        }
+    }
+
+    // max_cost needs the meter the scorer charges, ie: BedrockService::with_cost_meter
+    pub fn with_cost_meter(mut self, cost_meter: &CostMeter) -> Self {
+        self.cost_meter = cost_meter.to_owned();
+        self
+    }
+
+    pub fn with_cipher(mut self, cipher: Option<TextCipher>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    pub fn with_warning_threshold(mut self, warning_threshold: f64) -> Self {
+        self.warning_threshold = warning_threshold;
+        self
+    }
+
+    // {model}@{prompt version} of the configured model and prompt
+    pub fn default_generation_id(&self) -> Result<String> {
+        let (model_id, prompt_version) = self.scorer.configuration();
+        Ok(generation_id(&model_id, prompt_version.as_deref()))
+    }
+
+    // entries that already have the generation are skipped unless force, so an interrupted job can be run again
+    pub async fn run(&self, selection: &RescoreSelection, limits: &RescoreLimits, generation: &str, force: bool) -> Result<RescoreReport> {
+        if limits.concurrency == 0 {
+            bail!("concurrency must be positive")
+        }
+        let (model_id, prompt_version) = self.scorer.configuration();
+        let mut report = RescoreReport {
+            generation: generation.to_owned(),
+            model_id,
+            prompt_version,
+            selection: Some(selection.to_owned()),
+            started_at: chrono::Utc::now().timestamp() as u64,
+            warning_threshold: self.warning_threshold,
+            ..Default::default()
+        };
+
+        let entries = self.select(selection).await?;
+        report.selected = entries.len();
+        println!("rescoring {} entries from {} to {} as {}", entries.len(), selection.from, selection.to, generation);
+
+        let started = AtomicUsize::new(0);
+        let mut outcomes = stream::iter(entries)
+            .map(|entry| {
+                let started = &started;
+                async move { self.rescore(entry, limits, generation, force, started).await }
+            })
+            .buffer_unordered(limits.concurrency);
+
+        while let Some(outcome) = outcomes.next().await {
+            match outcome {
+                Outcome::Rescored { event_id, old, new } => {
+                    report.rescored += 1;
+                    report.add_diff(&event_id, &old, &new);
+                },
+                Outcome::Existing => report.skipped_existing += 1,
+                Outcome::WithoutText => report.skipped_without_text += 1,
+                Outcome::LimitReached => {
+                    report.not_rescored += 1;
+                    report.limit_reached = true;
+                },
+                Outcome::Failed => report.failed += 1,
+            }
+        }
+
+        report.estimated_cost = self.cost_meter.spent();
+        report.finished_at = chrono::Utc::now().timestamp() as u64;
+        Ok(report)
+    }
+
+    // a user or channel selection is read from its index, any other from each date
+    async fn select(&self, selection: &RescoreSelection) -> Result<Vec<EmotionTableEntry>> {
+        let dates = selection.dates()?;
+        let (from, to) = get_day_range(&selection.from, &selection.to)?;
+        let entries = match (&selection.user_id, &selection.channel_id) {
+            (Some(user_id), _) => self.repository.query_user(&UserQuery::new(user_id, from, to)).await?,
+            (None, Some(channel_id)) => self.repository.query_channel(&ChannelQuery::new(channel_id, from, to)).await?,
+            (None, None) => {
+                let mut entries = vec![];
+                for date in dates {
+                    entries.extend(self.repository.query_date(&date).await?);
+                }
+                entries
+            },
+        };
+        Ok(entries.into_iter().filter(|e| selection.matches(e)).collect())
+    }
+
+    // a failure is counted in the report rather than stopping the job
+    async fn rescore(&self, entry: EmotionTableEntry, limits: &RescoreLimits, generation: &str, force: bool, started: &AtomicUsize) -> Outcome {
+        if entry.generations.contains_key(generation) && !force {
+            return Outcome::Existing
+        }
+        let entry = match &self.cipher {
+            Some(cipher) if entry.is_encrypted() => match cipher.open(&entry).await {
+                Ok(entry) => entry,
+                Err(error) => {
+                    println!("Error decrypting {}: {:?}", entry.event_id, error);
+                    return Outcome::Failed
+                },
+            },
+            _ => entry,
+        };
+        if entry.text.trim().is_empty() {
+            return Outcome::WithoutText
+        }
+
+        if limits.max_cost.map(|max_cost| self.cost_meter.spent() >= max_cost).unwrap_or(false) {
+            return Outcome::LimitReached
+        }
+        let index = started.fetch_add(1, Ordering::SeqCst);
+        if limits.max_messages.map(|max_messages| index >= max_messages).unwrap_or(false) {
+            return Outcome::LimitReached
+        }
+
+        // scored the way the sqs handler scores new messages
+        let text = self.redactor.redact(&normalize(&entry.text).text).text;
+        let result = match self.scorer.score(&text, &entry.language).await {
+            Ok(result) => result,
+            Err(error) => {
+                println!("Error scoring {}: {:?}", entry.event_id, error);
+                return Outcome::Failed
+            },
+        };
+        let scoring_generation = ScoringGeneration::new(&result, chrono::Utc::now().timestamp() as u64);
+        if let Err(error) = self.repository.put_generation(&entry.event_id, generation, &scoring_generation).await {
+            println!("Error storing generation of {}: {:?}", entry.event_id, error);
+            return Outcome::Failed
+        }
+        Outcome::Rescored { event_id: entry.event_id, old: entry.scores, new: result.scores }
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use aws_sdk_dynamodb::types::{AttributeValue, TransactWriteItem, Update};
+use futures::future::try_join_all;
+use serde_dynamo::{from_item, from_items, to_item};
+
+use crate::{env_keys::{ROLLUP_TABLE_NAME, WORKSPACE_ID}, rollup::{is_within, Granularity, Rollup, RollupDelta, RollupKey, RollupQuery, RollupScope, ARGMAX_SUFFIX, MAX_SUFFIX, SUM_SQ_SUFFIX, SUM_SUFFIX}, taxonomy::taxonomy};
+use super::{dynamo_service::structs::EmotionTableEntry, usage_service::DEFAULT_WORKSPACE_ID};
+
+
+// Rollups of the emotion table, pk rollup_id ({scope}#{id}) and sk period ({granularity}#{start}).
+// Kept up to date as entries are written, see updates.
+#[derive(Debug, Clone)]
+pub struct RollupService {
+    client: aws_sdk_dynamodb::Client,
+    // empty if rollups are not maintained
+    table_name: String,
+    workspace_id: String,
+}
+
+impl RollupService {
+    pub fn new(client: &aws_sdk_dynamodb::Client) -> Self {
+        Self {
+            client: client.to_owned(),
+            table_name: std::env::var(ROLLUP_TABLE_NAME).unwrap_or("".to_owned()),
+            workspace_id: std::env::var(WORKSPACE_ID).ok().filter(|w| !w.is_empty()).unwrap_or(DEFAULT_WORKSPACE_ID.to_owned()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.table_name.is_empty()
+    }
+
+    pub fn workspace_id(&self) -> &str {
+        &self.workspace_id
+    }
+
+    // updates adding the entry to each of its rollups, previous is the entry it replaced if any.
+    // written in one transaction with the entry, see DynamoService::register_entry.
+    // count, sum and sum of squares are ADDs. a raised max is SET on condition that the max read is still the current one,
+    // a concurrent write then cancels the transaction rather than being overwritten.
+    // a replaced entry is subtracted, but the max can not go down: rebuild the rollups to get it exact.
+    pub async fn updates(&self, entry: &EmotionTableEntry, previous: Option<&EmotionTableEntry>) -> Result<Vec<TransactWriteItem>> {
+        let keys = RollupKey::for_entry(entry, &self.workspace_id)?;
+        let rollups = try_join_all(keys.iter().map(|key| self.get(key))).await?;
+        let delta = RollupDelta::new(entry, previous);
+        keys.iter()
+            .zip(rollups)
+            .map(|(key, rollup)| Ok(TransactWriteItem::builder().update(self.update(key, &delta, entry, rollup.as_ref())?).build()))
+            .collect()
+    }
+
+    // consistent, the transaction is only as good as what it was computed from
+    async fn get(&self, key: &RollupKey) -> Result<Option<Rollup>> {
+        let output = self.client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("rollup_id", AttributeValue::S(key.rollup_id()))
+            .key("period", AttributeValue::S(key.period()))
+            .consistent_read(true)
+            .send()
+            .await?;
+        Ok(output.item.map(from_item).transpose()?)
+    }
+
+    // current is the rollup as read before the transaction, none if there is none yet
+    fn update(&self, key: &RollupKey, delta: &RollupDelta, entry: &EmotionTableEntry, current: Option<&Rollup>) -> Result<Update> {
+        // count and emotion names are aliased, they may be reserved words
+        let mut attribute_names: HashMap<String, String> = HashMap::from([
+            ("#count".to_owned(), "count".to_owned()),
+            ("#scope".to_owned(), "scope".to_owned()),
+            ("#scope_id".to_owned(), "scope_id".to_owned()),
+            ("#granularity".to_owned(), "granularity".to_owned()),
+            ("#period_start".to_owned(), "period_start".to_owned()),
+        ]);
+        let mut attribute_values: HashMap<String, AttributeValue> = HashMap::from([
+            (":count".to_owned(), AttributeValue::N(delta.count.to_string())),
+            (":scope".to_owned(), AttributeValue::S(key.scope.code().to_owned())),
+            (":scope_id".to_owned(), AttributeValue::S(key.scope_id.to_owned())),
+            (":granularity".to_owned(), AttributeValue::S(key.granularity.code().to_owned())),
+            (":period_start".to_owned(), AttributeValue::S(key.period_start.to_owned())),
+        ]);
+
+        let mut assignments = vec![
+            "#scope = :scope".to_owned(),
+            "#scope_id = :scope_id".to_owned(),
+            "#granularity = :granularity".to_owned(),
+            "#period_start = :period_start".to_owned(),
+        ];
+        let mut additions = vec!["#count :count".to_owned()];
+        for (index, (name, sum, sum_sq)) in delta.sums.iter().enumerate() {
+            attribute_names.insert(format!("#sum{}", index), format!("{}{}", name, SUM_SUFFIX));
+            attribute_names.insert(format!("#sum_sq{}", index), format!("{}{}", name, SUM_SQ_SUFFIX));
+            attribute_values.insert(format!(":sum{}", index), AttributeValue::N(sum.to_string()));
+            attribute_values.insert(format!(":sum_sq{}", index), AttributeValue::N(sum_sq.to_string()));
+            additions.push(format!("#sum{} :sum{}", index, index));
+            additions.push(format!("#sum_sq{} :sum_sq{}", index, index));
+        }
+
+        let mut conditions = vec![];
+        for (index, name) in taxonomy().names().enumerate() {
+            let score = entry.scores.get(name);
+            let max = current.and_then(|rollup| rollup.stats.get(name).max);
+            if max.map(|max| score <= max).unwrap_or(false) {
+                continue;
+            }
+            attribute_names.insert(format!("#max{}", index), format!("{}{}", name, MAX_SUFFIX));
+            attribute_names.insert(format!("#argmax{}", index), format!("{}{}", name, ARGMAX_SUFFIX));
+            attribute_values.insert(format!(":max{}", index), AttributeValue::N(score.to_string()));
+            attribute_values.insert(format!(":argmax{}", index), AttributeValue::S(entry.event_id.to_owned()));
+            assignments.push(format!("#max{} = :max{}", index, index));
+            assignments.push(format!("#argmax{} = :argmax{}", index, index));
+            match max {
+                Some(max) => {
+                    attribute_values.insert(format!(":current_max{}", index), AttributeValue::N(max.to_string()));
+                    conditions.push(format!("#max{} = :current_max{}", index, index));
+                },
+                None => conditions.push(format!("attribute_not_exists(#max{})", index)),
+            }
+        }
+
+        Ok(Update::builder()
+            .table_name(&self.table_name)
+            .key("rollup_id", AttributeValue::S(key.rollup_id()))
+            .key("period", AttributeValue::S(key.period()))
+            .update_expression(format!("SET {} ADD {}", assignments.join(", "), additions.join(", ")))
+            .set_condition_expression(Some(conditions.join(" AND ")).filter(|c| !c.is_empty()))
+            .set_expression_attribute_names(Some(attribute_names))
+            .set_expression_attribute_values(Some(attribute_values))
+            .build()?)
+    }
+
+    // replaces the rollup, for rebuilds
+    pub async fn put(&self, rollup: &Rollup) -> Result<()> {
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(to_item(rollup)?))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    // deletes the rollups of the periods within start and end that are not in keep (rollup_id, period), returns how many.
+    // a scan, rebuilds are rare
+    pub async fn delete_stale(&self, start: NaiveDate, end: NaiveDate, keep: &HashSet<(String, String)>) -> Result<usize> {
+        let mut deleted = 0;
+        let mut exclusive_start_key = None;
+        loop {
+            let output = self.client
+                .scan()
+                .table_name(&self.table_name)
+                .filter_expression("#period_start BETWEEN :start AND :end")
+                .expression_attribute_names("#period_start", "period_start")
+                .expression_attribute_values(":start", AttributeValue::S(start.to_string()))
+                .expression_attribute_values(":end", AttributeValue::S(end.to_string()))
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await?;
+            let rollups: Vec<Rollup> = from_items(output.items.unwrap_or_default())?;
+            for rollup in rollups {
+                if !is_within(&rollup, start, end)? || keep.contains(&(rollup.rollup_id.to_owned(), rollup.period.to_owned())) {
+                    continue;
+                }
+                self.client
+                    .delete_item()
+                    .table_name(&self.table_name)
+                    .key("rollup_id", AttributeValue::S(rollup.rollup_id))
+                    .key("period", AttributeValue::S(rollup.period))
+                    .send()
+                    .await?;
+                deleted += 1;
+            }
+            exclusive_start_key = output.last_evaluated_key;
+            if exclusive_start_key.is_none() {
+                break
+            }
+        }
+        Ok(deleted)
+    }
+
+    // deletes every rollup of the scope (ie: user#U0123), returns how many
+    pub async fn delete_all(&self, rollup_id: &str) -> Result<usize> {
+        let mut deleted = 0;
+        let mut exclusive_start_key = None;
+        loop {
+            let output = self.client
+                .query()
+                .table_name(&self.table_name)
+                .key_condition_expression("rollup_id = :rollup_id")
+                .expression_attribute_values(":rollup_id", AttributeValue::S(rollup_id.to_owned()))
+                .projection_expression("rollup_id, #period")
+                .expression_attribute_names("#period", "period")
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await?;
+            for key in output.items.unwrap_or_default() {
+                self.client
+                    .delete_item()
+                    .table_name(&self.table_name)
+                    .set_key(Some(key))
+                    .send()
+                    .await?;
+                deleted += 1;
+            }
+            exclusive_start_key = output.last_evaluated_key;
+            if exclusive_start_key.is_none() {
+                break
+            }
+        }
+        Ok(deleted)
+    }
+
+    // oldest period first
+    pub async fn query(&self, query: &RollupQuery) -> Result<Vec<Rollup>> {
+        let attribute_values: HashMap<String, AttributeValue> = HashMap::from([
+            (":rollup_id".to_owned(), AttributeValue::S(query.rollup_id())),
+            (":from".to_owned(), AttributeValue::S(format!("{}#{}", query.granularity.code(), query.from))),
+            (":to".to_owned(), AttributeValue::S(format!("{}#{}", query.granularity.code(), query.to))),
+        ]);
+
+        let mut rollups = vec![];
+        let mut exclusive_start_key = None;
+        loop {
+            let output = self.client
+                .query()
+                .scan_index_forward(true)
+                .table_name(&self.table_name)
+                .key_condition_expression("rollup_id = :rollup_id AND #period BETWEEN :from AND :to")
+                .expression_attribute_names("#period", "period")
+                .set_expression_attribute_values(Some(attribute_values.clone()))
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await?;
+            let items = output.items.context("items not available")?;
+            rollups.append(&mut from_items(items)?);
+            exclusive_start_key = output.last_evaluated_key;
+            if exclusive_start_key.is_none() {
+                break
+            }
+        }
+        Ok(rollups)
+    }
+
+    // gsi-period, pk period and sk rollup_id
+    pub async fn query_period(&self, scope: RollupScope, granularity: Granularity, period_start: &str) -> Result<Vec<Rollup>> {
+        let attribute_values: HashMap<String, AttributeValue> = HashMap::from([
+            (":period".to_owned(), AttributeValue::S(format!("{}#{}", granularity.code(), period_start))),
+            (":scope".to_owned(), AttributeValue::S(format!("{}#", scope.code()))),
+        ]);
+
+        let mut rollups = vec![];
+        let mut exclusive_start_key = None;
+        loop {
+            let output = self.client
+                .query()
+                .table_name(&self.table_name)
+                .index_name("gsi-period")
+                .key_condition_expression("#period = :period AND begins_with(rollup_id, :scope)")
+                .expression_attribute_names("#period", "period")
+                .set_expression_attribute_values(Some(attribute_values.clone()))
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await?;
+            let items = output.items.context("items not available")?;
+            rollups.append(&mut from_items(items)?);
+            exclusive_start_key = output.last_evaluated_key;
+            if exclusive_start_key.is_none() {
+                break
+            }
+        }
+        Ok(rollups)
+    }
+}
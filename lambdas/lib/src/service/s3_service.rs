@@ -1,9 +1,66 @@
 
+use std::collections::BTreeMap;
+
 use anyhow::{Context, Result};
-use aws_sdk_s3::types::{Delete, ObjectIdentifier};
+use aws_sdk_s3::{primitives::ByteStream, types::{Delete, ObjectIdentifier}};
+use chrono::DateTime;
+use serde_json::Value;
 
 pub const MANIFEST_JSON: &str = "manifest-files.json";
+pub const MANIFEST_SUMMARY_JSON: &str = "manifest-summary.json";
+// where dynamo writes table exports, one AWSDynamoDB/{export id}/ prefix per export
+pub const EXPORT_PREFIX: &str = "AWSDynamoDB/";
+pub const RETENTION_REPORT_PREFIX: &str = "retention-reports/";
+pub const USER_DATA_AUDIT_PREFIX: &str = "audit/user-data/";
 const DATA_FOLDER: &str = "data/";
+// the most keys delete_objects takes at once
+const DELETE_BATCH_SIZE: usize = 1000;
+
+// the objects of one table export
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableExport {
+    // AWSDynamoDB/{export id}/
+    pub prefix: String,
+    pub keys: Vec<String>,
+    // epoch seconds, the exportTime of its manifest summary.
+    // when its last object was written for exports without one, objects are rewritten on erasure
+    pub written_at: u64,
+}
+
+// groups the objects (key, last modified in epoch seconds) listed under EXPORT_PREFIX by export, ordered by prefix.
+// keys that are not under an export id are left out
+pub fn group_exports(objects: Vec<(String, u64)>) -> Vec<TableExport> {
+    let mut exports: BTreeMap<String, TableExport> = BTreeMap::new();
+    for (key, last_modified) in objects {
+        let Some(export_id) = key.strip_prefix(EXPORT_PREFIX).and_then(|k| k.split('/').next()).filter(|id| !id.is_empty()) else {
+            continue;
+        };
+        let prefix = format!("{}{}/", EXPORT_PREFIX, export_id);
+        let export = exports.entry(prefix.to_owned()).or_insert_with(|| TableExport { prefix, ..Default::default() });
+        export.keys.push(key);
+        export.written_at = export.written_at.max(last_modified);
+    }
+    exports.into_values().collect()
+}
+
+// exportTime of a manifest-summary.json in epoch seconds, none if it has none
+pub fn export_time(summary: &str) -> Result<Option<u64>> {
+    let summary: Value = serde_json::from_str(summary).context("Error parsing manifest summary")?;
+    let Some(export_time) = summary["exportTime"].as_str() else {
+        return Ok(None)
+    };
+    let export_time = DateTime::parse_from_rfc3339(export_time).context(format!("Invalid exportTime {}", export_time))?;
+    Ok(Some(export_time.timestamp().max(0) as u64))
+}
+
+// the exports written before cutoff (epoch seconds), never the latest one
+pub fn expired_exports(exports: Vec<TableExport>, cutoff: u64) -> Vec<TableExport> {
+    let latest = exports.iter().max_by_key(|export| export.written_at).map(|export| export.prefix.to_owned());
+    exports.into_iter()
+        .filter(|export| export.written_at < cutoff && Some(&export.prefix) != latest.as_ref())
+        .collect()
+}
+
 
 #[derive(Debug, Clone)]
 pub struct S3Service {
@@ -45,28 +102,80 @@ impl S3Service {
         Ok(())
     }
 
-    async fn delete_objects(&self, bucket_name: &str, object_keys: &Vec<String>) -> Result<()> {
-        let mut delete_object_ids: Vec<ObjectIdentifier> = vec![];
-        for key in object_keys {
-            println!("delete object: {}", key);
-            let obj_id = ObjectIdentifier::builder()
-            .key(key)
-            .build()?;
-            delete_object_ids.push(obj_id);
+    async fn delete_objects(&self, bucket_name: &str, object_keys: &[String]) -> Result<()> {
+        for batch in object_keys.chunks(DELETE_BATCH_SIZE) {
+            let mut delete_object_ids: Vec<ObjectIdentifier> = vec![];
+            for key in batch {
+                println!("delete object: {}", key);
+                let obj_id = ObjectIdentifier::builder()
+                .key(key)
+                .build()?;
+                delete_object_ids.push(obj_id);
+            }
+            self.client.delete_objects()
+                .bucket(bucket_name)
+                .delete(
+                    Delete::builder()
+                        .set_objects(Some(delete_object_ids))
+                        .build()?
+                )
+                .send()
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    // deletes the table exports written before cutoff (epoch seconds), always keeping the latest one.
+    // returns the export prefixes deleted and the number of objects deleted
+    pub async fn purge_exports(&self, bucket_name: &str, cutoff: u64) -> Result<(Vec<String>, usize)> {
+        let mut exports = group_exports(self.list_objects_modified(bucket_name, EXPORT_PREFIX).await?);
+        for export in exports.iter_mut() {
+            let summary_key = format!("{}{}", export.prefix, MANIFEST_SUMMARY_JSON);
+            if !export.keys.contains(&summary_key) {
+                continue;
+            }
+            let summary = String::from_utf8(self.get_object_bytes(bucket_name, &summary_key).await?)?;
+            if let Some(export_time) = export_time(&summary)? {
+                export.written_at = export_time;
+            }
+        }
+        let expired = expired_exports(exports, cutoff);
+        let keys: Vec<String> = expired.iter().flat_map(|export| export.keys.to_owned()).collect();
+        if !keys.is_empty() {
+            self.delete_objects(bucket_name, &keys).await?;
         }
-        self.client.delete_objects()
+        Ok((expired.into_iter().map(|export| export.prefix).collect(), keys.len()))
+    }
+
+    pub async fn put_object(&self, bucket_name: &str, key: &str, body: &str) -> Result<()> {
+        self.put_object_bytes(bucket_name, key, body.as_bytes().to_vec()).await
+    }
+
+    pub async fn put_object_bytes(&self, bucket_name: &str, key: &str, body: Vec<u8>) -> Result<()> {
+        self.client.put_object()
             .bucket(bucket_name)
-            .delete(
-                Delete::builder()
-                    .set_objects(Some(delete_object_ids))
-                    .build()?
-            )
+            .key(key)
+            .body(ByteStream::from(body))
             .send()
             .await?;
-
         Ok(())
     }
 
+    pub async fn get_object_bytes(&self, bucket_name: &str, key: &str) -> Result<Vec<u8>> {
+        let response = self.client.get_object()
+            .bucket(bucket_name)
+            .key(key)
+            .send()
+            .await?;
+        Ok(response.body.collect().await?.into_bytes().to_vec())
+    }
+
+    // every key under the prefix, across pages
+    pub async fn list_all_objects(&self, bucket_name: &str, prefix: &str) -> Result<Vec<String>> {
+        Ok(self.list_objects_modified(bucket_name, prefix).await?.into_iter().map(|(key, _)| key).collect())
+    }
+
     pub async fn list_objects(&self, bucket_name: &str, prefix: &str) -> Result<Vec<String>> {
         let response = self.client
             .list_objects_v2()
@@ -87,6 +196,32 @@ impl S3Service {
         Ok(keys)
     }
 
+    // (key, last modified in epoch seconds) of every object under the prefix, across pages
+    async fn list_objects_modified(&self, bucket_name: &str, prefix: &str) -> Result<Vec<(String, u64)>> {
+        let mut objects = vec![];
+        let mut continuation_token = None;
+        loop {
+            let response = self.client
+                .list_objects_v2()
+                .bucket(bucket_name)
+                .prefix(prefix)
+                .set_continuation_token(continuation_token)
+                .send()
+                .await?;
+
+            for object in response.contents.unwrap_or_default() {
+                if let (Some(key), Some(last_modified)) = (object.key, object.last_modified) {
+                    objects.push((key, last_modified.secs().max(0) as u64));
+                }
+            }
+            continuation_token = response.next_continuation_token;
+            if continuation_token.is_none() {
+                break
+            }
+        }
+        Ok(objects)
+    }
+
     fn get_file_name(&self, key: &str) -> Result<String> {
         Ok(key.split("/").last().context("file name not found")?.to_owned())
     }
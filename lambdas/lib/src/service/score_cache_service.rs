@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde::{Deserialize, Serialize};
+use serde_dynamo::{from_item, to_item};
+use sha2::{Digest, Sha256};
+
+use crate::{env_keys::{SCORE_CACHE_MAX_LENGTH, SCORE_CACHE_TABLE_NAME, SCORE_CACHE_TTL_DAYS}, taxonomy::taxonomy, utilities::get_date_month};
+use super::common_structs::{EmotionScores, NegativityTarget, ScoringResult};
+
+const DEFAULT_TTL_DAYS: u64 = 30;
+const DEFAULT_MAX_LENGTH: usize = 40;
+const STATS_KEY_PREFIX: &str = "#stats#";
+
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScoreCacheEntry {
+    pub cache_key: String,
+    #[serde(flatten)]
+    pub scores: EmotionScores,
+    pub model_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub std_dev: Option<EmotionScores>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub samples: Option<u32>,
+    // the mention token is positional, so it holds for any message with the same normalised text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<NegativityTarget>,
+    // epoch seconds, dynamo TTL attribute
+    pub ttl: u64,
+}
+
+
+// Scores of short, frequently repeated messages ("ok", "thanks!", "lgtm", emoji only),
+// keyed by a hash of the normalised text, the model, the prompt version and the emotions of the taxonomy.
+#[derive(Debug, Clone)]
+pub struct ScoreCacheService {
+    client: aws_sdk_dynamodb::Client,
+    // empty if the cache is disabled
+    table_name: String,
+    ttl_seconds: u64,
+    max_length: usize,
+}
+
+impl ScoreCacheService {
+    pub fn new(client: &aws_sdk_dynamodb::Client) -> Self {
+        Self {
+            client: client.to_owned(),
+            table_name: std::env::var(SCORE_CACHE_TABLE_NAME).unwrap_or("".to_owned()),
+            ttl_seconds: std::env::var(SCORE_CACHE_TTL_DAYS).ok().and_then(|d| d.parse().ok()).unwrap_or(DEFAULT_TTL_DAYS) * 24 * 3600,
+            max_length: std::env::var(SCORE_CACHE_MAX_LENGTH).ok().and_then(|l| l.parse().ok()).unwrap_or(DEFAULT_MAX_LENGTH),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.table_name.is_empty()
+    }
+
+    // none if the text is too long to be worth caching
+    // sampling is the temperature of each sample, a mean of more samples is a different result
+    pub fn cache_key(&self, text: &str, model_id: &str, prompt_version: Option<&str>, sampling: &[Option<f32>]) -> Option<String> {
+        let normalized = normalize(text);
+        if normalized.is_empty() || normalized.chars().count() > self.max_length {
+            return None
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(normalized.as_bytes());
+        hasher.update([0]);
+        hasher.update(model_id.as_bytes());
+        hasher.update([0]);
+        hasher.update(prompt_version.unwrap_or("").as_bytes());
+        hasher.update([0]);
+        let temperatures: Vec<String> = sampling.iter().map(|t| t.map(|t| t.to_string()).unwrap_or("default".to_owned())).collect();
+        hasher.update(format!("{}:{}", sampling.len(), temperatures.join(",")).as_bytes());
+        hasher.update([0]);
+        hasher.update(taxonomy().fingerprint().as_bytes());
+        Some(hex::encode(hasher.finalize()))
+    }
+
+    pub async fn get(&self, cache_key: &str) -> Result<Option<ScoringResult>> {
+        let output = self.client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("cache_key", AttributeValue::S(cache_key.to_owned()))
+            .send()
+            .await?;
+
+        let Some(item) = output.item else {
+            return Ok(None)
+        };
+        let entry: ScoreCacheEntry = from_item(item)?;
+        // dynamo deletes expired items lazily
+        if entry.ttl <= now() {
+            return Ok(None)
+        }
+
+        Ok(Some(ScoringResult {
+            scores: entry.scores,
+            model_id: entry.model_id,
+            prompt_version: entry.prompt_version,
+            from_cache: true,
+            std_dev: entry.std_dev,
+            samples: entry.samples,
+            target: entry.target,
+        }))
+    }
+
+    pub async fn put(&self, cache_key: &str, result: &ScoringResult) -> Result<()> {
+        let entry = ScoreCacheEntry {
+            cache_key: cache_key.to_owned(),
+            scores: result.scores.to_owned(),
+            model_id: result.model_id.to_owned(),
+            prompt_version: result.prompt_version.to_owned(),
+            std_dev: result.std_dev.to_owned(),
+            samples: result.samples,
+            target: result.target.to_owned(),
+            ttl: now() + self.ttl_seconds,
+        };
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(to_item(&entry)?))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    // daily hit and miss counters, kept in the cache table under #stats#{date}
+    pub async fn record_lookup(&self, hit: bool) -> Result<()> {
+        let (date, _) = get_date_month(now())?;
+        let counter = if hit { "hits" } else { "misses" };
+        let attribute_values: HashMap<String, AttributeValue> = HashMap::from([
+            (":one".to_owned(), AttributeValue::N("1".to_owned())),
+        ]);
+
+        self.client
+            .update_item()
+            .table_name(&self.table_name)
+            .key("cache_key", AttributeValue::S(format!("{}{}", STATS_KEY_PREFIX, date)))
+            .update_expression("ADD #counter :one")
+            .expression_attribute_names("#counter", counter)
+            .set_expression_attribute_values(Some(attribute_values))
+            .send()
+            .await?;
+        Ok(())
+    }
+}
+
+
+// "  Thanks!\n" and "thanks!" share the cache entry
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn now() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
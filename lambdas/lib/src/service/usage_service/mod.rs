@@ -0,0 +1,144 @@
+pub mod structs;
+pub mod pricing;
+
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+
+use anyhow::{Context, Result};
+use aws_sdk_dynamodb::{operation::query::QueryOutput, types::AttributeValue};
+use pricing::PriceTable;
+use serde_dynamo::{from_items, to_item};
+use structs::{CostSummary, UsageRecord};
+
+use crate::{env_keys::{USAGE_TABLE_NAME, WORKSPACE_ID}, utilities::{get_date_month, get_week_range}};
+
+pub const DEFAULT_WORKSPACE_ID: &str = "default";
+
+
+// Estimated cost (USD) added up over the calls sharing the meter, clones share the total.
+#[derive(Debug, Clone)]
+pub struct CostMeter {
+    spent: Arc<Mutex<f64>>,
+    prices: PriceTable,
+}
+
+impl Default for CostMeter {
+    fn default() -> Self {
+        Self { spent: Arc::new(Mutex::new(0.0)), prices: PriceTable::from_env() }
+    }
+}
+
+impl CostMeter {
+    // priced the way the usage table prices the call, without depending on it being recorded
+    pub fn charge(&self, model_id: &str, input_tokens: u64, output_tokens: u64) {
+        self.add(self.prices.estimate_cost(model_id, input_tokens, output_tokens));
+    }
+
+    pub fn add(&self, cost: f64) {
+        if let Ok(mut spent) = self.spent.lock() {
+            *spent += cost;
+        }
+    }
+
+    pub fn spent(&self) -> f64 {
+        self.spent.lock().map(|s| *s).unwrap_or(0.0)
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct UsageService {
+    client: aws_sdk_dynamodb::Client,
+    // empty if usage is not recorded
+    table_name: String,
+    workspace_id: String,
+    prices: PriceTable,
+}
+
+impl UsageService {
+    pub fn new(client: &aws_sdk_dynamodb::Client) -> Self {
+        Self {
+            client: client.to_owned(),
+            table_name: std::env::var(USAGE_TABLE_NAME).unwrap_or("".to_owned()),
+            workspace_id: std::env::var(WORKSPACE_ID).ok().filter(|w| !w.is_empty()).unwrap_or(DEFAULT_WORKSPACE_ID.to_owned()),
+            prices: PriceTable::from_env(),
+        }
+    }
+
+    pub fn workspace_id(&self) -> &str {
+        &self.workspace_id
+    }
+
+    // error: the kind of error of a failed attempt, none if the model answered
+    pub async fn record(&self, operation: &str, model_id: &str, input_tokens: u64, output_tokens: u64, latency_ms: u64, error: Option<&str>) -> Result<UsageRecord> {
+        let timestamp_ms = chrono::Utc::now().timestamp_millis() as u64;
+        let (date, _) = get_date_month(timestamp_ms / 1000)?;
+        let record = UsageRecord {
+            workspace_id: self.workspace_id.to_owned(),
+            record_id: format!("{}#{}", timestamp_ms, uuid::Uuid::new_v4()),
+            timestamp_ms,
+            date,
+            operation: operation.to_owned(),
+            model_id: model_id.to_owned(),
+            input_tokens,
+            output_tokens,
+            latency_ms,
+            estimated_cost: self.prices.estimate_cost(model_id, input_tokens, output_tokens),
+            error: error.map(|e| e.to_owned()),
+        };
+        println!("usage: {:?}", record);
+
+        if self.table_name.is_empty() {
+            return Ok(record)
+        }
+
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(to_item(&record)?))
+            .send()
+            .await?;
+        Ok(record)
+    }
+
+    // [from_ms, to_ms)
+    pub async fn query_usage(&self, workspace_id: &str, from_ms: u64, to_ms: u64) -> Result<Vec<UsageRecord>> {
+        let attribute_values: HashMap<String, AttributeValue> = HashMap::from([
+            (":workspace_id".to_owned(), AttributeValue::S(workspace_id.to_owned())),
+            (":from".to_owned(), AttributeValue::S(from_ms.to_string())),
+            // '#' sorts before '$', so this includes every record at to_ms - 1
+            (":to".to_owned(), AttributeValue::S(format!("{}$", to_ms - 1))),
+        ]);
+
+        let mut builder = self.client
+            .query()
+            .table_name(&self.table_name)
+            .key_condition_expression("workspace_id = :workspace_id AND record_id BETWEEN :from AND :to")
+            .set_expression_attribute_values(Some(attribute_values));
+
+        let output = builder.clone().send().await?;
+        let mut records = self.output_to_records(&output)?;
+        let mut last_evaluated_key = output.last_evaluated_key;
+
+        while last_evaluated_key.is_some() {
+            builder = builder.clone().set_exclusive_start_key(last_evaluated_key.clone());
+            let output = builder.clone().send().await?;
+            records.append(&mut self.output_to_records(&output)?);
+            last_evaluated_key = output.last_evaluated_key;
+        }
+
+        Ok(records)
+    }
+
+    // the JST week (MON-SUN) containing date (YYYY-MM-DD), current week if none
+    pub async fn weekly_cost_summary(&self, workspace_id: &str, date: Option<&str>) -> Result<CostSummary> {
+        let (from, to, from_ms, to_ms) = get_week_range(date)?;
+        let records = self.query_usage(workspace_id, from_ms, to_ms).await?;
+        Ok(CostSummary::from_records(workspace_id, &from, &to, &records))
+    }
+
+    fn output_to_records(&self, output: &QueryOutput) -> Result<Vec<UsageRecord>> {
+        let items = output.clone().items.context("items not available")?;
+        let records: Vec<UsageRecord> = from_items(items)?;
+        Ok(records)
+    }
+}
@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::env_keys::MODEL_PRICES;
+
+
+// USD per 1000 tokens
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct ModelPrice {
+    pub input_per_1k: f64,
+    pub output_per_1k: f64,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct PriceTable {
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl PriceTable {
+    // on-demand prices in us-east-1, overridden (or extended) by MODEL_PRICES,
+    // ie: {"anthropic.claude-3-haiku-20240307-v1:0": {"input_per_1k": 0.00025, "output_per_1k": 0.00125}}
+    pub fn from_env() -> Self {
+        let mut prices: HashMap<String, ModelPrice> = HashMap::from([
+            ("anthropic.claude-3-haiku-20240307-v1:0".to_owned(), ModelPrice { input_per_1k: 0.00025, output_per_1k: 0.00125 }),
+            ("anthropic.claude-3-5-haiku-20241022-v1:0".to_owned(), ModelPrice { input_per_1k: 0.0008, output_per_1k: 0.004 }),
+            ("anthropic.claude-3-sonnet-20240229-v1:0".to_owned(), ModelPrice { input_per_1k: 0.003, output_per_1k: 0.015 }),
+            ("anthropic.claude-3-5-sonnet-20240620-v1:0".to_owned(), ModelPrice { input_per_1k: 0.003, output_per_1k: 0.015 }),
+        ]);
+
+        match std::env::var(MODEL_PRICES).map(|p| serde_json::from_str::<HashMap<String, ModelPrice>>(&p)) {
+            Ok(Ok(overrides)) => prices.extend(overrides),
+            Ok(Err(error)) => println!("Error parsing model prices: {:?}", error),
+            Err(_) => {},
+        }
+
+        Self { prices }
+    }
+
+    // 0 for models without a price, ie: the lexicon scorer
+    pub fn estimate_cost(&self, model_id: &str, input_tokens: u64, output_tokens: u64) -> f64 {
+        let Some(price) = self.prices.get(model_id) else {
+            return 0.0
+        };
+        (input_tokens as f64 / 1000.0) * price.input_per_1k + (output_tokens as f64 / 1000.0) * price.output_per_1k
+    }
+}
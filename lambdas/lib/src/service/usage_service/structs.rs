@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsageRecord {
+    pub workspace_id: String,
+    // {timestamp_ms}#{uuid}, sort key
+    pub record_id: String,
+    pub timestamp_ms: u64,
+    pub date: String,
+
+    pub operation: String,
+    pub model_id: String,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub latency_ms: u64,
+    pub estimated_cost: f64,
+    // kind of error of a failed attempt, which reports no tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct UsageTotal {
+    pub calls: u64,
+    #[serde(default)]
+    pub failed_calls: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub estimated_cost: f64,
+}
+
+impl UsageTotal {
+    pub fn add(&mut self, record: &UsageRecord) {
+        self.calls += 1;
+        if record.error.is_some() {
+            self.failed_calls += 1;
+        }
+        self.input_tokens += record.input_tokens;
+        self.output_tokens += record.output_tokens;
+        self.estimated_cost += record.estimated_cost;
+    }
+}
+
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CostSummary {
+    pub workspace_id: String,
+    pub from: String,
+    pub to: String,
+    pub total: UsageTotal,
+    pub by_model: HashMap<String, UsageTotal>,
+    pub by_operation: HashMap<String, UsageTotal>,
+    pub by_date: HashMap<String, UsageTotal>,
+}
+
+impl CostSummary {
+    pub fn from_records(workspace_id: &str, from: &str, to: &str, records: &[UsageRecord]) -> Self {
+        let mut summary = Self {
+            workspace_id: workspace_id.to_owned(),
+            from: from.to_owned(),
+            to: to.to_owned(),
+            total: UsageTotal::default(),
+            by_model: HashMap::new(),
+            by_operation: HashMap::new(),
+            by_date: HashMap::new(),
+        };
+        for record in records {
+            summary.total.add(record);
+            summary.by_model.entry(record.model_id.to_owned()).or_default().add(record);
+            summary.by_operation.entry(record.operation.to_owned()).or_default().add(record);
+            summary.by_date.entry(record.date.to_owned()).or_default().add(record);
+        }
+        summary
+    }
+}
@@ -0,0 +1,563 @@
+use std::{collections::{BTreeMap, HashMap}, io::{Read, Write}, sync::Arc};
+
+use anyhow::{bail, Context, Result};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use hmac::{Hmac, Mac};
+use md5::{Digest, Md5};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+
+use crate::{env_keys::{BUCKET_NAME, PROCESSED_S3_FOLDER, USER_ID_HASH_KEY}, rollup::{Granularity, Rollup, RollupQuery, RollupScope}, utilities::get_date_month};
+use super::{common_structs::TargetCategory, dynamo_service::structs::EmotionTableEntry, encryption::text_cipher::TextCipher, emotion_repository::{EmotionRepository, UserQuery}, s3_service::{S3Service, EXPORT_PREFIX, MANIFEST_JSON, MANIFEST_SUMMARY_JSON, USER_DATA_AUDIT_PREFIX}};
+
+const EXPORT_DATA_FOLDER: &str = "/data/";
+const EXPORT_DATA_SUFFIX: &str = ".json.gz";
+// attributes dropped from anonymised entries, see EmotionTableEntry::scores_only
+const TEXT_ATTRIBUTES: &[&str] = &["text", "encrypted_text", "rewrite", "encrypted_rewrite", "rewrite_scores", "rewrite_model_id", "rewrite_prompt_version", "rewrite_scores_model_id", "rewrite_scores_prompt_version"];
+// ts of the slack message, they find the message and its author in slack. dropped from anonymised entries too
+const SLACK_ATTRIBUTES: &[&str] = &["message_ts", "thread_ts"];
+// attributes of other users' entries that may mention the user
+const MENTION_ATTRIBUTES: &[&str] = &["text", "rewrite"];
+// what mentions of an erased user become, the placeholder of the user_mention redaction detector
+const MENTION_PLACEHOLDER: &str = "<@USER>";
+
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ErasureMode {
+    // the entries of the user are deleted
+    Delete,
+    // the entries of the user keep their scores under a random id, without text
+    Anonymise,
+}
+
+impl ErasureMode {
+    pub fn parse(mode: &str) -> Result<Self> {
+        match mode {
+            "delete" => Ok(Self::Delete),
+            "anonymise" | "anonymize" => Ok(Self::Anonymise),
+            _ => bail!("Unknown erasure mode {}, delete or anonymise", mode),
+        }
+    }
+}
+
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UserDataOperation {
+    Export,
+    Erase,
+}
+
+
+// a message of someone else aimed at the user, without the text or the author
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MentionRecord {
+    pub event_id: String,
+    pub timestamp: u64,
+    pub date: String,
+    pub channel_id: String,
+    pub category: TargetCategory,
+}
+
+
+// an export file in the data bucket holding records of the user
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct S3ObjectRecords {
+    pub key: String,
+    pub records: usize,
+}
+
+
+// everything held about a user
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UserDataBundle {
+    pub user_id: String,
+    // epoch seconds
+    pub generated_at: u64,
+    pub entries: Vec<EmotionTableEntry>,
+    pub mentions: Vec<MentionRecord>,
+    pub rollups: Vec<Rollup>,
+    // the exports keep the entries as they were at the time of the export
+    pub s3_objects: Vec<S3ObjectRecords>,
+}
+
+
+// what an export or an erasure did, written to audit/user-data/{date}/{request id}.json in the data bucket.
+// holds a hash of the user id rather than the id, so that an erasure can be proven without keeping it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UserDataAudit {
+    pub request_id: String,
+    pub operation: UserDataOperation,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<ErasureMode>,
+    // hmac-sha256 keyed with USER_ID_HASH_KEY, hex
+    pub user_id_hash: String,
+    // epoch seconds
+    pub started_at: u64,
+    pub finished_at: u64,
+    // entries of the user exported, deleted or anonymised
+    pub entries: usize,
+    // entries of others aimed at the user, exported or unlinked from the user
+    pub mentions: usize,
+    // entries of others whose text mentions the user, plain or encrypted, the mentions replaced by <@USER> on erasure
+    #[serde(default)]
+    pub text_mentions: usize,
+    // rollups of the user exported or deleted
+    pub rollups: usize,
+    // rollups of the channels and the workspace rebuilt over the dates of the erased entries
+    #[serde(default)]
+    pub rollups_rebuilt: usize,
+    // export files read or rewritten. the manifests of the rewritten raw exports are updated to match.
+    pub s3_objects: Vec<S3ObjectRecords>,
+}
+
+
+// a data file of a raw export as listed in its manifest-files.json
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataFileChecksum {
+    pub item_count: usize,
+    // base64 of the md5 of the gzipped file
+    pub md5_checksum: String,
+    // hex of the md5, the etag of a single part upload
+    pub etag: String,
+}
+
+impl DataFileChecksum {
+    pub fn new(gzipped: &[u8], item_count: usize) -> Self {
+        let digest = Md5::digest(gzipped);
+        Self {
+            item_count,
+            md5_checksum: BASE64_STANDARD.encode(digest),
+            etag: hex::encode(digest),
+        }
+    }
+}
+
+
+// Finds, exports and erases the data of a user across the emotion table, the rollups and the exports in the data bucket.
+#[derive(Debug, Clone)]
+pub struct UserDataService {
+    repository: Arc<dyn EmotionRepository>,
+    s3: S3Service,
+    // empty if the exports are not searched
+    bucket_name: String,
+    processed_folder: String,
+    // decrypts the text of the exported entries, when encryption is on
+    cipher: Option<TextCipher>,
+    // keys the hash of the user id in the audit, so that it can not be recomputed from a guessed id
+    hash_key: String,
+}
+
+impl UserDataService {
+    pub fn new(repository: &Arc<dyn EmotionRepository>, s3: &S3Service) -> Self {
+        Self {
+            repository: repository.to_owned(),
+            s3: s3.to_owned(),
+            bucket_name: std::env::var(BUCKET_NAME).unwrap_or_default(),
+            processed_folder: std::env::var(PROCESSED_S3_FOLDER).unwrap_or_default(),
+            cipher: None,
+            hash_key: std::env::var(USER_ID_HASH_KEY).unwrap_or_default(),
+        }
+    }
+
+    pub fn with_hash_key(self, hash_key: &str) -> Self {
+        Self {
+            hash_key: hash_key.to_owned(),
+            ..self
+        }
+    }
+
+    pub fn with_cipher(self, cipher: &TextCipher) -> Self {
+        Self {
+            cipher: Some(cipher.to_owned()),
+            ..self
+        }
+    }
+
+    pub async fn export(&self, user_id: &str) -> Result<(UserDataBundle, UserDataAudit)> {
+        let user_id_hash = self.hash_user_id(user_id)?;
+        let started_at = now();
+        let mut entries = self.repository.query_user(&UserQuery::new(user_id, 0, u64::MAX)).await?;
+        // the user gets their own text in the clear
+        for entry in entries.iter_mut().filter(|e| e.is_encrypted()) {
+            let cipher = self.cipher.as_ref().context(format!("Entry {} is encrypted but no cipher is configured", entry.event_id))?;
+            *entry = cipher.open(entry).await?;
+        }
+        let mentions: Vec<MentionRecord> = self.repository.query_mentions(user_id).await?.into_iter()
+            .map(|e| MentionRecord {
+                category: e.target.as_ref().map(|t| t.category).unwrap_or(TargetCategory::Person),
+                event_id: e.event_id,
+                timestamp: e.timestamp,
+                date: e.date,
+                channel_id: e.channel_id,
+            })
+            .collect();
+
+        let mut rollups = vec![];
+        for granularity in Granularity::ALL {
+            let query = RollupQuery {
+                scope: RollupScope::User,
+                scope_id: user_id.to_owned(),
+                granularity,
+                from: "0000".to_owned(),
+                to: "9999".to_owned(),
+            };
+            rollups.append(&mut self.repository.query_rollups(&query).await?);
+        }
+
+        let mut s3_objects = vec![];
+        for key in self.export_data_keys().await? {
+            let content = gunzip(&self.s3.get_object_bytes(&self.bucket_name, &key).await?)?;
+            let records = rewrite_export(&content, user_id, None)?.1;
+            if records > 0 {
+                s3_objects.push(S3ObjectRecords { key, records });
+            }
+        }
+
+        let audit = UserDataAudit {
+            request_id: uuid::Uuid::new_v4().to_string(),
+            operation: UserDataOperation::Export,
+            mode: None,
+            user_id_hash,
+            started_at,
+            finished_at: now(),
+            entries: entries.len(),
+            mentions: mentions.len(),
+            text_mentions: 0,
+            rollups: rollups.len(),
+            rollups_rebuilt: 0,
+            s3_objects: s3_objects.clone(),
+        };
+        self.record(&audit).await?;
+
+        let bundle = UserDataBundle {
+            user_id: user_id.to_owned(),
+            generated_at: started_at,
+            entries,
+            mentions,
+            rollups,
+            s3_objects,
+        };
+        Ok((bundle, audit))
+    }
+
+    // anonymised entries get pseudonymous event and channel ids, and lose the ts of their slack message.
+    // the rollups of the dates of the erased entries are rebuilt, so that no max points to them.
+    // mentions in encrypted text are scrubbed once decrypted, the erasure fails before changing anything without a cipher.
+    pub async fn erase(&self, user_id: &str, mode: ErasureMode) -> Result<UserDataAudit> {
+        let user_id_hash = self.hash_user_id(user_id)?;
+        let started_at = now();
+        if self.cipher.is_none() {
+            if let Some(entry) = self.repository.query_encrypted().await?.first() {
+                bail!("Entry {} is encrypted but no cipher is configured, its mentions of the user could not be scrubbed", entry.event_id);
+            }
+        }
+        let mut pseudonyms = Pseudonyms::new();
+
+        let entries = self.repository.query_user(&UserQuery::new(user_id, 0, u64::MAX)).await?;
+        for entry in &entries {
+            // the anonymised entry is written first, a failed erasure loses nothing
+            if mode == ErasureMode::Anonymise {
+                self.repository.replace_entry(&anonymised(entry, user_id, &mut pseudonyms)).await?;
+            }
+            self.repository.delete_entry(&entry.event_id).await?;
+        }
+
+        let mentions = self.repository.query_mentions(user_id).await?;
+        for entry in &mentions {
+            self.repository.replace_entry(&unlinked(entry, user_id)).await?;
+        }
+        // read after the mentions were unlinked, not to write an entry twice from a stale copy
+        let mentioning = self.repository.query_text_mentions(user_id).await?;
+        for entry in &mentioning {
+            self.repository.replace_entry(&scrubbed(entry, user_id)?).await?;
+        }
+        let mut text_mentions = mentioning.len();
+        if let Some(cipher) = &self.cipher {
+            for entry in self.repository.query_encrypted().await? {
+                let opened = cipher.open(&entry).await?;
+                if opened.mentions_in_text(user_id) {
+                    self.repository.replace_entry(&cipher.seal(&scrubbed(&opened, user_id)?).await?).await?;
+                    text_mentions += 1;
+                }
+            }
+        }
+
+        let rollups = self.repository.delete_user_rollups(user_id).await?;
+        // after the rollups of the user are deleted, so that the rebuild does not count them
+        let dates = entries.iter().map(|e| e.date.as_str());
+        let rollups_rebuilt = match (dates.clone().min(), dates.max()) {
+            (Some(from), Some(to)) => self.repository.rebuild_rollups(from, to).await?.rollups,
+            _ => 0,
+        };
+
+        let mut replacement = match mode {
+            ErasureMode::Delete => Replacement::Drop,
+            ErasureMode::Anonymise => Replacement::Pseudonymise(pseudonyms),
+        };
+        let mut s3_objects = vec![];
+        // export prefix -> data file key -> checksum, for the manifests of the raw exports
+        let mut rewritten_exports: BTreeMap<String, BTreeMap<String, DataFileChecksum>> = BTreeMap::new();
+        for key in self.export_data_keys().await? {
+            let content = gunzip(&self.s3.get_object_bytes(&self.bucket_name, &key).await?)?;
+            let (rewritten, records) = rewrite_export(&content, user_id, Some(&mut replacement))?;
+            if records > 0 {
+                let gzipped = gzip(&rewritten)?;
+                if let Some((prefix, _)) = key.split_once(EXPORT_DATA_FOLDER).filter(|_| key.starts_with(EXPORT_PREFIX)) {
+                    let checksum = DataFileChecksum::new(&gzipped, rewritten.lines().count());
+                    rewritten_exports.entry(format!("{}/", prefix)).or_default().insert(key.to_owned(), checksum);
+                }
+                self.s3.put_object_bytes(&self.bucket_name, &key, gzipped).await?;
+                s3_objects.push(S3ObjectRecords { key, records });
+            }
+        }
+        for (prefix, files) in &rewritten_exports {
+            self.update_manifests(prefix, files).await?;
+        }
+
+        let audit = UserDataAudit {
+            request_id: uuid::Uuid::new_v4().to_string(),
+            operation: UserDataOperation::Erase,
+            mode: Some(mode),
+            user_id_hash,
+            started_at,
+            finished_at: now(),
+            entries: entries.len(),
+            mentions: mentions.len(),
+            text_mentions,
+            rollups,
+            rollups_rebuilt,
+            s3_objects,
+        };
+        self.record(&audit).await?;
+        Ok(audit)
+    }
+
+    // data files of the raw exports and their copies in the processed folder
+    async fn export_data_keys(&self) -> Result<Vec<String>> {
+        if self.bucket_name.is_empty() {
+            return Ok(vec![])
+        }
+        let mut keys: Vec<String> = self.s3.list_all_objects(&self.bucket_name, EXPORT_PREFIX).await?.into_iter()
+            .filter(|key| key.contains(EXPORT_DATA_FOLDER) && key.ends_with(EXPORT_DATA_SUFFIX))
+            .collect();
+        if !self.processed_folder.is_empty() {
+            keys.extend(self.s3.list_all_objects(&self.bucket_name, &self.processed_folder).await?.into_iter()
+                .filter(|key| key.ends_with(EXPORT_DATA_SUFFIX)));
+        }
+        Ok(keys)
+    }
+
+    // the item counts and checksums of the rewritten data files, so that the export still checks out
+    async fn update_manifests(&self, prefix: &str, files: &BTreeMap<String, DataFileChecksum>) -> Result<()> {
+        let manifest_key = format!("{}{}", prefix, MANIFEST_JSON);
+        let manifest = String::from_utf8(self.s3.get_object_bytes(&self.bucket_name, &manifest_key).await?)?;
+        let (manifest, item_count) = rewrite_manifest(&manifest, files)?;
+        self.s3.put_object(&self.bucket_name, &manifest_key, &manifest).await?;
+
+        let summary_key = format!("{}{}", prefix, MANIFEST_SUMMARY_JSON);
+        let summary = String::from_utf8(self.s3.get_object_bytes(&self.bucket_name, &summary_key).await?)?;
+        self.s3.put_object(&self.bucket_name, &summary_key, &rewrite_manifest_summary(&summary, item_count)?).await
+    }
+
+    fn hash_user_id(&self, user_id: &str) -> Result<String> {
+        if self.hash_key.is_empty() {
+            bail!("{} is not set, the audit would hold an unkeyed hash of the user id", USER_ID_HASH_KEY);
+        }
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.hash_key.as_bytes())?;
+        mac.update(user_id.as_bytes());
+        Ok(hex::encode(mac.finalize().into_bytes()))
+    }
+
+    async fn record(&self, audit: &UserDataAudit) -> Result<()> {
+        let audit_json = serde_json::to_string_pretty(audit)?;
+        println!("user data audit: {}", audit_json);
+        if self.bucket_name.is_empty() {
+            return Ok(())
+        }
+        let (date, _) = get_date_month(audit.finished_at)?;
+        let key = format!("{}{}/{}.json", USER_DATA_AUDIT_PREFIX, date, audit.request_id);
+        self.s3.put_object(&self.bucket_name, &key, &audit_json).await
+    }
+}
+
+
+// what becomes of the records of the user in the exports
+enum Replacement {
+    Drop,
+    // the same pseudonyms as the anonymised entries in the table
+    Pseudonymise(Pseudonyms),
+}
+
+
+// ids given to the anonymised data of a user, one for the user and one per event and channel.
+// channels keep their entries together without being named.
+struct Pseudonyms {
+    user_id: String,
+    // original id -> pseudonym
+    event_ids: HashMap<String, String>,
+    channel_ids: HashMap<String, String>,
+}
+
+impl Pseudonyms {
+    fn new() -> Self {
+        Self {
+            user_id: anonymous_id(),
+            event_ids: HashMap::new(),
+            channel_ids: HashMap::new(),
+        }
+    }
+
+    fn event_id(&mut self, event_id: &str) -> String {
+        self.event_ids.entry(event_id.to_owned()).or_insert_with(anonymous_id).to_owned()
+    }
+
+    fn channel_id(&mut self, channel_id: &str) -> String {
+        self.channel_ids.entry(channel_id.to_owned()).or_insert_with(anonymous_id).to_owned()
+    }
+}
+
+fn anonymous_id() -> String {
+    format!("anonymous-{}", uuid::Uuid::new_v4())
+}
+
+// rewrites the lines (dynamo json, {"Item": {...}}) of an export file, or only counts them without a replacement.
+// returns the content and the number of records of the user, aimed at the user or mentioning the user.
+fn rewrite_export(content: &str, user_id: &str, mut replacement: Option<&mut Replacement>) -> Result<(String, usize)> {
+    let mention = mention_pattern(user_id)?;
+    let mut lines = vec![];
+    let mut records = 0;
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        let mut record: Value = serde_json::from_str(line).context("Error parsing export line")?;
+        let item = record.get_mut("Item").and_then(|i| i.as_object_mut()).context("Item not in export line")?;
+
+        let authored = item.get("user_id").and_then(|u| u.get("S")).and_then(|u| u.as_str()) == Some(user_id);
+        let target = item.get_mut("target").and_then(|t| t.get_mut("M")).and_then(|t| t.as_object_mut());
+        let aimed_at = match target {
+            Some(target) if target.get("user_id").and_then(|u| u.get("S")).and_then(|u| u.as_str()) == Some(user_id) => {
+                if replacement.is_some() {
+                    target.remove("user_id");
+                }
+                true
+            },
+            _ => false,
+        };
+        let mut mentioned = false;
+        for attribute in MENTION_ATTRIBUTES {
+            let Some(text) = item.get_mut(*attribute).and_then(|t| t.get_mut("S")) else {
+                continue;
+            };
+            if let Some(value) = text.as_str().filter(|t| mention.is_match(t)) {
+                mentioned = true;
+                if replacement.is_some() {
+                    *text = Value::String(mention.replace_all(value, MENTION_PLACEHOLDER).into_owned());
+                }
+            }
+        }
+        if authored || aimed_at || mentioned {
+            records += 1;
+        }
+
+        match (authored, replacement.as_deref_mut()) {
+            (true, Some(Replacement::Drop)) => continue,
+            (true, Some(Replacement::Pseudonymise(pseudonyms))) => {
+                item.insert("user_id".to_owned(), serde_json::json!({"S": pseudonyms.user_id}));
+                if let Some(event_id) = item.get("event_id").and_then(|e| e.get("S")).and_then(|e| e.as_str()) {
+                    let event_id = pseudonyms.event_id(event_id);
+                    item.insert("event_id".to_owned(), serde_json::json!({"S": event_id}));
+                }
+                if let Some(channel_id) = item.get("channel_id").and_then(|c| c.get("S")).and_then(|c| c.as_str()) {
+                    let channel_id = pseudonyms.channel_id(channel_id);
+                    item.insert("channel_id".to_owned(), serde_json::json!({"S": channel_id}));
+                }
+                for attribute in TEXT_ATTRIBUTES.iter().chain(SLACK_ATTRIBUTES) {
+                    item.remove(*attribute);
+                }
+            },
+            _ => {},
+        }
+        lines.push(serde_json::to_string(&record)?);
+    }
+    let content = lines.iter().map(|line| format!("{}\n", line)).collect();
+    Ok((content, records))
+}
+
+fn anonymised(entry: &EmotionTableEntry, user_id: &str, pseudonyms: &mut Pseudonyms) -> EmotionTableEntry {
+    let entry = unlinked(entry, user_id).scores_only();
+    EmotionTableEntry {
+        event_id: pseudonyms.event_id(&entry.event_id),
+        user_id: pseudonyms.user_id.to_owned(),
+        channel_id: pseudonyms.channel_id(&entry.channel_id),
+        message_ts: None,
+        thread_ts: None,
+        ..entry
+    }
+}
+
+// the entry without the user as its target
+fn unlinked(entry: &EmotionTableEntry, user_id: &str) -> EmotionTableEntry {
+    let mut entry = entry.to_owned();
+    if let Some(target) = entry.target.as_mut().filter(|t| t.user_id.as_deref() == Some(user_id)) {
+        target.user_id = None;
+    }
+    entry
+}
+
+// the entry with its mentions of the user replaced, see MENTION_PLACEHOLDER
+fn scrubbed(entry: &EmotionTableEntry, user_id: &str) -> Result<EmotionTableEntry> {
+    let mention = mention_pattern(user_id)?;
+    let mut entry = entry.to_owned();
+    entry.text = mention.replace_all(&entry.text, MENTION_PLACEHOLDER).into_owned();
+    entry.rewrite = entry.rewrite.map(|r| mention.replace_all(&r, MENTION_PLACEHOLDER).into_owned());
+    Ok(entry)
+}
+
+// <@U0123> and <@U0123|name>
+fn mention_pattern(user_id: &str) -> Result<Regex> {
+    Ok(Regex::new(&format!(r"<@{}(?:\|[^>]*)?>", regex::escape(user_id)))?)
+}
+
+// sets the item count and checksums of the files in a manifest-files.json (one json object per line),
+// returns it with the item count of the whole export
+pub fn rewrite_manifest(content: &str, files: &BTreeMap<String, DataFileChecksum>) -> Result<(String, usize)> {
+    let mut lines = vec![];
+    let mut item_count = 0;
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        let mut file: Value = serde_json::from_str(line).context("Error parsing manifest line")?;
+        let key = file["dataFileS3Key"].as_str().context("dataFileS3Key not in manifest line")?.to_owned();
+        if let Some(checksum) = files.get(&key) {
+            file["itemCount"] = checksum.item_count.into();
+            file["md5Checksum"] = checksum.md5_checksum.to_owned().into();
+            file["etag"] = checksum.etag.to_owned().into();
+        }
+        item_count += file["itemCount"].as_u64().unwrap_or(0) as usize;
+        lines.push(serde_json::to_string(&file)?);
+    }
+    Ok((lines.iter().map(|line| format!("{}\n", line)).collect(), item_count))
+}
+
+pub fn rewrite_manifest_summary(content: &str, item_count: usize) -> Result<String> {
+    let mut summary: Value = serde_json::from_str(content).context("Error parsing manifest summary")?;
+    summary["itemCount"] = item_count.into();
+    Ok(serde_json::to_string(&summary)?)
+}
+
+fn gunzip(bytes: &[u8]) -> Result<String> {
+    let mut content = String::new();
+    MultiGzDecoder::new(bytes).read_to_string(&mut content).context("Error decompressing export file")?;
+    Ok(content)
+}
+
+fn gzip(content: &str) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(content.as_bytes())?;
+    Ok(encoder.finish()?)
+}
+
+fn now() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
@@ -0,0 +1,224 @@
+use std::sync::OnceLock;
+
+use regex::{Captures, Regex};
+
+const MENTION_TOKEN_PREFIX: &str = "@user";
+// a code block with any of these is summarised as an error log
+const LOG_MARKERS: &[&str] = &["Exception", "Error", "error:", "Traceback", "panicked at", "FATAL", "WARN", "    at ", "Caused by"];
+
+// shortcodes whose words are not just the name with _ replaced
+const EMOJI_WORDS: &[(&str, &str)] = &[
+    ("+1", "thumbs up"),
+    ("-1", "thumbs down"),
+    ("thumbsup", "thumbs up"),
+    ("thumbsdown", "thumbs down"),
+    ("joy", "tears of joy"),
+    ("rage", "furious face"),
+    ("angry", "angry face"),
+    ("triumph", "frustrated face"),
+    ("pray", "thank you"),
+    ("sob", "crying loudly"),
+    ("cry", "crying face"),
+    ("100", "hundred points"),
+    ("tada", "party popper"),
+    ("smile", "smiling face"),
+    ("blush", "smiling face"),
+    ("heart", "red heart"),
+    ("white_check_mark", "check mark"),
+    ("heavy_check_mark", "check mark"),
+    ("x", "cross mark"),
+    ("scream", "screaming in fear"),
+    ("unamused", "unamused face"),
+    ("face_vomiting", "vomiting face"),
+    ("exploding_head", "mind blown"),
+];
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NormalizedText {
+    pub text: String,
+    // user ids in the order they were first mentioned, mentions[0] is @user1
+    pub mentions: Vec<String>,
+}
+
+impl NormalizedText {
+    // @user2 -> U0456
+    pub fn resolve_mention(&self, token: &str) -> Option<&str> {
+        let index: usize = token.strip_prefix(MENTION_TOKEN_PREFIX)?.parse().ok()?;
+        self.mentions.get(index.checked_sub(1)?).map(|id| id.as_str())
+    }
+
+    // the text without the tokens normalize puts in: mentions, emoji words, code summaries and (link).
+    // for language detection, where they would count as english
+    pub fn prose(&self) -> String {
+        tidy_whitespace(&placeholder_regex().replace_all(&self.text, ""))
+    }
+
+    // puts the slack mentions back, ie: in a rewrite of the normalised text
+    pub fn restore_mentions(&self, text: &str) -> String {
+        mention_token_regex().replace_all(text, |captures: &Captures| {
+            match self.resolve_mention(&captures[0]) {
+                Some(user_id) => format!("<@{}>", user_id),
+                None => captures[0].to_owned(),
+            }
+        }).into_owned()
+    }
+}
+
+
+// Turns slack mrkdwn into plain prose for scoring.
+// - user mentions become @user1, @user2, ... in order of appearance, see NormalizedText::mentions
+// - channels, special mentions and links become their label
+// - emoji shortcodes become words in brackets, ie: :+1: -> (thumbs up)
+// - code blocks are summarised, ie: [code block, 3 lines], [error log, 12 lines]
+pub fn normalize(text: &str) -> NormalizedText {
+    let mut mentions: Vec<String> = vec![];
+
+    let text = summarize_code_blocks(text);
+    let text = inline_code_regex().replace_all(&text, "$1").into_owned();
+    let text = markup_regex().replace_all(&text, |captures: &Captures| replace_markup(&captures[1], &mut mentions)).into_owned();
+    let text = emoji_regex().replace_all(&text, |captures: &Captures| replace_emoji(&captures[0], &captures[1])).into_owned();
+    let text = unescape(&text);
+
+    NormalizedText {
+        text: tidy_whitespace(&text),
+        mentions,
+    }
+}
+
+
+// an unterminated fence runs to the end of the message
+fn code_block_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"(?s)```(.*?)(?:```|\z)").unwrap())
+}
+
+fn inline_code_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"`([^`\n]+)`").unwrap())
+}
+
+// slack escapes < and > in user text, so any <...> left is markup
+fn markup_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"<([^<>\s][^<>]*)>").unwrap())
+}
+
+fn emoji_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r":([a-z0-9_+'-]+):").unwrap())
+}
+
+fn mention_token_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"@user\d+").unwrap())
+}
+
+fn placeholder_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"@user\d+|\[(?:code block|error log), \d+ lines?\]|\([a-z0-9 '+-]+\)").unwrap())
+}
+
+
+// the summary goes on its own line
+fn summarize_code_blocks(text: &str) -> String {
+    let mut summarized = String::new();
+    let mut last = 0;
+    for captures in code_block_regex().captures_iter(text) {
+        let block = captures.get(0).unwrap();
+        summarized.push_str(&text[last..block.start()]);
+        if !summarized.is_empty() && !summarized.ends_with('\n') {
+            summarized.push('\n');
+        }
+        summarized.push_str(&summarize_code(&captures[1]));
+        if block.end() < text.len() && !text[block.end()..].starts_with('\n') {
+            summarized.push('\n');
+        }
+        last = block.end();
+    }
+    summarized.push_str(&text[last..]);
+    summarized
+}
+
+fn summarize_code(code: &str) -> String {
+    let lines: Vec<&str> = code.lines().filter(|line| !line.trim().is_empty()).collect();
+    let kind = if lines.iter().any(|line| LOG_MARKERS.iter().any(|marker| line.contains(marker))) { "error log" } else { "code block" };
+    match lines.len() {
+        1 => format!("[{}, 1 line]", kind),
+        count => format!("[{}, {} lines]", kind, count),
+    }
+}
+
+fn replace_markup(markup: &str, mentions: &mut Vec<String>) -> String {
+    let (target, label) = match markup.split_once('|') {
+        Some((target, label)) => (target, Some(label)),
+        None => (markup, None),
+    };
+
+    if let Some(user_id) = target.strip_prefix('@') {
+        let index = match mentions.iter().position(|id| id == user_id) {
+            Some(index) => index,
+            None => {
+                mentions.push(user_id.to_owned());
+                mentions.len() - 1
+            },
+        };
+        return format!("{}{}", MENTION_TOKEN_PREFIX, index + 1)
+    }
+
+    if target.starts_with('#') {
+        return match label {
+            Some(label) if !label.is_empty() => format!("#{}", label),
+            _ => "#channel".to_owned(),
+        }
+    }
+
+    if let Some(special) = target.strip_prefix('!') {
+        return match (special, label) {
+            ("here" | "channel" | "everyone", _) => format!("@{}", special),
+            (_, Some(label)) => label.to_owned(),
+            _ => "".to_owned(),
+        }
+    }
+
+    match label {
+        Some(label) if !label.is_empty() => label.to_owned(),
+        _ if target.starts_with("mailto:") => target.trim_start_matches("mailto:").to_owned(),
+        _ => "(link)".to_owned(),
+    }
+}
+
+fn replace_emoji(shortcode: &str, name: &str) -> String {
+    // clock times (12:30:45) and the like are not emoji
+    if !name.chars().any(|c| c.is_ascii_alphabetic()) && !EMOJI_WORDS.iter().any(|(code, _)| *code == name) {
+        return shortcode.to_owned()
+    }
+    if name.starts_with("skin-tone-") {
+        return "".to_owned()
+    }
+    let words = match EMOJI_WORDS.iter().find(|(code, _)| *code == name) {
+        Some((_, words)) => words.to_string(),
+        None => name.replace(['_', '-'], " "),
+    };
+    format!("({})", words)
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&")
+}
+
+// trailing spaces, runs of spaces and blank lines left by the replacements
+fn tidy_whitespace(text: &str) -> String {
+    let lines: Vec<String> = text.lines()
+        .map(|line| line.split(' ').filter(|word| !word.is_empty()).collect::<Vec<&str>>().join(" "))
+        .collect();
+
+    let mut tidy: Vec<String> = vec![];
+    for line in lines {
+        if line.is_empty() && tidy.last().map(|l| l.is_empty()).unwrap_or(true) {
+            continue;
+        }
+        tidy.push(line);
+    }
+    tidy.join("\n").trim().to_owned()
+}
@@ -0,0 +1,167 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{language::Language, service::{common_structs::EmotionScores, dynamo_service::structs::EmotionTableEntry}, taxonomy::taxonomy};
+
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScoreSummary {
+    pub count: usize,
+    pub mean: EmotionScores,
+    pub std_dev: EmotionScores,
+}
+
+impl ScoreSummary {
+    pub fn from_scores(scores: &[EmotionScores]) -> Option<Self> {
+        if scores.is_empty() {
+            return None
+        }
+        let count = scores.len() as f64;
+        let mean = scores.iter()
+            .fold(EmotionScores::zero(), |sum, s| sum.combine(s, |a, b| a + b))
+            .map(|sum| sum / count);
+        let variance = scores.iter()
+            .fold(EmotionScores::zero(), |sum, s| sum.combine(&s.combine(&mean, |x, m| (x - m).powi(2)), |a, b| a + b))
+            .map(|sum| sum / count);
+
+        Some(Self {
+            count: scores.len(),
+            mean,
+            std_dev: variance.map(f64::sqrt),
+        })
+    }
+}
+
+
+// score distribution per detected language, to see whether one language is scored systematically harsher
+pub fn summarize_by_language(entries: &[EmotionTableEntry]) -> HashMap<Language, ScoreSummary> {
+    let mut map: HashMap<Language, Vec<EmotionScores>> = HashMap::new();
+    for entry in entries {
+        map.entry(entry.language).or_default().push(entry.scores.to_owned());
+    }
+
+    map.into_iter()
+        .filter_map(|(language, scores)| ScoreSummary::from_scores(&scores).map(|summary| (language, summary)))
+        .collect()
+}
+
+
+// p (0 to 1) of sorted scores, interpolated between the closest ranks
+pub fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None
+    }
+    let rank = p.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64))
+}
+
+
+// how the scores of an emotion spread over the messages of a window
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct EmotionDistribution {
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub max: f64,
+    // messages scoring over the threshold of the mood
+    pub above_threshold: usize,
+}
+
+
+// Per emotion distribution of the messages of a channel from to to (epoch seconds, to excluded).
+// No emotions if there is no message.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ChannelMood {
+    pub channel_id: String,
+    pub from: u64,
+    pub to: u64,
+    pub message_count: usize,
+    pub user_count: usize,
+    // messages per JST date
+    pub daily_counts: BTreeMap<String, usize>,
+    pub threshold: f64,
+    pub emotions: BTreeMap<String, EmotionDistribution>,
+}
+
+impl ChannelMood {
+    // entries outside the channel or the window are left out
+    pub fn from_entries(channel_id: &str, from: u64, to: u64, entries: &[EmotionTableEntry], threshold: f64) -> Self {
+        let entries: Vec<&EmotionTableEntry> = entries.iter()
+            .filter(|e| e.channel_id == channel_id && e.timestamp >= from && e.timestamp < to)
+            .collect();
+        let mut daily_counts: BTreeMap<String, usize> = BTreeMap::new();
+        for entry in &entries {
+            *daily_counts.entry(entry.date.to_owned()).or_default() += 1;
+        }
+
+        let emotions = match entries.is_empty() {
+            true => BTreeMap::new(),
+            false => taxonomy().names()
+                .map(|name| {
+                    let mut scores: Vec<f64> = entries.iter().map(|e| e.scores.get(name)).collect();
+                    scores.sort_by(|a, b| a.total_cmp(b));
+                    let distribution = EmotionDistribution {
+                        mean: scores.iter().sum::<f64>() / scores.len() as f64,
+                        p50: percentile(&scores, 0.5).unwrap_or_default(),
+                        p90: percentile(&scores, 0.9).unwrap_or_default(),
+                        max: scores.last().copied().unwrap_or_default(),
+                        above_threshold: scores.iter().filter(|s| **s > threshold).count(),
+                    };
+                    (name.to_owned(), distribution)
+                })
+                .collect(),
+        };
+
+        Self {
+            channel_id: channel_id.to_owned(),
+            from,
+            to,
+            message_count: entries.len(),
+            user_count: entries.iter().map(|e| e.user_id.as_str()).collect::<HashSet<&str>>().len(),
+            daily_counts,
+            threshold,
+            emotions,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&EmotionDistribution> {
+        self.emotions.get(name)
+    }
+
+    // the highest p90 among the emotions that trigger warnings, relative to the threshold
+    pub fn forecast(&self) -> Forecast {
+        let p90 = taxonomy().warning_emotions()
+            .filter_map(|e| self.get(&e.name).map(|d| d.p90))
+            .fold(0.0, f64::max);
+        match self.message_count {
+            0 => Forecast::Quiet,
+            _ if p90 >= self.threshold => Forecast::Stormy,
+            _ if p90 >= self.threshold * 0.5 => Forecast::Cloudy,
+            _ => Forecast::Sunny,
+        }
+    }
+}
+
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Forecast {
+    // no messages
+    Quiet,
+    Sunny,
+    Cloudy,
+    Stormy,
+}
+
+impl Forecast {
+    pub fn emoji(&self) -> &'static str {
+        match self {
+            Self::Quiet => ":zzz:",
+            Self::Sunny => ":sunny:",
+            Self::Cloudy => ":cloud:",
+            Self::Stormy => ":thunder_cloud_and_rain:",
+        }
+    }
+}
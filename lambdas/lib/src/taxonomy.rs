@@ -0,0 +1,136 @@
+use std::{collections::{HashMap, HashSet}, sync::OnceLock};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{env_keys::EMOTION_TAXONOMY, language::Language};
+
+const DEFAULT_TAXONOMY: &str = include_str!("../taxonomy/default.json");
+
+// attributes of the emotion table and the score cache table, scores are stored next to them
+const RESERVED_NAMES: &[&str] = &[
+    "event_id", "user_id", "timestamp", "date", "month", "channel_id", "channel_type", "text", "text_redacted", "language",
+    "model_id", "prompt_version", "from_cache", "rewrite", "rewrite_scores", "toxicity", "std_dev", "samples", "target", "cache_key", "ttl",
+    "text_purged", "encrypted_text", "encrypted_rewrite", "schema_version", "message_ts", "thread_ts", "generations",
+];
+
+static TAXONOMY: OnceLock<Taxonomy> = OnceLock::new();
+
+// an attribute stored next to the scores, never an emotion
+pub fn is_reserved_name(name: &str) -> bool {
+    RESERVED_NAMES.contains(&name)
+}
+
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Polarity {
+    Positive,
+    Negative,
+    Neutral,
+}
+
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Emotion {
+    // also the attribute name in dynamo and the column name in Glue
+    pub name: String,
+    // given to the model in the tool schema
+    pub description: String,
+    pub polarity: Polarity,
+    // language code -> message posted when the score goes over IMMEDIATE_WARNING_THRESHOLD.
+    // emotions without warnings never trigger one, and are not picked up in the daily report.
+    #[serde(default)]
+    pub warnings: HashMap<String, String>,
+}
+
+impl Emotion {
+    pub fn warns(&self) -> bool {
+        !self.warnings.is_empty()
+    }
+
+    // falls back to the english message
+    pub fn warning(&self, language: &Language) -> Option<&str> {
+        self.warnings.get(language.code())
+            .or(self.warnings.get(Language::En.code()))
+            .or(self.warnings.values().next())
+            .map(|w| w.as_str())
+    }
+}
+
+
+// The emotions to score, in the order they are reported.
+// Defined in lib/taxonomy/default.json, or the EMOTION_TAXONOMY env with the same format.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Taxonomy {
+    pub emotions: Vec<Emotion>,
+}
+
+impl Taxonomy {
+    pub fn parse(json: &str) -> Result<Self> {
+        let taxonomy: Self = serde_json::from_str(json)?;
+        if taxonomy.emotions.is_empty() {
+            bail!("Taxonomy has no emotions")
+        }
+        let mut names = HashSet::new();
+        for emotion in &taxonomy.emotions {
+            let name = emotion.name.as_str();
+            let valid = name.starts_with(|c: char| c.is_ascii_lowercase())
+                && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+            if !valid {
+                bail!("Invalid emotion name {}, use lower case letters, digits and _", name)
+            }
+            if RESERVED_NAMES.contains(&name) {
+                bail!("Emotion name {} is reserved", name)
+            }
+            if !names.insert(name) {
+                bail!("Duplicated emotion name {}", name)
+            }
+        }
+        Ok(taxonomy)
+    }
+
+    pub fn from_env() -> Self {
+        if let Ok(json) = std::env::var(EMOTION_TAXONOMY) {
+            if !json.trim().is_empty() {
+                match Self::parse(&json) {
+                    Ok(taxonomy) => return taxonomy,
+                    Err(error) => println!("Error parsing emotion taxonomy, using the default: {:?}", error),
+                }
+            }
+        }
+        Self::parse(DEFAULT_TAXONOMY).expect("default taxonomy is invalid")
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.emotions.iter().map(|e| e.name.as_str())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Emotion> {
+        self.emotions.iter().find(|e| e.name == name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    // the emotions that trigger an immediate warning
+    pub fn warning_emotions(&self) -> impl Iterator<Item = &Emotion> {
+        self.emotions.iter().filter(|e| e.warns())
+    }
+
+    pub fn with_polarity(&self, polarity: Polarity) -> impl Iterator<Item = &Emotion> {
+        self.emotions.iter().filter(move |e| e.polarity == polarity)
+    }
+
+    // changes whenever emotions are added, removed or renamed
+    pub fn fingerprint(&self) -> String {
+        self.names().collect::<Vec<&str>>().join(",")
+    }
+}
+
+
+// loaded once per process
+pub fn taxonomy() -> &'static Taxonomy {
+    TAXONOMY.get_or_init(Taxonomy::from_env)
+}
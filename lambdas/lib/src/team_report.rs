@@ -0,0 +1,150 @@
+use std::collections::{BTreeMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{env_keys::{DIGEST_TEAMS, TEAM_REPORT_CHANNEL_ID, TEAM_REPORT_GROUPS, TEAM_REPORT_MIN_GROUP_SIZE}, service::dynamo_service::structs::EmotionTableEntry, taxonomy::taxonomy, warnings::warning_threshold_from_env};
+
+pub const DEFAULT_MIN_GROUP_SIZE: usize = 5;
+
+
+// users and channels whose messages are reported together, a message belongs to the group if either matches
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct TeamGroup {
+    #[serde(default)]
+    pub users: Vec<String>,
+    #[serde(default)]
+    pub channels: Vec<String>,
+}
+
+impl TeamGroup {
+    pub fn contains(&self, entry: &EmotionTableEntry) -> bool {
+        self.users.contains(&entry.user_id) || self.channels.contains(&entry.channel_id)
+    }
+}
+
+
+// TEAM_REPORT_GROUPS: {"group name": {"users": ["U0123", ...], "channels": ["C0123", ...]}, ...}
+// the teams of DIGEST_TEAMS: {"team name": ["U0123", ...], ...} are groups of their users, unless a group has the same name
+pub fn team_groups_from_env() -> BTreeMap<String, TeamGroup> {
+    let mut groups = match std::env::var(TEAM_REPORT_GROUPS).map(|g| serde_json::from_str::<BTreeMap<String, TeamGroup>>(&g)) {
+        Ok(Ok(groups)) => groups,
+        Ok(Err(error)) => {
+            println!("Error parsing team report groups: {:?}", error);
+            BTreeMap::new()
+        },
+        Err(_) => BTreeMap::new(),
+    };
+    match std::env::var(DIGEST_TEAMS).map(|t| serde_json::from_str::<BTreeMap<String, Vec<String>>>(&t)) {
+        Ok(Ok(teams)) => {
+            for (name, users) in teams {
+                groups.entry(name).or_insert(TeamGroup { users, channels: vec![] });
+            }
+        },
+        Ok(Err(error)) => println!("Error parsing digest teams: {:?}", error),
+        Err(_) => {},
+    }
+    groups
+}
+
+// TEAM_REPORT_MIN_GROUP_SIZE, never below 2 so that a statistic never comes from a single person
+pub fn min_group_size_from_env() -> usize {
+    std::env::var(TEAM_REPORT_MIN_GROUP_SIZE).ok()
+        .and_then(|k| k.parse().ok())
+        .unwrap_or(DEFAULT_MIN_GROUP_SIZE)
+        .max(2)
+}
+
+// TEAM_REPORT_CHANNEL_ID is set and there is a group to report on, the entries need not be read otherwise
+pub fn team_reports_enabled_from_env() -> bool {
+    !std::env::var(TEAM_REPORT_CHANNEL_ID).map(|c| c.is_empty()).unwrap_or(true) && !team_groups_from_env().is_empty()
+}
+
+// the reports of every group of team_groups_from_env, none without TEAM_REPORT_CHANNEL_ID to send them to
+pub fn team_reports_from_env(entries: &[EmotionTableEntry]) -> Vec<TeamReport> {
+    if !team_reports_enabled_from_env() {
+        return vec![]
+    }
+    let min_group_size = min_group_size_from_env();
+    let threshold = warning_threshold_from_env();
+    team_groups_from_env().iter()
+        .map(|(name, group)| TeamReport::new(name, group, entries, min_group_size, threshold))
+        .collect()
+}
+
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct TeamEmotionStats {
+    pub mean: f64,
+    // messages over the threshold, none if they were written by fewer than min_group_size people
+    pub above_threshold: Option<usize>,
+}
+
+
+// Statistics of the messages of a group that never point at a person:
+// there is no text nor user id, and a statistic computed from fewer than min_group_size people or messages is left out (none).
+// Messages aimed at a person are counted separately, and are not part of the emotions.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct TeamReport {
+    pub group: String,
+    pub min_group_size: usize,
+    pub threshold: f64,
+    // none, and so is everything else, if the group is too small
+    pub people: Option<usize>,
+    pub message_count: Option<usize>,
+    pub emotions: Option<BTreeMap<String, TeamEmotionStats>>,
+    // messages aimed at a person, with the mean of their highest score among the emotions that warn
+    pub person_directed_count: Option<usize>,
+    pub person_directed_mean: Option<f64>,
+}
+
+impl TeamReport {
+    // entries of any group, the ones of the group are picked here
+    pub fn new(name: &str, group: &TeamGroup, entries: &[EmotionTableEntry], min_group_size: usize, threshold: f64) -> Self {
+        let entries: Vec<&EmotionTableEntry> = entries.iter().filter(|e| group.contains(e)).collect();
+        let mut report = Self {
+            group: name.to_owned(),
+            min_group_size,
+            threshold,
+            ..Default::default()
+        };
+        let k_anonymous = |entries: &[&EmotionTableEntry]| entries.len() >= min_group_size && people(entries) >= min_group_size;
+        if !k_anonymous(&entries) {
+            return report
+        }
+        report.people = Some(people(&entries));
+        report.message_count = Some(entries.len());
+
+        let (person_directed, others): (Vec<&EmotionTableEntry>, Vec<&EmotionTableEntry>) = entries.iter().partition(|e| e.is_person_directed());
+        if k_anonymous(&others) {
+            let emotions = taxonomy().names()
+                .map(|name| {
+                    let above: Vec<&EmotionTableEntry> = others.iter().copied().filter(|e| e.scores.get(name) > threshold).collect();
+                    let stats = TeamEmotionStats {
+                        mean: others.iter().map(|e| e.scores.get(name)).sum::<f64>() / others.len() as f64,
+                        // a count of 0 points at no one
+                        above_threshold: match above.is_empty() || people(&above) >= min_group_size {
+                            true => Some(above.len()),
+                            false => None,
+                        },
+                    };
+                    (name.to_owned(), stats)
+                })
+                .collect();
+            report.emotions = Some(emotions);
+        }
+        if k_anonymous(&person_directed) {
+            report.person_directed_count = Some(person_directed.len());
+            report.person_directed_mean = Some(person_directed.iter().map(|e| e.scores.max_negative()).sum::<f64>() / person_directed.len() as f64);
+        }
+        report
+    }
+
+    pub fn is_suppressed(&self) -> bool {
+        self.message_count.is_none()
+    }
+}
+
+
+fn people(entries: &[&EmotionTableEntry]) -> usize {
+    entries.iter().map(|e| e.user_id.as_str()).collect::<HashSet<&str>>().len()
+}
@@ -1,5 +1,5 @@
 use anyhow::{Context, Result};
-use chrono::{DateTime, Datelike, Days, FixedOffset, Utc, Weekday};
+use chrono::{DateTime, Datelike, Timelike, Days, FixedOffset, NaiveDate, TimeZone, Utc, Weekday};
 
 
 // JST: (2024-10-13, 2024-10)
@@ -12,6 +12,12 @@ pub fn get_date_month(timestamp: u64) -> Result<(String, String)> {
     Ok((date, month))
 }
 
+// JST hour of the day, 0 to 23
+pub fn get_jst_hour(timestamp: u64) -> Result<u32> {
+    let date_time = DateTime::from_timestamp(timestamp as i64, 0).context("Error converting from timestamp")?;
+    Ok(date_time.with_timezone(&get_jst_timezone()?).hour())
+}
+
 // JST Previous Weekday
 pub fn get_previous_weekday() -> Result<String> {
     let date_time = Utc::now();
@@ -26,6 +32,53 @@ pub fn get_previous_weekday() -> Result<String> {
     Ok(day_string)
 }
 
+// JST week (MON-SUN) containing the date (YYYY-MM-DD), current week if none
+// (monday, sunday, monday 00:00 in ms, next monday 00:00 in ms)
+pub fn get_week_range(date: Option<&str>) -> Result<(String, String, u64, u64)> {
+    let timezone = get_jst_timezone()?;
+    let date = match date {
+        Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d")?,
+        None => Utc::now().with_timezone(&timezone).date_naive(),
+    };
+    let monday = date.checked_sub_days(Days::new(date.weekday().num_days_from_monday() as u64)).context("Error getting monday")?;
+    let next_monday = monday.checked_add_days(Days::new(7)).context("Error getting next monday")?;
+    let sunday = next_monday.pred_opt().context("Error getting sunday")?;
+
+    let to_ms = |date: NaiveDate| -> Result<u64> {
+        let start = date.and_hms_opt(0, 0, 0).context("Error getting start of day")?;
+        let local = timezone.from_local_datetime(&start).single().context("Error converting to local time")?;
+        Ok(local.timestamp_millis() as u64)
+    };
+
+    Ok((monday.format("%Y-%m-%d").to_string(), sunday.format("%Y-%m-%d").to_string(), to_ms(monday)?, to_ms(next_monday)?))
+}
+
+// JST days from to to (YYYY-MM-DD), both included: (from 00:00, the day after to 00:00) in epoch seconds
+pub fn get_day_range(from: &str, to: &str) -> Result<(u64, u64)> {
+    let timezone = get_jst_timezone()?;
+    let from = NaiveDate::parse_from_str(from, "%Y-%m-%d")?;
+    let to = NaiveDate::parse_from_str(to, "%Y-%m-%d")?.succ_opt().context("Error getting next day")?;
+
+    let to_seconds = |date: NaiveDate| -> Result<u64> {
+        let start = date.and_hms_opt(0, 0, 0).context("Error getting start of day")?;
+        let local = timezone.from_local_datetime(&start).single().context("Error converting to local time")?;
+        Ok(local.timestamp() as u64)
+    };
+    Ok((to_seconds(from)?, to_seconds(to)?))
+}
+
+// the last days JST days up to today: (first day, today) as YYYY-MM-DD
+pub fn get_recent_days(days: u64) -> Result<(String, String)> {
+    let today = get_today()?;
+    let from = today.checked_sub_days(Days::new(days.saturating_sub(1))).context("Error getting first day")?;
+    Ok((from.format("%Y-%m-%d").to_string(), today.format("%Y-%m-%d").to_string()))
+}
+
+// JST
+pub fn get_today() -> Result<NaiveDate> {
+    Ok(Utc::now().with_timezone(&get_jst_timezone()?).date_naive())
+}
+
 // +09:00
 fn get_jst_timezone() -> Result<FixedOffset> {
     FixedOffset::east_opt(9 * 3600).context("Error getting timezone")
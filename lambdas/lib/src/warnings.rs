@@ -1,3 +1,67 @@
-pub const ANGER_WARNING: &str = "Pause and Breathe! Practice Mindfulness!";
-pub const CONTEMPT_WARNING: &str = "Practice Empathy! Avoid Sarcasm and Mockery!";
-pub const DISGUST_WARNING: &str = "Avoid Derogatory Language! Focus on the Issue, Not the Person!";
+use crate::{env_keys, language::Language, service::dynamo_service::structs::EmotionTableEntry};
+
+// emotion warnings are part of the taxonomy, see taxonomy::Emotion::warning
+
+pub const REWRITE_SUGGESTION: &str = "Only you can see this. How about saying it like this instead?";
+
+pub const REWRITE_SUGGESTION_JA: &str = "このメッセージはあなたにしか見えていません。こんな言い方はいかがでしょう？";
+
+pub const PERSON_DIRECTED_WARNING: &str = "Only you can see this. Your message seems to be aimed at a colleague rather than at the problem.";
+
+pub const PERSON_DIRECTED_WARNING_JA: &str = "このメッセージはあなたにしか見えていません。問題ではなく、特定の方に向けた言葉になっているようです。";
+
+pub const DEFAULT_WARNING_THRESHOLD: f64 = 0.6;
+
+
+pub fn rewrite_suggestion(language: &Language) -> &'static str {
+    match language {
+        Language::Ja => REWRITE_SUGGESTION_JA,
+        _ => REWRITE_SUGGESTION,
+    }
+}
+
+pub fn person_directed_warning(language: &Language) -> &'static str {
+    match language {
+        Language::Ja => PERSON_DIRECTED_WARNING_JA,
+        _ => PERSON_DIRECTED_WARNING,
+    }
+}
+
+// IMMEDIATE_WARNING_THRESHOLD, default to DEFAULT_WARNING_THRESHOLD if not set or invalid
+pub fn warning_threshold_from_env() -> f64 {
+    std::env::var(env_keys::IMMEDIATE_WARNING_THRESHOLD).ok().and_then(|t| t.parse().ok()).unwrap_or(DEFAULT_WARNING_THRESHOLD)
+}
+
+
+// how messages aimed at a person are warned about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PersonDirectedPolicy {
+    // a reply in the thread, like any other warning
+    Public,
+    // only visible to the author
+    Private,
+    // posted to the moderator channel
+    Moderator,
+    None,
+}
+
+impl PersonDirectedPolicy {
+    // public | private (default) | moderator | none, moderator is private without a moderator channel
+    pub fn parse(policy: &str, moderator_channel_id: &str) -> Self {
+        match policy {
+            "public" => Self::Public,
+            "moderator" if !moderator_channel_id.is_empty() => Self::Moderator,
+            "none" => Self::None,
+            _ => Self::Private,
+        }
+    }
+
+    pub fn from_env(moderator_channel_id: &str) -> Self {
+        Self::parse(&std::env::var(env_keys::PERSON_DIRECTED_WARNING).unwrap_or_default(), moderator_channel_id)
+    }
+
+    // the entry is warned about by this policy instead of the public warning
+    pub fn applies_to(&self, entry: &EmotionTableEntry) -> bool {
+        entry.is_person_directed() && *self != Self::Public
+    }
+}
@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use aws_sdk_bedrockruntime::{config::http::HttpResponse, error::SdkError, operation::converse::ConverseError};
+use aws_sdk_bedrockruntime::types::error::{AccessDeniedException, ModelNotReadyException, ResourceNotFoundException, ThrottlingException, ValidationException};
+use aws_smithy_types::body::SdkBody;
+use lib::service::bedrock_service::errors::{backoff_delay, is_throttling_error, BedrockErrorKind, MAX_BACKOFF_MS};
+
+
+fn service_error(status: u16, error: ConverseError) -> anyhow::Error {
+    let response = HttpResponse::new(status.try_into().unwrap(), SdkBody::empty());
+    anyhow::Error::new(SdkError::service_error(error, response))
+}
+
+
+#[test]
+fn throttled_and_unavailable_models_are_retried() {
+    let throttled = service_error(429, ConverseError::ThrottlingException(ThrottlingException::builder().build()));
+    let not_ready = service_error(429, ConverseError::ModelNotReadyException(ModelNotReadyException::builder().build()));
+
+    assert_eq!(BedrockErrorKind::classify(&throttled), BedrockErrorKind::Retryable);
+    assert_eq!(BedrockErrorKind::classify(&not_ready), BedrockErrorKind::Retryable);
+    assert!(is_throttling_error(&throttled));
+}
+
+#[test]
+fn transport_failures_are_retried() {
+    let timeout = anyhow::Error::new(SdkError::<ConverseError>::timeout_error("read timed out"));
+
+    assert_eq!(BedrockErrorKind::classify(&timeout), BedrockErrorKind::Retryable);
+}
+
+#[test]
+fn disabled_or_missing_models_move_on_to_the_next_model() {
+    let denied = service_error(403, ConverseError::AccessDeniedException(AccessDeniedException::builder().build()));
+    let not_found = service_error(404, ConverseError::ResourceNotFoundException(ResourceNotFoundException::builder().build()));
+
+    assert_eq!(BedrockErrorKind::classify(&denied), BedrockErrorKind::ModelUnavailable);
+    assert_eq!(BedrockErrorKind::classify(&not_found), BedrockErrorKind::ModelUnavailable);
+    assert!(!is_throttling_error(&denied));
+}
+
+#[test]
+fn bad_requests_and_other_errors_are_fatal() {
+    let invalid = service_error(400, ConverseError::ValidationException(ValidationException::builder().build()));
+    let not_built = anyhow::Error::new(SdkError::<ConverseError>::construction_failure("missing model id"));
+    let tool_output = anyhow!("Missing tool output");
+
+    assert_eq!(BedrockErrorKind::classify(&invalid), BedrockErrorKind::Fatal);
+    assert_eq!(BedrockErrorKind::classify(&not_built), BedrockErrorKind::Fatal);
+    assert_eq!(BedrockErrorKind::classify(&tool_output), BedrockErrorKind::Fatal);
+    assert!(!is_throttling_error(&tool_output));
+}
+
+#[test]
+fn backoff_stays_under_the_doubling_ceiling() {
+    for attempt in 0..6 {
+        let ceiling = Duration::from_millis(500 * 2_u64.pow(attempt));
+        for _ in 0..50 {
+            assert!(backoff_delay(500, attempt) <= ceiling, "attempt {attempt}");
+        }
+    }
+}
+
+#[test]
+fn backoff_is_capped() {
+    for attempt in [6, 10, 64, u32::MAX] {
+        assert!(backoff_delay(500, attempt) <= Duration::from_millis(MAX_BACKOFF_MS), "attempt {attempt}");
+    }
+    assert!(backoff_delay(u64::MAX, 3) <= Duration::from_millis(MAX_BACKOFF_MS));
+}
+
+#[test]
+fn backoff_without_base_does_not_wait() {
+    assert_eq!(backoff_delay(0, 0), Duration::ZERO);
+    assert_eq!(backoff_delay(0, 5), Duration::ZERO);
+}
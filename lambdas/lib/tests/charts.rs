@@ -0,0 +1,80 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use lib::charts::{mean_profile, render_radar, render_timeline, EmotionTimeline};
+use lib::rollup::{compute_rollups, RollupScope};
+use lib::service::dynamo_service::structs::EmotionTableEntry;
+use serde_json::json;
+use common::{entry, DAY};
+
+
+fn entries() -> Vec<EmotionTableEntry> {
+    vec![
+        entry("e1").timestamp(DAY).scores(json!({"anger": 0.5, "joy": 0.25, "sad": 0.25})).build(),
+        entry("e2").timestamp(DAY + 1800).scores(json!({"anger": 0.25, "joy": 0.75})).build(),
+        entry("e3").timestamp(DAY + 4 * 3600).scores(json!({"contempt": 0.5, "fear": 0.25, "surprise": 0.5})).build(),
+        entry("e4").timestamp(DAY + 8 * 3600).scores(json!({"disgust": 0.75, "anger": 0.75, "sad": 0.5})).build(),
+        entry("e5").timestamp(DAY + 24 * 3600).scores(json!({"joy": 1.0})).build(),
+    ]
+}
+
+// set UPDATE_GOLDEN=1 to write the golden images again after an intended change to the charts
+fn assert_golden(name: &str, png: &[u8]) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(name);
+    if std::env::var("UPDATE_GOLDEN").is_ok() {
+        std::fs::write(&path, png).unwrap();
+    }
+    let golden = std::fs::read(&path).unwrap();
+    assert!(golden == png, "{} differs from the golden image, see UPDATE_GOLDEN", name);
+}
+
+#[test]
+fn timeline_buckets_by_jst_hour_and_date() {
+    let entries = entries();
+    let refs: Vec<&EmotionTableEntry> = entries.iter().collect();
+
+    let hourly = EmotionTimeline::hourly(&refs).unwrap();
+    assert_eq!(hourly.buckets.len(), 24);
+    // e5 is the day after at the same hour
+    assert_eq!(hourly.buckets[9]["anger"], 0.25);
+    assert_eq!(hourly.buckets[13]["contempt"], 0.5);
+    assert_eq!(hourly.buckets[9].len(), 7);
+    assert!(hourly.buckets[10].is_empty());
+
+    let dates = vec!["2024-10-14".to_owned(), "2024-10-15".to_owned(), "2024-10-16".to_owned()];
+    let rollups: Vec<_> = compute_rollups(&entries, "W0123").unwrap().into_iter().filter(|r| r.scope == RollupScope::User).collect();
+    let daily = EmotionTimeline::daily(&rollups, &dates);
+    assert!(daily.buckets[0].is_empty());
+    assert_eq!(daily.buckets[1]["anger"], 0.375);
+    assert_eq!(daily.buckets[2]["joy"], 1.0);
+}
+
+#[test]
+fn timeline_matches_golden() {
+    let entries = entries();
+    let refs: Vec<&EmotionTableEntry> = entries.iter().collect();
+    let png = render_timeline(&EmotionTimeline::hourly(&refs).unwrap()).unwrap();
+    assert_golden("timeline.png", &png);
+    assert!(render_timeline(&EmotionTimeline::default()).is_err());
+}
+
+#[test]
+fn radar_matches_golden() {
+    let entries = entries();
+    let refs: Vec<&EmotionTableEntry> = entries.iter().collect();
+    let png = render_radar(&mean_profile(&refs)).unwrap();
+    assert_golden("radar.png", &png);
+    // an empty profile is drawn at the center
+    assert_golden("radar_empty.png", &render_radar(&BTreeMap::new()).unwrap());
+}
+
+#[test]
+fn rendering_is_deterministic() {
+    let entries = entries();
+    let refs: Vec<&EmotionTableEntry> = entries.iter().collect();
+    let timeline = EmotionTimeline::hourly(&refs).unwrap();
+    assert_eq!(render_timeline(&timeline).unwrap(), render_timeline(&timeline.clone()).unwrap());
+    assert_eq!(render_radar(&mean_profile(&refs)).unwrap(), render_radar(&mean_profile(&refs)).unwrap());
+}
@@ -0,0 +1,61 @@
+// every test crate uses a part of the builder only
+#![allow(dead_code)]
+
+use lib::service::dynamo_service::structs::EmotionTableEntry;
+use lib::utilities::get_date_month;
+use serde_json::{json, Value};
+
+// 2024-10-15 09:00 JST
+pub const DAY: u64 = 1728950400;
+
+
+// an entry of U0123 in C0123 at DAY, without text or scores: entry("e1").scores(json!({"anger": 0.5})).build()
+pub struct EntryBuilder {
+    value: Value,
+}
+
+pub fn entry(event_id: &str) -> EntryBuilder {
+    EntryBuilder {
+        value: json!({
+            "event_id": event_id,
+            "user_id": "U0123",
+            "timestamp": DAY,
+            "date": "2024-10-15",
+            "month": "2024-10",
+            "channel_id": "C0123",
+            "channel_type": "channel",
+            "text": "",
+        }),
+    }
+}
+
+impl EntryBuilder {
+    pub fn user(self, user_id: &str) -> Self {
+        self.with(json!({"user_id": user_id}))
+    }
+
+    pub fn channel(self, channel_id: &str) -> Self {
+        self.with(json!({"channel_id": channel_id}))
+    }
+
+    // the date and month follow the timestamp, JST
+    pub fn timestamp(self, timestamp: u64) -> Self {
+        let (date, month) = get_date_month(timestamp).unwrap();
+        self.with(json!({"timestamp": timestamp, "date": date, "month": month}))
+    }
+
+    // ie: json!({"anger": 0.5, "joy": 0.25})
+    pub fn scores(self, scores: Value) -> Self {
+        self.with(scores)
+    }
+
+    // any other field, ie: json!({"text": "hello", "rewrite": "hi"})
+    pub fn with(mut self, fields: Value) -> Self {
+        self.value.as_object_mut().unwrap().extend(fields.as_object().unwrap().to_owned());
+        self
+    }
+
+    pub fn build(self) -> EmotionTableEntry {
+        serde_json::from_value(self.value).unwrap()
+    }
+}
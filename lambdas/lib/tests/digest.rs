@@ -0,0 +1,110 @@
+mod common;
+
+use chrono::NaiveDate;
+use lib::digest::{main_language, Digest, DigestPeriod};
+use lib::language::Language;
+use lib::rollup::{Granularity, Rollup, RollupKey, RollupScope};
+use lib::service::{common_structs::DigestSummary, dynamo_service::structs::EmotionTableEntry, line_service::digest_text};
+use serde_json::json;
+use common::entry;
+
+
+// the rollup of U0123 as RollupService keeps it
+fn rollup(granularity: Granularity, period_start: &str, entries: &[&EmotionTableEntry]) -> Rollup {
+    let mut rollup = Rollup::new(&RollupKey {
+        scope: RollupScope::User,
+        scope_id: "U0123".to_owned(),
+        granularity,
+        period_start: period_start.to_owned(),
+    });
+    for entry in entries {
+        rollup.add(entry);
+    }
+    rollup
+}
+
+fn date(date: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+}
+
+#[test]
+fn ranges_are_the_last_whole_periods() {
+    // a wednesday
+    let today = date("2024-10-16");
+    assert_eq!(DigestPeriod::Week.ranges(today).unwrap(), ((date("2024-10-07"), date("2024-10-13")), (date("2024-09-30"), date("2024-10-06"))));
+    assert_eq!(DigestPeriod::Month.ranges(today).unwrap(), ((date("2024-09-01"), date("2024-09-30")), (date("2024-08-01"), date("2024-08-31"))));
+    // run on the 1st of march of a leap year
+    assert_eq!(DigestPeriod::Month.ranges(date("2024-03-01")).unwrap().0, (date("2024-02-01"), date("2024-02-29")));
+    assert!(DigestPeriod::parse("day").is_err());
+}
+
+#[test]
+fn digest_compares_with_the_previous_period() {
+    let ranges = DigestPeriod::Week.ranges(date("2024-10-16")).unwrap();
+    let e1 = entry("e1").scores(json!({"anger": 0.5, "joy": 0.25})).build();
+    let e2 = entry("e2").scores(json!({"anger": 0.25, "joy": 0.75})).build();
+    let previous = entry("p1").scores(json!({"anger": 0.5, "joy": 0.25, "surprise": 0.75})).build();
+    let current = rollup(Granularity::Week, "2024-10-07", &[&e1, &e2]);
+    let previous = rollup(Granularity::Week, "2024-09-30", &[&previous]);
+    let digest = Digest::new(DigestPeriod::Week, "U0123", ranges, &current, Some(&previous)).unwrap();
+
+    assert_eq!((digest.from.as_str(), digest.to.as_str()), ("2024-10-07", "2024-10-13"));
+    assert_eq!((digest.message_count, digest.previous_message_count), (2, 1));
+    assert_eq!(digest.emotions["anger"].mean, 0.375);
+    assert_eq!(digest.emotions["anger"].change, Some(-0.125));
+    assert_eq!(digest.emotions["joy"].change, Some(0.25));
+    // neutral emotions never count as improved
+    assert_eq!(digest.emotions["surprise"].change, Some(-0.75));
+    assert_eq!(digest.most_improved.as_deref(), Some("joy"));
+
+    // the argmax of the rollup, the text is filled in when sent
+    let most_negative = digest.most_negative.unwrap();
+    assert_eq!((most_negative.event_id.as_str(), most_negative.emotion.as_str(), most_negative.score), ("e1", "anger", 0.5));
+    assert!(most_negative.text.is_empty());
+    assert_eq!(digest.most_positive.unwrap().event_id, "e2");
+}
+
+#[test]
+fn digest_without_previous_period_has_no_change() {
+    let ranges = DigestPeriod::Month.ranges(date("2024-10-16")).unwrap();
+    let e1 = entry("e1").scores(json!({"anger": 0.5})).build();
+    let digest = Digest::new(DigestPeriod::Month, "U0123", ranges, &rollup(Granularity::Month, "2024-09", &[&e1]), None).unwrap();
+    assert_eq!(digest.emotions["anger"].previous_mean, None);
+    assert_eq!(digest.most_improved, None);
+    assert_eq!(digest.most_positive, None);
+    assert_eq!(digest.user_id, "U0123");
+
+    let empty = rollup(Granularity::Month, "2024-09", &[]);
+    assert!(Digest::new(DigestPeriod::Month, "U0123", ranges, &empty, Some(&rollup(Granularity::Month, "2024-08", &[&e1]))).is_none());
+    // an empty previous rollup, ie: every message of it was deleted, is no previous period
+    let digest = Digest::new(DigestPeriod::Month, "U0123", ranges, &rollup(Granularity::Month, "2024-09", &[&e1]), Some(&empty)).unwrap();
+    assert_eq!((digest.emotions["anger"].change, digest.previous_message_count), (None, 0));
+}
+
+#[test]
+fn digest_quotes_the_picks() {
+    let ranges = DigestPeriod::Week.ranges(date("2024-10-16")).unwrap();
+    let e1 = entry("e1").scores(json!({"anger": 0.5})).build();
+    let e2 = entry("e2").scores(json!({"joy": 0.75})).build();
+    let mut digest = Digest::new(DigestPeriod::Week, "U0123", ranges, &rollup(Granularity::Week, "2024-10-07", &[&e1, &e2]), None).unwrap();
+    for pick in digest.picks_mut() {
+        if pick.event_id == "e2" {
+            pick.text = "shipped it, thanks all".to_owned();
+        }
+    }
+    let summary = DigestSummary { summary: "a calm week".to_owned(), advice: "keep going".to_owned(), model_id: None, prompt_version: None };
+
+    let text = digest_text(&digest, &summary);
+    assert!(text.contains("*Most positive (joy 0.75)*: shipped it, thanks all"));
+    // purged since
+    assert!(text.contains("*Most negative (anger 0.50)*: _text no longer kept_"));
+}
+
+#[test]
+fn main_language_is_the_most_written() {
+    let mut ja = entry("e1").build();
+    ja.language = Language::Ja;
+    let en = entry("e2").build();
+    assert_eq!(main_language(&[&ja, &en, &ja]), Language::Ja);
+    assert_eq!(main_language(&[]), Language::Unknown);
+}
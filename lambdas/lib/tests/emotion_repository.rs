@@ -0,0 +1,749 @@
+use std::{env, sync::Arc};
+
+use futures::TryStreamExt;
+use lib::service::{dynamo_service::{structs::EmotionTableEntry, DynamoService}, emotion_repository::{file_repository::FileRepository, ChannelQuery, memory_repository::MemoryRepository, EmotionRepository, EntryPage, UserQuery, DYNAMO_REPOSITORY}};
+use lib::rescoring::ScoringGeneration;
+use lib::rollup::{Granularity, RollupQuery, RollupScope};
+use lib::utilities::get_date_month;
+use serde_json::json;
+
+// 2024-10-15 09:00 JST
+const DAY: u64 = 1728950400;
+const HOUR: u64 = 3600;
+
+
+// Every check only looks at the entries it registered, under a prefix unique to the run,
+// so that the suite also holds against a shared table.
+struct Fixture {
+    repository: Arc<dyn EmotionRepository>,
+    prefix: String,
+}
+
+impl Fixture {
+    fn new(repository: Arc<dyn EmotionRepository>) -> Self {
+        Self { repository, prefix: uuid::Uuid::new_v4().to_string() }
+    }
+
+    fn user(&self, name: &str) -> String {
+        format!("{}-{}", self.prefix, name)
+    }
+
+    fn channel(&self, name: &str) -> String {
+        format!("{}-{}", self.prefix, name)
+    }
+
+    fn channel_entry(&self, event: &str, user: &str, channel: &str, timestamp: u64) -> EmotionTableEntry {
+        EmotionTableEntry { channel_id: self.channel(channel), ..self.entry(event, user, timestamp) }
+    }
+
+    fn entry(&self, event: &str, user: &str, timestamp: u64) -> EmotionTableEntry {
+        self.scored_entry(event, user, timestamp, 0.5)
+    }
+
+    fn scored_entry(&self, event: &str, user: &str, timestamp: u64, anger: f64) -> EmotionTableEntry {
+        let (date, month) = get_date_month(timestamp).unwrap();
+        serde_json::from_value(json!({
+            "event_id": format!("{}-{}", self.prefix, event),
+            "user_id": self.user(user),
+            "timestamp": timestamp,
+            "date": date,
+            "month": month,
+            "channel_id": "C0123",
+            "channel_type": "channel",
+            "text": format!("message {}", event),
+            "anger": anger,
+            "joy": 0.25,
+            "rewrite": "calmer",
+        })).unwrap()
+    }
+
+    async fn register(&self, entries: &[&EmotionTableEntry]) {
+        for entry in entries {
+            self.repository.register_entry(entry).await.unwrap();
+        }
+    }
+
+    // event names of the entries registered by this fixture, in order
+    fn events(&self, entries: &[EmotionTableEntry]) -> Vec<String> {
+        let prefix = format!("{}-", self.prefix);
+        entries.iter().filter_map(|e| e.event_id.strip_prefix(&prefix).map(|e| e.to_owned())).collect()
+    }
+}
+
+
+mod checks {
+    use super::*;
+
+    pub async fn registered_entry_is_found_by_date(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let entry = fixture.entry("e1", "ken", DAY);
+        fixture.register(&[&entry]).await;
+
+        let entries = fixture.repository.query_date(&entry.date).await.unwrap();
+        assert_eq!(fixture.events(&entries), vec!["e1"]);
+    }
+
+    pub async fn entry_round_trips_every_field(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let mut value = serde_json::to_value(fixture.entry("e1", "ken", DAY)).unwrap();
+        let optional_fields = json!({
+            "message_ts": "1728950400.000100",
+            "thread_ts": "1728950000.000200",
+            "text_redacted": true,
+            "language": "ja",
+            "model_id": "model",
+            "prompt_version": "emotion_scores/v3",
+            "from_cache": true,
+            "std_dev": {"anger": 0.125},
+            "samples": 3,
+            "target": {"category": "person", "mention": "@user1", "user_id": "U0456"},
+            "ttl": DAY + 90 * 24 * HOUR,
+            "rewrite": "calmer",
+            "rewrite_scores": {"anger": 0.0625},
+            "rewrite_model_id": "model",
+            "rewrite_prompt_version": "calmer_rewrite/v1",
+            "rewrite_scores_model_id": "model",
+            "rewrite_scores_prompt_version": "emotion_scores/v3",
+            "toxicity": {
+                "insult": {"score": 0.5, "target": "individual"},
+                "identity_attack": {"score": 0.0, "target": "none"},
+                "threat": {"score": 0.0, "target": "none"},
+                "sexual_content": {"score": 0.0, "target": "none"},
+            },
+            "generations": {
+                "model@emotion_scores/v2": {"model_id": "model", "prompt_version": "emotion_scores/v2", "scores": {"anger": 0.375}, "scored_at": DAY + HOUR},
+            },
+        });
+        value.as_object_mut().unwrap().extend(optional_fields.as_object().unwrap().to_owned());
+        let entry: EmotionTableEntry = serde_json::from_value(value).unwrap();
+        fixture.register(&[&entry]).await;
+
+        let entries = fixture.repository.query_date(&entry.date).await.unwrap();
+        let found = entries.iter().find(|e| e.event_id == entry.event_id).expect("entry not found");
+        assert_eq!(serde_json::to_value(found).unwrap(), serde_json::to_value(&entry).unwrap());
+    }
+
+    pub async fn register_replaces_entry_with_same_event_id(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let entry = fixture.entry("e1", "ken", DAY);
+        let mut replaced = entry.clone();
+        replaced.text = "replaced".to_owned();
+        fixture.register(&[&entry, &replaced]).await;
+
+        let entries = fixture.repository.query_date(&entry.date).await.unwrap();
+        assert_eq!(fixture.events(&entries), vec!["e1"]);
+        assert_eq!(entries.iter().find(|e| e.event_id == entry.event_id).unwrap().text, "replaced");
+    }
+
+    pub async fn get_entry_finds_the_entry_by_event_id(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let entry = fixture.entry("e1", "ken", DAY);
+        fixture.register(&[&entry, &fixture.entry("e2", "ken", DAY)]).await;
+
+        let found = fixture.repository.get_entry(&entry.event_id).await.unwrap().expect("entry not found");
+        assert_eq!(serde_json::to_value(found).unwrap(), serde_json::to_value(&entry).unwrap());
+        assert!(fixture.repository.get_entry(&format!("{}-missing", fixture.prefix)).await.unwrap().is_none());
+    }
+
+    pub async fn query_date_returns_that_date_oldest_first(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let later = fixture.entry("later", "ken", DAY + 2 * HOUR);
+        let earlier = fixture.entry("earlier", "aya", DAY + HOUR);
+        let next_day = fixture.entry("next_day", "ken", DAY + 24 * HOUR);
+        fixture.register(&[&later, &next_day, &earlier]).await;
+
+        let entries = fixture.repository.query_date(&later.date).await.unwrap();
+        assert_eq!(fixture.events(&entries), vec!["earlier", "later"]);
+        let entries = fixture.repository.query_date(&next_day.date).await.unwrap();
+        assert_eq!(fixture.events(&entries), vec!["next_day"]);
+    }
+
+    pub async fn query_date_scores_only_leaves_out_text(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let entry = fixture.scored_entry("e1", "ken", DAY, 0.75);
+        fixture.register(&[&entry]).await;
+
+        let entries = fixture.repository.query_date_scores_only(&entry.date).await.unwrap();
+        assert_eq!(fixture.events(&entries), vec!["e1"]);
+        let read = entries.iter().find(|e| e.event_id == entry.event_id).unwrap();
+        assert!(read.text.is_empty());
+        assert!(read.rewrite.is_none());
+        assert_eq!(read.scores, entry.scores);
+        assert_eq!(read.user_id, entry.user_id);
+    }
+
+    pub async fn query_user_is_half_open_and_oldest_first(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let before = fixture.entry("before", "ken", DAY - 1);
+        let from = fixture.entry("from", "ken", DAY);
+        let inside = fixture.entry("inside", "ken", DAY + HOUR);
+        let to = fixture.entry("to", "ken", DAY + 2 * HOUR);
+        let other_user = fixture.entry("other_user", "aya", DAY + HOUR);
+        fixture.register(&[&inside, &to, &before, &other_user, &from]).await;
+
+        let entries = fixture.repository.query_user(&UserQuery::new(&fixture.user("ken"), DAY, DAY + 2 * HOUR)).await.unwrap();
+        assert_eq!(fixture.events(&entries), vec!["from", "inside"]);
+        assert!(entries.iter().all(|e| e.user_id == fixture.user("ken")));
+    }
+
+    pub async fn query_user_with_empty_range_returns_nothing(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let entry = fixture.entry("e1", "ken", DAY);
+        fixture.register(&[&entry]).await;
+
+        assert!(fixture.repository.query_user(&UserQuery::new(&fixture.user("ken"), DAY, DAY)).await.unwrap().is_empty());
+        assert!(fixture.repository.query_user(&UserQuery::new(&fixture.user("ken"), DAY + 1, DAY)).await.unwrap().is_empty());
+        assert!(fixture.repository.query_user(&UserQuery::new(&fixture.user("nobody"), 0, u64::MAX)).await.unwrap().is_empty());
+    }
+
+    pub async fn user_pages_stream_every_entry_once(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let entries: Vec<EmotionTableEntry> = (0..7).map(|i| fixture.entry(&format!("e{}", i), "ken", DAY + i * HOUR)).collect();
+        // two entries at the same timestamp, the cursor has to tell them apart
+        let same_time = fixture.entry("e3b", "ken", DAY + 3 * HOUR);
+        fixture.register(&entries.iter().chain([&same_time]).collect::<Vec<&EmotionTableEntry>>()).await;
+
+        let query = UserQuery::new(&fixture.user("ken"), DAY, DAY + 24 * HOUR).with_page_size(3);
+        let pages: Vec<EntryPage> = fixture.repository.user_pages(&query).try_collect().await.unwrap();
+        assert!(pages.len() >= 3, "expected at least 3 pages, got {}", pages.len());
+        assert!(pages.iter().all(|page| page.entries.len() <= 3));
+        assert!(pages.last().unwrap().cursor.is_none());
+
+        let mut events: Vec<String> = pages.iter().flat_map(|page| fixture.events(&page.entries)).collect();
+        events.sort();
+        assert_eq!(events, vec!["e0", "e1", "e2", "e3", "e3b", "e4", "e5", "e6"]);
+    }
+
+    pub async fn user_page_continues_from_cursor(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let entries: Vec<EmotionTableEntry> = (0..4).map(|i| fixture.entry(&format!("e{}", i), "ken", DAY + i * HOUR)).collect();
+        fixture.register(&entries.iter().collect::<Vec<&EmotionTableEntry>>()).await;
+
+        let query = UserQuery::new(&fixture.user("ken"), DAY, DAY + 24 * HOUR).with_page_size(2);
+        let first = fixture.repository.query_user_page(&query, None).await.unwrap();
+        assert_eq!(fixture.events(&first.entries), vec!["e0", "e1"]);
+        let second = fixture.repository.query_user_page(&query, first.cursor.as_deref()).await.unwrap();
+        assert_eq!(fixture.events(&second.entries), vec!["e2", "e3"]);
+        assert!(fixture.repository.query_user_page(&query, Some("not a cursor")).await.is_err());
+    }
+
+    pub async fn query_user_filters_on_scores(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let calm = fixture.scored_entry("calm", "ken", DAY, 0.2);
+        let at_threshold = fixture.scored_entry("at_threshold", "ken", DAY + HOUR, 0.6);
+        let angry = fixture.scored_entry("angry", "ken", DAY + 2 * HOUR, 0.9);
+        fixture.register(&[&calm, &at_threshold, &angry]).await;
+
+        let query = UserQuery::new(&fixture.user("ken"), DAY, DAY + 24 * HOUR).with_score_above("anger", 0.6).with_page_size(1);
+        let entries = fixture.repository.query_user(&query).await.unwrap();
+        assert_eq!(fixture.events(&entries), vec!["angry"]);
+
+        let query = UserQuery::new(&fixture.user("ken"), DAY, DAY + 24 * HOUR).with_score_above("anger", 0.1).with_score_above("joy", 0.5);
+        assert!(fixture.repository.query_user(&query).await.unwrap().is_empty());
+    }
+
+    pub async fn query_user_projects_scores_only(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let entry = fixture.scored_entry("e1", "ken", DAY, 0.75);
+        fixture.register(&[&entry]).await;
+
+        let query = UserQuery::new(&fixture.user("ken"), DAY, DAY + HOUR).with_scores_only();
+        let entries = fixture.repository.query_user(&query).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].text, "");
+        assert_eq!(entries[0].rewrite, None);
+        assert_eq!(entries[0].scores.get("anger"), 0.75);
+        assert_eq!(serde_json::to_value(&entries[0]).unwrap(), serde_json::to_value(entry.scores_only()).unwrap());
+    }
+
+    pub async fn start_export_returns_new_ids_and_keeps_entries(repository: Arc<dyn EmotionRepository>, destination: &str) {
+        let fixture = Fixture::new(repository);
+        let entry = fixture.entry("e1", "ken", DAY);
+        fixture.register(&[&entry]).await;
+
+        let first = fixture.repository.start_export(destination).await.unwrap();
+        let second = fixture.repository.start_export(destination).await.unwrap();
+        assert!(!first.is_empty());
+        assert_ne!(first, second);
+
+        let entries = fixture.repository.query_date(&entry.date).await.unwrap();
+        assert_eq!(fixture.events(&entries), vec!["e1"]);
+    }
+
+    pub async fn rollups_aggregate_entries_by_period(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        // tuesday and wednesday of the same week
+        let e1 = fixture.scored_entry("e1", "ken", DAY, 0.5);
+        let e2 = fixture.scored_entry("e2", "ken", DAY + HOUR, 0.75);
+        let e3 = fixture.scored_entry("e3", "ken", DAY + 24 * HOUR, 0.25);
+        fixture.register(&[&e1, &e2, &e3]).await;
+
+        let query = RollupQuery::new(RollupScope::User, &fixture.user("ken"), Granularity::Day, &e1.date, &e3.date).unwrap();
+        let days = fixture.repository.query_rollups(&query).await.unwrap();
+        assert_eq!(days.iter().map(|r| (r.period_start.as_str(), r.count)).collect::<Vec<_>>(), vec![(e1.date.as_str(), 2), (e3.date.as_str(), 1)]);
+        let anger = days[0].stats.get("anger");
+        assert_eq!((anger.sum, anger.sum_sq, anger.max), (1.25, 0.8125, Some(0.75)));
+        assert_eq!(anger.argmax, Some(e2.event_id.to_owned()));
+        assert_eq!(days[0].mean("anger"), 0.625);
+        assert_eq!(days[0].std_dev("anger"), 0.125);
+
+        for granularity in [Granularity::Week, Granularity::Month] {
+            let query = RollupQuery::new(RollupScope::User, &fixture.user("ken"), granularity, &e1.date, &e3.date).unwrap();
+            let rollups = fixture.repository.query_rollups(&query).await.unwrap();
+            assert_eq!(rollups.len(), 1);
+            assert_eq!(rollups[0].count, 3);
+            assert_eq!(rollups[0].stats.get("anger").sum, 1.5);
+            assert_eq!(rollups[0].stats.get("joy").sum, 0.75);
+        }
+    }
+
+    pub async fn period_rollups_list_every_user_of_the_period(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let ken = fixture.scored_entry("e1", "ken", DAY, 0.5);
+        let mio = fixture.scored_entry("e2", "mio", DAY + HOUR, 0.75);
+        // the week after
+        let later = fixture.scored_entry("e3", "ken", DAY + 7 * 24 * HOUR, 0.25);
+        fixture.register(&[&ken, &mio, &later]).await;
+
+        let week = Granularity::Week.period_start(&ken.date).unwrap();
+        let rollups = fixture.repository.query_period_rollups(RollupScope::User, Granularity::Week, &week).await.unwrap();
+        let mut users: Vec<(String, u64)> = rollups.into_iter()
+            .filter(|r| r.scope_id.starts_with(&fixture.prefix))
+            .map(|r| (r.scope_id, r.count))
+            .collect();
+        users.sort();
+        assert_eq!(users, vec![(fixture.user("ken"), 1), (fixture.user("mio"), 1)]);
+    }
+
+    pub async fn rollups_follow_replaced_entries(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let entry = fixture.scored_entry("e1", "ken", DAY, 0.5);
+        let replaced = fixture.scored_entry("e1", "ken", DAY, 0.25);
+        fixture.register(&[&entry, &replaced]).await;
+
+        let query = RollupQuery::new(RollupScope::User, &fixture.user("ken"), Granularity::Day, &entry.date, &entry.date).unwrap();
+        let rollups = fixture.repository.query_rollups(&query).await.unwrap();
+        assert_eq!(rollups.len(), 1);
+        assert_eq!(rollups[0].count, 1);
+        assert_eq!(rollups[0].stats.get("anger").sum, 0.25);
+        assert_eq!(rollups[0].stats.get("anger").sum_sq, 0.0625);
+    }
+
+    pub async fn rollups_follow_replaced_entries_with_other_emotions(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let entry = fixture.scored_entry("e1", "ken", DAY, 0.5);
+        let mut replaced = entry.clone();
+        replaced.scores = serde_json::from_value(json!({"sad": 0.75, "fear": 0.25})).unwrap();
+        fixture.register(&[&entry, &replaced]).await;
+
+        let query = RollupQuery::new(RollupScope::User, &fixture.user("ken"), Granularity::Day, &entry.date, &entry.date).unwrap();
+        let rollups = fixture.repository.query_rollups(&query).await.unwrap();
+        assert_eq!(rollups[0].count, 1);
+        // the emotions the replaced entry no longer has are back to 0
+        assert_eq!((rollups[0].stats.get("anger").sum, rollups[0].stats.get("anger").sum_sq), (0.0, 0.0));
+        assert_eq!(rollups[0].stats.get("joy").sum, 0.0);
+        assert_eq!((rollups[0].stats.get("sad").sum, rollups[0].stats.get("sad").sum_sq), (0.75, 0.5625));
+        assert_eq!(rollups[0].stats.get("fear").sum, 0.25);
+    }
+
+    pub async fn rebuild_rollups_deletes_stale_rollups(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let entry = fixture.entry("e1", "ken", DAY);
+        fixture.register(&[&entry]).await;
+        fixture.repository.delete_entry(&entry.event_id).await.unwrap();
+
+        fixture.repository.rebuild_rollups(&entry.date, &entry.date).await.unwrap();
+        let query = RollupQuery::new(RollupScope::User, &fixture.user("ken"), Granularity::Month, &entry.date, &entry.date).unwrap();
+        assert!(fixture.repository.query_rollups(&query).await.unwrap().is_empty());
+    }
+
+    pub async fn rebuild_rollups_covers_whole_weeks_and_months(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let entry = fixture.scored_entry("e1", "ken", DAY, 0.5);
+        let replaced = fixture.scored_entry("e1", "ken", DAY, 0.25);
+        fixture.register(&[&entry, &replaced]).await;
+
+        let report = fixture.repository.rebuild_rollups(&entry.date, &entry.date).await.unwrap();
+        // 2024-10 starts on a tuesday and ends on a thursday
+        assert_eq!((report.from.as_str(), report.to.as_str()), ("2024-09-30", "2024-11-03"));
+        assert!(report.entries >= 1);
+        assert!(report.rollups >= 9);
+
+        let query = RollupQuery::new(RollupScope::User, &fixture.user("ken"), Granularity::Month, &entry.date, &entry.date).unwrap();
+        let rollups = fixture.repository.query_rollups(&query).await.unwrap();
+        assert_eq!(rollups.len(), 1);
+        assert_eq!(rollups[0].count, 1);
+        // exact again after the rebuild
+        assert_eq!(rollups[0].stats.get("anger").max, Some(0.25));
+    }
+
+    pub async fn purge_text_keeps_scores(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let entry = fixture.scored_entry("e1", "ken", DAY, 0.75);
+        let next_day = fixture.entry("next_day", "ken", DAY + 24 * HOUR);
+        fixture.register(&[&entry, &next_day]).await;
+
+        assert!(fixture.repository.purge_text(&entry.date).await.unwrap() >= 1);
+        let entries = fixture.repository.query_date(&entry.date).await.unwrap();
+        let purged = entries.iter().find(|e| e.event_id == entry.event_id).expect("entry not found");
+        assert_eq!(purged.text, "");
+        assert_eq!(purged.rewrite, None);
+        assert!(purged.text_purged);
+        assert_eq!(purged.scores.get("anger"), 0.75);
+
+        // other dates are left alone, purging again finds nothing left
+        let entries = fixture.repository.query_date(&next_day.date).await.unwrap();
+        assert_eq!(entries.iter().find(|e| e.event_id == next_day.event_id).unwrap().text, "message next_day");
+        fixture.repository.purge_text(&entry.date).await.unwrap();
+        let entries = fixture.repository.query_date(&entry.date).await.unwrap();
+        assert_eq!(fixture.events(&entries), vec!["e1"]);
+    }
+
+    pub async fn delete_expired_keeps_unexpired_entries(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let mut expired = fixture.entry("expired", "ken", DAY);
+        expired.ttl = Some(DAY + HOUR);
+        let mut unexpired = fixture.entry("unexpired", "ken", DAY + 1);
+        unexpired.ttl = Some(DAY + 3 * HOUR);
+        let forever = fixture.entry("forever", "ken", DAY + 2);
+        fixture.register(&[&expired, &unexpired, &forever]).await;
+
+        let deleted = fixture.repository.delete_expired(DAY + 2 * HOUR).await.unwrap();
+        let entries = fixture.repository.query_date(&expired.date).await.unwrap();
+        let events = fixture.events(&entries);
+        assert!(events.contains(&"unexpired".to_owned()));
+        assert!(events.contains(&"forever".to_owned()));
+        // dynamo deletes expired entries on its own through the ttl, some time after it passes
+        if fixture.repository.name() == DYNAMO_REPOSITORY {
+            assert_eq!(deleted, None);
+        } else {
+            assert_eq!(deleted, Some(1));
+            assert!(!events.contains(&"expired".to_owned()));
+        }
+    }
+
+    pub async fn query_mentions_finds_entries_aimed_at_user(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let mut aimed = fixture.entry("aimed", "aya", DAY);
+        aimed.target = serde_json::from_value(json!({"category": "person", "mention": "@user1", "user_id": fixture.user("ken")})).unwrap();
+        let mut elsewhere = fixture.entry("elsewhere", "aya", DAY + 1);
+        elsewhere.target = serde_json::from_value(json!({"category": "person", "mention": "@user1", "user_id": fixture.user("mio")})).unwrap();
+        let authored = fixture.entry("authored", "ken", DAY + 2);
+        fixture.register(&[&aimed, &elsewhere, &authored]).await;
+
+        let entries = fixture.repository.query_mentions(&fixture.user("ken")).await.unwrap();
+        assert_eq!(fixture.events(&entries), vec!["aimed"]);
+    }
+
+    pub async fn query_text_mentions_finds_mentions_in_text(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let ken = fixture.user("ken");
+        let mut mention = fixture.entry("mention", "yui", DAY);
+        mention.text = format!("thanks <@{}|ken>", ken);
+        let mut longer_id = fixture.entry("longer_id", "yui", DAY + 1);
+        longer_id.text = format!("<@{}2>", ken);
+        fixture.register(&[&mention, &longer_id]).await;
+
+        let entries = fixture.repository.query_text_mentions(&ken).await.unwrap();
+        assert_eq!(fixture.events(&entries), vec!["mention"]);
+    }
+
+    pub async fn query_encrypted_finds_entries_with_encrypted_text(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let encrypted = |event: &str| serde_json::from_value(json!({
+            "workspace_id": "T0123",
+            "provider": "local",
+            "wrapped_key": "a2V5",
+            "nonce": format!("nonce of {}", event),
+            "ciphertext": "Y2lwaGVy",
+        })).unwrap();
+        let mut text = fixture.entry("text", "ken", DAY);
+        text.text = "".to_owned();
+        text.encrypted_text = Some(encrypted("text"));
+        let mut rewrite = fixture.entry("rewrite", "ken", DAY + 1);
+        rewrite.rewrite = None;
+        rewrite.encrypted_rewrite = Some(encrypted("rewrite"));
+        let plain = fixture.entry("plain", "ken", DAY + 2);
+        fixture.register(&[&text, &rewrite, &plain]).await;
+
+        let entries = fixture.repository.query_encrypted().await.unwrap();
+        assert_eq!(fixture.events(&entries), vec!["text", "rewrite"]);
+    }
+
+    pub async fn replace_and_delete_entry(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let e1 = fixture.entry("e1", "ken", DAY);
+        let e2 = fixture.entry("e2", "ken", DAY + 1);
+        fixture.register(&[&e1, &e2]).await;
+
+        let mut moved = e1.clone();
+        moved.user_id = fixture.user("anonymous");
+        fixture.repository.replace_entry(&moved).await.unwrap();
+        fixture.repository.delete_entry(&e2.event_id).await.unwrap();
+        // deleting twice is fine
+        fixture.repository.delete_entry(&e2.event_id).await.unwrap();
+
+        let entries = fixture.repository.query_user(&UserQuery::new(&fixture.user("ken"), DAY, DAY + HOUR)).await.unwrap();
+        assert!(entries.is_empty());
+        let entries = fixture.repository.query_user(&UserQuery::new(&fixture.user("anonymous"), DAY, DAY + HOUR)).await.unwrap();
+        assert_eq!(fixture.events(&entries), vec!["e1"]);
+    }
+    pub async fn put_generation_keeps_original_scores(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let entry = fixture.scored_entry("e1", "ken", DAY, 0.5);
+        fixture.register(&[&entry]).await;
+
+        let first: ScoringGeneration = serde_json::from_value(json!({"model_id": "a", "scores": {"anger": 0.75}, "scored_at": DAY + HOUR})).unwrap();
+        let second: ScoringGeneration = serde_json::from_value(json!({"model_id": "b", "prompt_version": "emotion_scores/v2", "scores": {"anger": 0.25}, "scored_at": DAY + HOUR})).unwrap();
+        fixture.repository.put_generation(&entry.event_id, "a", &first).await.unwrap();
+        fixture.repository.put_generation(&entry.event_id, "b", &second).await.unwrap();
+        // putting again replaces the generation
+        fixture.repository.put_generation(&entry.event_id, "a", &first).await.unwrap();
+
+        let entries = fixture.repository.query_date(&entry.date).await.unwrap();
+        let found = entries.iter().find(|e| e.event_id == entry.event_id).expect("entry not found");
+        assert_eq!(found.scores, entry.scores);
+        assert_eq!(found.generations.len(), 2);
+        assert_eq!(found.generations["a"], first);
+        assert_eq!(found.generations["b"], second);
+
+        assert!(fixture.repository.put_generation(&format!("{}-missing", fixture.prefix), "a", &first).await.is_err());
+        let entries = fixture.repository.query_date(&entry.date).await.unwrap();
+        assert_eq!(fixture.events(&entries), vec!["e1"]);
+    }
+    pub async fn query_channel_is_half_open_and_oldest_first(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let before = fixture.channel_entry("before", "ken", "general", DAY - 1);
+        let from = fixture.channel_entry("from", "ken", "general", DAY);
+        let inside = fixture.channel_entry("inside", "aya", "general", DAY + HOUR);
+        let to = fixture.channel_entry("to", "ken", "general", DAY + 2 * HOUR);
+        let other_channel = fixture.channel_entry("other_channel", "ken", "random", DAY + HOUR);
+        fixture.register(&[&inside, &to, &before, &other_channel, &from]).await;
+
+        let query = ChannelQuery::new(&fixture.channel("general"), DAY, DAY + 2 * HOUR);
+        let entries = fixture.repository.query_channel(&query).await.unwrap();
+        assert_eq!(fixture.events(&entries), vec!["from", "inside"]);
+        assert_eq!(entries[1].text, "message inside");
+
+        let entries = fixture.repository.query_channel(&query.with_scores_only()).await.unwrap();
+        assert_eq!(serde_json::to_value(&entries[0]).unwrap(), serde_json::to_value(from.scores_only()).unwrap());
+    }
+
+    pub async fn channel_page_continues_from_cursor(repository: Arc<dyn EmotionRepository>) {
+        let fixture = Fixture::new(repository);
+        let entries: Vec<EmotionTableEntry> = (0..5).map(|i| fixture.channel_entry(&format!("e{}", i), if i % 2 == 0 { "ken" } else { "aya" }, "general", DAY + i * HOUR)).collect();
+        fixture.register(&entries.iter().collect::<Vec<&EmotionTableEntry>>()).await;
+
+        let query = ChannelQuery::new(&fixture.channel("general"), DAY, DAY + 24 * HOUR).with_page_size(2);
+        let first = fixture.repository.query_channel_page(&query, None).await.unwrap();
+        assert_eq!(fixture.events(&first.entries), vec!["e0", "e1"]);
+        let second = fixture.repository.query_channel_page(&query, first.cursor.as_deref()).await.unwrap();
+        assert_eq!(fixture.events(&second.entries), vec!["e2", "e3"]);
+
+        let pages: Vec<EntryPage> = fixture.repository.channel_pages(&query).try_collect().await.unwrap();
+        let events: Vec<String> = pages.into_iter().flat_map(|page| fixture.events(&page.entries)).collect();
+        assert_eq!(events, vec!["e0", "e1", "e2", "e3", "e4"]);
+    }
+}
+
+
+// runs every check against the repository returned by $repository, a fresh one per check
+macro_rules! conformance_suite {
+    ($module:ident, $repository:expr, $destination:expr $(, #[$attribute:meta])*) => {
+        mod $module {
+            use super::*;
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn registered_entry_is_found_by_date() {
+                checks::registered_entry_is_found_by_date($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn entry_round_trips_every_field() {
+                checks::entry_round_trips_every_field($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn register_replaces_entry_with_same_event_id() {
+                checks::register_replaces_entry_with_same_event_id($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn get_entry_finds_the_entry_by_event_id() {
+                checks::get_entry_finds_the_entry_by_event_id($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn query_date_returns_that_date_oldest_first() {
+                checks::query_date_returns_that_date_oldest_first($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn query_date_scores_only_leaves_out_text() {
+                checks::query_date_scores_only_leaves_out_text($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn query_user_is_half_open_and_oldest_first() {
+                checks::query_user_is_half_open_and_oldest_first($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn query_user_with_empty_range_returns_nothing() {
+                checks::query_user_with_empty_range_returns_nothing($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn user_pages_stream_every_entry_once() {
+                checks::user_pages_stream_every_entry_once($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn user_page_continues_from_cursor() {
+                checks::user_page_continues_from_cursor($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn query_user_filters_on_scores() {
+                checks::query_user_filters_on_scores($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn query_user_projects_scores_only() {
+                checks::query_user_projects_scores_only($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn start_export_returns_new_ids_and_keeps_entries() {
+                checks::start_export_returns_new_ids_and_keeps_entries($repository.await, &$destination).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn rollups_aggregate_entries_by_period() {
+                checks::rollups_aggregate_entries_by_period($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn period_rollups_list_every_user_of_the_period() {
+                checks::period_rollups_list_every_user_of_the_period($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn rollups_follow_replaced_entries() {
+                checks::rollups_follow_replaced_entries($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn rollups_follow_replaced_entries_with_other_emotions() {
+                checks::rollups_follow_replaced_entries_with_other_emotions($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn rebuild_rollups_deletes_stale_rollups() {
+                checks::rebuild_rollups_deletes_stale_rollups($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn rebuild_rollups_covers_whole_weeks_and_months() {
+                checks::rebuild_rollups_covers_whole_weeks_and_months($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn purge_text_keeps_scores() {
+                checks::purge_text_keeps_scores($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn delete_expired_keeps_unexpired_entries() {
+                checks::delete_expired_keeps_unexpired_entries($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn query_mentions_finds_entries_aimed_at_user() {
+                checks::query_mentions_finds_entries_aimed_at_user($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn query_text_mentions_finds_mentions_in_text() {
+                checks::query_text_mentions_finds_mentions_in_text($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn query_encrypted_finds_entries_with_encrypted_text() {
+                checks::query_encrypted_finds_entries_with_encrypted_text($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn replace_and_delete_entry() {
+                checks::replace_and_delete_entry($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn put_generation_keeps_original_scores() {
+                checks::put_generation_keeps_original_scores($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn query_channel_is_half_open_and_oldest_first() {
+                checks::query_channel_is_half_open_and_oldest_first($repository.await).await
+            }
+
+            #[tokio::test]
+            $(#[$attribute])*
+            async fn channel_page_continues_from_cursor() {
+                checks::channel_page_continues_from_cursor($repository.await).await
+            }
+        }
+    };
+}
+
+
+async fn memory_repository() -> Arc<dyn EmotionRepository> {
+    Arc::new(MemoryRepository::new())
+}
+
+async fn file_repository() -> Arc<dyn EmotionRepository> {
+    Arc::new(FileRepository::new(temp_path("jsonl")))
+}
+
+// the table (with gsi-date and gsi-userid) from TABLE_NAME and TABLE_ARN, the rollup table (with gsi-period) from ROLLUP_TABLE_NAME,
+// ie: DynamoDB local with AWS_ENDPOINT_URL=http://localhost:8000
+async fn dynamo_repository() -> Arc<dyn EmotionRepository> {
+    let config = aws_config::load_from_env().await;
+    Arc::new(DynamoService::new(&aws_sdk_dynamodb::Client::new(&config)))
+}
+
+fn temp_path(extension: &str) -> String {
+    env::temp_dir().join(format!("emotion_repository_{}.{}", uuid::Uuid::new_v4(), extension)).to_str().unwrap().to_owned()
+}
+
+conformance_suite!(memory, memory_repository(), "");
+conformance_suite!(file, file_repository(), temp_path("exports"));
+conformance_suite!(dynamo, dynamo_repository(), env::var("BUCKET_NAME").unwrap_or_default(), #[ignore = "needs a DynamoDB table, see dynamo_repository"]);
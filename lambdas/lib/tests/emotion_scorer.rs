@@ -0,0 +1,140 @@
+use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use aws_sdk_bedrockruntime::{config::http::HttpResponse, error::SdkError, operation::converse::ConverseError, types::error::{ThrottlingException, ValidationException}};
+use aws_smithy_types::body::SdkBody;
+use lib::language::Language;
+use lib::service::common_structs::ScoringResult;
+use lib::service::emotion_scorer::{lexicon_scorer::LexiconScorer, EmotionScorer, FallbackScorer};
+
+
+fn converse_error(error: ConverseError) -> anyhow::Error {
+    let response = HttpResponse::new(400.try_into().unwrap(), SdkBody::empty());
+    anyhow::Error::new(SdkError::service_error(error, response))
+}
+
+fn throttling_error() -> anyhow::Error {
+    converse_error(ConverseError::ThrottlingException(ThrottlingException::builder().message("Too many requests").build()))
+}
+
+fn validation_error() -> anyhow::Error {
+    converse_error(ConverseError::ValidationException(ValidationException::builder().message("Malformed input").build()))
+}
+
+
+// fails every call with the error made by `error`, counting the calls
+#[derive(Debug)]
+struct FailingScorer {
+    error: fn() -> anyhow::Error,
+    calls: AtomicUsize,
+}
+
+impl FailingScorer {
+    fn new(error: fn() -> anyhow::Error) -> Self {
+        Self { error, calls: AtomicUsize::new(0) }
+    }
+}
+
+#[async_trait]
+impl EmotionScorer for FailingScorer {
+    fn name(&self) -> &str {
+        "failing"
+    }
+
+    fn configuration(&self) -> (String, Option<String>) {
+        ("failing".to_owned(), None)
+    }
+
+    async fn score(&self, _text: &str, _language: &Language) -> Result<ScoringResult> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Err((self.error)())
+    }
+}
+
+
+#[test]
+fn lexicon_scores_the_matching_emotion() {
+    let scores = LexiconScorer::new().score_text("This is ridiculous, I am so angry");
+
+    assert!(scores.get("anger") > 0.5);
+    assert_eq!(scores.get("joy"), 0.0);
+    assert_eq!(scores.get("fear"), 0.0);
+}
+
+#[test]
+fn lexicon_scores_neutral_text_zero() {
+    let scores = LexiconScorer::new().score_text("The meeting moved to room 3");
+
+    assert!(scores.to_pairs().iter().all(|(_, score)| *score == 0.0));
+}
+
+#[test]
+fn lexicon_ignores_negated_english_terms() {
+    let scorer = LexiconScorer::new();
+
+    assert!(scorer.score_text("I am happy").get("joy") > 0.0);
+    assert_eq!(scorer.score_text("I am not happy").get("joy"), 0.0);
+    // the negation only reaches 2 words back
+    assert!(scorer.score_text("not that I was ever happy").get("joy") > 0.0);
+}
+
+#[test]
+fn lexicon_ignores_negated_japanese_terms() {
+    let scorer = LexiconScorer::new();
+
+    assert!(scorer.score_text("嬉しいです").get("joy") > 0.0);
+    assert_eq!(scorer.score_text("嬉しくないです").get("joy"), 0.0);
+}
+
+#[test]
+fn lexicon_intensity_raises_anger() {
+    let scorer = LexiconScorer::new();
+
+    let calm = scorer.score_text("I am angry").get("anger");
+    let loud = scorer.score_text("I AM ANGRY!!!").get("anger");
+    assert!(loud > calm, "{loud} should be above {calm}");
+}
+
+#[test]
+fn lexicon_scores_stay_within_0_and_1() {
+    let scores = LexiconScorer::new().score_text("angry furious mad hate wtf damn stupid pissed 😡🤬💢!!!");
+
+    assert!(scores.to_pairs().iter().all(|(_, score)| (0.0..=1.0).contains(score)));
+    assert!(scores.get("anger") > 0.9);
+}
+
+#[tokio::test]
+async fn fallback_is_used_when_primary_is_throttled() {
+    let primary = Arc::new(FailingScorer::new(throttling_error));
+    let scorer = FallbackScorer::new(primary.clone(), Arc::new(LexiconScorer::new()));
+
+    let result = scorer.score("I am so angry", &Language::En).await.unwrap();
+
+    assert_eq!(primary.calls.load(Ordering::SeqCst), 1);
+    assert_eq!(result.model_id, "lexicon");
+    assert!(result.scores.get("anger") > 0.0);
+}
+
+#[tokio::test]
+async fn fallback_is_not_used_for_other_errors() {
+    let fallback = Arc::new(FailingScorer::new(throttling_error));
+    let scorer = FallbackScorer::new(Arc::new(FailingScorer::new(validation_error)), fallback.clone());
+
+    let error = scorer.score("I am so angry", &Language::En).await.unwrap_err();
+
+    assert_eq!(fallback.calls.load(Ordering::SeqCst), 0);
+    assert!(error.downcast_ref::<SdkError<ConverseError>>().is_some());
+
+    let scorer = FallbackScorer::new(Arc::new(FailingScorer::new(|| anyhow!("Missing tool output"))), fallback.clone());
+    assert!(scorer.score("I am so angry", &Language::En).await.is_err());
+    assert_eq!(fallback.calls.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn fallback_keeps_the_primary_configuration() {
+    let scorer = FallbackScorer::new(Arc::new(FailingScorer::new(throttling_error)), Arc::new(LexiconScorer::new()));
+
+    assert_eq!(scorer.name(), "failing");
+    assert_eq!(scorer.configuration(), ("failing".to_owned(), None));
+}
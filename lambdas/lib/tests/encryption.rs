@@ -0,0 +1,80 @@
+mod common;
+
+use std::{env, sync::Arc};
+
+use lib::service::{dynamo_service::structs::EmotionTableEntry, emotion_repository::{memory_repository::MemoryRepository, EmotionRepository}, encryption::{local_key_provider::LocalKeyProvider, text_cipher::TextCipher, KeyProvider}};
+use serde_json::json;
+use common::entry;
+
+
+fn key_file() -> String {
+    env::temp_dir().join(format!("text_encryption_{}.key", uuid::Uuid::new_v4())).to_str().unwrap().to_owned()
+}
+
+fn cipher(path: &str) -> TextCipher {
+    let provider: Arc<dyn KeyProvider> = Arc::new(LocalKeyProvider::from_file(path).unwrap());
+    TextCipher::new(provider, "T0123")
+}
+
+
+#[tokio::test]
+async fn sealed_entry_is_stored_without_plaintext() {
+    let path = key_file();
+    let cipher = cipher(&path);
+    let repository: Arc<dyn EmotionRepository> = Arc::new(MemoryRepository::new());
+
+    let original = entry("e1").scores(json!({"anger": 0.75})).with(json!({"text": "this is secret", "rewrite": "this is calmer"})).build();
+    repository.register_entry(&cipher.seal(&original).await.unwrap()).await.unwrap();
+
+    let stored = repository.query_date("2024-10-15").await.unwrap().remove(0);
+    let stored_json = serde_json::to_string(&stored).unwrap();
+    assert!(stored.is_encrypted());
+    assert!(stored.text.is_empty());
+    assert!(stored.rewrite.is_none());
+    assert!(!stored_json.contains("secret") && !stored_json.contains("calmer"));
+    assert_eq!(stored.scores, original.scores);
+
+    let opened = cipher.open(&stored).await.unwrap();
+    assert_eq!(opened.text, "this is secret");
+    assert_eq!(opened.rewrite.as_deref(), Some("this is calmer"));
+    assert!(!opened.is_encrypted());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn another_cipher_with_the_same_key_file_decrypts() {
+    let path = key_file();
+    let sealed = cipher(&path).seal(&entry("e1").scores(json!({"anger": 0.75})).with(json!({"text": "this is secret"})).build()).await.unwrap();
+
+    // a new process generates another data key, the entry carries the one it was encrypted with
+    let other = cipher(&path);
+    assert_eq!(other.open(&sealed).await.unwrap().text, "this is secret");
+
+    let wrong_path = key_file();
+    assert!(cipher(&wrong_path).open(&sealed).await.is_err());
+    std::fs::remove_file(path).unwrap();
+    std::fs::remove_file(wrong_path).unwrap();
+}
+
+#[tokio::test]
+async fn ciphertext_is_bound_to_its_entry() {
+    let path = key_file();
+    let cipher = cipher(&path);
+    let sealed = cipher.seal(&entry("e1").scores(json!({"anger": 0.75})).with(json!({"text": "this is secret"})).build()).await.unwrap();
+
+    let moved = EmotionTableEntry {
+        event_id: "e2".to_owned(),
+        ..sealed
+    };
+    assert!(cipher.open(&moved).await.is_err());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn empty_text_is_left_alone() {
+    let path = key_file();
+    let cipher = cipher(&path);
+    let sealed = cipher.seal(&entry("e1").scores(json!({"anger": 0.75})).build()).await.unwrap();
+    assert!(!sealed.is_encrypted());
+    std::fs::remove_file(path).unwrap();
+}
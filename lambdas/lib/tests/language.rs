@@ -0,0 +1,28 @@
+use lib::language::Language;
+use lib::slack_markup::normalize;
+
+
+#[test]
+fn japanese_with_a_few_english_words_is_japanese() {
+    assert_eq!(Language::detect("PR見ました、LGTMです"), Language::Ja);
+    assert_eq!(Language::detect("I reviewed the PR, looks good"), Language::En);
+    assert_eq!(Language::detect("(thumbs up) 123"), Language::En);
+    assert_eq!(Language::detect("123 !!"), Language::Unknown);
+}
+
+#[test]
+fn ideographs_without_kana_are_not_japanese() {
+    assert_eq!(Language::detect("我们今天开会讨论这个问题"), Language::Unknown);
+    assert_eq!(Language::detect("会議室予約"), Language::Unknown);
+    assert_eq!(Language::detect("会議室を予約しました"), Language::Ja);
+}
+
+#[test]
+fn markup_does_not_make_japanese_english() {
+    let text = "<@U0123ABCD> <https://github.com/example/repository/pull/1234|PR> 見てください :pray:\n```\nError: connection refused at Database.connect\n```";
+    assert_eq!(Language::detect(text), Language::En);
+    let normalized = normalize(text);
+    assert_eq!(Language::detect(&normalized.text), Language::En);
+    assert_eq!(normalized.prose(), "PR 見てください");
+    assert_eq!(Language::detect(&normalized.prose()), Language::Ja);
+}
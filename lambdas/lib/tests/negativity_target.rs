@@ -0,0 +1,70 @@
+mod common;
+
+use lib::service::common_structs::{NegativityTarget, TargetCategory};
+use lib::warnings::PersonDirectedPolicy;
+use serde_json::json;
+use common::entry;
+
+
+fn target(category: TargetCategory, mention: Option<&str>) -> NegativityTarget {
+    NegativityTarget { category, mention: mention.map(|m| m.to_owned()), user_id: None }
+}
+
+
+#[test]
+fn most_common_takes_the_majority_category() {
+    let targets = vec![
+        target(TargetCategory::Thing, None),
+        target(TargetCategory::Person, Some("@user1")),
+        target(TargetCategory::Person, Some("@user2")),
+    ];
+
+    // with the first target of that category
+    assert_eq!(NegativityTarget::most_common(&targets), Some(target(TargetCategory::Person, Some("@user1"))));
+}
+
+#[test]
+fn most_common_ties_go_to_the_first_sample() {
+    let targets = vec![
+        target(TargetCategory::Group, None),
+        target(TargetCategory::Person, Some("@user1")),
+        target(TargetCategory::Person, None),
+        target(TargetCategory::Group, None),
+    ];
+
+    assert_eq!(NegativityTarget::most_common(&targets).map(|t| t.category), Some(TargetCategory::Group));
+}
+
+#[test]
+fn most_common_of_no_targets_is_none() {
+    assert_eq!(NegativityTarget::most_common(&[]), None);
+}
+
+#[test]
+fn policy_defaults_to_private() {
+    assert_eq!(PersonDirectedPolicy::parse("", "C0MOD"), PersonDirectedPolicy::Private);
+    assert_eq!(PersonDirectedPolicy::parse("unknown", "C0MOD"), PersonDirectedPolicy::Private);
+    assert_eq!(PersonDirectedPolicy::parse("public", "C0MOD"), PersonDirectedPolicy::Public);
+    assert_eq!(PersonDirectedPolicy::parse("none", "C0MOD"), PersonDirectedPolicy::None);
+}
+
+#[test]
+fn moderator_policy_needs_a_moderator_channel() {
+    assert_eq!(PersonDirectedPolicy::parse("moderator", "C0MOD"), PersonDirectedPolicy::Moderator);
+    assert_eq!(PersonDirectedPolicy::parse("moderator", ""), PersonDirectedPolicy::Private);
+}
+
+#[test]
+fn policy_applies_to_person_directed_entries_only() {
+    let at_person = entry("E1").scores(json!({"anger": 0.9})).with(json!({"target": {"category": "person", "mention": "@user1", "user_id": "U0456"}})).build();
+    let at_thing = entry("E2").scores(json!({"anger": 0.9})).with(json!({"target": {"category": "thing"}})).build();
+    let unknown = entry("E3").scores(json!({"anger": 0.9})).build();
+
+    for policy in [PersonDirectedPolicy::Private, PersonDirectedPolicy::Moderator, PersonDirectedPolicy::None] {
+        assert!(policy.applies_to(&at_person), "{policy:?}");
+        assert!(!policy.applies_to(&at_thing), "{policy:?}");
+        assert!(!policy.applies_to(&unknown), "{policy:?}");
+    }
+    // public warns like any other message
+    assert!(!PersonDirectedPolicy::Public.applies_to(&at_person));
+}
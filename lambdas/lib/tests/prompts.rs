@@ -0,0 +1,119 @@
+use lib::language::Language;
+use lib::taxonomy::Taxonomy;
+use lib::service::bedrock_service::prompts::{example_scores_variables, PromptRegistry, CALMER_REWRITE_PROMPT, EMOTION_SCORES_PROMPT, TOXICITY_PROMPT};
+
+
+const TOOL_NAME: (&str, &str) = ("tool_name", "emotion_scores_tool");
+
+
+#[test]
+fn render_substitutes_variables() {
+    let prompt = PromptRegistry::from_env().render(EMOTION_SCORES_PROMPT, Some("v1"), &Language::En, &[TOOL_NAME]).unwrap();
+
+    assert!(prompt.text.contains("emotion_scores_tool"));
+    assert!(!prompt.text.contains("{{"));
+    assert_eq!(prompt.version, "emotion_scores/v1");
+}
+
+#[test]
+fn render_fails_on_a_missing_variable() {
+    let error = PromptRegistry::from_env().render(EMOTION_SCORES_PROMPT, Some("v1"), &Language::En, &[]).unwrap_err();
+
+    assert!(error.to_string().contains("{{tool_name}}"), "{error}");
+}
+
+#[test]
+fn render_uses_the_template_of_the_language() {
+    let registry = PromptRegistry::from_env();
+
+    let en = registry.render(EMOTION_SCORES_PROMPT, Some("v1"), &Language::En, &[TOOL_NAME]).unwrap();
+    let ja = registry.render(EMOTION_SCORES_PROMPT, Some("v1"), &Language::Ja, &[TOOL_NAME]).unwrap();
+
+    assert_ne!(en.text, ja.text);
+    assert_eq!(ja.version, en.version);
+}
+
+#[test]
+fn render_falls_back_to_english() {
+    let registry = PromptRegistry::from_env();
+
+    // there is no japanese template of toxicity/v1
+    let en = registry.render(TOXICITY_PROMPT, Some("v1"), &Language::En, &[TOOL_NAME]).unwrap();
+    let ja = registry.render(TOXICITY_PROMPT, Some("v1"), &Language::Ja, &[TOOL_NAME]).unwrap();
+    let unknown = registry.render(TOXICITY_PROMPT, Some("v1"), &Language::Unknown, &[TOOL_NAME]).unwrap();
+
+    assert_eq!(ja.text, en.text);
+    assert_eq!(unknown.text, en.text);
+}
+
+#[test]
+fn render_fails_on_an_unknown_template() {
+    let registry = PromptRegistry::from_env();
+
+    assert!(registry.render(EMOTION_SCORES_PROMPT, Some("v99"), &Language::En, &[TOOL_NAME]).is_err());
+    assert!(registry.render("no_such_prompt", None, &Language::En, &[TOOL_NAME]).is_err());
+}
+
+#[test]
+fn version_defaults_are_configured_for_every_prompt() {
+    let registry = PromptRegistry::from_env();
+
+    assert_eq!(registry.version(EMOTION_SCORES_PROMPT).unwrap(), "v3");
+    assert_eq!(registry.version(CALMER_REWRITE_PROMPT).unwrap(), "v3");
+    assert_eq!(registry.version(TOXICITY_PROMPT).unwrap(), "v1");
+    assert!(registry.version("no_such_prompt").is_err());
+}
+
+#[test]
+fn with_version_selects_the_rendered_version() {
+    let registry = PromptRegistry::from_env().with_version(CALMER_REWRITE_PROMPT, "v1").unwrap();
+
+    assert_eq!(registry.version(CALMER_REWRITE_PROMPT).unwrap(), "v1");
+    let prompt = registry.render(CALMER_REWRITE_PROMPT, None, &Language::En, &[TOOL_NAME]).unwrap();
+    assert_eq!(prompt.version, "calmer_rewrite/v1");
+    // an explicit version wins over the selected one
+    let prompt = registry.render(CALMER_REWRITE_PROMPT, Some("v2"), &Language::En, &[TOOL_NAME, ("warning_emotions", "anger")]).unwrap();
+    assert_eq!(prompt.version, "calmer_rewrite/v2");
+}
+
+#[test]
+fn with_version_rejects_an_unknown_version() {
+    assert!(PromptRegistry::from_env().with_version(EMOTION_SCORES_PROMPT, "v99").is_err());
+}
+
+#[test]
+fn example_scores_list_every_emotion_of_the_taxonomy() {
+    let taxonomy = Taxonomy::from_env();
+
+    let examples = example_scores_variables(&taxonomy, &Language::En);
+
+    // the default taxonomy renders the examples emotion_scores/v3 was released with
+    assert_eq!(examples[0], ("example_scores_1".to_owned(), "{anger: 0.0, contempt: 0.0, disgust: 0.0, fear: 0.0, joy: 0.8, surprise: 0.1, sad: 0.0, target: {category: none}}".to_owned()));
+    assert_eq!(examples[2].1, "{anger: 0.4, contempt: 0.5, disgust: 0.1, fear: 0.0, joy: 0.0, surprise: 0.0, sad: 0.0, target: {category: person, mention: @user1}}");
+    assert_eq!(example_scores_variables(&taxonomy, &Language::Ja)[1].1, "{anger: 0.6, contempt: 0.2, disgust: 0.2, fear: 0.0, joy: 0.0, surprise: 0.2, sad: 0.0, target: {category: thing}}");
+}
+
+#[test]
+fn example_scores_follow_a_custom_taxonomy() {
+    let taxonomy = Taxonomy::parse(r#"{"emotions": [
+        {"name": "anger", "description": "Irritation", "polarity": "negative"},
+        {"name": "stress", "description": "Pressure", "polarity": "negative"}
+    ]}"#).unwrap();
+
+    let examples = example_scores_variables(&taxonomy, &Language::En);
+
+    assert_eq!(examples.len(), 4);
+    assert_eq!(examples[1].1, "{anger: 0.7, stress: 0.0, target: {category: thing}}");
+    assert!(examples.iter().all(|(_, scores)| !scores.contains("joy") && !scores.contains("contempt")));
+}
+
+#[test]
+fn emotion_scores_v3_renders_with_the_example_scores() {
+    let examples = example_scores_variables(&Taxonomy::from_env(), &Language::Ja);
+    let mut variables = vec![TOOL_NAME, ("emotions", "- anger")];
+    variables.extend(examples.iter().map(|(key, value)| (key.as_str(), value.as_str())));
+
+    let prompt = PromptRegistry::from_env().render(EMOTION_SCORES_PROMPT, Some("v3"), &Language::Ja, &variables).unwrap();
+
+    assert!(prompt.text.contains("{anger: 0.2, contempt: 0.0, disgust: 0.1, fear: 0.0, joy: 0.0, surprise: 0.0, sad: 0.3, target: {category: self}}"));
+}
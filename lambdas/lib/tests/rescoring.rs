@@ -0,0 +1,174 @@
+use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use lib::language::Language;
+use lib::rescoring::{generation_id, RescoreLimits, RescoreReport, RescoreSelection};
+use lib::service::{common_structs::{EmotionScores, ScoringResult}, dynamo_service::structs::EmotionTableEntry, emotion_repository::{memory_repository::MemoryRepository, EmotionRepository}, emotion_scorer::EmotionScorer, rescoring_service::RescoringService, usage_service::CostMeter};
+use serde_json::json;
+
+
+// scores anger 0.25 and charges the meter 0.5 per call
+#[derive(Debug, Default)]
+struct FakeScorer {
+    calls: AtomicUsize,
+    cost_meter: CostMeter,
+}
+
+#[async_trait]
+impl EmotionScorer for FakeScorer {
+    fn name(&self) -> &str {
+        "fake"
+    }
+
+    fn configuration(&self) -> (String, Option<String>) {
+        ("fake-model".to_owned(), Some("emotion_scores/v9".to_owned()))
+    }
+
+    async fn score(&self, _text: &str, _language: &Language) -> Result<ScoringResult> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        self.cost_meter.add(0.5);
+        Ok(ScoringResult {
+            scores: scores(0.25),
+            model_id: "fake-model".to_owned(),
+            prompt_version: Some("emotion_scores/v9".to_owned()),
+            from_cache: false,
+            std_dev: None,
+            samples: None,
+            target: None,
+        })
+    }
+}
+
+async fn repository(texts: &[&str]) -> Arc<dyn EmotionRepository> {
+    let repository: Arc<dyn EmotionRepository> = Arc::new(MemoryRepository::new());
+    for (i, text) in texts.iter().enumerate() {
+        repository.register_entry(&serde_json::from_value(json!({
+            "event_id": format!("e{}", i), "user_id": "U0123", "timestamp": 1728950400_u64 + i as u64, "date": "2024-10-15", "month": "2024-10",
+            "channel_id": "C0123", "channel_type": "channel", "text": text, "anger": 0.75,
+        })).unwrap()).await.unwrap();
+    }
+    repository
+}
+
+fn sequential(max_messages: Option<usize>, max_cost: Option<f64>) -> RescoreLimits {
+    RescoreLimits { concurrency: 1, max_messages, max_cost }
+}
+
+
+fn scores(anger: f64) -> EmotionScores {
+    EmotionScores::from_pairs([("anger", anger)])
+}
+
+#[test]
+fn report_diffs_every_emotion() {
+    let mut report = RescoreReport { warning_threshold: 0.6, ..Default::default() };
+    report.add_diff("e1", &scores(0.5), &scores(0.75));
+    report.add_diff("e2", &scores(0.75), &scores(0.25));
+    report.add_diff("e3", &scores(0.25), &scores(0.25));
+
+    let anger = &report.emotions["anger"];
+    assert_eq!(anger.count, 3);
+    assert_eq!(anger.old_mean, 0.5);
+    assert!((anger.new_mean - 0.4166).abs() < 0.001);
+    assert!((anger.mean_delta + 0.0833).abs() < 0.001);
+    assert!((anger.mean_abs_delta - 0.25).abs() < 0.001);
+    assert_eq!(anger.max_abs_delta, 0.5);
+    assert_eq!(anger.max_abs_delta_event_id.as_deref(), Some("e2"));
+    assert_eq!((anger.crossed_up, anger.crossed_down), (1, 1));
+    // emotions without scores are diffed as 0
+    assert_eq!(report.emotions["joy"].count, 3);
+    assert_eq!(report.emotions["joy"].max_abs_delta, 0.0);
+}
+
+#[test]
+fn selection_matches_user_and_channel() {
+    let entry: EmotionTableEntry = serde_json::from_value(json!({
+        "event_id": "e1", "user_id": "U0123", "timestamp": 1728950400_u64, "date": "2024-10-15", "month": "2024-10",
+        "channel_id": "C0123", "channel_type": "channel", "text": "hello",
+    })).unwrap();
+    let selection = RescoreSelection::new("2024-10-14", "2024-10-16");
+    assert_eq!(selection.dates().unwrap(), vec!["2024-10-14", "2024-10-15", "2024-10-16"]);
+    assert!(selection.matches(&entry));
+    assert!(RescoreSelection { user_id: Some("U0123".to_owned()), channel_id: Some("C0123".to_owned()), ..selection.clone() }.matches(&entry));
+    assert!(!RescoreSelection { channel_id: Some("C0456".to_owned()), ..selection.clone() }.matches(&entry));
+    assert!(RescoreSelection::new("2024-10-16", "2024-10-14").dates().is_err());
+}
+
+#[test]
+fn generation_id_includes_prompt_version() {
+    assert_eq!(generation_id("model", Some("emotion_scores/v3")), "model@emotion_scores/v3");
+    assert_eq!(generation_id("model", None), "model");
+}
+
+#[tokio::test]
+async fn run_stops_at_max_messages_and_resumes() {
+    let repository = repository(&["one", "two", "three", ""]).await;
+    let scorer = Arc::new(FakeScorer::default());
+    let service = RescoringService::new(&repository, scorer.clone()).with_cost_meter(&scorer.cost_meter);
+    let selection = RescoreSelection::new("2024-10-15", "2024-10-15");
+    let generation = service.default_generation_id().unwrap();
+    assert_eq!(generation, "fake-model@emotion_scores/v9");
+
+    let report = service.run(&selection, &sequential(Some(2), None), &generation, false).await.unwrap();
+    assert_eq!((report.selected, report.rescored, report.not_rescored, report.skipped_without_text), (4, 2, 1, 1));
+    assert!(report.limit_reached);
+    assert_eq!(report.model_id, "fake-model");
+    assert_eq!(report.estimated_cost, 1.0);
+    assert_eq!(report.emotions["anger"].old_mean, 0.75);
+    assert_eq!(report.emotions["anger"].new_mean, 0.25);
+
+    // the generations already stored are skipped, and the original scores are kept
+    let report = service.run(&selection, &sequential(None, None), &generation, false).await.unwrap();
+    assert_eq!((report.rescored, report.skipped_existing, report.not_rescored), (1, 2, 0));
+    assert!(!report.limit_reached);
+    assert_eq!(scorer.calls.load(Ordering::SeqCst), 3);
+    let stored = repository.query_date("2024-10-15").await.unwrap();
+    assert_eq!(stored.iter().filter(|e| e.generations.contains_key(&generation)).count(), 3);
+    assert!(stored.iter().all(|e| e.scores == scores(0.75)));
+}
+
+#[tokio::test]
+async fn run_with_force_rescores_existing_generations() {
+    let repository = repository(&["one", "two"]).await;
+    let scorer = Arc::new(FakeScorer::default());
+    let service = RescoringService::new(&repository, scorer.clone()).with_cost_meter(&scorer.cost_meter);
+    let selection = RescoreSelection::new("2024-10-15", "2024-10-15");
+
+    service.run(&selection, &sequential(None, None), "g1", false).await.unwrap();
+    let report = service.run(&selection, &sequential(None, None), "g1", true).await.unwrap();
+    assert_eq!((report.rescored, report.skipped_existing), (2, 0));
+    assert_eq!(scorer.calls.load(Ordering::SeqCst), 4);
+}
+
+#[tokio::test]
+async fn run_stops_at_max_cost() {
+    let repository = repository(&["one", "two", "three", "four"]).await;
+    let scorer = Arc::new(FakeScorer::default());
+    let service = RescoringService::new(&repository, scorer.clone()).with_cost_meter(&scorer.cost_meter);
+    let selection = RescoreSelection::new("2024-10-15", "2024-10-15");
+
+    let report = service.run(&selection, &sequential(None, Some(1.0)), "g1", false).await.unwrap();
+    assert_eq!((report.rescored, report.not_rescored), (2, 2));
+    assert!(report.limit_reached);
+    assert_eq!(report.estimated_cost, 1.0);
+    assert_eq!(scorer.calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn run_reads_the_selected_user_only() {
+    let repository = repository(&["one", "two"]).await;
+    repository.register_entry(&serde_json::from_value(json!({
+        "event_id": "other", "user_id": "U0456", "timestamp": 1728950400_u64, "date": "2024-10-15", "month": "2024-10",
+        "channel_id": "C0123", "channel_type": "channel", "text": "three", "anger": 0.75,
+    })).unwrap()).await.unwrap();
+    let scorer = Arc::new(FakeScorer::default());
+    let service = RescoringService::new(&repository, scorer.clone());
+    let selection = RescoreSelection { user_id: Some("U0456".to_owned()), ..RescoreSelection::new("2024-10-15", "2024-10-15") };
+
+    let report = service.run(&selection, &sequential(None, None), "g1", false).await.unwrap();
+    assert_eq!((report.selected, report.rescored), (1, 1));
+    let selection = RescoreSelection { channel_id: Some("C0123".to_owned()), ..RescoreSelection::new("2024-10-15", "2024-10-15") };
+    let report = service.run(&selection, &sequential(None, None), "g1", false).await.unwrap();
+    assert_eq!((report.selected, report.rescored, report.skipped_existing), (3, 2, 1));
+}
@@ -0,0 +1,127 @@
+use lib::env_keys::{ENTRY_RETENTION_DAYS, EXPORT_RETENTION_DAYS, TEXT_RETENTION_DAYS};
+use lib::retention::RetentionPolicy;
+use lib::service::s3_service::{expired_exports, export_time, group_exports, TableExport};
+
+
+const DAY: u64 = 24 * 3600;
+// 2024-11-01 00:00 JST
+const NOVEMBER_FIRST: u64 = 1730386800;
+
+fn policy(text_days: Option<u64>, entry_days: Option<u64>, export_days: Option<u64>, lookback_days: u64) -> RetentionPolicy {
+    RetentionPolicy { text_days, entry_days, export_days, lookback_days }
+}
+
+fn export(id: &str, written_at: u64) -> TableExport {
+    TableExport {
+        prefix: format!("AWSDynamoDB/{}/", id),
+        keys: vec![format!("AWSDynamoDB/{}/manifest-summary.json", id)],
+        written_at,
+    }
+}
+
+fn prefixes(exports: &[TableExport]) -> Vec<&str> {
+    exports.iter().map(|export| export.prefix.as_str()).collect()
+}
+
+
+// the only test of the file that sets the environment, the others build the policy
+#[test]
+fn exports_default_to_the_shorter_of_the_text_and_entry_retention() {
+    std::env::set_var(ENTRY_RETENTION_DAYS, "365");
+    std::env::set_var(EXPORT_RETENTION_DAYS, "");
+    let policy = RetentionPolicy::from_env();
+    assert_eq!((policy.entry_days, policy.export_days), (Some(365), Some(365)));
+
+    // the exports hold the text of the entries
+    std::env::set_var(TEXT_RETENTION_DAYS, "90");
+    assert_eq!(RetentionPolicy::from_env().export_days, Some(90));
+    std::env::remove_var(ENTRY_RETENTION_DAYS);
+    assert_eq!(RetentionPolicy::from_env().export_days, Some(90));
+    std::env::set_var(ENTRY_RETENTION_DAYS, "30");
+    assert_eq!(RetentionPolicy::from_env().export_days, Some(30));
+
+    std::env::set_var(EXPORT_RETENTION_DAYS, "7");
+    assert_eq!(RetentionPolicy::from_env().export_days, Some(7));
+
+    std::env::remove_var(TEXT_RETENTION_DAYS);
+    std::env::remove_var(ENTRY_RETENTION_DAYS);
+    std::env::remove_var(EXPORT_RETENTION_DAYS);
+    assert_eq!(RetentionPolicy::from_env().export_days, None);
+}
+
+#[test]
+fn text_purge_dates_follow_the_jst_day() {
+    let policy = policy(Some(30), None, None, 3);
+
+    // 30 days before 2024-11-01 00:00 JST is 2024-10-02 00:00 JST, the whole of 2024-10-01 is past retention
+    assert_eq!(policy.text_purge_dates(NOVEMBER_FIRST).unwrap(), vec!["2024-09-29", "2024-09-30", "2024-10-01"]);
+    // a second earlier it is still 2024-10-01 in JST, though already 2024-10-01 15:00 in UTC
+    assert_eq!(policy.text_purge_dates(NOVEMBER_FIRST - 1).unwrap(), vec!["2024-09-28", "2024-09-29", "2024-09-30"]);
+}
+
+#[test]
+fn text_purge_dates_go_back_the_lookback() {
+    assert_eq!(policy(Some(30), None, None, 1).text_purge_dates(NOVEMBER_FIRST).unwrap(), vec!["2024-10-01"]);
+    assert_eq!(policy(Some(30), None, None, 0).text_purge_dates(NOVEMBER_FIRST).unwrap(), Vec::<String>::new());
+    assert_eq!(policy(Some(30), None, None, 31).text_purge_dates(NOVEMBER_FIRST).unwrap().first().map(|d| d.as_str()), Some("2024-09-01"));
+}
+
+#[test]
+fn text_is_kept_forever_without_text_days() {
+    assert!(policy(None, Some(365), None, 7).text_purge_dates(NOVEMBER_FIRST).unwrap().is_empty());
+}
+
+#[test]
+fn entries_expire_after_entry_days() {
+    assert_eq!(policy(Some(30), Some(90), None, 7).expires_at(NOVEMBER_FIRST), Some(NOVEMBER_FIRST + 90 * DAY));
+    assert_eq!(policy(Some(30), None, None, 7).expires_at(NOVEMBER_FIRST), None);
+}
+
+#[test]
+fn export_cutoff_is_export_days_before_now() {
+    assert_eq!(policy(None, Some(365), Some(30), 7).export_cutoff(NOVEMBER_FIRST), Some(NOVEMBER_FIRST - 30 * DAY));
+    assert_eq!(policy(None, Some(365), None, 7).export_cutoff(NOVEMBER_FIRST), None);
+    assert_eq!(policy(None, None, Some(30), 7).export_cutoff(DAY), Some(0));
+}
+
+#[test]
+fn exports_are_grouped_by_export_id() {
+    let exports = group_exports(vec![
+        ("AWSDynamoDB/02-bbb/manifest-summary.json".to_owned(), 300),
+        ("AWSDynamoDB/01-aaa/data/one.json.gz".to_owned(), 100),
+        ("AWSDynamoDB/01-aaa/manifest-summary.json".to_owned(), 150),
+        ("AWSDynamoDB//stray.json".to_owned(), 50),
+    ]);
+
+    assert_eq!(prefixes(&exports), vec!["AWSDynamoDB/01-aaa/", "AWSDynamoDB/02-bbb/"]);
+    assert_eq!(exports[0].keys, vec!["AWSDynamoDB/01-aaa/data/one.json.gz", "AWSDynamoDB/01-aaa/manifest-summary.json"]);
+    assert_eq!(exports[0].written_at, 150);
+}
+
+#[test]
+fn exports_written_before_the_cutoff_expire() {
+    let cutoff = NOVEMBER_FIRST - 30 * DAY;
+    let exports = vec![export("old", cutoff - 1), export("boundary", cutoff), export("latest", NOVEMBER_FIRST)];
+
+    assert_eq!(prefixes(&expired_exports(exports, cutoff)), vec!["AWSDynamoDB/old/"]);
+}
+
+#[test]
+fn the_latest_export_is_never_expired() {
+    let cutoff = NOVEMBER_FIRST - 30 * DAY;
+
+    assert!(expired_exports(vec![export("only", cutoff - 10 * DAY)], cutoff).is_empty());
+    let expired = expired_exports(vec![export("older", cutoff - 10 * DAY), export("latest", cutoff - DAY)], cutoff);
+    assert_eq!(prefixes(&expired), vec!["AWSDynamoDB/older/"]);
+}
+
+#[test]
+fn exports_are_as_old_as_their_export_time() {
+    let summary = r#"{"version":"2020-06-30","exportArn":"arn:aws:dynamodb:ap-northeast-1:0123:table/entries/export/01-aaa","startTime":"2024-10-31T14:58:00.000Z","endTime":"2024-10-31T15:04:00.000Z","exportTime":"2024-10-31T15:00:00.000Z","itemCount":3}"#;
+
+    assert_eq!(export_time(summary).unwrap(), Some(NOVEMBER_FIRST));
+    // rewritten by an erasure, the export time is kept
+    assert_eq!(export_time(r#"{"exportTime":"2024-10-31T15:00:00Z","itemCount":2}"#).unwrap(), Some(NOVEMBER_FIRST));
+    assert_eq!(export_time(r#"{"itemCount":3}"#).unwrap(), None);
+    assert!(export_time(r#"{"exportTime":"yesterday"}"#).is_err());
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use lib::rollup::{compute_rollups, RollupDelta};
+use serde_json::json;
+use common::entry;
+
+
+// the deltas RollupService adds to the table, applied in memory
+#[test]
+fn deltas_of_a_replaced_entry_with_other_emotions_match_a_rebuild() {
+    let previous = entry("e1").scores(json!({"anger": 0.5, "joy": 0.25})).build();
+    let replaced = entry("e1").scores(json!({"sad": 0.75, "fear": 0.25, "not_an_emotion": 1.0})).build();
+
+    let mut rollups = compute_rollups(std::slice::from_ref(&previous), "W0123").unwrap();
+    for rollup in rollups.iter_mut() {
+        rollup.apply(&RollupDelta::new(&replaced, Some(&previous)));
+    }
+    let rebuilt = compute_rollups(&[replaced], "W0123").unwrap();
+
+    assert_eq!(rollups.len(), rebuilt.len());
+    for (rollup, rebuilt) in rollups.iter().zip(rebuilt.iter()) {
+        assert_eq!((&rollup.rollup_id, &rollup.period, rollup.count), (&rebuilt.rollup_id, &rebuilt.period, rebuilt.count));
+        for name in ["anger", "joy", "sad", "fear"] {
+            assert_eq!((rollup.stats.get(name).sum, rollup.stats.get(name).sum_sq), (rebuilt.stats.get(name).sum, rebuilt.stats.get(name).sum_sq), "{}", name);
+        }
+    }
+    // the max can not go down, see RollupService::apply
+    assert_eq!(rollups[0].stats.get("anger").max, Some(0.5));
+}
+
+#[test]
+fn deltas_of_a_new_entry_count_it() {
+    let delta = RollupDelta::new(&entry("e1").scores(json!({"anger": 0.5})).build(), None);
+    assert_eq!(delta.count, 1);
+    assert!(delta.sums.contains(&("anger".to_owned(), 0.5, 0.25)));
+    assert!(delta.sums.contains(&("joy".to_owned(), 0.0, 0.0)));
+}
@@ -0,0 +1,90 @@
+use std::env;
+
+use lib::schema::{CURRENT_SCHEMA_VERSION, UNKNOWN_MODEL_ID};
+use lib::service::{dynamo_service::structs::EmotionTableEntry, migration_service::MigrationCheckpoint};
+use serde_json::{json, Value};
+
+// 2024-10-14 23:30 UTC, 2024-10-15 08:30 JST
+const TIMESTAMP: u64 = 1728948600;
+
+
+fn original_entry() -> Value {
+    json!({
+        "event_id": "e1",
+        "user_id": "U0123",
+        "timestamp": TIMESTAMP,
+        "date": "2024-10-15",
+        "month": "2024-10",
+        "channel_id": "C0123",
+        "channel_type": "channel",
+        "text": "hello",
+        "anger": 0.5,
+    })
+}
+
+
+#[test]
+fn entry_without_version_is_upgraded() {
+    let entry: EmotionTableEntry = serde_json::from_value(original_entry()).unwrap();
+    assert_eq!(entry.schema_version, CURRENT_SCHEMA_VERSION);
+    // written in JST from the start
+    assert_eq!(entry.date, "2024-10-15");
+    assert_eq!(entry.model_id.as_deref(), Some(UNKNOWN_MODEL_ID));
+    assert_eq!(entry.message_ts, None);
+    assert_eq!(entry.scores.get("anger"), 0.5);
+}
+
+#[test]
+fn entry_of_current_version_is_read_as_is() {
+    let mut value = original_entry();
+    value["schema_version"] = json!(CURRENT_SCHEMA_VERSION);
+    value["message_ts"] = json!("1728948600.000100");
+    let entry: EmotionTableEntry = serde_json::from_value(value).unwrap();
+    assert_eq!(entry.model_id, None);
+    assert_eq!(entry.message_ts.as_deref(), Some("1728948600.000100"));
+}
+
+#[test]
+fn upgraded_entry_is_written_with_its_version() {
+    let entry: EmotionTableEntry = serde_json::from_value(original_entry()).unwrap();
+    let value = serde_json::to_value(&entry).unwrap();
+    assert_eq!(value["schema_version"], json!(CURRENT_SCHEMA_VERSION));
+
+    // upgrading again changes nothing
+    let again: EmotionTableEntry = serde_json::from_value(value.clone()).unwrap();
+    assert_eq!(serde_json::to_value(&again).unwrap(), value);
+}
+
+#[test]
+fn emotions_unknown_to_the_taxonomy_are_kept() {
+    let mut value = original_entry();
+    value["schema_version"] = json!(CURRENT_SCHEMA_VERSION);
+    value["frustration"] = json!(0.75);
+    value["note"] = json!("not a score");
+    let entry: EmotionTableEntry = serde_json::from_value(value).unwrap();
+    assert_eq!(entry.scores.get("frustration"), 0.75);
+
+    let written = serde_json::to_value(&entry).unwrap();
+    assert_eq!(written["frustration"], json!(0.75));
+    assert_eq!(written["anger"], json!(0.5));
+    assert!(written.get("note").is_none());
+}
+
+#[test]
+fn checkpoint_resumes_only_with_the_same_segments() {
+    let path = env::temp_dir().join(format!("migration_checkpoint_{}.json", uuid::Uuid::new_v4())).to_str().unwrap().to_owned();
+    let mut checkpoint = MigrationCheckpoint::load_or_new(&path, 4).unwrap();
+    assert_eq!(checkpoint.segments.len(), 4);
+    assert!(!checkpoint.is_done());
+
+    checkpoint.segments[1].last_event_id = Some("e1".to_owned());
+    checkpoint.segments[1].scanned = 100;
+    checkpoint.segments[1].migrated = 10;
+    checkpoint.save(&path).unwrap();
+
+    let resumed = MigrationCheckpoint::load_or_new(&path, 4).unwrap();
+    assert_eq!(resumed, checkpoint);
+    assert_eq!(resumed.totals(), (100, 10, 0, 0));
+    assert!(MigrationCheckpoint::load_or_new(&path, 8).is_err());
+    std::fs::remove_file(path).unwrap();
+}
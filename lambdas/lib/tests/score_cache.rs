@@ -0,0 +1,83 @@
+use lib::service::score_cache_service::ScoreCacheService;
+
+
+const MODEL: &str = "anthropic.claude-3-haiku-20240307-v1:0";
+
+fn cache() -> ScoreCacheService {
+    let config = aws_config::SdkConfig::builder().behavior_version(aws_config::BehaviorVersion::latest()).build();
+    ScoreCacheService::new(&aws_sdk_dynamodb::Client::new(&config))
+}
+
+
+#[test]
+fn key_ignores_case_and_whitespace() {
+    let cache = cache();
+
+    let key = cache.cache_key("thanks!", MODEL, Some("emotion_scores/v3"), &[None]).unwrap();
+
+    assert_eq!(cache.cache_key("  Thanks!\n", MODEL, Some("emotion_scores/v3"), &[None]).unwrap(), key);
+    assert_eq!(cache.cache_key("THANKS!", MODEL, Some("emotion_scores/v3"), &[None]).unwrap(), key);
+    assert_ne!(cache.cache_key("thanks", MODEL, Some("emotion_scores/v3"), &[None]).unwrap(), key);
+}
+
+#[test]
+fn key_collapses_inner_whitespace() {
+    let cache = cache();
+
+    assert_eq!(cache.cache_key("looks good", MODEL, None, &[None]), cache.cache_key("looks \t\n  good", MODEL, None, &[None]));
+    assert_ne!(cache.cache_key("looks good", MODEL, None, &[None]), cache.cache_key("looksgood", MODEL, None, &[None]));
+}
+
+#[test]
+fn key_depends_on_the_model_and_the_prompt_version() {
+    let cache = cache();
+
+    let key = cache.cache_key("lgtm", MODEL, Some("emotion_scores/v3"), &[None]).unwrap();
+
+    assert_ne!(cache.cache_key("lgtm", "anthropic.claude-3-5-haiku-20241022-v1:0", Some("emotion_scores/v3"), &[None]).unwrap(), key);
+    assert_ne!(cache.cache_key("lgtm", MODEL, Some("emotion_scores/v2"), &[None]).unwrap(), key);
+    assert_ne!(cache.cache_key("lgtm", MODEL, None, &[None]).unwrap(), key);
+}
+
+#[test]
+fn key_depends_on_the_sample_count_and_temperatures() {
+    let cache = cache();
+
+    let key = cache.cache_key("lgtm", MODEL, Some("emotion_scores/v3"), &[Some(0.2), Some(0.7)]).unwrap();
+
+    assert_eq!(cache.cache_key("lgtm", MODEL, Some("emotion_scores/v3"), &[Some(0.2), Some(0.7)]).unwrap(), key);
+    assert_ne!(cache.cache_key("lgtm", MODEL, Some("emotion_scores/v3"), &[Some(0.2)]).unwrap(), key);
+    assert_ne!(cache.cache_key("lgtm", MODEL, Some("emotion_scores/v3"), &[Some(0.2), Some(0.7), Some(0.2)]).unwrap(), key);
+    assert_ne!(cache.cache_key("lgtm", MODEL, Some("emotion_scores/v3"), &[Some(0.2), Some(0.9)]).unwrap(), key);
+    assert_ne!(cache.cache_key("lgtm", MODEL, Some("emotion_scores/v3"), &[None, None]).unwrap(), key);
+}
+
+#[test]
+fn key_fields_do_not_run_into_each_other() {
+    let cache = cache();
+
+    // the same bytes split differently between the text and the model
+    assert_ne!(cache.cache_key("ok", "model", None, &[None]), cache.cache_key("okm", "odel", None, &[None]));
+}
+
+#[test]
+fn key_is_a_sha256_hex_digest() {
+    let key = cache().cache_key("ok", MODEL, None, &[None]).unwrap();
+
+    assert_eq!(key.len(), 64);
+    assert!(key.chars().all(|c| c.is_ascii_hexdigit()));
+}
+
+#[test]
+fn empty_and_long_texts_are_not_cached() {
+    let cache = cache();
+
+    assert_eq!(cache.cache_key("", MODEL, None, &[None]), None);
+    assert_eq!(cache.cache_key(" \n\t", MODEL, None, &[None]), None);
+    // 40 characters by default, counted after normalisation
+    assert!(cache.cache_key(&"a".repeat(40), MODEL, None, &[None]).is_some());
+    assert!(cache.cache_key(&format!("  {}  ", "a".repeat(40)), MODEL, None, &[None]).is_some());
+    assert_eq!(cache.cache_key(&"a".repeat(41), MODEL, None, &[None]), None);
+    // characters, not bytes
+    assert!(cache.cache_key(&"あ".repeat(40), MODEL, None, &[None]).is_some());
+}
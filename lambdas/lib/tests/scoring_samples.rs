@@ -0,0 +1,111 @@
+use lib::service::common_structs::{EmotionScores, ScoringResult};
+use lib::service::dynamo_service::structs::EmotionTableEntry;
+use serde_json::json;
+
+
+const HAIKU: &str = "anthropic.claude-3-haiku-20240307-v1:0";
+const SONNET: &str = "anthropic.claude-3-5-sonnet-20240620-v1:0";
+
+fn sample(model_id: &str, prompt_version: &str, anger: f64) -> ScoringResult {
+    ScoringResult {
+        scores: EmotionScores::from_pairs([("anger", anger), ("joy", 0.0)]),
+        model_id: model_id.to_owned(),
+        prompt_version: Some(prompt_version.to_owned()),
+        from_cache: false,
+        std_dev: None,
+        samples: None,
+        target: None,
+    }
+}
+
+
+#[test]
+fn samples_of_one_model_are_averaged() {
+    let result = ScoringResult::from_samples(vec![
+        sample(HAIKU, "emotion_scores/v3", 0.2),
+        sample(HAIKU, "emotion_scores/v3", 0.4),
+        sample(HAIKU, "emotion_scores/v3", 0.6),
+    ]).unwrap();
+
+    assert!((result.scores.get("anger") - 0.4).abs() < 1e-9);
+    assert!(result.std_dev.unwrap().get("anger") > 0.0);
+    assert_eq!(result.samples, Some(3));
+    assert_eq!(result.model_id, HAIKU);
+    assert_eq!(result.prompt_version.as_deref(), Some("emotion_scores/v3"));
+}
+
+#[test]
+fn samples_of_the_most_common_model_are_kept() {
+    let result = ScoringResult::from_samples(vec![
+        sample(HAIKU, "emotion_scores/v3", 0.2),
+        sample(SONNET, "emotion_scores/v3", 0.9),
+        sample(HAIKU, "emotion_scores/v3", 0.4),
+    ]).unwrap();
+
+    assert!((result.scores.get("anger") - 0.3).abs() < 1e-9);
+    assert_eq!(result.samples, Some(2));
+    assert_eq!(result.model_id, HAIKU);
+}
+
+#[test]
+fn fallback_samples_outnumbering_the_first_model_are_kept() {
+    let result = ScoringResult::from_samples(vec![
+        sample(HAIKU, "emotion_scores/v3", 0.2),
+        sample(SONNET, "emotion_scores/v3", 0.6),
+        sample(SONNET, "emotion_scores/v3", 0.8),
+    ]).unwrap();
+
+    assert!((result.scores.get("anger") - 0.7).abs() < 1e-9);
+    assert_eq!(result.samples, Some(2));
+    assert_eq!(result.model_id, SONNET);
+}
+
+#[test]
+fn tied_models_keep_the_first_sample() {
+    let result = ScoringResult::from_samples(vec![
+        sample(HAIKU, "emotion_scores/v3", 0.2),
+        sample(HAIKU, "emotion_scores/v2", 0.4),
+    ]).unwrap();
+
+    assert!((result.scores.get("anger") - 0.2).abs() < 1e-9);
+    assert_eq!(result.samples, Some(1));
+    assert_eq!(result.prompt_version.as_deref(), Some("emotion_scores/v3"));
+}
+
+#[test]
+fn no_samples_is_an_error() {
+    assert!(ScoringResult::from_samples(vec![]).is_err());
+}
+
+#[test]
+fn rewrite_scored_by_a_fallback_model_is_not_compared() {
+    let mut entry: EmotionTableEntry = serde_json::from_value(json!({
+        "event_id": "e1",
+        "user_id": "U0123",
+        "timestamp": 1728950400,
+        "date": "2024-10-15",
+        "month": "2024-10",
+        "channel_id": "C0123",
+        "channel_type": "channel",
+        "text": "",
+        "anger": 0.75,
+        "model_id": HAIKU,
+        "prompt_version": "emotion_scores/v3",
+        "rewrite": "calmer",
+        "rewrite_scores": {"anger": 0.125},
+        "rewrite_scores_model_id": HAIKU,
+        "rewrite_scores_prompt_version": "emotion_scores/v3",
+    })).unwrap();
+    assert!(entry.rewrite_scored_alike());
+
+    entry.rewrite_scores_model_id = Some(SONNET.to_owned());
+    assert!(!entry.rewrite_scored_alike());
+    // the lexicon scorer has no prompt
+    entry.rewrite_scores_model_id = Some(HAIKU.to_owned());
+    entry.rewrite_scores_prompt_version = None;
+    assert!(!entry.rewrite_scored_alike());
+    // entries written before the model of the rewrite scores was recorded
+    entry.rewrite_scores_model_id = None;
+    entry.rewrite_scores_prompt_version = Some("emotion_scores/v3".to_owned());
+    assert!(!entry.rewrite_scored_alike());
+}
@@ -0,0 +1,97 @@
+use std::{fs, path::PathBuf};
+
+use lib::slack_markup::normalize;
+
+// Each fixture is {name}.in.txt (the slack text) with {name}.out.txt (the normalised text),
+// and optionally {name}.mentions.txt (the mentioned user ids, one per line, in token order).
+fn fixtures_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/slack_markup")
+}
+
+fn read_fixture(path: &PathBuf) -> String {
+    let content = fs::read_to_string(path).unwrap_or_else(|e| panic!("failed to read {:?}: {}", path, e));
+    content.strip_suffix('\n').unwrap_or(&content).to_owned()
+}
+
+
+#[test]
+fn normalizes_all_fixtures() {
+    let mut names: Vec<String> = fs::read_dir(fixtures_dir())
+        .unwrap()
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.strip_suffix(".in.txt").map(|n| n.to_owned()))
+        .collect();
+    names.sort();
+    assert!(!names.is_empty(), "no fixtures found");
+
+    let mut failures = vec![];
+    for name in &names {
+        let input = read_fixture(&fixtures_dir().join(format!("{}.in.txt", name)));
+        let expected = read_fixture(&fixtures_dir().join(format!("{}.out.txt", name)));
+        let mentions_path = fixtures_dir().join(format!("{}.mentions.txt", name));
+        let expected_mentions: Vec<String> = match mentions_path.exists() {
+            true => read_fixture(&mentions_path).lines().map(|l| l.to_owned()).collect(),
+            false => vec![],
+        };
+
+        let normalized = normalize(&input);
+        if normalized.text != expected {
+            failures.push(format!("{}: text\n  expected: {:?}\n  actual:   {:?}", name, expected, normalized.text));
+        }
+        if normalized.mentions != expected_mentions {
+            failures.push(format!("{}: mentions\n  expected: {:?}\n  actual:   {:?}", name, expected_mentions, normalized.mentions));
+        }
+    }
+
+    assert!(failures.is_empty(), "{} of {} fixtures failed:\n{}", failures.len(), names.len(), failures.join("\n"));
+}
+
+#[test]
+fn every_fixture_has_an_expected_output() {
+    for entry in fs::read_dir(fixtures_dir()).unwrap() {
+        let file_name = entry.unwrap().file_name().to_str().unwrap().to_owned();
+        if let Some(name) = file_name.strip_suffix(".in.txt") {
+            assert!(fixtures_dir().join(format!("{}.out.txt", name)).exists(), "{} has no .out.txt", name);
+        }
+    }
+}
+
+#[test]
+fn normalizing_twice_changes_nothing() {
+    for entry in fs::read_dir(fixtures_dir()).unwrap() {
+        let path = entry.unwrap().path();
+        if !path.to_str().unwrap().ends_with(".out.txt") {
+            continue;
+        }
+        let expected = read_fixture(&path);
+        // escaped markup is unescaped once, by design
+        if path.file_name().unwrap().to_str().unwrap().starts_with("escaped_markup") {
+            continue;
+        }
+        assert_eq!(normalize(&expected).text, expected, "{:?}", path);
+    }
+}
+
+#[test]
+fn resolves_mention_tokens() {
+    let normalized = normalize("<@U1> and <@U2|ken>");
+    assert_eq!(normalized.resolve_mention("@user1"), Some("U1"));
+    assert_eq!(normalized.resolve_mention("@user2"), Some("U2"));
+    assert_eq!(normalized.resolve_mention("@user3"), None);
+    assert_eq!(normalized.resolve_mention("@user0"), None);
+    assert_eq!(normalized.resolve_mention("user1"), None);
+}
+
+#[test]
+fn restores_mentions_in_rewrites() {
+    let normalized = normalize("<@U1> why did you break it again?");
+    assert_eq!(normalized.restore_mentions("@user1, could you take a look?"), "<@U1>, could you take a look?");
+    // unknown tokens are left alone
+    assert_eq!(normalized.restore_mentions("@user7 hi"), "@user7 hi");
+}
+
+#[test]
+fn empty_text_stays_empty() {
+    let normalized = normalize("");
+    assert_eq!(normalized.text, "");
+    assert!(normalized.mentions.is_empty());
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use lib::statistics::{percentile, ChannelMood, Forecast};
+use lib::utilities::get_day_range;
+use serde_json::json;
+use common::{entry, DAY};
+
+const HOUR: u64 = 3600;
+
+
+#[test]
+fn percentile_interpolates_between_ranks() {
+    let sorted = [0.0, 0.25, 0.5, 1.0];
+    assert_eq!(percentile(&sorted, 0.0), Some(0.0));
+    assert_eq!(percentile(&sorted, 0.5), Some(0.375));
+    assert_eq!(percentile(&sorted, 1.0), Some(1.0));
+    assert_eq!(percentile(&[0.5], 0.9), Some(0.5));
+    assert_eq!(percentile(&[], 0.5), None);
+}
+
+#[test]
+fn channel_mood_of_the_window() {
+    let entries = vec![
+        entry("e1").user("U1").channel("C1").timestamp(DAY).scores(json!({"anger": 0.25})).build(),
+        entry("e2").user("U2").channel("C1").timestamp(DAY + HOUR).scores(json!({"anger": 0.75})).build(),
+        entry("e3").user("U1").channel("C1").timestamp(DAY + 24 * HOUR).scores(json!({"anger": 0.5})).build(),
+        entry("outside").user("U3").channel("C1").timestamp(DAY + 48 * HOUR).scores(json!({"anger": 1.0})).build(),
+        entry("other_channel").user("U3").channel("C2").timestamp(DAY).scores(json!({"anger": 1.0})).build(),
+    ];
+    let mood = ChannelMood::from_entries("C1", DAY, DAY + 48 * HOUR, &entries, 0.6);
+    assert_eq!(mood.message_count, 3);
+    assert_eq!(mood.user_count, 2);
+    assert_eq!(mood.daily_counts.get("2024-10-15"), Some(&2));
+    assert_eq!(mood.daily_counts.get("2024-10-16"), Some(&1));
+
+    let anger = mood.get("anger").unwrap();
+    assert_eq!(anger.mean, 0.5);
+    assert_eq!(anger.p50, 0.5);
+    assert_eq!(anger.p90, 0.7);
+    assert_eq!(anger.max, 0.75);
+    assert_eq!(anger.above_threshold, 1);
+    assert_eq!(mood.get("joy").unwrap().max, 0.0);
+    assert_eq!(mood.forecast(), Forecast::Stormy);
+}
+
+#[test]
+fn empty_channel_is_quiet() {
+    let mood = ChannelMood::from_entries("C1", DAY, DAY + HOUR, &[], 0.6);
+    assert_eq!(mood.message_count, 0);
+    assert!(mood.emotions.is_empty());
+    assert_eq!(mood.forecast(), Forecast::Quiet);
+}
+
+#[test]
+fn day_range_covers_whole_jst_days() {
+    // 2024-10-15 00:00 JST to 2024-10-17 00:00 JST
+    assert_eq!(get_day_range("2024-10-15", "2024-10-16").unwrap(), (DAY - 9 * HOUR, DAY + 39 * HOUR));
+}
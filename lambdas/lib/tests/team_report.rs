@@ -0,0 +1,92 @@
+mod common;
+
+use lib::service::dynamo_service::structs::EmotionTableEntry;
+use lib::service::line_service::team_report_text;
+use lib::env_keys::{DIGEST_TEAMS, TEAM_REPORT_GROUPS};
+use lib::team_report::{team_groups_from_env, TeamGroup, TeamReport};
+use serde_json::json;
+use common::entry;
+
+
+fn person_directed(event: &str, user_id: &str, anger: f64) -> EmotionTableEntry {
+    entry(event).user(user_id).scores(json!({"anger": anger})).with(json!({"target": {"category": "person", "mention": "@user1", "user_id": "U9999"}})).build()
+}
+
+fn group() -> TeamGroup {
+    TeamGroup { users: vec![], channels: vec!["C0123".to_owned()] }
+}
+
+#[test]
+fn small_groups_are_suppressed() {
+    let entries: Vec<EmotionTableEntry> = (0..4).map(|i| entry(&format!("e{}", i)).user(&format!("U{}", i)).scores(json!({"anger": 0.75})).build()).collect();
+    let report = TeamReport::new("platform", &group(), &entries, 5, 0.6);
+    assert!(report.is_suppressed());
+    assert_eq!((report.people, report.emotions, report.person_directed_count), (None, None, None));
+
+    // many messages of few people are still too few people
+    let entries: Vec<EmotionTableEntry> = (0..10).map(|i| entry(&format!("e{}", i)).user(&format!("U{}", i % 2)).scores(json!({"anger": 0.75})).with(json!({"text": "a message that is never quoted"})).build()).collect();
+    let report = TeamReport::new("platform", &group(), &entries, 5, 0.6);
+    assert!(report.is_suppressed());
+    let text = team_report_text(&report);
+    assert!(text.contains("fewer than 5"));
+    assert!(!text.contains("U0") && !text.contains("never quoted"));
+}
+
+#[test]
+fn messages_over_the_threshold_of_few_people_are_not_counted() {
+    let mut entries: Vec<EmotionTableEntry> = (0..5).map(|i| entry(&format!("e{}", i)).user(&format!("U{}", i)).scores(json!({"anger": 0.25, "joy": 0.75})).build()).collect();
+    // a single person over the threshold of anger
+    entries.push(entry("e5").user("U0").scores(json!({"anger": 0.85})).build());
+    let report = TeamReport::new("platform", &group(), &entries, 5, 0.6);
+
+    assert_eq!((report.people, report.message_count), (Some(5), Some(6)));
+    let emotions = report.emotions.as_ref().unwrap();
+    assert_eq!(emotions["anger"].above_threshold, None);
+    assert_eq!(emotions["joy"].above_threshold, Some(5));
+    // a count of 0 points at no one
+    assert_eq!(emotions["sad"].above_threshold, Some(0));
+    assert!((emotions["anger"].mean - (0.25 * 5.0 + 0.85) / 6.0).abs() < 1e-9);
+}
+
+#[test]
+fn person_directed_messages_are_reported_apart() {
+    let mut entries: Vec<EmotionTableEntry> = (0..5).map(|i| entry(&format!("e{}", i)).user(&format!("U{}", i)).scores(json!({"anger": 0.25})).build()).collect();
+    entries.extend((0..5).map(|i| person_directed(&format!("p{}", i), &format!("U{}", i), 0.75)));
+    let report = TeamReport::new("platform", &group(), &entries, 5, 0.6);
+
+    assert_eq!(report.message_count, Some(10));
+    assert_eq!(report.emotions.as_ref().unwrap()["anger"].mean, 0.25);
+    assert_eq!((report.person_directed_count, report.person_directed_mean), (Some(5), Some(0.75)));
+
+    // too few of them to be reported
+    entries.truncate(7);
+    let report = TeamReport::new("platform", &group(), &entries, 5, 0.6);
+    assert!(!report.is_suppressed());
+    assert_eq!((report.person_directed_count, report.person_directed_mean), (None, None));
+    assert!(team_report_text(&report).contains("*Aimed at a person*: _fewer than 5"));
+}
+
+#[test]
+fn groups_match_users_or_channels() {
+    let group = TeamGroup { users: vec!["U0".to_owned()], channels: vec!["C0123".to_owned()] };
+    assert!(group.contains(&entry("e1").user("U0").channel("C9999").build()));
+    assert!(group.contains(&entry("e2").user("U1").build()));
+    assert!(!group.contains(&entry("e3").user("U1").channel("C9999").build()));
+
+    let entries: Vec<EmotionTableEntry> = (0..5).map(|i| entry(&format!("e{}", i)).user(&format!("U{}", i)).channel("C9999").build()).collect();
+    assert_eq!(TeamReport::new("platform", &group, &entries, 2, 0.6).message_count, None);
+}
+
+// the only test of the file that sets the environment
+#[test]
+fn digest_teams_are_groups_of_their_users() {
+    std::env::set_var(TEAM_REPORT_GROUPS, r#"{"platform": {"channels": ["C0123"]}}"#);
+    std::env::set_var(DIGEST_TEAMS, r#"{"platform": ["U0"], "design": ["U1", "U2"]}"#);
+    let groups = team_groups_from_env();
+    std::env::remove_var(TEAM_REPORT_GROUPS);
+    std::env::remove_var(DIGEST_TEAMS);
+
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups["platform"], TeamGroup { users: vec![], channels: vec!["C0123".to_owned()] });
+    assert_eq!(groups["design"], TeamGroup { users: vec!["U1".to_owned(), "U2".to_owned()], channels: vec![] });
+}
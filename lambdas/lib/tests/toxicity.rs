@@ -0,0 +1,60 @@
+use lib::service::common_structs::{ToxicityScores, ToxicityTarget};
+use serde_json::json;
+
+
+// every category at 0.0 and aimed at no one, but those given, ie: json!({"insult": {"score": 0.9, "target": "individual"}})
+fn scores(labels: serde_json::Value) -> ToxicityScores {
+    let mut value = json!({
+        "insult": {"score": 0.0},
+        "identity_attack": {"score": 0.0},
+        "threat": {"score": 0.0},
+        "sexual_content": {"score": 0.0},
+    });
+    value.as_object_mut().unwrap().extend(labels.as_object().unwrap().to_owned());
+    serde_json::from_value(value).unwrap()
+}
+
+fn categories(scores: &ToxicityScores, threshold: f64) -> Vec<&'static str> {
+    scores.escalations(threshold).into_iter().map(|(category, _)| category).collect()
+}
+
+
+#[test]
+fn targeted_categories_over_the_threshold_escalate() {
+    let scores = scores(json!({
+        "insult": {"score": 0.9, "target": "individual"},
+        "threat": {"score": 0.8, "target": "group"},
+        "identity_attack": {"score": 0.75, "target": "other"},
+    }));
+
+    assert_eq!(categories(&scores, 0.7), vec!["insult", "identity_attack", "threat"]);
+    assert_eq!(scores.escalations(0.7)[0].1.target, ToxicityTarget::Individual);
+}
+
+#[test]
+fn untargeted_swearing_does_not_escalate() {
+    let scores = scores(json!({
+        "insult": {"score": 0.95, "target": "none"},
+        "threat": {"score": 0.9},
+    }));
+
+    assert!(scores.escalations(0.7).is_empty());
+}
+
+#[test]
+fn sexual_content_escalates_without_a_target() {
+    let scores = scores(json!({"sexual_content": {"score": 0.8}}));
+
+    assert_eq!(categories(&scores, 0.7), vec!["sexual_content"]);
+}
+
+#[test]
+fn scores_must_exceed_the_threshold() {
+    let scores = scores(json!({
+        "insult": {"score": 0.7, "target": "individual"},
+        "sexual_content": {"score": 0.7},
+    }));
+
+    assert!(scores.escalations(0.7).is_empty());
+    assert_eq!(categories(&scores, 0.69), vec!["insult", "sexual_content"]);
+}
@@ -0,0 +1,105 @@
+use lib::service::usage_service::{pricing::PriceTable, structs::{CostSummary, UsageRecord}, CostMeter};
+
+
+const HAIKU: &str = "anthropic.claude-3-haiku-20240307-v1:0";
+const SONNET: &str = "anthropic.claude-3-5-sonnet-20240620-v1:0";
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 1e-9, "{actual} is not {expected}");
+}
+
+fn record(date: &str, operation: &str, model_id: &str, input_tokens: u64, output_tokens: u64, error: Option<&str>) -> UsageRecord {
+    UsageRecord {
+        workspace_id: "T0123".to_owned(),
+        record_id: format!("{}#{}", date, operation),
+        timestamp_ms: 0,
+        date: date.to_owned(),
+        operation: operation.to_owned(),
+        model_id: model_id.to_owned(),
+        input_tokens,
+        output_tokens,
+        latency_ms: 100,
+        estimated_cost: PriceTable::from_env().estimate_cost(model_id, input_tokens, output_tokens),
+        error: error.map(|e| e.to_owned()),
+    }
+}
+
+
+#[test]
+fn cost_is_priced_per_1000_tokens() {
+    let prices = PriceTable::from_env();
+
+    // 2000 * 0.00025 / 1000 + 400 * 0.00125 / 1000
+    assert_close(prices.estimate_cost(HAIKU, 2000, 400), 0.001);
+    assert_close(prices.estimate_cost(SONNET, 1000, 1000), 0.018);
+    assert_close(prices.estimate_cost(HAIKU, 0, 0), 0.0);
+}
+
+#[test]
+fn unknown_models_cost_nothing() {
+    assert_eq!(PriceTable::from_env().estimate_cost("lexicon", 1_000_000, 1_000_000), 0.0);
+}
+
+#[test]
+fn cost_meter_adds_up_charges() {
+    let meter = CostMeter::default();
+    let shared = meter.clone();
+
+    meter.charge(HAIKU, 2000, 400);
+    shared.charge(SONNET, 1000, 1000);
+    shared.charge("lexicon", 1000, 1000);
+
+    assert_close(meter.spent(), 0.019);
+}
+
+#[test]
+fn summary_totals_every_record() {
+    let records = vec![
+        record("2024-10-15", "emotion_scores", HAIKU, 2000, 400, None),
+        record("2024-10-15", "calmer_rewrite", SONNET, 1000, 1000, None),
+        record("2024-10-16", "emotion_scores", HAIKU, 0, 0, Some("retryable")),
+        record("2024-10-16", "emotion_scores", HAIKU, 2000, 400, None),
+    ];
+
+    let summary = CostSummary::from_records("T0123", "2024-10-15", "2024-10-16", &records);
+
+    assert_eq!(summary.total.calls, 4);
+    assert_eq!(summary.total.failed_calls, 1);
+    assert_eq!(summary.total.input_tokens, 5000);
+    assert_eq!(summary.total.output_tokens, 1800);
+    assert_close(summary.total.estimated_cost, 0.02);
+}
+
+#[test]
+fn summary_groups_by_model_operation_and_date() {
+    let records = vec![
+        record("2024-10-15", "emotion_scores", HAIKU, 2000, 400, None),
+        record("2024-10-15", "calmer_rewrite", SONNET, 1000, 1000, None),
+        record("2024-10-16", "emotion_scores", HAIKU, 0, 0, Some("retryable")),
+        record("2024-10-16", "emotion_scores", HAIKU, 2000, 400, None),
+    ];
+
+    let summary = CostSummary::from_records("T0123", "2024-10-15", "2024-10-16", &records);
+
+    assert_eq!(summary.by_model.len(), 2);
+    assert_eq!(summary.by_model[HAIKU].calls, 3);
+    assert_eq!(summary.by_model[HAIKU].failed_calls, 1);
+    assert_close(summary.by_model[HAIKU].estimated_cost, 0.002);
+    assert_close(summary.by_model[SONNET].estimated_cost, 0.018);
+
+    assert_eq!(summary.by_operation["emotion_scores"].calls, 3);
+    assert_eq!(summary.by_operation["calmer_rewrite"].calls, 1);
+
+    assert_eq!(summary.by_date["2024-10-15"].input_tokens, 3000);
+    assert_close(summary.by_date["2024-10-15"].estimated_cost, 0.019);
+    assert_close(summary.by_date["2024-10-16"].estimated_cost, 0.001);
+}
+
+#[test]
+fn summary_of_no_records_is_zero() {
+    let summary = CostSummary::from_records("T0123", "2024-10-15", "2024-10-16", &[]);
+
+    assert_eq!(summary.total.calls, 0);
+    assert_eq!(summary.total.estimated_cost, 0.0);
+    assert!(summary.by_model.is_empty() && summary.by_operation.is_empty() && summary.by_date.is_empty());
+}
@@ -0,0 +1,212 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use lib::rollup::{Granularity, RollupQuery, RollupScope};
+use lib::service::{common_structs::EmotionScores, dynamo_service::structs::EmotionTableEntry, emotion_repository::{memory_repository::MemoryRepository, EmotionRepository, UserQuery}, encryption::{local_key_provider::LocalKeyProvider, text_cipher::TextCipher, KeyProvider}, s3_service::S3Service, user_data_service::{rewrite_manifest, rewrite_manifest_summary, DataFileChecksum, ErasureMode, UserDataService}};
+use lib::utilities::get_date_month;
+use serde_json::json;
+
+const DAY: u64 = 1728950400;
+
+
+fn entry(event_id: &str, user_id: &str, timestamp: u64, target_user_id: Option<&str>) -> EmotionTableEntry {
+    let (date, month) = get_date_month(timestamp).unwrap();
+    serde_json::from_value(json!({
+        "event_id": event_id,
+        "user_id": user_id,
+        "timestamp": timestamp,
+        "date": date,
+        "month": month,
+        "channel_id": "C0123",
+        "channel_type": "channel",
+        "message_ts": "1728950400.000100",
+        "thread_ts": "1728950000.000200",
+        "text": format!("message {}", event_id),
+        "anger": 0.75,
+        "rewrite": "calmer",
+        "target": target_user_id.map(|id| json!({"category": "person", "mention": "@user1", "user_id": id})),
+    })).unwrap()
+}
+
+// without BUCKET_NAME the data bucket is left out
+async fn user_data(entries: &[EmotionTableEntry]) -> (Arc<dyn EmotionRepository>, UserDataService) {
+    let repository: Arc<dyn EmotionRepository> = Arc::new(MemoryRepository::new());
+    for entry in entries {
+        repository.register_entry(entry).await.unwrap();
+    }
+    let config = aws_config::SdkConfig::builder().behavior_version(aws_config::BehaviorVersion::latest()).build();
+    let s3 = S3Service::new(&aws_sdk_s3::Client::new(&config));
+    let service = UserDataService::new(&repository, &s3).with_hash_key("test key");
+    (repository, service)
+}
+
+fn cipher() -> TextCipher {
+    let provider: Arc<dyn KeyProvider> = Arc::new(LocalKeyProvider::new(&[7; 32]).unwrap());
+    TextCipher::new(provider, "T0123")
+}
+
+async fn all_entries(repository: &Arc<dyn EmotionRepository>, date: &str) -> Vec<EmotionTableEntry> {
+    repository.query_date(date).await.unwrap()
+}
+
+
+#[tokio::test]
+async fn export_bundles_entries_mentions_and_rollups() {
+    let (_, service) = user_data(&[
+        entry("e1", "U1", DAY, None),
+        entry("e2", "U1", DAY + 1, None),
+        entry("aimed", "U2", DAY + 2, Some("U1")),
+        entry("other", "U2", DAY + 3, None),
+    ]).await;
+
+    let (bundle, audit) = service.export("U1").await.unwrap();
+    assert_eq!(bundle.entries.iter().map(|e| e.event_id.as_str()).collect::<Vec<_>>(), vec!["e1", "e2"]);
+    assert_eq!(bundle.mentions.iter().map(|m| m.event_id.as_str()).collect::<Vec<_>>(), vec!["aimed"]);
+    // a day, a week and a month
+    assert_eq!(bundle.rollups.len(), 3);
+    assert_eq!((audit.entries, audit.mentions, audit.rollups), (2, 1, 3));
+    assert!(!serde_json::to_string(&audit).unwrap().contains("U1"));
+}
+
+#[tokio::test]
+async fn erase_deletes_entries_and_unlinks_mentions() {
+    let entries = [
+        entry("e1", "U1", DAY, None),
+        entry("aimed", "U2", DAY + 2, Some("U1")),
+        entry("other", "U2", DAY + 3, Some("U3")),
+    ];
+    let (repository, service) = user_data(&entries).await;
+
+    let audit = service.erase("U1", ErasureMode::Delete).await.unwrap();
+    assert_eq!((audit.entries, audit.mentions), (1, 1));
+
+    let remaining = all_entries(&repository, &entries[0].date).await;
+    assert_eq!(remaining.iter().map(|e| e.event_id.as_str()).collect::<Vec<_>>(), vec!["aimed", "other"]);
+    assert_eq!(remaining[0].target.as_ref().unwrap().user_id, None);
+    assert_eq!(remaining[0].text, "message aimed");
+    assert_eq!(remaining[1].target.as_ref().unwrap().user_id.as_deref(), Some("U3"));
+}
+
+#[tokio::test]
+async fn erase_anonymises_entries_keeping_scores() {
+    let entries = [entry("e1", "U1", DAY, None), entry("e2", "U1", DAY + 1, None)];
+    let (repository, service) = user_data(&entries).await;
+
+    service.erase("U1", ErasureMode::Anonymise).await.unwrap();
+
+    assert!(repository.query_user(&UserQuery::new("U1", 0, u64::MAX)).await.unwrap().is_empty());
+    let remaining = all_entries(&repository, &entries[0].date).await;
+    assert_eq!(remaining.len(), 2);
+    // one pseudonym for every entry of the user
+    assert!(remaining[0].user_id.starts_with("anonymous-"));
+    assert_eq!(remaining[0].user_id, remaining[1].user_id);
+    assert!(remaining.iter().all(|e| e.text.is_empty() && e.rewrite.is_none() && e.scores.get("anger") == 0.75));
+}
+
+#[tokio::test]
+async fn erase_anonymises_event_and_channel_ids() {
+    let mut elsewhere = entry("e3", "U1", DAY + 2, None);
+    elsewhere.channel_id = "C0456".to_owned();
+    let entries = [entry("e1", "U1", DAY, None), entry("e2", "U1", DAY + 1, None), elsewhere];
+    let (repository, service) = user_data(&entries).await;
+
+    service.erase("U1", ErasureMode::Anonymise).await.unwrap();
+
+    let remaining = all_entries(&repository, &entries[0].date).await;
+    assert_eq!(remaining.len(), 3);
+    assert!(remaining.iter().all(|e| e.event_id.starts_with("anonymous-") && e.channel_id.starts_with("anonymous-")));
+    assert!(remaining.iter().all(|e| e.message_ts.is_none() && e.thread_ts.is_none()));
+    // entries of a channel stay together
+    let channels: std::collections::HashSet<&str> = remaining.iter().map(|e| e.channel_id.as_str()).collect();
+    assert_eq!(channels.len(), 2);
+    let serialized = serde_json::to_string(&remaining).unwrap();
+    assert!(["U1", "C0123", "C0456", "1728950400.000100"].iter().all(|id| !serialized.contains(id)), "{serialized}");
+}
+
+#[tokio::test]
+async fn erase_rebuilds_the_rollups_of_the_erased_dates() {
+    let mut highest = entry("highest", "U1", DAY, None);
+    highest.scores = EmotionScores::from_pairs([("anger", 0.875)]);
+    let entries = [highest, entry("kept", "U2", DAY + 1, None)];
+    let (repository, service) = user_data(&entries).await;
+
+    let audit = service.erase("U1", ErasureMode::Delete).await.unwrap();
+
+    // a day, a week and a month of the channel, the workspace and U2
+    assert_eq!(audit.rollups_rebuilt, 9);
+    let query = RollupQuery::new(RollupScope::Channel, "C0123", Granularity::Day, &entries[0].date, &entries[0].date).unwrap();
+    let anger = repository.query_rollups(&query).await.unwrap()[0].stats.get("anger");
+    assert_eq!((anger.max, anger.argmax.as_deref()), (Some(0.75), Some("kept")));
+}
+
+#[tokio::test]
+async fn erase_scrubs_mentions_in_encrypted_text() {
+    let cipher = cipher();
+    let mut mentioning = entry("mentioning", "U2", DAY, None);
+    mentioning.text = "thanks <@U1>".to_owned();
+    let sealed = cipher.seal(&mentioning).await.unwrap();
+    let (repository, service) = user_data(&[entry("e1", "U1", DAY + 1, None), sealed]).await;
+
+    let audit = service.with_cipher(&cipher).erase("U1", ErasureMode::Delete).await.unwrap();
+
+    assert_eq!(audit.text_mentions, 1);
+    let remaining = all_entries(&repository, &mentioning.date).await;
+    assert!(remaining[0].is_encrypted());
+    assert_eq!(cipher.open(&remaining[0]).await.unwrap().text, "thanks <@USER>");
+}
+
+#[tokio::test]
+async fn erase_fails_on_encrypted_text_without_a_cipher() {
+    let sealed = cipher().seal(&entry("sealed", "U2", DAY, None)).await.unwrap();
+    let (repository, service) = user_data(&[entry("e1", "U1", DAY + 1, None), sealed]).await;
+
+    assert!(service.erase("U1", ErasureMode::Delete).await.is_err());
+    // nothing was erased
+    assert_eq!(repository.query_user(&UserQuery::new("U1", 0, u64::MAX)).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn erase_scrubs_mentions_of_the_user_from_other_texts() {
+    let mut mentioning = entry("mentioning", "U2", DAY, None);
+    mentioning.text = "thanks <@U1> and <@U1|ken>, not <@U10>".to_owned();
+    let entries = [entry("e1", "U1", DAY + 1, None), mentioning];
+    let (repository, service) = user_data(&entries).await;
+
+    let audit = service.erase("U1", ErasureMode::Delete).await.unwrap();
+    assert_eq!(audit.text_mentions, 1);
+    let remaining = all_entries(&repository, &entries[0].date).await;
+    assert_eq!(remaining[0].text, "thanks <@USER> and <@USER>, not <@U10>");
+}
+
+#[tokio::test]
+async fn audit_hash_is_keyed() {
+    let (repository, service) = user_data(&[entry("e1", "U1", DAY, None)]).await;
+    let (_, audit) = service.export("U1").await.unwrap();
+    let (_, other_key) = service.with_hash_key("other key").export("U1").await.unwrap();
+    assert_ne!(audit.user_id_hash, other_key.user_id_hash);
+    assert_eq!(audit.user_id_hash.len(), 64);
+
+    // never an unkeyed hash
+    let s3 = S3Service::new(&aws_sdk_s3::Client::new(&aws_config::SdkConfig::builder().behavior_version(aws_config::BehaviorVersion::latest()).build()));
+    let unkeyed = UserDataService::new(&repository, &s3).with_hash_key("");
+    assert!(unkeyed.export("U1").await.is_err());
+}
+
+#[test]
+fn manifest_follows_rewritten_data_files() {
+    let manifest = concat!(
+        r#"{"itemCount":3,"md5Checksum":"old","etag":"old","dataFileS3Key":"AWSDynamoDB/0123-abcd/data/a.json.gz"}"#, "\n",
+        r#"{"itemCount":2,"md5Checksum":"kept","etag":"kept","dataFileS3Key":"AWSDynamoDB/0123-abcd/data/b.json.gz"}"#, "\n",
+    );
+    let checksum = DataFileChecksum::new(b"rewritten", 1);
+    let files = BTreeMap::from([("AWSDynamoDB/0123-abcd/data/a.json.gz".to_owned(), checksum.clone())]);
+
+    let (rewritten, item_count) = rewrite_manifest(manifest, &files).unwrap();
+    assert_eq!(item_count, 3);
+    let lines: Vec<serde_json::Value> = rewritten.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!((lines[0]["itemCount"].as_u64(), lines[0]["md5Checksum"].as_str(), lines[0]["etag"].as_str()), (Some(1), Some(checksum.md5_checksum.as_str()), Some(checksum.etag.as_str())));
+    assert_eq!(lines[1]["md5Checksum"], "kept");
+    assert_eq!(checksum.etag.len(), 32);
+
+    let summary = rewrite_manifest_summary(r#"{"itemCount":5,"outputFormat":"DYNAMODB_JSON"}"#, item_count).unwrap();
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&summary).unwrap(), json!({"itemCount": 3, "outputFormat": "DYNAMODB_JSON"}));
+}
@@ -0,0 +1,79 @@
+use anyhow::{bail, Context, Result};
+
+use lib::schema::CURRENT_SCHEMA_VERSION;
+use lib::service::{encryption::text_cipher::text_cipher_from_env, migration_service::{MigrationCheckpoint, MigrationService}};
+
+
+const USAGE: &str = "usage: migrate_entries [--segments 4] [--page-size 100] [--checkpoint migration_checkpoint.json] [--dry-run] [--encrypt-text]";
+const DEFAULT_SEGMENTS: u32 = 4;
+const DEFAULT_PAGE_SIZE: i32 = 100;
+const DEFAULT_CHECKPOINT: &str = "migration_checkpoint.json";
+
+
+// Rewrites the entries of the emotion table to the current schema version, see lib::schema.
+// Run locally with the lambda environment (TABLE_NAME, and ENTRY_RETENTION_DAYS to give older entries a ttl):
+// cargo run -p migrate_entries -- --segments 8
+// Progress is saved to the checkpoint after every page, run the same command again to resume.
+// With --encrypt-text, the text of entries written before TEXT_ENCRYPTION was set is encrypted as well.
+#[tokio::main]
+async fn main() -> Result<()> {
+    let options = parse_args(std::env::args().skip(1).collect())?;
+
+    let config = aws_config::load_from_env().await;
+    let client = aws_sdk_dynamodb::Client::new(&config);
+    let mut service = MigrationService::new(&client)
+        .with_page_size(options.page_size)
+        .with_dry_run(options.dry_run);
+    if options.encrypt_text {
+        let cipher = text_cipher_from_env(&aws_sdk_kms::Client::new(&config), &client)?.context("--encrypt-text needs TEXT_ENCRYPTION")?;
+        service = service.with_cipher(&cipher);
+    }
+
+    let checkpoint = MigrationCheckpoint::load_or_new(&options.checkpoint, options.segments)?;
+    if checkpoint.is_done() {
+        println!("{} is already done, remove it to run the migration again", options.checkpoint);
+        return Ok(())
+    }
+    println!("migrating entries to version {} with {} segments{}", CURRENT_SCHEMA_VERSION, options.segments, if options.dry_run { " (dry run)" } else { "" });
+
+    let checkpoint = service.run(checkpoint, &options.checkpoint).await?;
+    let (scanned, migrated, ttl_set, encrypted) = checkpoint.totals();
+    println!("{}", serde_json::to_string_pretty(&checkpoint)?);
+    println!("scanned {}, migrated {}, ttl set {}, encrypted {}", scanned, migrated, ttl_set, encrypted);
+    Ok(())
+}
+
+
+#[derive(Debug)]
+struct Options {
+    segments: u32,
+    page_size: i32,
+    checkpoint: String,
+    dry_run: bool,
+    encrypt_text: bool,
+}
+
+fn parse_args(args: Vec<String>) -> Result<Options> {
+    let mut options = Options {
+        segments: DEFAULT_SEGMENTS,
+        page_size: DEFAULT_PAGE_SIZE,
+        checkpoint: DEFAULT_CHECKPOINT.to_owned(),
+        dry_run: false,
+        encrypt_text: false,
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--segments" => options.segments = args.next().context(USAGE)?.parse().context(USAGE)?,
+            "--page-size" => options.page_size = args.next().context(USAGE)?.parse().context(USAGE)?,
+            "--checkpoint" => options.checkpoint = args.next().context(USAGE)?,
+            "--dry-run" => options.dry_run = true,
+            "--encrypt-text" => options.encrypt_text = true,
+            _ => bail!("unknown argument {}\n{}", arg, USAGE),
+        }
+    }
+    if options.segments == 0 || options.page_size <= 0 {
+        bail!("--segments and --page-size must be positive\n{}", USAGE);
+    }
+    Ok(options)
+}
@@ -0,0 +1,118 @@
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::Response;
+use lib::env_keys::ADMIN_API_TOKEN;
+use lib::service::{emotion_repository::ChannelQuery, line_service::weather_report, CommonService};
+use lib::statistics::ChannelMood;
+use lib::utilities::{get_day_range, get_recent_days};
+use lib::warnings::warning_threshold_from_env;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::handlers::{build_error_response_with_status, build_success_response};
+
+const ADMIN_TOKEN_HEADER: &str = "x-admin-token";
+pub(crate) const DEFAULT_WEATHER_DAYS: u64 = 7;
+
+
+#[derive(Debug, Deserialize)]
+pub struct WeeklyUsageQuery {
+    pub workspace_id: Option<String>,
+    // any day (YYYY-MM-DD) in the week, current week if not specified
+    pub date: Option<String>,
+}
+
+
+#[derive(Debug, Deserialize)]
+pub struct ChannelWeatherQuery {
+    pub channel_id: String,
+    // YYYY-MM-DD, both included. the last 7 days up to today if not specified
+    pub from: Option<String>,
+    pub to: Option<String>,
+    // also posts the report to the channel
+    #[serde(default)]
+    pub post: bool,
+}
+
+
+// admin endpoints are disabled unless ADMIN_API_TOKEN is set
+fn verify_admin_token(headers: &HeaderMap) -> bool {
+    let Ok(admin_token) = std::env::var(ADMIN_API_TOKEN) else {
+        return false
+    };
+    if admin_token.is_empty() {
+        return false
+    }
+    let Some(token) = headers.get(ADMIN_TOKEN_HEADER) else {
+        return false
+    };
+    // constant time, memcmp::eq panics on slices of different lengths
+    token.len() == admin_token.len() && openssl::memcmp::eq(token.as_bytes(), admin_token.as_bytes())
+}
+
+
+pub async fn weekly_usage(
+    State(service): State<CommonService>,
+    headers: HeaderMap,
+    Query(query): Query<WeeklyUsageQuery>
+) -> Response {
+    if !verify_admin_token(&headers) {
+        return build_error_response_with_status("Unauthorized.", StatusCode::UNAUTHORIZED);
+    }
+
+    let workspace_id = query.workspace_id.unwrap_or(service.usage.workspace_id().to_owned());
+    match service.usage.weekly_cost_summary(&workspace_id, query.date.as_deref()).await {
+        Ok(summary) => {
+            match serde_json::to_value(&summary) {
+                Ok(body) => build_success_response(&body),
+                Err(error) => build_error_response_with_status(&error.to_string(), StatusCode::INTERNAL_SERVER_ERROR),
+            }
+        },
+        Err(error) => {
+            println!("Error getting weekly usage: {:?}", error);
+            build_error_response_with_status(&error.to_string(), StatusCode::BAD_REQUEST)
+        },
+    }
+}
+
+
+pub async fn channel_weather(
+    State(service): State<CommonService>,
+    headers: HeaderMap,
+    Query(query): Query<ChannelWeatherQuery>
+) -> Response {
+    if !verify_admin_token(&headers) {
+        return build_error_response_with_status("Unauthorized.", StatusCode::UNAUTHORIZED);
+    }
+
+    match get_channel_weather(&service, &query).await {
+        Ok(body) => build_success_response(&body),
+        Err(error) => {
+            println!("Error getting channel weather: {:?}", error);
+            build_error_response_with_status(&error.to_string(), StatusCode::BAD_REQUEST)
+        },
+    }
+}
+
+async fn get_channel_weather(service: &CommonService, query: &ChannelWeatherQuery) -> anyhow::Result<serde_json::Value> {
+    let (recent_from, today) = get_recent_days(DEFAULT_WEATHER_DAYS)?;
+    let from = query.from.to_owned().unwrap_or(recent_from);
+    let to = query.to.to_owned().unwrap_or(today);
+    let mood = get_channel_mood(service, &query.channel_id, &from, &to).await?;
+    if query.post {
+        service.line.send_channel_weather(&mood).await?;
+    }
+    Ok(json!({
+        "forecast": mood.forecast(),
+        "report": weather_report(&mood)?,
+        "mood": mood,
+    }))
+}
+
+// from and to: YYYY-MM-DD, both included
+pub(crate) async fn get_channel_mood(service: &CommonService, channel_id: &str, from: &str, to: &str) -> anyhow::Result<ChannelMood> {
+    let (from, to) = get_day_range(from, to)?;
+    let threshold = warning_threshold_from_env();
+    let entries = service.repository.query_channel(&ChannelQuery::new(channel_id, from, to).with_scores_only()).await?;
+    Ok(ChannelMood::from_entries(channel_id, from, to, &entries, threshold))
+}
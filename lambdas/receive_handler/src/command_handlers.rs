@@ -0,0 +1,64 @@
+use axum::extract::{Form, State};
+use axum::response::Response;
+use lib::service::{line_service::{weather_report, SlashCommandRequest}, CommonService};
+use lib::team_report::min_group_size_from_env;
+use lib::utilities::get_recent_days;
+use serde_json::json;
+
+use crate::admin_handlers::{get_channel_mood, DEFAULT_WEATHER_DAYS};
+use crate::handlers::build_success_response;
+
+const MAX_WEATHER_DAYS: u64 = 90;
+
+
+// slack shows any reply, errors included, so every reply is a 200
+fn ephemeral_response(text: &str) -> Response {
+    build_success_response(&json!({
+        "response_type": "ephemeral",
+        "text": text
+    }))
+}
+
+
+// `/weather [days]`: the weather report of the channel the command is used in, over the last 7 days by default.
+// channels where fewer people than TEAM_REPORT_MIN_GROUP_SIZE wrote get no report, so no one's mood is singled out
+pub async fn weather_command(
+    State(service): State<CommonService>,
+    Form(command_request): Form<SlashCommandRequest>
+) -> Response {
+    println!("command: {}, channel_id {}, user_id {}", command_request.command, command_request.channel_id, command_request.user_id);
+
+    if let Err(error) = service.line.verify_slash_command(&command_request) {
+        println!("Error verifying command: {:?}", error);
+        return ephemeral_response("The command could not be verified.");
+    }
+
+    let text = command_request.text.trim();
+    let days = match text.is_empty() {
+        true => DEFAULT_WEATHER_DAYS,
+        false => match text.parse::<u64>() {
+            Ok(days) if (1..=MAX_WEATHER_DAYS).contains(&days) => days,
+            _ => return ephemeral_response(&format!("Usage: {} [days], with days from 1 to {}.", command_request.command, MAX_WEATHER_DAYS)),
+        },
+    };
+
+    let report = async {
+        let (from, to) = get_recent_days(days)?;
+        let mood = get_channel_mood(&service, &command_request.channel_id, &from, &to).await?;
+        if mood.user_count < min_group_size_from_env() {
+            return Ok(None)
+        }
+        weather_report(&mood).map(Some)
+    };
+    match report.await {
+        Ok(Some(report)) => build_success_response(&json!({
+            "response_type": "in_channel",
+            "text": report
+        })),
+        Ok(None) => ephemeral_response("Too few people wrote in this channel for a weather report."),
+        Err(error) => {
+            println!("Error getting channel weather: {:?}", error);
+            ephemeral_response("The weather report could not be made, please try again later.")
+        },
+    }
+}
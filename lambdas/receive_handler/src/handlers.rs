@@ -11,6 +11,10 @@ use lib::service::CommonService;
 
 
 fn build_error_response(message: &str) -> Response {
+    build_error_response_with_status(message, StatusCode::BAD_REQUEST)
+}
+
+pub(crate) fn build_error_response_with_status(message: &str, status: StatusCode) -> Response {
     let mut json_header = HeaderMap::new();
     json_header.insert(CONTENT_TYPE, "application/json".parse().unwrap());
 
@@ -18,15 +22,15 @@ fn build_error_response(message: &str) -> Response {
         "success": false,
         "message": message
     }).to_string());
-    *response.status_mut() = StatusCode::BAD_REQUEST;
-    return (json_header, response).into_response();
+    *response.status_mut() = status;
+    (json_header, response).into_response()
 }
 
-fn build_success_response(body: &Value) -> Response {
+pub(crate) fn build_success_response(body: &Value) -> Response {
     let mut json_header = HeaderMap::new();
     json_header.insert(CONTENT_TYPE, "application/json".parse().unwrap());
     let response = Response::new(body.to_string());
-    return (json_header, response).into_response();
+    (json_header, response).into_response()
 }
 
 
@@ -35,13 +39,12 @@ pub async fn webhook_received(
     Json(params): Json<Value>
 ) -> Response {
 
-    println!("params: {}", params);
+    // never the event itself, it holds the message text
+    println!("params: type {}, event_id {}", params["type"], params["event_id"]);
 
-    let challenge_request = serde_json::from_value::<EventChallengeRequest>(params.clone());
-    if challenge_request.is_ok() {
-        let challenge_request = challenge_request.unwrap();
+    if let Ok(challenge_request) = serde_json::from_value::<EventChallengeRequest>(params.clone()) {
         let verification_result = service.line.verify_challenge(&challenge_request);
-        if verification_result.is_err() || verification_result.unwrap() == false {
+        if !verification_result.unwrap_or(false) {
             return build_error_response("Error Verifying.");
         } else {
             let response_body = json!({
@@ -80,5 +83,5 @@ pub async fn webhook_received(
         },
     }
 
-    return build_success_response(&json!({}));
+    build_success_response(&json!({}))
 }
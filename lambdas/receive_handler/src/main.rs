@@ -1,7 +1,11 @@
 pub mod handlers;
+pub mod admin_handlers;
+pub mod command_handlers;
 
 use axum::Router;
-use axum::routing::post;
+use axum::routing::{get, post};
+use admin_handlers::{channel_weather, weekly_usage};
+use command_handlers::weather_command;
 use handlers::webhook_received;
 use lambda_http::{run, tracing, Error};
 use lib::service::CommonService;
@@ -18,6 +22,9 @@ use std::env::set_var;
 
     let app = Router::new()
         .route("/", post(post(webhook_received)))
+        .route("/usage/weekly", get(weekly_usage))
+        .route("/channels/weather", get(channel_weather))
+        .route("/commands/weather", post(weather_command))
         .with_state(service);
 
     run(app).await
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+
+use lib::rescoring::{RescoreLimits, RescoreSelection};
+use lib::service::{bedrock_service::prompts::EMOTION_SCORES_PROMPT, rescoring_service::RescoringService, usage_service::CostMeter, CommonService};
+use lib::warnings::warning_threshold_from_env;
+
+
+const USAGE: &str = "usage: rescore --from YYYY-MM-DD [--to YYYY-MM-DD] [--user U0123] [--channel C0123] --model MODEL_ID [--prompt-version v3] [--generation ID] [--concurrency 4] [--max-messages N] [--max-cost USD] [--force] [--output report.json]
+--max-cost is checked before each call and the calls in flight still finish, so the cost can go over it by up to concurrency - 1 calls";
+
+
+// Scores stored messages again with another model or prompt version, the scores are stored next to the original ones
+// under generations.{generation} (default {model}@emotion_scores/{version}) and a diff against the original scores is printed.
+// Run locally with the lambda environment (TABLE_NAME, USAGE_TABLE_NAME, TEXT_ENCRYPTION, ...):
+// cargo run -p rescore -- --from 2024-10-01 --to 2024-10-31 --model anthropic.claude-3-5-sonnet-20240620-v1:0 --max-cost 5
+// Messages that already have the generation are skipped, run the same command again to resume.
+#[tokio::main]
+async fn main() -> Result<()> {
+    let options = parse_args(std::env::args().skip(1).collect())?;
+
+    let config = aws_config::load_from_env().await;
+    let common = CommonService::new(&config);
+    let cost_meter = CostMeter::default();
+    let mut bedrock = common.bedrock.to_owned().with_chat_model(&options.model).with_cost_meter(&cost_meter);
+    if let Some(prompt_version) = &options.prompt_version {
+        bedrock = bedrock.with_prompt_version(EMOTION_SCORES_PROMPT, prompt_version)?;
+    }
+    let service = RescoringService::new(&common.repository, Arc::new(bedrock))
+        .with_cost_meter(&cost_meter)
+        .with_cipher(common.cipher.to_owned())
+        .with_warning_threshold(warning_threshold_from_env());
+    let generation = match &options.generation {
+        Some(generation) => generation.to_owned(),
+        None => service.default_generation_id()?,
+    };
+
+    let report = service.run(&options.selection, &options.limits, &generation, options.force).await?;
+    let json = serde_json::to_string_pretty(&report)?;
+    println!("{}", json);
+    if let Some(output) = &options.output {
+        std::fs::write(output, json).context(format!("Error writing {}", output))?;
+    }
+    if report.limit_reached {
+        println!("a limit was reached, {} messages were not rescored: run the same command again to continue", report.not_rescored);
+    }
+    Ok(())
+}
+
+
+#[derive(Debug)]
+struct Options {
+    selection: RescoreSelection,
+    limits: RescoreLimits,
+    model: String,
+    prompt_version: Option<String>,
+    generation: Option<String>,
+    force: bool,
+    output: Option<String>,
+}
+
+fn parse_args(args: Vec<String>) -> Result<Options> {
+    let mut from = None;
+    let mut to = None;
+    let mut user_id = None;
+    let mut channel_id = None;
+    let mut model = None;
+    let mut prompt_version = None;
+    let mut generation = None;
+    let mut limits = RescoreLimits::default();
+    let mut force = false;
+    let mut output = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" => from = Some(args.next().context(USAGE)?),
+            "--to" => to = Some(args.next().context(USAGE)?),
+            "--user" => user_id = Some(args.next().context(USAGE)?),
+            "--channel" => channel_id = Some(args.next().context(USAGE)?),
+            "--model" => model = Some(args.next().context(USAGE)?),
+            "--prompt-version" => prompt_version = Some(args.next().context(USAGE)?),
+            "--generation" => generation = Some(args.next().context(USAGE)?),
+            "--concurrency" => limits.concurrency = args.next().context(USAGE)?.parse().context(USAGE)?,
+            "--max-messages" => limits.max_messages = Some(args.next().context(USAGE)?.parse().context(USAGE)?),
+            "--max-cost" => limits.max_cost = Some(args.next().context(USAGE)?.parse().context(USAGE)?),
+            "--force" => force = true,
+            "--output" => output = Some(args.next().context(USAGE)?),
+            _ => bail!("unknown argument {}\n{}", arg, USAGE),
+        }
+    }
+    let from = from.context(USAGE)?;
+    let to = to.unwrap_or(from.to_owned());
+    if limits.concurrency == 0 {
+        bail!("--concurrency must be positive\n{}", USAGE);
+    }
+    Ok(Options {
+        selection: RescoreSelection { user_id, channel_id, ..RescoreSelection::new(&from, &to) },
+        limits,
+        model: model.context(USAGE)?,
+        prompt_version,
+        generation,
+        force,
+        output,
+    })
+}
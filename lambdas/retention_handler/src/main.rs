@@ -0,0 +1,66 @@
+use aws_lambda_events::eventbridge::EventBridgeEvent;
+
+use lambda_runtime::{service_fn, tracing::{self}, Error, LambdaEvent};
+use serde_json::{json, Value};
+
+use lib::env_keys::BUCKET_NAME;
+use lib::retention::{PurgeReport, RetentionPolicy};
+use lib::service::{s3_service::RETENTION_REPORT_PREFIX, CommonService};
+use lib::utilities::get_date_month;
+
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing::init_default_subscriber();
+
+    let config = aws_config::load_from_env().await;
+    let service = CommonService::new(&config);
+    let service_function = service_fn(|event| async { eventbridge_handler(event, &service).await });
+    lambda_runtime::run(service_function).await?;
+
+    Ok(())
+
+}
+
+async fn eventbridge_handler(event: LambdaEvent<EventBridgeEvent>, service: &CommonService) -> Result<Value, Error> {
+    println!("{:?}", event.payload);
+    match process_event(service).await {
+        Ok(_) => {
+            println!("finish processing event with success!")
+        },
+        Err(error) => {
+            println!("Error processing event: {:?}", error)
+        },
+    }
+    Ok(json!({}))
+}
+
+
+async fn process_event(service: &CommonService) -> anyhow::Result<()> {
+    let policy = RetentionPolicy::from_env();
+    let bucket_name = std::env::var(BUCKET_NAME).unwrap_or_default();
+    let now = chrono::Utc::now().timestamp() as u64;
+    let mut report = PurgeReport::new(&policy, now);
+
+    for date in policy.text_purge_dates(now)? {
+        let purged = service.repository.purge_text(&date).await?;
+        if purged > 0 {
+            report.texts_purged.insert(date, purged);
+        }
+    }
+    report.entries_deleted = service.repository.delete_expired(now).await?;
+
+    if let (Some(cutoff), false) = (policy.export_cutoff(now), bucket_name.is_empty()) {
+        let (prefixes, objects) = service.s3.purge_exports(&bucket_name, cutoff).await?;
+        report.exports_deleted = prefixes;
+        report.objects_deleted = objects;
+    }
+
+    let report_json = serde_json::to_string_pretty(&report)?;
+    println!("retention report: {}", report_json);
+    if !bucket_name.is_empty() {
+        let (date, _) = get_date_month(now)?;
+        service.s3.put_object(&bucket_name, &format!("{}{}.json", RETENTION_REPORT_PREFIX, date), &report_json).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,43 @@
+
+use anyhow::{bail, Context, Result};
+
+use lib::service::CommonService;
+
+
+const USAGE: &str = "usage: rollup_rebuild --from YYYY-MM-DD [--to YYYY-MM-DD]";
+
+
+// Recomputes the rollups from the emotion table, ie: after a failed update or a replaced entry.
+// Run locally with the lambda environment (TABLE_NAME, ROLLUP_TABLE_NAME, WORKSPACE_ID):
+// cargo run -p rollup_rebuild -- --from 2024-10-01 --to 2024-10-31
+#[tokio::main]
+async fn main() -> Result<()> {
+    let (from, to) = parse_args(std::env::args().skip(1).collect())?;
+
+    let config = aws_config::load_from_env().await;
+    let service = CommonService::new(&config);
+    println!("rebuilding rollups of {} from {} to {}", service.repository.name(), from, to);
+
+    let report = service.repository.rebuild_rollups(&from, &to).await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+fn parse_args(args: Vec<String>) -> Result<(String, String)> {
+    let mut from = None;
+    let mut to = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" => from = Some(args.next().context(USAGE)?),
+            "--to" => to = Some(args.next().context(USAGE)?),
+            _ => bail!("unknown argument {}\n{}", arg, USAGE),
+        }
+    }
+    let from = from.context(USAGE)?;
+    let to = to.unwrap_or(from.to_owned());
+    if to < from {
+        bail!("--to {} is before --from {}", to, from);
+    }
+    Ok((from, to))
+}
@@ -1,9 +1,11 @@
 
 use aws_lambda_events::sqs::SqsEvent;
 use lambda_runtime::{service_fn, tracing::{self}, Error, LambdaEvent};
-use lib::{env_keys::{IMMEDIATE_WARNING_THRESHOLD, QUEUE_ARN, TABLE_NAME}, service::{line_service::MessageEventRequest, CommonService}, warnings::{ANGER_WARNING, CONTEMPT_WARNING, DISGUST_WARNING}};
+use lib::{env_keys::{MODERATOR_CHANNEL_ID, PERSON_DIRECTED_WARNING_THRESHOLD, QUEUE_ARN, TOXICITY_CLASSIFIER_ENABLED, TOXICITY_THRESHOLD, WARNING_MAX_STD_DEV}, language::Language, service::{bedrock_service::errors::is_throttling_error, common_structs::{EmotionScores, MessageRewrite, ScoringResult}, dynamo_service::structs::EmotionTableEntry, line_service::{person_directed_reason, toxicity_reason, MessageEventRequest}, CommonService}, redaction::Redactor, retention::RetentionPolicy, slack_markup::normalize, taxonomy::{taxonomy, Emotion}, warnings::{warning_threshold_from_env, PersonDirectedPolicy}};
 use serde_json::{json, Value};
 
+const DEFAULT_TOXICITY_THRESHOLD: f64 = 0.7;
+
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -11,31 +13,44 @@ async fn main() -> Result<(), Error> {
 
     let config = aws_config::load_from_env().await;
     let service = CommonService::new(&config);
-    let service_function = service_fn(|event| async { sqs_handler(event, &service).await });
+    let redactor = Redactor::from_env();
+    let service_function = service_fn(|event| async { sqs_handler(event, &service, &redactor).await });
     lambda_runtime::run(service_function).await?;
 
     Ok(())
 
 }
 
-async fn sqs_handler(event: LambdaEvent<SqsEvent>, service: &CommonService) -> Result<Value, Error> {
-    println!("{:?}", event.payload);
-    match process_event(event.payload, service).await {
+async fn sqs_handler(event: LambdaEvent<SqsEvent>, service: &CommonService, redactor: &Redactor) -> Result<Value, Error> {
+    // the payload carries the raw message text, so only its size is logged
+    println!("sqs event with {} records", event.payload.records.len());
+    match process_event(event.payload, service, redactor).await {
         Ok(_) => {
             println!("finish processing sqs event with success!")
         },
+        // fail the invocation so that the message goes back to the queue instead of never being scored
+        Err(error) if is_throttling_error(&error) => {
+            println!("Bedrock unavailable, returning message to the queue: {:?}", error);
+            return Err(error.into())
+        },
         Err(error) => {
             println!("Error processing sqs event: {:?}", error)
         },
     }
-    return Ok(json!({}))
+    Ok(json!({}))
 }
 
 
-async fn process_event(event: SqsEvent, service: &CommonService) -> anyhow::Result<()> {
+async fn process_event(event: SqsEvent, service: &CommonService, redactor: &Redactor) -> anyhow::Result<()> {
     let queue_arn = std::env::var(QUEUE_ARN)?;
-    let table_name = std::env::var(TABLE_NAME)?;
-    let threshold: f64 = std::env::var(IMMEDIATE_WARNING_THRESHOLD)?.parse()?;
+    let threshold = warning_threshold_from_env();
+    let max_std_dev: Option<f64> = std::env::var(WARNING_MAX_STD_DEV).ok().and_then(|s| s.parse().ok());
+    let toxicity_enabled = std::env::var(TOXICITY_CLASSIFIER_ENABLED).unwrap_or_default() == "true";
+    let toxicity_threshold: f64 = std::env::var(TOXICITY_THRESHOLD).ok().and_then(|t| t.parse().ok()).unwrap_or(DEFAULT_TOXICITY_THRESHOLD);
+    let moderator_channel_id = std::env::var(MODERATOR_CHANNEL_ID).unwrap_or_default();
+    let person_directed_policy = PersonDirectedPolicy::from_env(&moderator_channel_id);
+    let person_directed_threshold: f64 = std::env::var(PERSON_DIRECTED_WARNING_THRESHOLD).ok().and_then(|t| t.parse().ok()).unwrap_or(threshold);
+    let retention = RetentionPolicy::from_env();
 
     for record in event.records.into_iter() {
         if record.event_source_arn.is_some() && record.event_source_arn.unwrap() != queue_arn {
@@ -55,24 +70,117 @@ async fn process_event(event: SqsEvent, service: &CommonService) -> anyhow::Resu
             },
         };
 
-        println!("message request: {:?}", message_request);
+        println!("message request: {}", message_request.event_id);
 
         let event = message_request.clone().event;
-        let scores = service.bedrock.get_emotion_scroe(&event.text).await?;
-        let entry = service.dynamo.register_entry(&table_name, &message_request, &scores).await?;
-        println!("Entry registered to Dynamo: {:?}", entry);
+        // Bedrock only sees the redacted prose, without slack markup
+        let redaction = redactor.redact(&event.text);
+        let normalized = normalize(&event.text);
+        // on the prose only, links, mentions, emoji and code would count as english
+        let language = Language::detect(&normalized.prose());
+        let scoring_text = redactor.redact(&normalized.text).text;
+        let text = scoring_text.as_str();
+        let scoring = service.scorer.score(text, &language).await?;
+        let scores = scoring.scores.to_owned();
+        let mut entry = EmotionTableEntry::new(&message_request, &scoring, language)?;
+        entry.text = redactor.persisted_text(&event.text, &redaction).to_owned();
+        entry.text_redacted = entry.text != event.text;
+        entry.ttl = retention.expires_at(entry.timestamp);
+        if let Some(target) = entry.target.as_mut() {
+            target.user_id = target.mention.as_deref().and_then(|m| normalized.resolve_mention(m)).map(|id| id.to_owned());
+        }
 
+        // a score over the threshold only warns if the samples agree on it
+        let person_directed = entry.is_person_directed();
+        let threshold = if person_directed { person_directed_threshold } else { threshold };
+        let warning_emotions: Vec<&Emotion> = taxonomy().warning_emotions()
+            .filter(|emotion| scores.get(&emotion.name) > threshold && !entry.is_uncertain(&emotion.name, max_std_dev))
+            .collect();
+        if !warning_emotions.is_empty() {
+            match get_calmer_rewrite(text, &language, &scores, service).await {
+                Ok((rewrite, rewrite_scoring)) => {
+                    entry.rewrite = Some(rewrite.rewrite);
+                    entry.rewrite_model_id = rewrite.model_id;
+                    entry.rewrite_prompt_version = rewrite.prompt_version;
+                    entry.rewrite_scores = Some(rewrite_scoring.scores);
+                    entry.rewrite_scores_model_id = Some(rewrite_scoring.model_id);
+                    entry.rewrite_scores_prompt_version = rewrite_scoring.prompt_version;
+                },
+                Err(error) => {
+                    println!("Error getting calmer rewrite: {:?}", error);
+                },
+            }
+        }
 
-        if scores.anger > threshold {
-            service.line.send_immediate_warning(&event.channel, &event.event_ts, &event.user, ANGER_WARNING).await?
+        if toxicity_enabled {
+            match service.bedrock.get_toxicity(text, &language).await {
+                Ok(toxicity) => entry.toxicity = Some(toxicity),
+                Err(error) => println!("Error classifying toxicity: {:?}", error),
+            }
         }
-        if scores.disgust > threshold {
-            service.line.send_immediate_warning(&event.channel, &event.event_ts, &event.user, DISGUST_WARNING).await?
+
+        // the warnings below use the entry in the clear, only the stored one is encrypted
+        let stored = service.seal(&entry).await?;
+        service.repository.register_entry(&stored).await?;
+        println!("Entry registered to Dynamo: {}", stored.event_id);
+
+
+        let escalations = entry.toxicity.as_ref().map(|t| t.escalations(toxicity_threshold)).unwrap_or_default();
+        if !escalations.is_empty() && !moderator_channel_id.is_empty() {
+            // harassment goes to the moderators, not a generic public warning
+            service.line.send_moderator_alert(&moderator_channel_id, &event.event_ts, &entry, &toxicity_reason(&escalations)).await?
+        } else if person_directed_policy.applies_to(&entry) {
+            send_person_directed_warning(&entry, &event.event_ts, &warning_emotions, person_directed_policy, &moderator_channel_id, service).await?
+        } else {
+            for emotion in warning_emotions {
+                if let Some(warning) = emotion.warning(&language) {
+                    service.line.send_immediate_warning(&event.channel, &event.event_ts, &event.user, warning).await?
+                }
+            }
         }
-        if scores.contempt > threshold {
-            service.line.send_immediate_warning(&event.channel, &event.event_ts, &event.user, CONTEMPT_WARNING).await?
+
+        // only suggest the rewrite if it is actually calmer than the original,
+        // as told by the same model: scores of a fallback model do not compare
+        if let (Some(rewrite), Some(rewrite_scores)) = (&entry.rewrite, &entry.rewrite_scores) {
+            if !entry.rewrite_scored_alike() {
+                println!("Rewrite scored by {:?} ({:?}), the message by {:?} ({:?}), not suggested", entry.rewrite_scores_model_id, entry.rewrite_scores_prompt_version, entry.model_id, entry.prompt_version);
+            } else if rewrite_scores.max_negative() < scores.max_negative() {
+                // the mentions are restored in the ephemeral message only, the entry keeps the placeholders
+                service.line.send_rewrite_suggestion(&event.event_ts, &entry, &normalized.restore_mentions(rewrite)).await?
+            } else {
+                println!("Rewrite is not calmer than the original. original: {:?}, rewrite: {:?}", scores, rewrite_scores);
+            }
         }
     }
 
     Ok(())
 }
+
+
+async fn send_person_directed_warning(entry: &EmotionTableEntry, message_ts: &str, warning_emotions: &[&Emotion], policy: PersonDirectedPolicy, moderator_channel_id: &str, service: &CommonService) -> anyhow::Result<()> {
+    if warning_emotions.is_empty() {
+        return Ok(())
+    }
+    match policy {
+        PersonDirectedPolicy::Private => {
+            let warnings = warning_emotions.iter()
+                .filter_map(|emotion| emotion.warning(&entry.language))
+                .collect::<Vec<&str>>()
+                .join("\n");
+            service.line.send_private_warning(&entry.channel_id, message_ts, &entry.user_id, &entry.language, &warnings).await
+        },
+        PersonDirectedPolicy::Moderator => {
+            let emotions: Vec<(&str, f64)> = warning_emotions.iter().map(|e| (e.name.as_str(), entry.scores.get(&e.name))).collect();
+            let target_user_id = entry.target.as_ref().and_then(|t| t.user_id.as_deref());
+            service.line.send_moderator_alert(moderator_channel_id, message_ts, entry, &person_directed_reason(&emotions, target_user_id)).await
+        },
+        PersonDirectedPolicy::Public | PersonDirectedPolicy::None => Ok(()),
+    }
+}
+
+
+async fn get_calmer_rewrite(text: &str, language: &Language, scores: &EmotionScores, service: &CommonService) -> anyhow::Result<(MessageRewrite, ScoringResult)> {
+    let rewrite = service.bedrock.get_calmer_rewrite(text, scores, language).await?;
+    let rewrite_scoring = service.scorer.score(&rewrite.rewrite, language).await?;
+    Ok((rewrite, rewrite_scoring))
+}
@@ -0,0 +1,74 @@
+
+use anyhow::{bail, Context, Result};
+
+use lib::service::{user_data_service::{ErasureMode, UserDataService}, CommonService};
+
+
+const USAGE: &str = "usage:
+  user_data export USER_ID [--output bundle.json]
+  user_data erase USER_ID --mode delete|anonymise";
+
+
+// Right of access and right to erasure for a single user, across the emotion table, the rollups and the data bucket.
+// Run locally with the environment of the lambdas (TABLE_NAME, ROLLUP_TABLE_NAME, BUCKET_NAME, PROCESSED_S3_FOLDER,
+// and TEXT_ENCRYPTION with its key settings if the text is encrypted) and USER_ID_HASH_KEY for the audit:
+// cargo run -p user_data -- export U0123 --output U0123.json
+// cargo run -p user_data -- erase U0123 --mode anonymise
+// Both write an audit record to audit/user-data/ in the data bucket.
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (Some(operation), Some(user_id)) = (args.first(), args.get(1)) else {
+        bail!(USAGE);
+    };
+    let options = parse_options(&args[2..])?;
+
+    let config = aws_config::load_from_env().await;
+    let service = CommonService::new(&config);
+    let user_data = match &service.cipher {
+        Some(cipher) => UserDataService::new(&service.repository, &service.s3).with_cipher(cipher),
+        None => UserDataService::new(&service.repository, &service.s3),
+    };
+
+    match operation.as_str() {
+        "export" => {
+            let (bundle, audit) = user_data.export(user_id).await?;
+            let bundle_json = serde_json::to_string_pretty(&bundle)?;
+            match options.output {
+                Some(output) => {
+                    std::fs::write(&output, bundle_json).context(format!("Error writing {}", output))?;
+                    println!("bundle written to {}", output);
+                },
+                None => println!("{}", bundle_json),
+            }
+            println!("export recorded: {}", audit.request_id);
+        },
+        "erase" => {
+            let mode = ErasureMode::parse(&options.mode.context(USAGE)?)?;
+            let audit = user_data.erase(user_id, mode).await?;
+            println!("erasure recorded: {}", audit.request_id);
+        },
+        _ => bail!("unknown operation {}\n{}", operation, USAGE),
+    }
+    Ok(())
+}
+
+
+#[derive(Debug, Default)]
+struct Options {
+    output: Option<String>,
+    mode: Option<String>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options> {
+    let mut options = Options::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => options.output = Some(args.next().context(USAGE)?.to_owned()),
+            "--mode" => options.mode = Some(args.next().context(USAGE)?.to_owned()),
+            _ => bail!("unknown argument {}\n{}", arg, USAGE),
+        }
+    }
+    Ok(options)
+}